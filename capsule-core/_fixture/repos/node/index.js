@@ -0,0 +1 @@
+require("http").createServer((req, res) => res.end("hello")).listen(process.env.PORT);
@@ -0,0 +1 @@
+DROP TABLE capsule_application_redirects;
\ No newline at end of file
@@ -0,0 +1,10 @@
+CREATE TABLE capsule_application_redirects
+(
+    id             serial primary key,
+    from_name      varchar(200) not null,
+    application_id bigint       not null,
+    expire_at      timestamp    not null,
+    create_at      timestamp    not null
+);
+
+create unique index capsule_application_redirects_from_name_uindex on capsule_application_redirects (from_name);
@@ -0,0 +1 @@
+DROP TABLE capsule_application_collaborators;
\ No newline at end of file
@@ -0,0 +1,2 @@
+DROP TABLE capsule_organization_members;
+DROP TABLE capsule_organizations;
\ No newline at end of file
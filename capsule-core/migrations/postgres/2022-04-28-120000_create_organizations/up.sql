@@ -0,0 +1,19 @@
+CREATE TABLE capsule_organizations
+(
+    id                serial primary key,
+    organization_name varchar(200) not null,
+    create_at         timestamp    not null
+);
+
+create unique index capsule_organizations_name_uindex on capsule_organizations (organization_name);
+
+CREATE TABLE capsule_organization_members
+(
+    id                serial primary key,
+    organization_name varchar(200) not null,
+    user_name         varchar(200) not null,
+    role              varchar(20)  not null,
+    create_at         timestamp    not null
+);
+
+create unique index capsule_organization_members_organization_user_uindex on capsule_organization_members (organization_name, user_name);
@@ -0,0 +1 @@
+DROP TABLE capsule_application_releases;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE capsule_application_builds;
\ No newline at end of file
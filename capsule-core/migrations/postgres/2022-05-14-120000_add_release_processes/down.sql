@@ -0,0 +1 @@
+ALTER TABLE capsule_application_releases DROP COLUMN processes;
\ No newline at end of file
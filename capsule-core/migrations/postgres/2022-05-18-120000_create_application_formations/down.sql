@@ -0,0 +1,3 @@
+DROP TABLE capsule_owner_limits;
+DROP TABLE capsule_formation_events;
+DROP TABLE capsule_application_formations;
\ No newline at end of file
@@ -0,0 +1,16 @@
+CREATE TABLE capsule_application_domains
+(
+    id                 serial primary key,
+    application_id     bigint       not null,
+    hostname           varchar(253) not null,
+    verification_token varchar(64)  not null,
+    verified_at        timestamp,
+    last_checked_at    timestamp,
+    last_error         text,
+    create_at          timestamp    not null
+);
+
+-- applications may claim the same host name until one of them verified it.
+create unique index capsule_application_domains_hostname_uindex on capsule_application_domains (hostname) where verified_at is not null;
+create unique index capsule_application_domains_application_id_hostname_uindex on capsule_application_domains (application_id, hostname);
+create index capsule_application_domains_application_id_index on capsule_application_domains (application_id);
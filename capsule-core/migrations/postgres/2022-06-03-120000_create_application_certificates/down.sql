@@ -0,0 +1,2 @@
+DROP TABLE capsule_acme_accounts;
+DROP TABLE capsule_application_certificates;
\ No newline at end of file
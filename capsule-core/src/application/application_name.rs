@@ -0,0 +1,191 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fmt::{Display, Formatter};
+
+use anarchist_readable_name_generator_lib::readable_name_custom;
+use rand::Rng;
+
+use crate::application::ApplicationError;
+
+pub const MIN_APPLICATION_NAME_LENGTH: usize = 3;
+pub const MAX_APPLICATION_NAME_LENGTH: usize = 63;
+
+const RESERVED_APPLICATION_NAMES: [&str; 14] = [
+    "admin", "api", "app", "capsule", "dashboard", "ftp", "git", "git-ctl", "mail", "ns1", "ns2", "smtp", "status", "www",
+];
+
+/// An application name that can be used as a DNS label (RFC 1123) and as a git repository path segment.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApplicationName(String);
+
+impl ApplicationName {
+    pub fn new(name: &str) -> Result<Self, ApplicationError> {
+        validate(name)?;
+
+        Ok(Self(name.to_string()))
+    }
+
+    // names read back from storage were validated when they were written.
+    pub(crate) fn unchecked(name: String) -> Self {
+        Self(name)
+    }
+
+    pub fn random() -> Self {
+        let mut rng = rand::thread_rng();
+        let random_number: u32 = rng.gen();
+        let suffix = format!("-{}", random_number);
+
+        let mut prefix = sanitize(readable_name_custom("-", &mut rng).as_str());
+        prefix.truncate(MAX_APPLICATION_NAME_LENGTH - suffix.len());
+        let prefix = prefix.trim_end_matches('-');
+
+        let name = if prefix.is_empty() {
+            format!("app{}", suffix)
+        } else {
+            format!("{}{}", prefix, suffix)
+        };
+
+        Self(name)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Display for ApplicationName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for ApplicationName {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl TryFrom<String> for ApplicationName {
+    type Error = ApplicationError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        ApplicationName::new(name.as_str())
+    }
+}
+
+fn validate(name: &str) -> Result<(), ApplicationError> {
+    if name.len() < MIN_APPLICATION_NAME_LENGTH || name.len() > MAX_APPLICATION_NAME_LENGTH {
+        return Err(invalid(format!("must be between {} and {} characters long", MIN_APPLICATION_NAME_LENGTH, MAX_APPLICATION_NAME_LENGTH)));
+    }
+
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err(invalid("may only contain lowercase letters, digits and hyphens".to_string()));
+    }
+
+    if name.starts_with('-') || name.ends_with('-') {
+        return Err(invalid("must start and end with a letter or digit".to_string()));
+    }
+
+    if RESERVED_APPLICATION_NAMES.contains(&name) {
+        return Err(invalid(format!("'{}' is reserved", name)));
+    }
+
+    Ok(())
+}
+
+fn sanitize(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            sanitized.push(c);
+        } else if !sanitized.is_empty() && !sanitized.ends_with('-') {
+            sanitized.push('-');
+        }
+    }
+
+    sanitized
+}
+
+fn invalid(message: String) -> ApplicationError {
+    ApplicationError::InvalidApplicationName { message }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::application::application_name::{ApplicationName, sanitize};
+    use crate::application::ApplicationError;
+
+    #[test]
+    fn should_accept_dns_safe_name() {
+        let name = ApplicationName::new("first-capsule-application").expect("invalid name");
+
+        assert_eq!(name.as_str(), "first-capsule-application");
+    }
+
+    #[test]
+    fn should_accept_name_starting_with_digit() {
+        assert!(ApplicationName::new("1st-application").is_ok());
+    }
+
+    #[test]
+    fn should_reject_underscores() {
+        assert!(ApplicationName::new("first_capsule_application").is_err());
+    }
+
+    #[test]
+    fn should_reject_uppercase_letters() {
+        assert!(ApplicationName::new("First-Application").is_err());
+    }
+
+    #[test]
+    fn should_reject_leading_or_trailing_hyphen() {
+        assert!(ApplicationName::new("-application").is_err());
+        assert!(ApplicationName::new("application-").is_err());
+    }
+
+    #[test]
+    fn should_reject_too_short_or_too_long_name() {
+        assert!(ApplicationName::new("ab").is_err());
+        assert!(ApplicationName::new("a".repeat(63).as_str()).is_ok());
+        assert!(ApplicationName::new("a".repeat(64).as_str()).is_err());
+    }
+
+    #[test]
+    fn should_reject_reserved_name() {
+        for reserved in ["www", "git", "api"] {
+            let result = ApplicationName::new(reserved);
+
+            match result {
+                Err(ApplicationError::InvalidApplicationName { message }) => assert!(message.contains("reserved")),
+                _ => panic!("{} should be reserved", reserved),
+            }
+        }
+    }
+
+    #[test]
+    fn should_generate_valid_random_names() {
+        for _ in 0..1000 {
+            let name = ApplicationName::random();
+
+            assert!(ApplicationName::new(name.as_str()).is_ok(), "invalid random name {}", name);
+        }
+    }
+
+    #[test]
+    fn should_sanitize_readable_name() {
+        assert_eq!(sanitize("diary of Makhno's wife"), "diary-of-makhno-s-wife");
+        assert_eq!(sanitize("pⓐther"), "p-ther");
+    }
+}
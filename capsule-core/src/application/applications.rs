@@ -11,9 +11,52 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+#[cfg(test)]
+use mockall::{automock, predicate::*};
+
 use crate::application::Application;
 use crate::CoreError;
 
+#[cfg_attr(test, automock)]
 pub trait Applications {
     fn add(&self, application: &Application) -> Result<Application, CoreError>;
-}
\ No newline at end of file
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Application>, CoreError>;
+
+    fn find_by_id(&self, id: i64) -> Result<Option<Application>, CoreError>;
+
+    fn list_by_owner(&self, owner: &str, page: &Page) -> Result<Vec<Application>, CoreError>;
+
+    /// Every application, oldest first, so pages stay stable while applications are added.
+    fn list(&self, page: &Page) -> Result<Vec<Application>, CoreError>;
+
+    fn delete(&self, id: i64) -> Result<(), CoreError>;
+
+    fn exists(&self, name: &str) -> Result<bool, CoreError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub number: i64,
+    pub size: i64,
+}
+
+impl Page {
+    pub fn new(number: i64, size: i64) -> Self {
+        Self { number, size }
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.number.max(1) - 1) * self.limit()
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.size.max(1)
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self { number: 1, size: 20 }
+    }
+}
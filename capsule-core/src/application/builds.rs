@@ -0,0 +1,123 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use derive_more::Display;
+#[cfg(test)]
+use mockall::automock;
+
+use crate::CoreError;
+
+const ZERO_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// One line of what git passes to a `post-receive` hook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushedRef {
+    pub ref_name: String,
+    pub old_sha: String,
+    pub new_sha: String,
+}
+
+impl PushedRef {
+    /// Only pushes creating or moving a branch have something to build; tags and deleted branches do not.
+    pub fn is_branch_update(&self) -> bool {
+        self.ref_name.starts_with("refs/heads/") && self.new_sha != ZERO_SHA
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+pub enum BuildStatus {
+    #[display(fmt = "pending")]
+    Pending,
+}
+
+impl FromStr for BuildStatus {
+    type Err = CoreError;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "pending" => Ok(BuildStatus::Pending),
+            _ => Err(CoreError { message: format!("unknown build status {}", status) }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Build {
+    pub id: i32,
+    pub application_id: i64,
+    pub ref_name: String,
+    pub commit_sha: String,
+    pub pushed_by: String,
+    pub status: BuildStatus,
+    pub create_at: SystemTime,
+}
+
+#[cfg_attr(test, automock)]
+pub trait Builds {
+    /// Queues a pending build of the pushed commit.
+    fn add(&self, application_id: i64, pushed_ref: &PushedRef, pushed_by: &str) -> Result<Build, CoreError>;
+
+    /// Builds of the application, latest first.
+    fn list(&self, application_id: i64) -> Result<Vec<Build>, CoreError>;
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+/// Queues a build for every branch the push created or moved.
+pub fn dispatch_builds(builds: &dyn Builds, application_id: i64, pushed_refs: &[PushedRef], pushed_by: &str) -> Result<Vec<Build>, CoreError> {
+    pushed_refs.iter()
+        .filter(|r| r.is_branch_update())
+        .map(|r| builds.add(application_id, r, pushed_by))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use crate::application::builds::{Build, BuildStatus, dispatch_builds, MockBuilds, PushedRef, ZERO_SHA};
+
+    fn pushed(ref_name: &str, new_sha: &str) -> PushedRef {
+        PushedRef { ref_name: ref_name.to_string(), old_sha: ZERO_SHA.to_string(), new_sha: new_sha.to_string() }
+    }
+
+    #[test]
+    fn should_only_build_branch_updates() {
+        let mut builds = MockBuilds::new();
+        builds.expect_add()
+            .withf(|app_id, r, user| *app_id == 1 && r.ref_name == "refs/heads/main" && user == "first_capsule_user")
+            .times(1)
+            .returning(|app_id, r, user| Ok(Build {
+                id: 1,
+                application_id: app_id,
+                ref_name: r.ref_name.clone(),
+                commit_sha: r.new_sha.clone(),
+                pushed_by: user.to_string(),
+                status: BuildStatus::Pending,
+                create_at: SystemTime::now(),
+            }));
+
+        let pushed_refs = vec![
+            pushed("refs/heads/main", "5e2c0b7a3f1d4c6e8a9b0c1d2e3f4a5b6c7d8e9f"),
+            pushed("refs/tags/v1.0", "5e2c0b7a3f1d4c6e8a9b0c1d2e3f4a5b6c7d8e9f"),
+            pushed("refs/heads/gone", ZERO_SHA),
+        ];
+        let dispatched = dispatch_builds(&builds, 1, &pushed_refs, "first_capsule_user").unwrap();
+
+        assert_eq!(1, dispatched.len());
+        assert_eq!("5e2c0b7a3f1d4c6e8a9b0c1d2e3f4a5b6c7d8e9f", dispatched[0].commit_sha);
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[cfg(test)]
+use mockall::{automock, predicate::*};
+use openssl::sha::sha256;
+
+use crate::application::{ApplicationError, DnsRecord, DomainNameService, RecordType};
+use crate::CoreError;
+
+/// DNS-01 challenges are answered by a TXT record `{DNS_CHALLENGE_PREFIX}.{hostname}`.
+pub const DNS_CHALLENGE_PREFIX: &str = "_acme-challenge";
+const DNS_CHALLENGE_TTL: u32 = 60;
+
+/// A TLS certificate of one host name, with the chain in PEM as the CA returned it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Certificate {
+    pub application_id: i64,
+    pub hostname: String,
+    pub certificate_chain: String,
+    pub private_key: String,
+    pub not_after: SystemTime,
+    pub issued_at: SystemTime,
+}
+
+impl Certificate {
+    pub fn needs_renewal(&self, now: SystemTime, renew_before: Duration) -> bool {
+        self.not_after <= now + renew_before
+    }
+}
+
+/// An account at an ACME CA, its key signs every request made for the account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcmeAccount {
+    pub directory_uri: String,
+    pub account_uri: String,
+    pub private_key: String,
+}
+
+/// What a CA hands out for a finalized order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssuedCertificate {
+    pub certificate_chain: String,
+    pub private_key: String,
+    pub not_after: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChallengeType {
+    Http01,
+    Dns01,
+}
+
+impl Display for ChallengeType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChallengeType::Http01 => write!(f, "http-01"),
+            ChallengeType::Dns01 => write!(f, "dns-01"),
+        }
+    }
+}
+
+impl FromStr for ChallengeType {
+    type Err = ApplicationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http-01" => Ok(ChallengeType::Http01),
+            "dns-01" => Ok(ChallengeType::Dns01),
+            _ => Err(ApplicationError::CertificateError { message: format!("unsupported challenge type {}", s) }),
+        }
+    }
+}
+
+/// A host name that should be served with a certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateTarget {
+    pub application_id: i64,
+    pub hostname: String,
+    pub challenge: ChallengeType,
+}
+
+/// Certificates and ACME accounts, private keys included, so implementations store them encrypted.
+#[cfg_attr(test, automock)]
+pub trait Certificates {
+    /// Replaces the certificate of the same host name.
+    fn save(&self, certificate: &Certificate) -> Result<(), CoreError>;
+
+    fn find(&self, hostname: &str) -> Result<Option<Certificate>, ApplicationError>;
+
+    fn list(&self, application_id: i64) -> Result<Vec<Certificate>, ApplicationError>;
+
+    fn hostnames(&self) -> Result<Vec<String>, CoreError>;
+
+    fn remove(&self, hostname: &str) -> Result<(), CoreError>;
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+
+    fn find_account(&self, directory_uri: &str) -> Result<Option<AcmeAccount>, ApplicationError>;
+
+    /// Replaces the account of the same directory.
+    fn save_account(&self, account: &AcmeAccount) -> Result<(), CoreError>;
+}
+
+/// Obtains a certificate for a host name, proving control of it with `challenge`.
+#[cfg_attr(test, automock)]
+pub trait CertificateIssuer {
+    fn issue(&self, hostname: &str, challenge: ChallengeType) -> Result<IssuedCertificate, ApplicationError>;
+}
+
+/// Makes the answer to a challenge visible to the CA, and takes it away once validated.
+pub trait ChallengeResponder {
+    fn present(&self, hostname: &str, token: &str, key_authorization: &str) -> Result<(), ApplicationError>;
+
+    fn clean_up(&self, hostname: &str, token: &str, key_authorization: &str) -> Result<(), ApplicationError>;
+}
+
+/// Key authorizations of pending HTTP-01 challenges, served at
+/// `http://{hostname}/.well-known/acme-challenge/{token}`. One instance is shared by the
+/// background renewal and every worker answering requests.
+#[derive(Default)]
+pub struct HttpChallenges {
+    key_authorizations: Mutex<HashMap<String, String>>,
+}
+
+impl HttpChallenges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn key_authorization(&self, token: &str) -> Option<String> {
+        self.key_authorizations.lock().unwrap().get(token).cloned()
+    }
+}
+
+impl ChallengeResponder for HttpChallenges {
+    fn present(&self, _hostname: &str, token: &str, key_authorization: &str) -> Result<(), ApplicationError> {
+        self.key_authorizations.lock().unwrap().insert(token.to_string(), key_authorization.to_string());
+        Ok(())
+    }
+
+    fn clean_up(&self, _hostname: &str, token: &str, _key_authorization: &str) -> Result<(), ApplicationError> {
+        self.key_authorizations.lock().unwrap().remove(token);
+        Ok(())
+    }
+}
+
+/// Answers DNS-01 challenges with TXT records in the zone managed by the domain name service,
+/// so it only works for host names below `domain`.
+pub struct DnsChallengeResponder {
+    pub domain_name_service: Arc<dyn DomainNameService>,
+    pub domain: String,
+}
+
+impl DnsChallengeResponder {
+    fn record(&self, hostname: &str, key_authorization: &str) -> Result<DnsRecord, ApplicationError> {
+        let domain = self.domain.trim_end_matches('.');
+        let name = match hostname.strip_suffix(&format!(".{}", domain)) {
+            Some(name) => format!("{}.{}", DNS_CHALLENGE_PREFIX, name),
+            None if hostname == domain => DNS_CHALLENGE_PREFIX.to_string(),
+            None => return Err(ApplicationError::CertificateError { message: format!("dns-01 can not prove control of {}, it is not below {}", hostname, domain) }),
+        };
+
+        Ok(DnsRecord::new(&name, RecordType::Txt, &dns_challenge_value(key_authorization), DNS_CHALLENGE_TTL))
+    }
+}
+
+impl ChallengeResponder for DnsChallengeResponder {
+    fn present(&self, hostname: &str, _token: &str, key_authorization: &str) -> Result<(), ApplicationError> {
+        self.domain_name_service.add_record(&self.record(hostname, key_authorization)?)
+    }
+
+    fn clean_up(&self, hostname: &str, _token: &str, key_authorization: &str) -> Result<(), ApplicationError> {
+        self.domain_name_service.delete_record(&self.record(hostname, key_authorization)?)
+    }
+}
+
+/// The TXT record value of a DNS-01 challenge, the unpadded base64url SHA-256 digest of the key authorization.
+pub fn dns_challenge_value(key_authorization: &str) -> String {
+    base64::encode_config(sha256(key_authorization.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+/// Outcome of obtaining the certificate of one host name.
+#[derive(Debug)]
+pub struct CertificateRenewal {
+    pub application_id: i64,
+    pub hostname: String,
+    pub result: Result<Certificate, ApplicationError>,
+}
+
+/// Keeps a valid certificate for every target.
+pub struct CertificateManager<'a> {
+    certificates: &'a dyn Certificates,
+    issuer: &'a dyn CertificateIssuer,
+    renew_before: Duration,
+}
+
+impl<'a> CertificateManager<'a> {
+    pub fn new(certificates: &'a dyn Certificates, issuer: &'a dyn CertificateIssuer, renew_before: Duration) -> Self {
+        Self { certificates, issuer, renew_before }
+    }
+
+    /// Obtains certificates for the targets without one, or with one expiring within
+    /// `renew_before`. Failing to obtain a certificate only fails that target.
+    pub fn renew_due(&self, targets: &[CertificateTarget], now: SystemTime) -> Result<Vec<CertificateRenewal>, ApplicationError> {
+        let mut renewals = Vec::new();
+        for target in targets {
+            let current = self.certificates.find(&target.hostname)?;
+            if current.is_some_and(|c| c.application_id == target.application_id && !c.needs_renewal(now, self.renew_before)) {
+                continue;
+            }
+
+            let result = self.issue(target, now);
+            renewals.push(CertificateRenewal { application_id: target.application_id, hostname: target.hostname.clone(), result });
+        }
+
+        Ok(renewals)
+    }
+
+    /// Removes the certificates of host names no longer targeted, like former names of
+    /// renamed applications, returning their host names.
+    pub fn remove_untargeted(&self, targets: &[CertificateTarget]) -> Result<Vec<String>, ApplicationError> {
+        let mut removed = Vec::new();
+        for hostname in self.certificates.hostnames()? {
+            if !targets.iter().any(|t| t.hostname == hostname) {
+                self.certificates.remove(&hostname)?;
+                removed.push(hostname);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn issue(&self, target: &CertificateTarget, now: SystemTime) -> Result<Certificate, ApplicationError> {
+        let issued = self.issuer.issue(&target.hostname, target.challenge)?;
+        let certificate = Certificate {
+            application_id: target.application_id,
+            hostname: target.hostname.clone(),
+            certificate_chain: issued.certificate_chain,
+            private_key: issued.private_key,
+            not_after: issued.not_after,
+            issued_at: now,
+        };
+        self.certificates.save(&certificate)?;
+
+        Ok(certificate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use mockall::predicate::{always, eq};
+
+    use crate::application::{ApplicationError, DnsRecord, RecordType};
+    use crate::application::certificates::{Certificate, CertificateManager, CertificateTarget, ChallengeResponder, ChallengeType, dns_challenge_value, DnsChallengeResponder, HttpChallenges, IssuedCertificate, MockCertificateIssuer, MockCertificates};
+    use crate::application::domain_name::MockDomainNameService;
+
+    const DAY: Duration = Duration::from_secs(86400);
+
+    fn target(hostname: &str) -> CertificateTarget {
+        CertificateTarget { application_id: 1, hostname: hostname.to_string(), challenge: ChallengeType::Http01 }
+    }
+
+    fn certificate(hostname: &str, not_after: SystemTime) -> Certificate {
+        Certificate { application_id: 1, hostname: hostname.to_string(), certificate_chain: "chain".to_string(), private_key: "key".to_string(), not_after, issued_at: SystemTime::now() }
+    }
+
+    fn issued(not_after: SystemTime) -> IssuedCertificate {
+        IssuedCertificate { certificate_chain: "new chain".to_string(), private_key: "new key".to_string(), not_after }
+    }
+
+    #[test]
+    fn should_issue_missing_and_expiring_certificates() {
+        let now = SystemTime::now();
+        let mut certificates = MockCertificates::new();
+        certificates.expect_find().with(eq("missing.capsuleapp.cyou")).returning(|_| Ok(None));
+        certificates.expect_find().with(eq("expiring.capsuleapp.cyou")).returning(move |h| Ok(Some(certificate(h, now + 10 * DAY))));
+        certificates.expect_find().with(eq("fresh.capsuleapp.cyou")).returning(move |h| Ok(Some(certificate(h, now + 60 * DAY))));
+        certificates.expect_save().times(2).returning(|_| Ok(()));
+        let mut issuer = MockCertificateIssuer::new();
+        issuer.expect_issue().with(eq("missing.capsuleapp.cyou"), eq(ChallengeType::Http01)).times(1).returning(move |_, _| Ok(issued(now + 90 * DAY)));
+        issuer.expect_issue().with(eq("expiring.capsuleapp.cyou"), eq(ChallengeType::Http01)).times(1).returning(move |_, _| Ok(issued(now + 90 * DAY)));
+        let targets = vec![target("missing.capsuleapp.cyou"), target("expiring.capsuleapp.cyou"), target("fresh.capsuleapp.cyou")];
+
+        let renewals = CertificateManager::new(&certificates, &issuer, 30 * DAY).renew_due(&targets, now).unwrap();
+
+        assert_eq!(vec!["missing.capsuleapp.cyou", "expiring.capsuleapp.cyou"], renewals.iter().map(|r| r.hostname.as_str()).collect::<Vec<_>>());
+        let renewed = renewals[1].result.as_ref().unwrap();
+        assert_eq!(("new chain", now + 90 * DAY, now), (renewed.certificate_chain.as_str(), renewed.not_after, renewed.issued_at));
+    }
+
+    #[test]
+    fn should_reissue_certificate_of_host_name_moved_to_another_application() {
+        let now = SystemTime::now();
+        let mut certificates = MockCertificates::new();
+        certificates.expect_find().returning(move |h| Ok(Some(certificate(h, now + 60 * DAY))));
+        certificates.expect_save().withf(|c| c.application_id == 2).times(1).returning(|_| Ok(()));
+        let mut issuer = MockCertificateIssuer::new();
+        issuer.expect_issue().times(1).returning(move |_, _| Ok(issued(now + 90 * DAY)));
+        let moved = CertificateTarget { application_id: 2, ..target("www.theirshop.com") };
+
+        let renewals = CertificateManager::new(&certificates, &issuer, 30 * DAY).renew_due(&[moved], now).unwrap();
+
+        assert!(renewals[0].result.is_ok());
+    }
+
+    #[test]
+    fn should_keep_renewing_other_targets_when_one_fails() {
+        let now = SystemTime::now();
+        let mut certificates = MockCertificates::new();
+        certificates.expect_find().returning(|_| Ok(None));
+        certificates.expect_save().withf(|c| c.hostname == "second.capsuleapp.cyou").times(1).returning(|_| Ok(()));
+        let mut issuer = MockCertificateIssuer::new();
+        issuer.expect_issue().with(eq("first.capsuleapp.cyou"), always())
+            .returning(|_, _| Err(ApplicationError::CertificateError { message: "acme urn:ietf:params:acme:error:rateLimited: too many certificates".to_string() }));
+        issuer.expect_issue().with(eq("second.capsuleapp.cyou"), always()).returning(move |_, _| Ok(issued(now + 90 * DAY)));
+
+        let renewals = CertificateManager::new(&certificates, &issuer, 30 * DAY)
+            .renew_due(&[target("first.capsuleapp.cyou"), target("second.capsuleapp.cyou")], now)
+            .unwrap();
+
+        assert!(matches!(renewals[0].result, Err(ApplicationError::CertificateError { .. })));
+        assert!(renewals[1].result.is_ok());
+    }
+
+    #[test]
+    fn should_remove_certificates_of_untargeted_host_names() {
+        let mut certificates = MockCertificates::new();
+        certificates.expect_hostnames().returning(|| Ok(vec!["old-name.capsuleapp.cyou".to_string(), "new-name.capsuleapp.cyou".to_string()]));
+        certificates.expect_remove().with(eq("old-name.capsuleapp.cyou")).times(1).returning(|_| Ok(()));
+        let issuer = MockCertificateIssuer::new();
+
+        let removed = CertificateManager::new(&certificates, &issuer, 30 * DAY).remove_untargeted(&[target("new-name.capsuleapp.cyou")]).unwrap();
+
+        assert_eq!(vec!["old-name.capsuleapp.cyou"], removed);
+    }
+
+    #[test]
+    fn should_serve_key_authorization_of_pending_http_challenge() {
+        let challenges = HttpChallenges::new();
+
+        challenges.present("first-app.capsuleapp.cyou", "token", "token.thumbprint").unwrap();
+        assert_eq!(Some("token.thumbprint".to_string()), challenges.key_authorization("token"));
+
+        challenges.clean_up("first-app.capsuleapp.cyou", "token", "token.thumbprint").unwrap();
+        assert_eq!(None, challenges.key_authorization("token"));
+    }
+
+    #[test]
+    fn should_answer_dns_challenge_with_txt_record_below_domain() {
+        assert_eq!("61rBZ_4knHblO0MNoxFsXZ_eTFUHum0B6IVRbhvUn5I", dns_challenge_value("token.thumbprint"));
+
+        let record = DnsRecord::new("_acme-challenge.first-app", RecordType::Txt, "61rBZ_4knHblO0MNoxFsXZ_eTFUHum0B6IVRbhvUn5I", 60);
+        let mut domain_name_service = MockDomainNameService::new();
+        domain_name_service.expect_add_record().with(eq(record.clone())).times(1).returning(|_| Ok(()));
+        domain_name_service.expect_delete_record().with(eq(record)).times(1).returning(|_| Ok(()));
+        let responder = DnsChallengeResponder { domain_name_service: Arc::new(domain_name_service), domain: "capsuleapp.cyou.".to_string() };
+
+        responder.present("first-app.capsuleapp.cyou", "token", "token.thumbprint").unwrap();
+        responder.clean_up("first-app.capsuleapp.cyou", "token", "token.thumbprint").unwrap();
+        assert!(matches!(responder.present("www.theirshop.com", "token", "token.thumbprint"), Err(ApplicationError::CertificateError { .. })));
+    }
+}
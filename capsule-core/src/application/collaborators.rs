@@ -0,0 +1,258 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+
+use derive_more::Display;
+#[cfg(test)]
+use mockall::{automock, predicate::*};
+
+use crate::application::{Application, ApplicationError, ProvisioningHook};
+use crate::CoreError;
+use crate::organization::{OrganizationRole, Organizations};
+
+/// What a collaborator may do with an application. Every role includes the permissions of the
+/// roles below it: owner > admin > deployer > viewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display)]
+pub enum Role {
+    #[display(fmt = "viewer")]
+    Viewer,
+    #[display(fmt = "deployer")]
+    Deployer,
+    #[display(fmt = "admin")]
+    Admin,
+    #[display(fmt = "owner")]
+    Owner,
+}
+
+impl Role {
+    pub fn grants(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+impl FromStr for Role {
+    type Err = ApplicationError;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "owner" => Ok(Role::Owner),
+            "admin" => Ok(Role::Admin),
+            "deployer" => Ok(Role::Deployer),
+            "viewer" => Ok(Role::Viewer),
+            _ => Err(ApplicationError::InvalidRole { message: format!("'{}' is not one of owner, admin, deployer, viewer", role) }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collaborator {
+    pub application_id: i64,
+    pub user_name: String,
+    pub role: Role,
+}
+
+#[cfg_attr(test, automock)]
+pub trait Collaborators {
+    /// Adds the collaborator, or changes the role if the user already collaborates on the application.
+    fn save(&self, collaborator: &Collaborator) -> Result<(), CoreError>;
+
+    fn find(&self, application_id: i64, user_name: &str) -> Result<Option<Collaborator>, CoreError>;
+
+    fn list(&self, application_id: i64) -> Result<Vec<Collaborator>, CoreError>;
+
+    fn remove(&self, application_id: i64, user_name: &str) -> Result<(), CoreError>;
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+/// Decides the role a user has on an application: the role as a collaborator, or the role derived
+/// from membership in the organization owning the application, whichever is higher. A user owning
+/// the application is its owner even without a collaborator row, so a partly deleted application
+/// stays deletable.
+pub struct ApplicationAccess<'a> {
+    collaborators: &'a dyn Collaborators,
+    organizations: &'a dyn Organizations,
+}
+
+impl<'a> ApplicationAccess<'a> {
+    pub fn new(collaborators: &'a dyn Collaborators, organizations: &'a dyn Organizations) -> Self {
+        Self { collaborators, organizations }
+    }
+
+    pub fn role_of(&self, application: &Application, user_name: &str) -> Result<Option<Role>, ApplicationError> {
+        if application.owner == user_name {
+            return Ok(Some(Role::Owner));
+        }
+
+        let collaborator_role = self.collaborators.find(application.id, user_name)?.map(|c| c.role);
+        let member_role = self.organizations.find_member(application.owner.as_str(), user_name)?.map(|m| match m.role {
+            OrganizationRole::Owner => Role::Owner,
+            OrganizationRole::Admin => Role::Admin,
+            OrganizationRole::Member => Role::Deployer,
+        });
+
+        Ok(collaborator_role.max(member_role))
+    }
+
+    /// Fails with `PermissionDenied` unless `user_name` has at least `required` on the application.
+    pub fn require(&self, application: &Application, user_name: &str, required: Role) -> Result<Role, ApplicationError> {
+        match self.role_of(application, user_name)? {
+            Some(role) if role.grants(required) => Ok(role),
+            _ => Err(ApplicationError::PermissionDenied {
+                message: format!("{} needs the {} role on application {}", user_name, required, application.name)
+            }),
+        }
+    }
+}
+
+/// Makes the owner of a new application its first collaborator, and removes all collaborators
+/// when the application goes away.
+pub struct OwnerCollaboratorHook<'a> {
+    collaborators: &'a dyn Collaborators,
+}
+
+impl<'a> OwnerCollaboratorHook<'a> {
+    pub fn new(collaborators: &'a dyn Collaborators) -> Self {
+        Self { collaborators }
+    }
+}
+
+impl<'a> ProvisioningHook for OwnerCollaboratorHook<'a> {
+    fn name(&self) -> String {
+        "add owner collaborator".to_string()
+    }
+
+    fn provision(&self, application: &Application) -> Result<(), ApplicationError> {
+        let owner = Collaborator { application_id: application.id, user_name: application.owner.clone(), role: Role::Owner };
+
+        Ok(self.collaborators.save(&owner)?)
+    }
+
+    fn rollback(&self, application: &Application) -> Result<(), ApplicationError> {
+        Ok(self.collaborators.remove_all(application.id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use mockall::predicate::eq;
+
+    use crate::application::{Application, ApplicationError, ApplicationName, ProvisioningHook};
+    use crate::application::collaborators::{ApplicationAccess, Collaborator, MockCollaborators, OwnerCollaboratorHook, Role};
+    use crate::organization::{Member, MockOrganizations, OrganizationRole};
+
+    fn application() -> Application {
+        Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())
+    }
+
+    #[test]
+    fn should_grant_permissions_of_lower_roles() {
+        assert!(Role::Owner.grants(Role::Admin));
+        assert!(Role::Admin.grants(Role::Deployer));
+        assert!(Role::Deployer.grants(Role::Viewer));
+        assert!(Role::Deployer.grants(Role::Deployer));
+        assert!(!Role::Viewer.grants(Role::Deployer));
+        assert!(!Role::Admin.grants(Role::Owner));
+    }
+
+    #[test]
+    fn should_parse_role() {
+        assert_eq!(Role::from_str("deployer").unwrap(), Role::Deployer);
+        assert_eq!(Role::from_str(Role::Admin.to_string().as_str()).unwrap(), Role::Admin);
+        assert!(matches!(Role::from_str("root"), Err(ApplicationError::InvalidRole { .. })));
+    }
+
+    fn organizations_without_members() -> MockOrganizations {
+        let mut organizations = MockOrganizations::new();
+        organizations.expect_find_member().returning(|_, _| Ok(None));
+        organizations
+    }
+
+    #[test]
+    fn should_allow_collaborator_with_required_role() {
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find().with(eq(1), eq("deploy_user"))
+            .returning(|id, user| Ok(Some(Collaborator { application_id: id, user_name: user.to_string(), role: Role::Admin })));
+        let organizations = organizations_without_members();
+
+        let role = ApplicationAccess::new(&collaborators, &organizations).require(&application(), "deploy_user", Role::Deployer).expect("should be allowed");
+
+        assert_eq!(role, Role::Admin);
+    }
+
+    #[test]
+    fn should_deny_collaborator_with_lower_role() {
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find()
+            .returning(|id, user| Ok(Some(Collaborator { application_id: id, user_name: user.to_string(), role: Role::Viewer })));
+        let organizations = organizations_without_members();
+
+        let result = ApplicationAccess::new(&collaborators, &organizations).require(&application(), "view_user", Role::Deployer);
+
+        assert!(matches!(result, Err(ApplicationError::PermissionDenied { .. })));
+    }
+
+    #[test]
+    fn should_deny_user_who_is_not_collaborator() {
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find().returning(|_, _| Ok(None));
+        let organizations = organizations_without_members();
+
+        let result = ApplicationAccess::new(&collaborators, &organizations).require(&application(), "stranger", Role::Viewer);
+
+        assert!(matches!(result, Err(ApplicationError::PermissionDenied { .. })));
+    }
+
+    #[test]
+    fn should_derive_role_from_membership_of_owning_organization() {
+        let application = Application::new(1, Some(ApplicationName::new("team-application").unwrap()), "capsule-team".to_string());
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find().returning(|_, _| Ok(None));
+        let mut organizations = MockOrganizations::new();
+        organizations.expect_find_member().with(eq("capsule-team"), eq("team_member"))
+            .returning(|org, user| Ok(Some(Member { organization_name: org.to_string(), user_name: user.to_string(), role: OrganizationRole::Member })));
+        organizations.expect_find_member().returning(|_, _| Ok(None));
+        let access = ApplicationAccess::new(&collaborators, &organizations);
+
+        assert_eq!(access.role_of(&application, "team_member").unwrap(), Some(Role::Deployer));
+        assert_eq!(access.role_of(&application, "stranger").unwrap(), None);
+    }
+
+    #[test]
+    fn should_grant_owner_role_to_owning_user_without_collaborator() {
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find().times(0);
+        let organizations = MockOrganizations::new();
+
+        let role = ApplicationAccess::new(&collaborators, &organizations).require(&application(), "first_capsule_user", Role::Owner).expect("should be allowed");
+
+        assert_eq!(role, Role::Owner);
+    }
+
+    #[test]
+    fn should_add_owner_as_collaborator_and_remove_all_on_rollback() {
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_save()
+            .with(eq(Collaborator { application_id: 1, user_name: "first_capsule_user".to_string(), role: Role::Owner }))
+            .times(1)
+            .returning(|_| Ok(()));
+        collaborators.expect_remove_all().with(eq(1)).times(1).returning(|_| Ok(()));
+        let hook = OwnerCollaboratorHook::new(&collaborators);
+
+        hook.provision(&application()).expect("provision failed");
+        hook.rollback(&application()).expect("rollback failed");
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::application::ApplicationError;
+
+type Compensation<'a> = (String, Box<dyn FnOnce() -> Result<(), ApplicationError> + 'a>);
+
+/// Undo actions of the steps completed so far, run in reverse order when a later step fails.
+pub(crate) struct Compensations<'a> {
+    compensations: Vec<Compensation<'a>>,
+}
+
+impl<'a> Compensations<'a> {
+    pub(crate) fn new() -> Self {
+        Self { compensations: vec![] }
+    }
+
+    pub(crate) fn push(&mut self, step: &str, compensation: impl FnOnce() -> Result<(), ApplicationError> + 'a) {
+        self.compensations.push((step.to_string(), Box::new(compensation)));
+    }
+
+    /// Returns the original error, or an internal error naming the steps that could not be undone.
+    pub(crate) fn unwind(self, error: ApplicationError) -> ApplicationError {
+        let mut failed_steps = vec![];
+
+        for (step, compensation) in self.compensations.into_iter().rev() {
+            if let Err(e) = compensation() {
+                failed_steps.push(format!("{} ({})", step, e));
+            }
+        }
+
+        if failed_steps.is_empty() {
+            return error;
+        }
+
+        ApplicationError::InternalError {
+            message: format!("{}, rollback failed: {}", error, failed_steps.join(", "))
+        }
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use crypto::aead::{AeadDecryptor, AeadEncryptor};
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+#[cfg(test)]
+use mockall::automock;
+use rand::RngCore;
+
+use crate::application::ApplicationError;
+use crate::CoreError;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Config vars of one application, ordered by key.
+pub type ConfigVarMap = BTreeMap<String, String>;
+
+/// Keys mapped to their new value, or to `None` to unset them.
+pub type ConfigVarChanges = BTreeMap<String, Option<String>>;
+
+/// Who changed which config vars of an application. Values are never recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub application_id: i64,
+    pub user_name: String,
+    pub keys: Vec<String>,
+    pub change_at: SystemTime,
+}
+
+#[cfg_attr(test, automock)]
+pub trait ConfigVars {
+    fn find_all(&self, application_id: i64) -> Result<ConfigVarMap, ApplicationError>;
+
+    /// Applies all changes at once and records the keys whose value actually changed, which are returned.
+    fn apply(&self, application_id: i64, changes: &ConfigVarChanges, changed_by: &str) -> Result<Vec<String>, ApplicationError>;
+
+    /// Changes of the application, oldest first.
+    fn history(&self, application_id: i64) -> Result<Vec<ConfigChange>, CoreError>;
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+/// Config var keys become environment variable names, so they are restricted to what every shell accepts.
+pub fn validate_key(key: &str) -> Result<(), ApplicationError> {
+    let mut chars = key.chars();
+    let valid = match chars.next() {
+        Some(first) => (first.is_ascii_alphabetic() || first == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        None => false,
+    };
+
+    if !valid {
+        return Err(ApplicationError::InvalidConfigVar { message: format!("{} is not a valid environment variable name", key) });
+    }
+
+    Ok(())
+}
+
+/// Changes turning `current` into exactly `replacement`.
+pub fn replacement_changes(current: &ConfigVarMap, replacement: &ConfigVarMap) -> ConfigVarChanges {
+    let mut changes: ConfigVarChanges = current.keys()
+        .filter(|key| !replacement.contains_key(*key))
+        .map(|key| (key.clone(), None))
+        .collect();
+    changes.extend(replacement.iter().map(|(key, value)| (key.clone(), Some(value.clone()))));
+
+    changes
+}
+
+/// Encrypts config var values with AES-256-GCM. Every value gets a random nonce and is bound to
+/// its application and key, so a stored value can not be moved to another key unnoticed.
+#[derive(Clone)]
+pub struct ConfigVarsCipher {
+    key: [u8; KEY_LEN],
+}
+
+impl ConfigVarsCipher {
+    /// Takes the key as 64 hex digits.
+    pub fn from_hex(hex_key: &str) -> Result<Self, ApplicationError> {
+        let invalid = || ApplicationError::InternalError { message: format!("config vars key must be {} hex digits", KEY_LEN * 2) };
+        if hex_key.len() != KEY_LEN * 2 || !hex_key.is_ascii() {
+            return Err(invalid());
+        }
+
+        let mut key = [0u8; KEY_LEN];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex_key[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+
+        Ok(Self { key })
+    }
+
+    /// Returns the nonce, the ciphertext and the tag in one buffer.
+    pub fn encrypt(&self, application_id: i64, key: &str, value: &str) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let aad = associated_data(application_id, key);
+        let mut ciphertext = vec![0u8; value.len()];
+        let mut tag = [0u8; TAG_LEN];
+        AesGcm::new(KeySize::KeySize256, &self.key, &nonce, &aad).encrypt(value.as_bytes(), &mut ciphertext, &mut tag);
+
+        [&nonce[..], &ciphertext[..], &tag[..]].concat()
+    }
+
+    pub fn decrypt(&self, application_id: i64, key: &str, sealed: &[u8]) -> Result<String, ApplicationError> {
+        let undecryptable = || ApplicationError::InternalError { message: format!("config var {} can not be decrypted", key) };
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(undecryptable());
+        }
+
+        let (nonce, rest) = sealed.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        let aad = associated_data(application_id, key);
+        let mut plaintext = vec![0u8; ciphertext.len()];
+        if !AesGcm::new(KeySize::KeySize256, &self.key, nonce, &aad).decrypt(ciphertext, &mut plaintext, tag) {
+            return Err(undecryptable());
+        }
+
+        String::from_utf8(plaintext).map_err(|_| undecryptable())
+    }
+}
+
+fn associated_data(application_id: i64, key: &str) -> Vec<u8> {
+    format!("{}:{}", application_id, key).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::application::ApplicationError;
+    use crate::application::config_vars::{ConfigVarMap, ConfigVarsCipher, replacement_changes, validate_key};
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn should_decrypt_encrypted_value() {
+        let cipher = ConfigVarsCipher::from_hex(KEY).unwrap();
+
+        let sealed = cipher.encrypt(1, "DATABASE_URL", "postgres://localhost/app");
+
+        assert!(!sealed.windows(9).any(|w| w == b"localhost"));
+        assert_eq!("postgres://localhost/app", cipher.decrypt(1, "DATABASE_URL", &sealed).unwrap());
+    }
+
+    #[test]
+    fn should_use_fresh_nonce_for_every_value() {
+        let cipher = ConfigVarsCipher::from_hex(KEY).unwrap();
+
+        assert_ne!(cipher.encrypt(1, "SECRET", "value"), cipher.encrypt(1, "SECRET", "value"));
+    }
+
+    #[test]
+    fn should_not_decrypt_value_moved_to_another_key_or_application() {
+        let cipher = ConfigVarsCipher::from_hex(KEY).unwrap();
+        let sealed = cipher.encrypt(1, "SECRET", "value");
+
+        assert!(matches!(cipher.decrypt(1, "OTHER", &sealed), Err(ApplicationError::InternalError { .. })));
+        assert!(matches!(cipher.decrypt(2, "SECRET", &sealed), Err(ApplicationError::InternalError { .. })));
+    }
+
+    #[test]
+    fn should_not_decrypt_with_another_key() {
+        let sealed = ConfigVarsCipher::from_hex(KEY).unwrap().encrypt(1, "SECRET", "value");
+        let other = ConfigVarsCipher::from_hex(&KEY.replace("00", "ff")).unwrap();
+
+        assert!(other.decrypt(1, "SECRET", &sealed).is_err());
+    }
+
+    #[test]
+    fn should_reject_malformed_key() {
+        assert!(ConfigVarsCipher::from_hex("0011").is_err());
+        assert!(ConfigVarsCipher::from_hex(&KEY.replace("0a", "zz")).is_err());
+    }
+
+    #[test]
+    fn should_validate_config_var_key() {
+        assert!(validate_key("DATABASE_URL").is_ok());
+        assert!(validate_key("_private1").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("1PORT").is_err());
+        assert!(validate_key("MY-VAR").is_err());
+    }
+
+    #[test]
+    fn should_unset_keys_missing_in_replacement() {
+        let current = ConfigVarMap::from([("KEEP".to_string(), "1".to_string()), ("DROP".to_string(), "2".to_string())]);
+        let replacement = ConfigVarMap::from([("KEEP".to_string(), "3".to_string())]);
+
+        let changes = replacement_changes(&current, &replacement);
+
+        assert_eq!(changes.get("DROP"), Some(&None));
+        assert_eq!(changes.get("KEEP"), Some(&Some("3".to_string())));
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::SystemTime;
+
+#[cfg(test)]
+use mockall::{automock, predicate::*};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use crate::application::ApplicationError;
+use crate::CoreError;
+
+/// The TXT record proving control of a custom domain is named `{VERIFICATION_PREFIX}.{hostname}`.
+pub const VERIFICATION_PREFIX: &str = "_capsule-verification";
+const TOKEN_LEN: usize = 32;
+
+/// A host name of the customer, like `www.theirshop.com`, served by an application once the
+/// customer proved controlling it with a TXT record holding `verification_token`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomDomain {
+    pub application_id: i64,
+    pub hostname: String,
+    pub verification_token: String,
+    pub create_at: SystemTime,
+    pub verified_at: Option<SystemTime>,
+    pub last_checked_at: Option<SystemTime>,
+    pub last_error: Option<String>,
+}
+
+impl CustomDomain {
+    /// Host names below `platform_domain` belong to applications by their name, so they can
+    /// not be added as custom domains.
+    pub fn new(application_id: i64, hostname: &str, platform_domain: &str, now: SystemTime) -> Result<Self, ApplicationError> {
+        let hostname = validate_hostname(hostname, platform_domain)?;
+        let verification_token = rand::thread_rng().sample_iter(&Alphanumeric).take(TOKEN_LEN).map(char::from).collect();
+
+        Ok(CustomDomain { application_id, hostname, verification_token, create_at: now, verified_at: None, last_checked_at: None, last_error: None })
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.verified_at.is_some()
+    }
+
+    /// Name of the TXT record to create at the DNS provider of the customer.
+    pub fn verification_name(&self) -> String {
+        format!("{}.{}", VERIFICATION_PREFIX, self.hostname)
+    }
+}
+
+/// Returns the host name lower cased and without a trailing dot.
+pub fn validate_hostname(hostname: &str, platform_domain: &str) -> Result<String, ApplicationError> {
+    let invalid = |message: String| Err(ApplicationError::InvalidCustomDomain { message });
+    let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+    let platform_domain = platform_domain.trim_end_matches('.').to_ascii_lowercase();
+
+    let labels: Vec<&str> = hostname.split('.').collect();
+    let valid_labels = labels.iter().all(|label| {
+        !label.is_empty() && label.len() <= 63 && !label.starts_with('-') && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    let numeric_tld = labels.last().is_none_or(|tld| tld.chars().all(|c| c.is_ascii_digit()));
+    if hostname.len() > 253 || labels.len() < 2 || !valid_labels || numeric_tld {
+        return invalid(format!("{} is not a valid host name", hostname));
+    }
+    if hostname == platform_domain || hostname.ends_with(&format!(".{}", platform_domain)) {
+        return invalid(format!("host names below {} are given to applications by their name", platform_domain));
+    }
+
+    Ok(hostname)
+}
+
+#[cfg_attr(test, automock)]
+pub trait CustomDomains {
+    /// Fails with `CustomDomainAlreadyExists` if the application claimed the host name already or
+    /// another application verified it. Unverified claims of several applications coexist.
+    fn add(&self, domain: &CustomDomain) -> Result<(), ApplicationError>;
+
+    fn find_verified(&self, hostname: &str) -> Result<Option<CustomDomain>, CoreError>;
+
+    fn list(&self, application_id: i64) -> Result<Vec<CustomDomain>, CoreError>;
+
+    /// Domains not verified yet, the ones checked longest ago first.
+    fn unverified(&self) -> Result<Vec<CustomDomain>, CoreError>;
+
+    fn verified(&self) -> Result<Vec<CustomDomain>, CoreError>;
+
+    /// Removes the unverified claims of other applications on the host name. Returns false,
+    /// removing the claim of the application instead, if another application verified it first.
+    fn mark_verified(&self, application_id: i64, hostname: &str, at: SystemTime) -> Result<bool, CoreError>;
+
+    fn record_failed_check(&self, application_id: i64, hostname: &str, at: SystemTime, error: &str) -> Result<(), CoreError>;
+
+    /// Returns whether the application had the domain.
+    fn remove(&self, application_id: i64, hostname: &str) -> Result<bool, CoreError>;
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+#[cfg_attr(test, automock)]
+pub trait TxtResolver: Send + Sync {
+    /// Texts of the TXT records of `name`, empty if there are none.
+    fn resolve_txt(&self, name: &str) -> Result<Vec<String>, ApplicationError>;
+}
+
+/// Looks up the verification records of unverified custom domains.
+pub struct DomainVerifier<'a> {
+    domains: &'a dyn CustomDomains,
+    resolver: &'a dyn TxtResolver,
+}
+
+impl<'a> DomainVerifier<'a> {
+    pub fn new(domains: &'a dyn CustomDomains, resolver: &'a dyn TxtResolver) -> Self {
+        Self { domains, resolver }
+    }
+
+    /// Checks every unverified domain once, returning the ones verified now. Failing to
+    /// resolve a record only fails the check of that domain.
+    pub fn verify_unverified(&self, now: SystemTime) -> Result<Vec<CustomDomain>, ApplicationError> {
+        let mut verified = Vec::new();
+        for domain in self.domains.unverified()? {
+            if self.verify(&domain, now)? {
+                verified.push(CustomDomain { verified_at: Some(now), last_checked_at: Some(now), last_error: None, ..domain });
+            }
+        }
+
+        Ok(verified)
+    }
+
+    pub fn verify(&self, domain: &CustomDomain, now: SystemTime) -> Result<bool, ApplicationError> {
+        let name = domain.verification_name();
+        let error = match self.resolver.resolve_txt(&name) {
+            Ok(texts) if texts.iter().any(|t| t.trim() == domain.verification_token) => {
+                return Ok(self.domains.mark_verified(domain.application_id, &domain.hostname, now)?);
+            }
+            Ok(texts) if texts.is_empty() => format!("no TXT record found at {}", name),
+            Ok(_) => format!("no TXT record at {} holds the verification token", name),
+            Err(e) => e.to_string(),
+        };
+
+        self.domains.record_failed_check(domain.application_id, &domain.hostname, now, &error)?;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use mockall::predicate::{always, eq};
+
+    use crate::application::ApplicationError;
+    use crate::application::custom_domains::{CustomDomain, DomainVerifier, MockCustomDomains, MockTxtResolver, validate_hostname};
+
+    fn domain(hostname: &str) -> CustomDomain {
+        CustomDomain::new(1, hostname, "capsuleapp.cyou", SystemTime::now()).unwrap()
+    }
+
+    #[test]
+    fn should_issue_verification_token_for_new_domain() {
+        let domain = CustomDomain::new(1, "WWW.TheirShop.com.", "capsuleapp.cyou", SystemTime::now()).unwrap();
+
+        assert_eq!("www.theirshop.com", domain.hostname);
+        assert_eq!("_capsule-verification.www.theirshop.com", domain.verification_name());
+        assert_eq!(32, domain.verification_token.len());
+        assert!(domain.verification_token.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(domain.verification_token, self::domain("www.theirshop.com").verification_token);
+        assert!(!domain.is_verified());
+    }
+
+    #[test]
+    fn should_reject_invalid_host_names() {
+        for hostname in ["theirshop", "www..theirshop.com", "-www.theirshop.com", "www_shop.theirshop.com", "*.theirshop.com", "192.0.2.10", "", &format!("{}.com", "a".repeat(64))] {
+            assert!(matches!(validate_hostname(hostname, "capsuleapp.cyou"), Err(ApplicationError::InvalidCustomDomain { .. })), "{} should be invalid", hostname);
+        }
+    }
+
+    #[test]
+    fn should_reject_host_names_of_platform_domain() {
+        assert!(validate_hostname("capsuleapp.cyou", "capsuleapp.cyou.").is_err());
+        assert!(validate_hostname("first-app.CapsuleApp.cyou", "capsuleapp.cyou").is_err());
+        assert!(validate_hostname("capsuleapp.cyou.theirshop.com", "capsuleapp.cyou").is_ok());
+    }
+
+    #[test]
+    fn should_verify_domain_with_token_in_txt_record() {
+        let pending = domain("www.theirshop.com");
+        let token = pending.verification_token.clone();
+        let now = SystemTime::now();
+        let mut domains = MockCustomDomains::new();
+        let unverified = pending.clone();
+        domains.expect_unverified().returning(move || Ok(vec![unverified.clone()]));
+        domains.expect_mark_verified().with(eq(1), eq("www.theirshop.com"), eq(now)).times(1).returning(|_, _, _| Ok(true));
+        domains.expect_record_failed_check().times(0);
+        let mut resolver = MockTxtResolver::new();
+        resolver.expect_resolve_txt().with(eq("_capsule-verification.www.theirshop.com"))
+            .returning(move |_| Ok(vec!["google-site-verification=abc".to_string(), format!("{} ", token)]));
+
+        let verified = DomainVerifier::new(&domains, &resolver).verify_unverified(now).unwrap();
+
+        assert_eq!(1, verified.len());
+        assert_eq!(Some(now), verified[0].verified_at);
+        assert_eq!(pending.hostname, verified[0].hostname);
+    }
+
+    #[test]
+    fn should_record_failed_check_without_token() {
+        let pending = domain("www.theirshop.com");
+        let mut domains = MockCustomDomains::new();
+        domains.expect_mark_verified().times(0);
+        domains.expect_record_failed_check()
+            .with(eq(1), eq("www.theirshop.com"), always(), eq("no TXT record at _capsule-verification.www.theirshop.com holds the verification token"))
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        let mut resolver = MockTxtResolver::new();
+        resolver.expect_resolve_txt().returning(|_| Ok(vec!["another-token".to_string()]));
+
+        assert!(!DomainVerifier::new(&domains, &resolver).verify(&pending, SystemTime::now()).unwrap());
+    }
+
+    #[test]
+    fn should_record_resolver_error_and_check_other_domains() {
+        let failing = domain("www.theirshop.com");
+        let other = domain("shop.example.org");
+        let other_token = other.verification_token.clone();
+        let mut domains = MockCustomDomains::new();
+        let unverified = vec![failing.clone(), other.clone()];
+        domains.expect_unverified().returning(move || Ok(unverified.clone()));
+        domains.expect_record_failed_check()
+            .with(eq(1), eq("www.theirshop.com"), always(), eq("domain name error resolver timed out"))
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        domains.expect_mark_verified().with(eq(1), eq("shop.example.org"), always()).times(1).returning(|_, _, _| Ok(true));
+        let mut resolver = MockTxtResolver::new();
+        resolver.expect_resolve_txt().with(eq("_capsule-verification.www.theirshop.com"))
+            .returning(|_| Err(ApplicationError::DomainNameError { message: "resolver timed out".to_string() }));
+        resolver.expect_resolve_txt().with(eq("_capsule-verification.shop.example.org"))
+            .returning(move |_| Ok(vec![other_token.clone()]));
+
+        let verified = DomainVerifier::new(&domains, &resolver).verify_unverified(SystemTime::now()).unwrap();
+
+        assert_eq!(vec!["shop.example.org"], verified.iter().map(|d| d.hostname.as_str()).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,711 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+use derive_more::Display;
+#[cfg(test)]
+use mockall::automock;
+
+use crate::application::ApplicationError;
+use crate::application::formation::{Formation, ProcessSize};
+use crate::application::health::{HealthCheckConfig, HealthChecker, HealthProbe, probe_instance};
+use crate::application::releases::Release;
+use crate::application::runtime::{ProcessInstance, ProcessSpec, ProcessStatus, RuntimeBackend};
+use crate::buildpack::{RELEASE_PROCESS_TYPE, WEB_PROCESS_TYPE};
+use crate::CoreError;
+
+const LOCALHOST: &str = "127.0.0.1";
+const RELEASE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum DeployStatus {
+    /// Waiting for the deploys of the application queued before it.
+    #[display(fmt = "pending")]
+    Pending,
+    #[display(fmt = "running")]
+    Running,
+    #[display(fmt = "succeeded")]
+    Succeeded,
+    /// A step failed, the previous release keeps running.
+    #[display(fmt = "failed")]
+    Failed,
+}
+
+impl FromStr for DeployStatus {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(DeployStatus::Pending),
+            "running" => Ok(DeployStatus::Running),
+            "succeeded" => Ok(DeployStatus::Succeeded),
+            "failed" => Ok(DeployStatus::Failed),
+            _ => Err(CoreError { message: format!("unknown deploy status {}", s) }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum DeployStepKind {
+    #[display(fmt = "release_phase")]
+    ReleasePhase,
+    #[display(fmt = "start_processes")]
+    StartProcesses,
+    #[display(fmt = "health_check")]
+    HealthCheck,
+    #[display(fmt = "replace")]
+    Replace,
+}
+
+impl FromStr for DeployStepKind {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "release_phase" => Ok(DeployStepKind::ReleasePhase),
+            "start_processes" => Ok(DeployStepKind::StartProcesses),
+            "health_check" => Ok(DeployStepKind::HealthCheck),
+            "replace" => Ok(DeployStepKind::Replace),
+            _ => Err(CoreError { message: format!("unknown deploy step {}", s) }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeployStep {
+    pub kind: DeployStepKind,
+    pub succeeded: bool,
+    pub message: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deploy {
+    pub id: i32,
+    pub application_id: i64,
+    pub release_version: i32,
+    pub status: DeployStatus,
+    /// Steps in the order they ran.
+    pub steps: Vec<DeployStep>,
+    pub created_by: String,
+    pub create_at: SystemTime,
+    pub finish_at: Option<SystemTime>,
+}
+
+#[cfg_attr(test, automock)]
+pub trait Deploys {
+    /// Queues a pending deploy of the release.
+    fn create(&self, application_id: i64, release_version: i32, created_by: &str) -> Result<Deploy, CoreError>;
+
+    fn find(&self, deploy_id: i32) -> Result<Option<Deploy>, CoreError>;
+
+    /// The latest `limit` deploys of the application, latest first.
+    fn list(&self, application_id: i64, limit: i64) -> Result<Vec<Deploy>, CoreError>;
+
+    /// Pending deploys of all applications, in the order they were queued.
+    fn pending(&self) -> Result<Vec<Deploy>, CoreError>;
+
+    /// Sets the finish time too when `status` is succeeded or failed.
+    fn update_status(&self, deploy_id: i32, status: DeployStatus) -> Result<(), CoreError>;
+
+    fn add_step(&self, deploy_id: i32, step: &DeployStep) -> Result<(), CoreError>;
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+/// What a deploy brings up: the release with the formation's scale, run from the unpacked build of
+/// the release in `working_dir`.
+pub struct DeployTarget {
+    pub release: Release,
+    pub formation: Formation,
+    pub working_dir: PathBuf,
+    pub health_check: HealthCheckConfig,
+}
+
+struct Started {
+    spec: ProcessSpec,
+    /// Where web instances listen.
+    address: Option<String>,
+}
+
+/// Deploys a release without downtime: runs the release phase, starts the new web instances next to
+/// the running ones and replaces the instances of previous releases only after the new ones passed
+/// their health checks. A failed step stops what the deploy started and leaves the previous release
+/// running. Every step is recorded on the deploy.
+pub struct DeployCoordinator<'a> {
+    runtime: &'a dyn RuntimeBackend,
+    probe: &'a dyn HealthProbe,
+    health_checker: &'a HealthChecker,
+    deploys: &'a dyn Deploys,
+    release_timeout: Duration,
+    poll_interval: Option<Duration>,
+}
+
+impl<'a> DeployCoordinator<'a> {
+    /// The release phase is stopped and fails the deploy after `release_timeout`.
+    pub fn new(runtime: &'a dyn RuntimeBackend, probe: &'a dyn HealthProbe, health_checker: &'a HealthChecker,
+               deploys: &'a dyn Deploys, release_timeout: Duration) -> Self {
+        Self { runtime, probe, health_checker, deploys, release_timeout, poll_interval: None }
+    }
+
+    /// Polls the release phase and probes new instances this often, instead of every second and
+    /// every health check interval.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// A deploy failing with an error is marked failed too, after stopping what it started.
+    pub fn deploy(&self, deploy: &Deploy, target: &DeployTarget) -> Result<DeployStatus, ApplicationError> {
+        self.deploys.update_status(deploy.id, DeployStatus::Running)?;
+
+        let mut started = vec![];
+        let status = match self.run_steps(deploy.id, target, &mut started) {
+            Ok(true) => DeployStatus::Succeeded,
+            Ok(false) => {
+                self.stop_all(&started);
+                DeployStatus::Failed
+            }
+            Err(e) => {
+                self.stop_all(&started);
+                let _ = self.deploys.update_status(deploy.id, DeployStatus::Failed);
+                return Err(e);
+            }
+        };
+        self.deploys.update_status(deploy.id, status)?;
+
+        Ok(status)
+    }
+
+    fn run_steps(&self, deploy_id: i32, target: &DeployTarget, started: &mut Vec<Started>) -> Result<bool, ApplicationError> {
+        if !self.record(deploy_id, DeployStepKind::ReleasePhase, self.release_phase(target))? {
+            return Ok(false);
+        }
+
+        let starting = self.start(target, |t| t == WEB_PROCESS_TYPE, started);
+        if !self.record(deploy_id, DeployStepKind::StartProcesses, starting)? {
+            return Ok(false);
+        }
+
+        if !self.record(deploy_id, DeployStepKind::HealthCheck, self.wait_healthy(started, &target.health_check))? {
+            return Ok(false);
+        }
+
+        let replacing = self.replace(target, started);
+        if replacing.is_ok() {
+            // the new release serves now, nothing may stop it anymore.
+            started.clear();
+        }
+        Ok(self.record(deploy_id, DeployStepKind::Replace, replacing)?)
+    }
+
+    fn record(&self, deploy_id: i32, kind: DeployStepKind, outcome: Result<String, String>) -> Result<bool, CoreError> {
+        let succeeded = outcome.is_ok();
+        let step = DeployStep { kind, succeeded, message: outcome.unwrap_or_else(|e| e), create_at: SystemTime::now() };
+        self.deploys.add_step(deploy_id, &step)?;
+
+        Ok(succeeded)
+    }
+
+    fn release_phase(&self, target: &DeployTarget) -> Result<String, String> {
+        let command = match target.release.spec.processes.get(RELEASE_PROCESS_TYPE) {
+            Some(command) => command,
+            None => return Ok("no release command, skipped".to_string()),
+        };
+
+        let spec = process_spec(target, RELEASE_PROCESS_TYPE, command, 0);
+        self.runtime.start(&spec).map_err(|e| e.to_string())?;
+
+        let started = Instant::now();
+        let status = loop {
+            match self.runtime.status(&spec.instance).map_err(|e| e.to_string())? {
+                ProcessStatus::Running { .. } if started.elapsed() >= self.release_timeout => {
+                    let _ = self.runtime.stop(&spec.instance);
+                    return Err(format!("`{}` did not finish within {} seconds", command, self.release_timeout.as_secs()));
+                }
+                ProcessStatus::Running { .. } => sleep(self.poll_interval.unwrap_or(RELEASE_POLL_INTERVAL)),
+                status => break status,
+            }
+        };
+        // the instance exited, stopping only lets the runtime forget it.
+        let _ = self.runtime.stop(&spec.instance);
+
+        match status {
+            ProcessStatus::Exited { code: Some(0) } => Ok(format!("`{}` exited with code 0", command)),
+            ProcessStatus::Exited { code: Some(code) } => Err(format!("`{}` exited with code {}", command, code)),
+            ProcessStatus::Exited { code: None } => Err(format!("`{}` was killed by a signal", command)),
+            status => Err(format!("`{}` is {}", command, status)),
+        }
+    }
+
+    /// Starts the scaled instances of the release's process types matching `process_types`, web
+    /// instances on a free port each.
+    fn start(&self, target: &DeployTarget, process_types: impl Fn(&str) -> bool, started: &mut Vec<Started>) -> Result<String, String> {
+        let mut names = vec![];
+        for (process_type, command) in target.release.spec.processes.iter().filter(|(t, _)| process_types(t.as_str())) {
+            let scale = target.formation.processes().get(process_type).copied().unwrap_or_default();
+
+            for index in 0..scale.quantity.max(0) as u32 {
+                let mut spec = process_spec(target, process_type, command, index);
+                spec.size = scale.size;
+                let address = if process_type == WEB_PROCESS_TYPE {
+                    let port = free_port()?;
+                    spec.env.insert("PORT".to_string(), port.to_string());
+                    Some(format!("{}:{}", LOCALHOST, port))
+                } else {
+                    None
+                };
+
+                self.runtime.start(&spec).map_err(|e| e.to_string())?;
+                names.push(match &address {
+                    Some(address) => format!("{} on {}", spec.instance.process_name(), address),
+                    None => spec.instance.process_name(),
+                });
+                started.push(Started { spec, address });
+            }
+        }
+
+        Ok(match names.is_empty() {
+            true => "no processes to start".to_string(),
+            false => format!("started {}", names.join(", ")),
+        })
+    }
+
+    /// Probes the new web instances until each passed `healthy_threshold` probes in a row. The
+    /// first to fail `unhealthy_threshold` probes in a row fails the deploy, and so do instances
+    /// still not healthy after `deploy_timeout_secs`, like ones passing and failing by turns.
+    fn wait_healthy(&self, started: &[Started], config: &HealthCheckConfig) -> Result<String, String> {
+        let web: Vec<(&ProcessInstance, &str)> = started.iter()
+            .filter_map(|s| s.address.as_deref().map(|address| (&s.spec.instance, address)))
+            .collect();
+        if web.is_empty() {
+            return Ok("no web processes to check".to_string());
+        }
+
+        let interval = self.poll_interval.unwrap_or(Duration::from_secs(config.interval_secs as u64));
+        let deadline = Instant::now() + Duration::from_secs(config.deploy_timeout_secs as u64);
+        let (mut successes, mut failures) = (vec![0; web.len()], vec![0; web.len()]);
+        while successes.iter().any(|s| *s < config.healthy_threshold) {
+            if Instant::now() >= deadline {
+                let unhealthy: Vec<String> = web.iter().zip(&successes)
+                    .filter(|(_, s)| **s < config.healthy_threshold)
+                    .map(|((instance, _), _)| instance.process_name())
+                    .collect();
+                return Err(format!("{} not healthy within {} seconds", unhealthy.join(", "), config.deploy_timeout_secs));
+            }
+            sleep(interval);
+
+            for (i, (instance, address)) in web.iter().enumerate() {
+                if successes[i] >= config.healthy_threshold {
+                    continue;
+                }
+
+                match probe_instance(self.runtime, self.probe, instance, address, config) {
+                    Ok(()) => {
+                        successes[i] += 1;
+                        failures[i] = 0;
+                    }
+                    Err(failure) => {
+                        successes[i] = 0;
+                        failures[i] += 1;
+                        if failures[i] >= config.unhealthy_threshold {
+                            return Err(format!("{} failed {} health checks in a row: {}", instance.process_name(), failures[i], failure));
+                        }
+                    }
+                }
+            }
+        }
+
+        let names: Vec<String> = web.iter().map(|(instance, _)| instance.process_name()).collect();
+        Ok(format!("{} passed health checks", names.join(", ")))
+    }
+
+    /// Starts the other process types of the release, then stops the instances of previous releases
+    /// and hands the new web instances to the health checker.
+    fn replace(&self, target: &DeployTarget, started: &mut Vec<Started>) -> Result<String, String> {
+        let starting = self.start(target, |t| t != WEB_PROCESS_TYPE && t != RELEASE_PROCESS_TYPE, started)?;
+
+        let version = target.release.version;
+        let previous: Vec<ProcessInstance> = self.runtime.instances(target.release.application_id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|i| i.release_version != version)
+            .collect();
+
+        // the new release serves already, an instance failing to stop is reported but does not fail the deploy.
+        let mut failed = vec![];
+        for instance in &previous {
+            self.health_checker.unwatch(instance);
+            if let Err(e) = self.runtime.stop(instance) {
+                failed.push(format!("{}: {}", instance, e));
+            }
+        }
+
+        let now = Instant::now();
+        for Started { spec, address } in started.iter() {
+            if let Some(address) = address {
+                self.health_checker.watch(spec.clone(), address.clone(), target.health_check.clone(), now);
+            }
+        }
+
+        let mut message = format!("{}, stopped {} instances of previous releases", starting, previous.len() - failed.len());
+        if !failed.is_empty() {
+            message.push_str(&format!(", failed to stop {}", failed.join(", ")));
+        }
+        Ok(message)
+    }
+
+    fn stop_all(&self, started: &[Started]) {
+        for Started { spec, .. } in started {
+            let _ = self.runtime.stop(&spec.instance);
+        }
+    }
+}
+
+fn process_spec(target: &DeployTarget, process_type: &str, command: &str, index: u32) -> ProcessSpec {
+    ProcessSpec {
+        instance: ProcessInstance {
+            application_id: target.release.application_id,
+            release_version: target.release.version,
+            process_type: process_type.to_string(),
+            index,
+        },
+        command: command.to_string(),
+        working_dir: target.working_dir.clone(),
+        env: target.release.spec.config_vars.clone(),
+        size: ProcessSize::Small,
+    }
+}
+
+fn free_port() -> Result<u16, String> {
+    TcpListener::bind((LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .map_err(|e| format!("find a free port: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, SystemTime};
+
+    use crate::application::config_vars::ConfigVarMap;
+    use crate::application::deploy::{Deploy, DeployCoordinator, DeployStatus, DeployStep, DeployStepKind, DeployTarget, MockDeploys};
+    use crate::application::formation::{Formation, ProcessScale, ProcessSize};
+    use crate::application::health::{HealthCheckConfig, HealthChecker, HealthStatus, MockHealthProbe, ProbeFailure, RestartPolicy};
+    use crate::application::releases::{Release, release_spec, ReleaseSpec};
+    use crate::application::runtime::{ProcessInstance, ProcessSpec, ProcessStatus, RuntimeBackend, RuntimeError};
+    use crate::buildpack::ProcessTypes;
+    use crate::CoreError;
+
+    /// Runs commands of the form `exit <code>` to completion right away and everything else forever.
+    #[derive(Default)]
+    struct FakeRuntime {
+        instances: Mutex<BTreeMap<ProcessInstance, (ProcessSpec, ProcessStatus)>>,
+        stopped: Mutex<Vec<ProcessInstance>>,
+    }
+
+    impl FakeRuntime {
+        fn running(&self, instance: ProcessInstance) {
+            let spec = ProcessSpec { instance: instance.clone(), command: "./app".to_string(), working_dir: PathBuf::from("/srv"), env: ConfigVarMap::new(), size: ProcessSize::Small };
+            self.instances.lock().unwrap().insert(instance, (spec, ProcessStatus::Running { pid: 1 }));
+        }
+
+        fn spec(&self, process_name: &str) -> ProcessSpec {
+            self.instances.lock().unwrap().values().map(|(spec, _)| spec).find(|s| s.instance.process_name() == process_name).unwrap().clone()
+        }
+
+        fn stopped(&self) -> Vec<String> {
+            self.stopped.lock().unwrap().iter().map(|i| i.to_string()).collect()
+        }
+    }
+
+    impl RuntimeBackend for FakeRuntime {
+        fn start(&self, spec: &ProcessSpec) -> Result<ProcessStatus, RuntimeError> {
+            let status = match spec.command.strip_prefix("exit ") {
+                Some(code) => ProcessStatus::Exited { code: code.parse().ok() },
+                None => ProcessStatus::Running { pid: 1 },
+            };
+            self.instances.lock().unwrap().insert(spec.instance.clone(), (spec.clone(), status));
+            Ok(status)
+        }
+
+        fn stop(&self, instance: &ProcessInstance) -> Result<(), RuntimeError> {
+            self.instances.lock().unwrap().remove(instance);
+            self.stopped.lock().unwrap().push(instance.clone());
+            Ok(())
+        }
+
+        fn status(&self, instance: &ProcessInstance) -> Result<ProcessStatus, RuntimeError> {
+            Ok(self.instances.lock().unwrap().get(instance).map_or(ProcessStatus::Stopped, |(_, status)| *status))
+        }
+
+        fn logs(&self, _instance: &ProcessInstance, _lines: usize) -> Result<Vec<String>, RuntimeError> {
+            Ok(vec![])
+        }
+
+        fn instances(&self, application_id: i64) -> Result<Vec<ProcessInstance>, RuntimeError> {
+            Ok(self.instances.lock().unwrap().keys().filter(|i| i.application_id == application_id).cloned().collect())
+        }
+    }
+
+    /// Statuses and steps a deploy went through, as reported to `MockDeploys`.
+    #[derive(Default)]
+    struct DeployRecord {
+        statuses: Arc<Mutex<Vec<DeployStatus>>>,
+        steps: Arc<Mutex<Vec<DeployStep>>>,
+    }
+
+    impl DeployRecord {
+        fn deploys(&self) -> MockDeploys {
+            let mut deploys = MockDeploys::new();
+            let statuses = self.statuses.clone();
+            deploys.expect_update_status()
+                .returning(move |_, status| {
+                    statuses.lock().unwrap().push(status);
+                    Ok(())
+                });
+            let steps = self.steps.clone();
+            deploys.expect_add_step()
+                .returning(move |_, step| {
+                    steps.lock().unwrap().push(step.clone());
+                    Ok(())
+                });
+
+            deploys
+        }
+
+        fn steps(&self) -> Vec<(DeployStepKind, bool)> {
+            self.steps.lock().unwrap().iter().map(|s| (s.kind, s.succeeded)).collect()
+        }
+
+        fn message(&self, kind: DeployStepKind) -> String {
+            self.steps.lock().unwrap().iter().find(|s| s.kind == kind).unwrap().message.clone()
+        }
+    }
+
+    fn deploy() -> Deploy {
+        Deploy {
+            id: 1,
+            application_id: 1,
+            release_version: 2,
+            status: DeployStatus::Pending,
+            steps: vec![],
+            created_by: "first_capsule_user".to_string(),
+            create_at: SystemTime::now(),
+            finish_at: None,
+        }
+    }
+
+    fn target(release_command: Option<&str>) -> DeployTarget {
+        let mut processes = ProcessTypes::from([("web".to_string(), "./app".to_string()), ("worker".to_string(), "./worker".to_string())]);
+        if let Some(command) = release_command {
+            processes.insert("release".to_string(), command.to_string());
+        }
+
+        DeployTarget {
+            release: Release {
+                application_id: 1,
+                version: 2,
+                spec: ReleaseSpec {
+                    config_vars: ConfigVarMap::from([("DATABASE_URL".to_string(), "postgres://db".to_string())]),
+                    processes,
+                    ..release_spec("bbb", &[])
+                },
+                create_at: SystemTime::now(),
+            },
+            formation: Formation::new(1, BTreeMap::from([
+                ("web".to_string(), ProcessScale { quantity: 2, size: ProcessSize::Medium }),
+                ("worker".to_string(), ProcessScale { quantity: 1, size: ProcessSize::Small }),
+            ])),
+            working_dir: PathBuf::from("/srv/first-capsule-application/2"),
+            health_check: HealthCheckConfig { path: "/health".to_string(), interval_secs: 5, timeout_secs: 1, healthy_threshold: 2, unhealthy_threshold: 2, deploy_timeout_secs: 60 },
+        }
+    }
+
+    fn instance(release_version: i32, process_type: &str, index: u32) -> ProcessInstance {
+        ProcessInstance { application_id: 1, release_version, process_type: process_type.to_string(), index }
+    }
+
+    fn probe(healthy: bool) -> MockHealthProbe {
+        let mut probe = MockHealthProbe::new();
+        probe.expect_probe().returning(move |_, _| if healthy { Ok(()) } else { Err(ProbeFailure { message: "503".to_string() }) });
+        probe
+    }
+
+    fn previous_release_running() -> Arc<FakeRuntime> {
+        let runtime = Arc::new(FakeRuntime::default());
+        runtime.running(instance(1, "web", 0));
+        runtime.running(instance(1, "worker", 0));
+        runtime
+    }
+
+    fn run(runtime: Arc<FakeRuntime>, probe: MockHealthProbe, target: &DeployTarget, record: &DeployRecord) -> (DeployStatus, HealthChecker) {
+        let probe = Arc::new(probe);
+        let checker = HealthChecker::new(runtime.clone(), probe.clone(), RestartPolicy { max_restarts: 1 });
+        let deploys = record.deploys();
+        let coordinator = DeployCoordinator::new(runtime.as_ref(), probe.as_ref(), &checker, &deploys, Duration::from_millis(100))
+            .with_poll_interval(Duration::from_millis(5));
+
+        let status = coordinator.deploy(&deploy(), target).unwrap();
+        (status, checker)
+    }
+
+    #[test]
+    fn should_replace_previous_release_after_new_web_instances_passed_health_checks() {
+        let runtime = previous_release_running();
+        let record = DeployRecord::default();
+
+        let (status, checker) = run(runtime.clone(), probe(true), &target(Some("exit 0")), &record);
+
+        assert_eq!(DeployStatus::Succeeded, status);
+        assert_eq!(vec![DeployStatus::Running, DeployStatus::Succeeded], *record.statuses.lock().unwrap());
+        assert_eq!(vec![
+            (DeployStepKind::ReleasePhase, true),
+            (DeployStepKind::StartProcesses, true),
+            (DeployStepKind::HealthCheck, true),
+            (DeployStepKind::Replace, true),
+        ], record.steps());
+        assert_eq!(vec!["app-1-v2-release-0", "app-1-v1-web-0", "app-1-v1-worker-0"], runtime.stopped());
+        assert_eq!(vec![instance(2, "web", 0), instance(2, "web", 1), instance(2, "worker", 0)], runtime.instances(1).unwrap());
+        let health = checker.application_health(1);
+        assert_eq!((HealthStatus::Starting, 2), (health.status, health.instances.len()));
+    }
+
+    #[test]
+    fn should_start_web_instances_with_release_config_scale_and_port() {
+        let runtime = Arc::new(FakeRuntime::default());
+        let record = DeployRecord::default();
+
+        run(runtime.clone(), probe(true), &target(None), &record);
+
+        let web = runtime.spec("web.1");
+        assert_eq!((PathBuf::from("/srv/first-capsule-application/2"), ProcessSize::Medium), (web.working_dir, web.size));
+        assert_eq!(Some(&"postgres://db".to_string()), web.env.get("DATABASE_URL"));
+        let port = web.env.get("PORT").unwrap();
+        assert!(record.message(DeployStepKind::StartProcesses).contains(&format!("web.1 on 127.0.0.1:{}", port)));
+        assert!(!runtime.spec("worker.0").env.contains_key("PORT"));
+        assert_eq!("no release command, skipped", record.message(DeployStepKind::ReleasePhase));
+    }
+
+    #[test]
+    fn should_abort_deploy_when_release_phase_failed() {
+        let runtime = previous_release_running();
+        let record = DeployRecord::default();
+
+        let (status, _) = run(runtime.clone(), probe(true), &target(Some("exit 1")), &record);
+
+        assert_eq!(DeployStatus::Failed, status);
+        assert_eq!(vec![(DeployStepKind::ReleasePhase, false)], record.steps());
+        assert_eq!("`exit 1` exited with code 1", record.message(DeployStepKind::ReleasePhase));
+        assert_eq!(vec![instance(1, "web", 0), instance(1, "worker", 0)], runtime.instances(1).unwrap());
+    }
+
+    #[test]
+    fn should_stop_release_phase_running_too_long() {
+        let runtime = Arc::new(FakeRuntime::default());
+        let record = DeployRecord::default();
+
+        let (status, _) = run(runtime.clone(), probe(true), &target(Some("./migrate --forever")), &record);
+
+        assert_eq!(DeployStatus::Failed, status);
+        assert!(record.message(DeployStepKind::ReleasePhase).contains("did not finish"));
+        assert_eq!(vec!["app-1-v2-release-0"], runtime.stopped());
+    }
+
+    #[test]
+    fn should_keep_previous_release_when_new_web_instances_failed_health_checks() {
+        let runtime = previous_release_running();
+        let record = DeployRecord::default();
+
+        let (status, checker) = run(runtime.clone(), probe(false), &target(None), &record);
+
+        assert_eq!(DeployStatus::Failed, status);
+        assert_eq!(vec![
+            (DeployStepKind::ReleasePhase, true),
+            (DeployStepKind::StartProcesses, true),
+            (DeployStepKind::HealthCheck, false),
+        ], record.steps());
+        assert_eq!("web.0 failed 2 health checks in a row: 503", record.message(DeployStepKind::HealthCheck));
+        assert_eq!(vec![instance(1, "web", 0), instance(1, "worker", 0)], runtime.instances(1).unwrap());
+        assert_eq!(HealthStatus::NotRunning, checker.application_health(1).status);
+    }
+
+    #[test]
+    fn should_fail_deploy_when_new_web_instances_flap_past_deploy_timeout() {
+        let runtime = previous_release_running();
+        let record = DeployRecord::default();
+        let mut target = target(None);
+        target.formation = Formation::new(1, BTreeMap::from([("web".to_string(), ProcessScale { quantity: 1, size: ProcessSize::Small })]));
+        target.health_check.deploy_timeout_secs = 1;
+        let passing = AtomicBool::new(false);
+        let mut probe = MockHealthProbe::new();
+        probe.expect_probe().returning(move |_, _| match passing.fetch_xor(true, Ordering::SeqCst) {
+            true => Err(ProbeFailure { message: "503".to_string() }),
+            false => Ok(()),
+        });
+
+        let (status, _) = run(runtime.clone(), probe, &target, &record);
+
+        assert_eq!(DeployStatus::Failed, status);
+        assert_eq!("web.0 not healthy within 1 seconds", record.message(DeployStepKind::HealthCheck));
+        assert_eq!(vec![instance(1, "web", 0), instance(1, "worker", 0)], runtime.instances(1).unwrap());
+    }
+
+    #[test]
+    fn should_mark_deploy_failed_and_stop_started_instances_on_error() {
+        let runtime = previous_release_running();
+        let record = DeployRecord::default();
+        let mut deploys = MockDeploys::new();
+        let statuses = record.statuses.clone();
+        deploys.expect_update_status().returning(move |_, status| {
+            statuses.lock().unwrap().push(status);
+            Ok(())
+        });
+        deploys.expect_add_step().returning(|_, step| match step.kind {
+            DeployStepKind::StartProcesses => Err(CoreError { message: "connection lost".to_string() }),
+            _ => Ok(()),
+        });
+        let probe = MockHealthProbe::new();
+        let checker = HealthChecker::new(runtime.clone(), Arc::new(MockHealthProbe::new()), RestartPolicy { max_restarts: 1 });
+
+        let result = DeployCoordinator::new(runtime.as_ref(), &probe, &checker, &deploys, Duration::from_millis(100))
+            .deploy(&deploy(), &target(None));
+
+        assert!(result.is_err());
+        assert_eq!(vec![DeployStatus::Running, DeployStatus::Failed], *record.statuses.lock().unwrap());
+        assert_eq!(vec![instance(1, "web", 0), instance(1, "worker", 0)], runtime.instances(1).unwrap());
+    }
+
+    #[test]
+    fn should_replace_without_health_checks_when_no_web_process_is_scaled() {
+        let runtime = previous_release_running();
+        let record = DeployRecord::default();
+        let mut target = target(None);
+        target.formation = Formation::new(1, BTreeMap::from([("worker".to_string(), ProcessScale { quantity: 1, size: ProcessSize::Small })]));
+
+        let (status, _) = run(runtime.clone(), MockHealthProbe::new(), &target, &record);
+
+        assert_eq!(DeployStatus::Succeeded, status);
+        assert_eq!("no web processes to check", record.message(DeployStepKind::HealthCheck));
+        assert_eq!(vec![instance(2, "worker", 0)], runtime.instances(1).unwrap());
+    }
+}
@@ -11,16 +11,207 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
 #[cfg(test)]
 use mockall::{automock, predicate::*};
 
 use crate::application::ApplicationError;
 
+/// Name of the record for the zone itself.
+pub const ZONE_APEX: &str = "@";
+const MIN_TTL: u32 = 60;
+const MAX_TTL: u32 = 604_800;
+const MAX_TXT_LEN: usize = 2048;
+
 #[cfg_attr(test, automock)]
 pub trait DomainNameService {
     fn add_cname_record(&self, cname: &str) -> Result<CnameRecord, ApplicationError>;
+
+    /// Removing a record that does not exist succeeds, so callers can safely retry.
+    fn remove_cname_record(&self, cname: &str) -> Result<(), ApplicationError>;
+
+    /// Every record of a supported type in the zone.
+    fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError>;
+
+    /// Records of `record_type` named `name`, empty if there are none.
+    fn get_records(&self, name: &str, record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError>;
+
+    /// Adding a record that already exists succeeds without duplicating it. A name has at most one
+    /// CNAME record, adding another replaces it.
+    fn add_record(&self, record: &DnsRecord) -> Result<(), ApplicationError>;
+
+    /// Replaces every record of the name and type of `record` with it, failing with
+    /// `DnsRecordNotFound` if there are none.
+    fn update_record(&self, record: &DnsRecord) -> Result<(), ApplicationError>;
+
+    /// Deletes the record with the name, type and target of `record`, other records of the same
+    /// name and type are kept. Deleting a record that does not exist succeeds.
+    fn delete_record(&self, record: &DnsRecord) -> Result<(), ApplicationError>;
 }
 
 pub struct CnameRecord {
     pub domain_name: String,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RecordType {
+    Cname,
+    A,
+    Aaaa,
+    Txt,
+}
+
+impl Display for RecordType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RecordType::Cname => "CNAME",
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Txt => "TXT",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = ApplicationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "CNAME" => Ok(RecordType::Cname),
+            "A" => Ok(RecordType::A),
+            "AAAA" => Ok(RecordType::Aaaa),
+            "TXT" => Ok(RecordType::Txt),
+            _ => Err(ApplicationError::InvalidDnsRecord { message: format!("record type {} is not one of CNAME, A, AAAA or TXT", s) }),
+        }
+    }
+}
+
+/// A record in the zone of a `DomainNameService`, `name` is relative to the zone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsRecord {
+    pub name: String,
+    pub record_type: RecordType,
+    /// Host name of a CNAME record, address of an A or AAAA record or text of a TXT record.
+    pub target: String,
+    pub ttl: u32,
+}
+
+impl DnsRecord {
+    pub fn new(name: &str, record_type: RecordType, target: &str, ttl: u32) -> Self {
+        DnsRecord { name: name.to_string(), record_type, target: target.to_string(), ttl }
+    }
+
+    pub fn validate(&self) -> Result<(), ApplicationError> {
+        let invalid = |message: String| Err(ApplicationError::InvalidDnsRecord { message });
+
+        if self.name != ZONE_APEX && !is_host_name(&self.name, true) {
+            return invalid(format!("{} is not a valid record name", self.name));
+        }
+        if !(MIN_TTL..=MAX_TTL).contains(&self.ttl) {
+            return invalid(format!("ttl must be between {} and {} seconds", MIN_TTL, MAX_TTL));
+        }
+
+        let valid_target = match self.record_type {
+            RecordType::Cname => is_host_name(self.target.trim_end_matches('.'), false),
+            RecordType::A => self.target.parse::<Ipv4Addr>().is_ok(),
+            RecordType::Aaaa => self.target.parse::<Ipv6Addr>().is_ok(),
+            RecordType::Txt => !self.target.is_empty() && self.target.len() <= MAX_TXT_LEN && self.target.chars().all(|c| !c.is_control()),
+        };
+        if !valid_target {
+            return invalid(format!("{} is not a valid target of a {} record", self.target, self.record_type));
+        }
+
+        Ok(())
+    }
+
+    pub fn has_name(&self, name: &str, record_type: RecordType) -> bool {
+        self.record_type == record_type && self.name.eq_ignore_ascii_case(name)
+    }
+
+    /// Whether both are the same record, which may differ in their TTL.
+    pub fn same_record(&self, other: &DnsRecord) -> bool {
+        if !self.has_name(&other.name, other.record_type) {
+            return false;
+        }
+
+        match self.record_type {
+            RecordType::Cname => self.target.trim_end_matches('.').eq_ignore_ascii_case(other.target.trim_end_matches('.')),
+            RecordType::A => self.target.parse::<Ipv4Addr>().ok() == other.target.parse::<Ipv4Addr>().ok(),
+            RecordType::Aaaa => self.target.parse::<Ipv6Addr>().ok() == other.target.parse::<Ipv6Addr>().ok(),
+            RecordType::Txt => self.target == other.target,
+        }
+    }
+}
+
+/// Labels of letters, digits and hyphens, record names may also use underscores, like
+/// `_acme-challenge`, and start with a `*` label.
+fn is_host_name(name: &str, record_name: bool) -> bool {
+    !name.is_empty() && name.len() <= 253 && name.split('.').enumerate().all(|(i, label)| {
+        if record_name && i == 0 && label == "*" {
+            return true;
+        }
+        !label.is_empty() && label.len() <= 63 && !label.starts_with('-') && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || (record_name && c == '_'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::application::ApplicationError;
+    use crate::application::domain_name::{DnsRecord, RecordType};
+
+    #[test]
+    fn should_parse_record_type_ignoring_case() {
+        assert_eq!(RecordType::Aaaa, "aaaa".parse().unwrap());
+        assert_eq!("CNAME", "cname".parse::<RecordType>().unwrap().to_string());
+        assert!(matches!("MX".parse::<RecordType>(), Err(ApplicationError::InvalidDnsRecord { .. })));
+    }
+
+    #[test]
+    fn should_accept_valid_records() {
+        let records = [
+            DnsRecord::new("first-app", RecordType::Cname, "router.capsuleapp.cyou.", 300),
+            DnsRecord::new("@", RecordType::A, "203.0.113.10", 1800),
+            DnsRecord::new("*.apps", RecordType::Aaaa, "2001:db8::10", 60),
+            DnsRecord::new("_acme-challenge.first-app", RecordType::Txt, "gfj9Xq...Rg85nM", 60),
+        ];
+
+        for record in records {
+            assert!(record.validate().is_ok(), "{:?} should be valid", record);
+        }
+    }
+
+    #[test]
+    fn should_reject_invalid_records() {
+        let records = [
+            DnsRecord::new("", RecordType::A, "203.0.113.10", 300),
+            DnsRecord::new("first app", RecordType::A, "203.0.113.10", 300),
+            DnsRecord::new("-first-app", RecordType::A, "203.0.113.10", 300),
+            DnsRecord::new("first-app", RecordType::A, "2001:db8::10", 300),
+            DnsRecord::new("first-app", RecordType::Aaaa, "203.0.113.10", 300),
+            DnsRecord::new("first-app", RecordType::Cname, "_router.capsuleapp.cyou", 300),
+            DnsRecord::new("first-app", RecordType::Txt, "", 300),
+            DnsRecord::new("first-app", RecordType::Txt, "line\nbreak", 300),
+            DnsRecord::new("first-app", RecordType::A, "203.0.113.10", 59),
+            DnsRecord::new("first-app", RecordType::A, "203.0.113.10", 604_801),
+        ];
+
+        for record in records {
+            assert!(matches!(record.validate(), Err(ApplicationError::InvalidDnsRecord { .. })), "{:?} should be invalid", record);
+        }
+    }
+
+    #[test]
+    fn should_compare_records_ignoring_ttl() {
+        let record = DnsRecord::new("First-App", RecordType::Cname, "router.capsuleapp.cyou", 300);
+
+        assert!(record.same_record(&DnsRecord::new("first-app", RecordType::Cname, "Router.capsuleapp.cyou.", 60)));
+        assert!(!record.same_record(&DnsRecord::new("first-app", RecordType::Cname, "other.capsuleapp.cyou", 300)));
+        assert!(DnsRecord::new("a", RecordType::Aaaa, "2001:db8::1", 60).same_record(&DnsRecord::new("a", RecordType::Aaaa, "2001:0db8:0:0::1", 60)));
+        assert!(!DnsRecord::new("a", RecordType::Txt, "Token", 60).same_record(&DnsRecord::new("a", RecordType::Txt, "token", 60)));
+    }
+}
@@ -0,0 +1,359 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use derive_more::Display;
+#[cfg(test)]
+use mockall::automock;
+
+use crate::application::ApplicationError;
+use crate::buildpack::{ProcessTypes, RELEASE_PROCESS_TYPE};
+use crate::CoreError;
+
+/// Resources every instance of a process gets, ordered from smallest to largest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display)]
+pub enum ProcessSize {
+    #[display(fmt = "small")]
+    Small,
+    #[display(fmt = "medium")]
+    Medium,
+    #[display(fmt = "large")]
+    Large,
+}
+
+impl ProcessSize {
+    pub fn memory_mb(&self) -> u32 {
+        match self {
+            ProcessSize::Small => 512,
+            ProcessSize::Medium => 1024,
+            ProcessSize::Large => 2048,
+        }
+    }
+
+    /// CPU time in thousandths of a core.
+    pub fn cpu_millis(&self) -> u32 {
+        match self {
+            ProcessSize::Small => 500,
+            ProcessSize::Medium => 1000,
+            ProcessSize::Large => 2000,
+        }
+    }
+}
+
+impl FromStr for ProcessSize {
+    type Err = ApplicationError;
+
+    fn from_str(size: &str) -> Result<Self, Self::Err> {
+        match size {
+            "small" => Ok(ProcessSize::Small),
+            "medium" => Ok(ProcessSize::Medium),
+            "large" => Ok(ProcessSize::Large),
+            _ => Err(ApplicationError::InvalidFormation { message: format!("unknown process size {}", size) }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessScale {
+    pub quantity: i32,
+    pub size: ProcessSize,
+}
+
+impl Default for ProcessScale {
+    fn default() -> Self {
+        Self { quantity: 0, size: ProcessSize::Small }
+    }
+}
+
+/// A requested change of one process type, absent fields are left as they are.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FormationChange {
+    pub quantity: Option<i32>,
+    pub size: Option<ProcessSize>,
+}
+
+/// What an owner may run, counted over all applications of the owner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OwnerLimits {
+    pub max_instances: i32,
+    pub max_size: ProcessSize,
+}
+
+/// A process type whose scale was changed, to be recorded as a `FormationEvent`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaledProcess {
+    pub process_type: String,
+    pub scale: ProcessScale,
+}
+
+/// A recorded change of the formation. Runtime backends follow the events in `id` order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormationEvent {
+    pub id: i32,
+    pub application_id: i64,
+    pub process_type: String,
+    pub scale: ProcessScale,
+    pub changed_by: String,
+    pub create_at: SystemTime,
+}
+
+/// How many instances of each process type of an application run, and at which size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formation {
+    application_id: i64,
+    processes: BTreeMap<String, ProcessScale>,
+}
+
+impl Formation {
+    pub fn new(application_id: i64, processes: BTreeMap<String, ProcessScale>) -> Self {
+        Self { application_id, processes }
+    }
+
+    pub fn application_id(&self) -> i64 {
+        self.application_id
+    }
+
+    /// Scale of every process type ever scaled.
+    pub fn processes(&self) -> &BTreeMap<String, ProcessScale> {
+        &self.processes
+    }
+
+    /// Scale of every process type declared by `process_types`, unscaled ones at the default scale,
+    /// and of process types no longer declared that still run. The release process type has none.
+    pub fn of_process_types(&self, process_types: &ProcessTypes) -> BTreeMap<String, ProcessScale> {
+        let mut processes: BTreeMap<String, ProcessScale> = process_types.keys()
+            .filter(|t| t.as_str() != RELEASE_PROCESS_TYPE)
+            .map(|t| (t.clone(), self.processes.get(t).copied().unwrap_or_default()))
+            .collect();
+        for (process_type, scale) in self.processes.iter().filter(|(_, s)| s.quantity > 0) {
+            processes.entry(process_type.clone()).or_insert(*scale);
+        }
+
+        processes
+    }
+
+    pub fn instances(&self) -> i32 {
+        self.processes.values().map(|p| p.quantity).sum()
+    }
+
+    /// Applies `changes` to process types the latest release declares. `other_instances` are the
+    /// instances the owner runs in other applications, which count against `limits` too.
+    /// Returns the process types whose scale actually changed.
+    pub fn scale(&mut self, changes: &BTreeMap<String, FormationChange>, process_types: &ProcessTypes,
+                 limits: &OwnerLimits, other_instances: i32) -> Result<Vec<ScaledProcess>, ApplicationError> {
+        let mut scaled = self.processes.clone();
+        for (process_type, change) in changes {
+            if !process_types.contains_key(process_type) {
+                return Err(ApplicationError::InvalidFormation { message: format!("the latest release has no process type {}", process_type) });
+            }
+            if process_type == RELEASE_PROCESS_TYPE {
+                return Err(ApplicationError::InvalidFormation { message: format!("{} runs once per deploy and cannot be scaled", process_type) });
+            }
+
+            let scale = scaled.entry(process_type.clone()).or_default();
+            if let Some(quantity) = change.quantity {
+                if quantity < 0 {
+                    return Err(ApplicationError::InvalidFormation { message: format!("quantity of {} must not be negative", process_type) });
+                }
+                scale.quantity = quantity;
+            }
+            if let Some(size) = change.size {
+                if size > limits.max_size {
+                    return Err(ApplicationError::LimitExceeded { message: format!("{} is larger than the allowed size {}", size, limits.max_size) });
+                }
+                scale.size = size;
+            }
+        }
+
+        let instances = other_instances + scaled.values().map(|p| p.quantity).sum::<i32>();
+        if instances > limits.max_instances {
+            return Err(ApplicationError::LimitExceeded {
+                message: format!("{} instances requested, the owner may run at most {}", instances, limits.max_instances)
+            });
+        }
+
+        let changed: Vec<ScaledProcess> = scaled.iter()
+            .filter(|(t, s)| self.processes.get(*t).copied().unwrap_or_default() != **s)
+            .map(|(t, s)| ScaledProcess { process_type: t.clone(), scale: *s })
+            .collect();
+        self.processes = scaled;
+
+        Ok(changed)
+    }
+
+    /// Scales every process type down to zero instances, e.g. before the application is deleted.
+    pub fn stop_all(&mut self) -> Vec<ScaledProcess> {
+        let mut stopped = vec![];
+        for (process_type, scale) in self.processes.iter_mut().filter(|(_, s)| s.quantity > 0) {
+            scale.quantity = 0;
+            stopped.push(ScaledProcess { process_type: process_type.clone(), scale: *scale });
+        }
+
+        stopped
+    }
+}
+
+#[cfg_attr(test, automock)]
+pub trait Formations {
+    /// The formation of the application, empty if it was never scaled.
+    fn find(&self, application_id: i64) -> Result<Formation, CoreError>;
+
+    /// Instances run by all applications of `owner` except `application_id`.
+    fn count_instances(&self, owner: &str, except_application_id: i64) -> Result<i32, CoreError>;
+
+    /// Applies `changes` like `Formation::scale` and saves the result. Scalings of applications of
+    /// the same owner run one after another, so together they can not exceed `limits`.
+    fn scale(&self, application_id: i64, owner: &str, changes: &BTreeMap<String, FormationChange>, process_types: &ProcessTypes,
+             limits: &OwnerLimits, changed_by: &str) -> Result<Formation, ApplicationError>;
+
+    /// Stores the scale of `scaled` process types and records an event for each, atomically.
+    fn save(&self, formation: &Formation, scaled: &[ScaledProcess], changed_by: &str) -> Result<Vec<FormationEvent>, CoreError>;
+
+    /// Events recorded after event `after_id`, oldest first, at most `limit`.
+    fn events_after(&self, after_id: i32, limit: i64) -> Result<Vec<FormationEvent>, CoreError>;
+
+    /// Removes the formation, keeping its events.
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+#[cfg_attr(test, automock)]
+pub trait OwnerLimitsRepository {
+    /// Limits configured for the owner, `None` if the defaults apply.
+    fn find(&self, owner: &str) -> Result<Option<OwnerLimits>, CoreError>;
+
+    fn save(&self, owner: &str, limits: &OwnerLimits) -> Result<(), CoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::application::ApplicationError;
+    use crate::application::formation::{Formation, FormationChange, OwnerLimits, ProcessScale, ProcessSize, ScaledProcess};
+    use crate::buildpack::ProcessTypes;
+
+    const LIMITS: OwnerLimits = OwnerLimits { max_instances: 5, max_size: ProcessSize::Medium };
+
+    fn process_types() -> ProcessTypes {
+        ProcessTypes::from([("web".to_string(), "./app".to_string()), ("worker".to_string(), "./worker".to_string())])
+    }
+
+    fn change(quantity: Option<i32>, size: Option<ProcessSize>) -> FormationChange {
+        FormationChange { quantity, size }
+    }
+
+    fn formation() -> Formation {
+        Formation::new(1, BTreeMap::from([("web".to_string(), ProcessScale { quantity: 1, size: ProcessSize::Small })]))
+    }
+
+    #[test]
+    fn should_scale_quantity_and_size() {
+        let mut formation = formation();
+        let changes = BTreeMap::from([
+            ("web".to_string(), change(Some(2), None)),
+            ("worker".to_string(), change(Some(1), Some(ProcessSize::Medium))),
+        ]);
+
+        let scaled = formation.scale(&changes, &process_types(), &LIMITS, 0).unwrap();
+
+        assert_eq!(vec![
+            ScaledProcess { process_type: "web".to_string(), scale: ProcessScale { quantity: 2, size: ProcessSize::Small } },
+            ScaledProcess { process_type: "worker".to_string(), scale: ProcessScale { quantity: 1, size: ProcessSize::Medium } },
+        ], scaled);
+        assert_eq!(3, formation.instances());
+    }
+
+    #[test]
+    fn should_report_only_changed_process_types() {
+        let mut formation = formation();
+        let changes = BTreeMap::from([("web".to_string(), change(Some(1), Some(ProcessSize::Small)))]);
+
+        assert!(formation.scale(&changes, &process_types(), &LIMITS, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_reject_process_type_not_in_release() {
+        let mut formation = formation();
+        let changes = BTreeMap::from([("clock".to_string(), change(Some(1), None))]);
+
+        let result = formation.scale(&changes, &process_types(), &LIMITS, 0);
+
+        assert!(matches!(result, Err(ApplicationError::InvalidFormation { .. })));
+        assert_eq!(self::formation(), formation);
+    }
+
+    #[test]
+    fn should_reject_negative_quantity() {
+        let changes = BTreeMap::from([("web".to_string(), change(Some(-1), None))]);
+
+        assert!(matches!(formation().scale(&changes, &process_types(), &LIMITS, 0), Err(ApplicationError::InvalidFormation { .. })));
+    }
+
+    #[test]
+    fn should_not_scale_release_process_type() {
+        let mut process_types = process_types();
+        process_types.insert("release".to_string(), "./migrate".to_string());
+        let changes = BTreeMap::from([("release".to_string(), change(Some(1), None))]);
+
+        assert!(matches!(formation().scale(&changes, &process_types, &LIMITS, 0), Err(ApplicationError::InvalidFormation { .. })));
+        assert!(!formation().of_process_types(&process_types).contains_key("release"));
+    }
+
+    #[test]
+    fn should_count_instances_of_other_applications_against_limit() {
+        let mut formation = formation();
+        let changes = BTreeMap::from([("worker".to_string(), change(Some(2), None))]);
+
+        let result = formation.scale(&changes, &process_types(), &LIMITS, 3);
+
+        assert!(matches!(result, Err(ApplicationError::LimitExceeded { .. })));
+        assert_eq!(1, formation.instances());
+    }
+
+    #[test]
+    fn should_reject_size_above_limit() {
+        let changes = BTreeMap::from([("web".to_string(), change(None, Some(ProcessSize::Large)))]);
+
+        assert!(matches!(formation().scale(&changes, &process_types(), &LIMITS, 0), Err(ApplicationError::LimitExceeded { .. })));
+    }
+
+    #[test]
+    fn should_stop_all_running_processes() {
+        let mut formation = formation();
+
+        let stopped = formation.stop_all();
+
+        assert_eq!(vec!["web".to_string()], stopped.iter().map(|s| s.process_type.clone()).collect::<Vec<_>>());
+        assert_eq!(0, formation.instances());
+    }
+
+    #[test]
+    fn should_list_unscaled_process_types_at_default_scale() {
+        let listed = formation().of_process_types(&process_types());
+
+        assert_eq!(ProcessScale { quantity: 1, size: ProcessSize::Small }, listed["web"]);
+        assert_eq!(ProcessScale::default(), listed["worker"]);
+    }
+
+    #[test]
+    fn should_list_running_process_types_no_longer_declared() {
+        let process_types = ProcessTypes::from([("worker".to_string(), "./worker".to_string())]);
+
+        let listed = formation().of_process_types(&process_types);
+
+        assert_eq!(vec!["web", "worker"], listed.keys().map(|t| t.as_str()).collect::<Vec<_>>());
+    }
+}
@@ -21,6 +21,14 @@ pub struct GitError;
 #[cfg_attr(test, automock)]
 pub trait GitService {
     fn create_repo(&self, owner: &str, app_name: &str) -> Result<GitRepository, GitError>;
+
+    fn rename_repo(&self, owner: &str, app_name: &str, new_app_name: &str) -> Result<GitRepository, GitError>;
+
+    /// Moves the repository to the namespace of `new_owner`, keeping its name.
+    fn transfer_repo(&self, owner: &str, app_name: &str, new_owner: &str) -> Result<GitRepository, GitError>;
+
+    /// Deleting a repository that does not exist succeeds, so callers can safely retry.
+    fn delete_repo(&self, owner: &str, app_name: &str) -> Result<(), GitError>;
 }
 
 pub struct GitRepository {
@@ -0,0 +1,474 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use derive_more::{Display, Error};
+#[cfg(test)]
+use mockall::automock;
+
+use crate::application::ApplicationError;
+use crate::application::runtime::{ProcessInstance, ProcessSpec, ProcessStatus, RuntimeBackend};
+use crate::CoreError;
+
+const MAX_INTERVAL_SECS: u32 = 300;
+const MAX_THRESHOLD: u32 = 10;
+const MAX_DEPLOY_TIMEOUT_SECS: u32 = 3600;
+
+/// An instance turns healthy after `healthy_threshold` passed probes in a row and unhealthy after
+/// `unhealthy_threshold` failed ones. A deploy fails unless all its new web instances turned healthy
+/// within `deploy_timeout_secs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthCheckConfig {
+    pub path: String,
+    pub interval_secs: u32,
+    pub timeout_secs: u32,
+    pub healthy_threshold: u32,
+    pub unhealthy_threshold: u32,
+    pub deploy_timeout_secs: u32,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self { path: "/".to_string(), interval_secs: 10, timeout_secs: 2, healthy_threshold: 2, unhealthy_threshold: 3, deploy_timeout_secs: 300 }
+    }
+}
+
+impl HealthCheckConfig {
+    pub fn validate(&self) -> Result<(), ApplicationError> {
+        let invalid = |message: String| Err(ApplicationError::InvalidHealthCheck { message });
+
+        if !self.path.starts_with('/') || self.path.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return invalid(format!("path {} must start with '/' and contain no whitespace", self.path));
+        }
+        if !(1..=MAX_INTERVAL_SECS).contains(&self.interval_secs) {
+            return invalid(format!("interval must be between 1 and {} seconds", MAX_INTERVAL_SECS));
+        }
+        if self.timeout_secs == 0 || self.timeout_secs > self.interval_secs {
+            return invalid("timeout must be at least 1 second and not longer than the interval".to_string());
+        }
+        if !(1..=MAX_THRESHOLD).contains(&self.healthy_threshold) || !(1..=MAX_THRESHOLD).contains(&self.unhealthy_threshold) {
+            return invalid(format!("thresholds must be between 1 and {}", MAX_THRESHOLD));
+        }
+        if self.deploy_timeout_secs < self.interval_secs || self.deploy_timeout_secs > MAX_DEPLOY_TIMEOUT_SECS {
+            return invalid(format!("deploy timeout must be between the interval and {} seconds", MAX_DEPLOY_TIMEOUT_SECS));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(test, automock)]
+pub trait HealthChecks {
+    /// The configuration of the application, `None` if it uses the defaults.
+    fn find(&self, application_id: i64) -> Result<Option<HealthCheckConfig>, CoreError>;
+
+    fn save(&self, application_id: i64, config: &HealthCheckConfig) -> Result<(), CoreError>;
+
+    fn remove(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+#[derive(Debug, Error, Display)]
+#[display(fmt = "{}", message)]
+pub struct ProbeFailure {
+    pub message: String,
+}
+
+#[cfg_attr(test, automock)]
+pub trait HealthProbe: Send + Sync {
+    fn probe(&self, address: &str, config: &HealthCheckConfig) -> Result<(), ProbeFailure>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum HealthState {
+    #[display(fmt = "starting")]
+    Starting,
+    #[display(fmt = "healthy")]
+    Healthy,
+    #[display(fmt = "unhealthy")]
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceHealth {
+    pub instance: ProcessInstance,
+    pub state: HealthState,
+    pub consecutive_successes: u32,
+    pub consecutive_failures: u32,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub last_checked: Option<SystemTime>,
+}
+
+impl InstanceHealth {
+    fn new(instance: ProcessInstance) -> Self {
+        Self { instance, state: HealthState::Starting, consecutive_successes: 0, consecutive_failures: 0, restarts: 0, last_error: None, last_checked: None }
+    }
+
+    /// Returns true if the instance just turned unhealthy.
+    fn record(&mut self, outcome: Result<(), ProbeFailure>, config: &HealthCheckConfig) -> bool {
+        self.last_checked = Some(SystemTime::now());
+        match outcome {
+            Ok(()) => {
+                self.consecutive_successes += 1;
+                self.consecutive_failures = 0;
+                self.last_error = None;
+                if self.consecutive_successes >= config.healthy_threshold {
+                    self.state = HealthState::Healthy;
+                    self.restarts = 0;
+                }
+                false
+            }
+            Err(failure) => {
+                self.consecutive_failures += 1;
+                self.consecutive_successes = 0;
+                self.last_error = Some(failure.message);
+                if self.consecutive_failures >= config.unhealthy_threshold && self.state != HealthState::Unhealthy {
+                    self.state = HealthState::Unhealthy;
+                    return true;
+                }
+                false
+            }
+        }
+    }
+
+    fn restarted(&mut self) {
+        self.state = HealthState::Starting;
+        self.consecutive_successes = 0;
+        self.consecutive_failures = 0;
+        self.restarts += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum HealthStatus {
+    #[display(fmt = "not_running")]
+    NotRunning,
+    #[display(fmt = "starting")]
+    Starting,
+    #[display(fmt = "healthy")]
+    Healthy,
+    #[display(fmt = "degraded")]
+    Degraded,
+    #[display(fmt = "unhealthy")]
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationHealth {
+    pub status: HealthStatus,
+    pub instances: Vec<InstanceHealth>,
+}
+
+impl ApplicationHealth {
+    fn of(instances: Vec<InstanceHealth>) -> Self {
+        let count = |state| instances.iter().filter(|i| i.state == state).count();
+        let status = match (instances.len(), count(HealthState::Healthy), count(HealthState::Unhealthy)) {
+            (0, _, _) => HealthStatus::NotRunning,
+            (all, healthy, _) if healthy == all => HealthStatus::Healthy,
+            (all, _, unhealthy) if unhealthy == all => HealthStatus::Unhealthy,
+            (_, _, 0) => HealthStatus::Starting,
+            _ => HealthStatus::Degraded,
+        };
+
+        Self { status, instances }
+    }
+}
+
+/// Unhealthy instances are restarted at most `max_restarts` times before the checker gives up on
+/// them, until they are healthy again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RestartPolicy {
+    pub max_restarts: u32,
+}
+
+struct Watched {
+    spec: ProcessSpec,
+    address: String,
+    config: HealthCheckConfig,
+    health: InstanceHealth,
+    next_check: Instant,
+}
+
+/// Restarts watched web instances turning unhealthy. An instance that is not running anymore
+/// fails its probe.
+pub struct HealthChecker {
+    runtime: Arc<dyn RuntimeBackend>,
+    probe: Arc<dyn HealthProbe>,
+    restart_policy: RestartPolicy,
+    watched: Mutex<BTreeMap<ProcessInstance, Watched>>,
+}
+
+impl HealthChecker {
+    pub fn new(runtime: Arc<dyn RuntimeBackend>, probe: Arc<dyn HealthProbe>, restart_policy: RestartPolicy) -> Self {
+        Self { runtime, probe, restart_policy, watched: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// The first probe comes one interval after `now`, giving the instance time to start listening.
+    pub fn watch(&self, spec: ProcessSpec, address: String, config: HealthCheckConfig, now: Instant) {
+        let instance = spec.instance.clone();
+        let watched = Watched { next_check: now + interval(&config), health: InstanceHealth::new(instance.clone()), spec, address, config };
+
+        self.watched.lock().unwrap().insert(instance, watched);
+    }
+
+    pub fn unwatch(&self, instance: &ProcessInstance) {
+        self.watched.lock().unwrap().remove(instance);
+    }
+
+    pub fn configure(&self, application_id: i64, config: &HealthCheckConfig) {
+        for watched in self.watched.lock().unwrap().values_mut().filter(|w| w.spec.instance.application_id == application_id) {
+            watched.config = config.clone();
+        }
+    }
+
+    pub fn application_health(&self, application_id: i64) -> ApplicationHealth {
+        let instances = self.watched.lock().unwrap().values()
+            .filter(|w| w.spec.instance.application_id == application_id)
+            .map(|w| w.health.clone())
+            .collect();
+
+        ApplicationHealth::of(instances)
+    }
+
+    pub fn healthy_addresses(&self, application_id: i64) -> Vec<String> {
+        self.watched.lock().unwrap().values()
+            .filter(|w| w.spec.instance.application_id == application_id && w.health.state == HealthState::Healthy)
+            .map(|w| w.address.clone())
+            .collect()
+    }
+
+    pub fn check_due(&self, now: Instant) -> Vec<ProcessInstance> {
+        let due: Vec<(ProcessInstance, String, HealthCheckConfig)> = {
+            let mut watched = self.watched.lock().unwrap();
+            watched.values_mut()
+                .filter(|w| w.next_check <= now)
+                .map(|w| {
+                    w.next_check = now + interval(&w.config);
+                    (w.spec.instance.clone(), w.address.clone(), w.config.clone())
+                })
+                .collect()
+        };
+
+        // probes and restarts take a while, the instances are not locked meanwhile.
+        let mut to_restart = vec![];
+        for (instance, address, config) in due {
+            let outcome = probe_instance(self.runtime.as_ref(), self.probe.as_ref(), &instance, &address, &config);
+
+            let mut watched = self.watched.lock().unwrap();
+            if let Some(w) = watched.get_mut(&instance) {
+                if w.health.record(outcome, &config) && w.health.restarts < self.restart_policy.max_restarts {
+                    to_restart.push(w.spec.clone());
+                }
+            }
+        }
+
+        to_restart.into_iter().map(|spec| self.restart(spec, now)).collect()
+    }
+
+    fn restart(&self, spec: ProcessSpec, now: Instant) -> ProcessInstance {
+        let restarted = self.runtime.stop(&spec.instance).and_then(|_| self.runtime.start(&spec));
+
+        let mut watched = self.watched.lock().unwrap();
+        if let Some(w) = watched.get_mut(&spec.instance) {
+            w.health.restarted();
+            if let Err(e) = restarted {
+                w.health.last_error = Some(e.to_string());
+            }
+            w.next_check = now + interval(&w.config);
+        }
+
+        spec.instance
+    }
+}
+
+pub(crate) fn probe_instance(runtime: &dyn RuntimeBackend, probe: &dyn HealthProbe, instance: &ProcessInstance,
+                             address: &str, config: &HealthCheckConfig) -> Result<(), ProbeFailure> {
+    match runtime.status(instance) {
+        Ok(ProcessStatus::Running { .. }) => probe.probe(address, config),
+        Ok(status) => Err(ProbeFailure { message: format!("process is {}", status) }),
+        Err(e) => Err(ProbeFailure { message: e.to_string() }),
+    }
+}
+
+fn interval(config: &HealthCheckConfig) -> Duration {
+    Duration::from_secs(config.interval_secs as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::application::ApplicationError;
+    use crate::application::config_vars::ConfigVarMap;
+    use crate::application::formation::ProcessSize;
+    use crate::application::health::{HealthCheckConfig, HealthChecker, HealthState, HealthStatus, MockHealthProbe, ProbeFailure, RestartPolicy};
+    use crate::application::runtime::{MockRuntimeBackend, ProcessInstance, ProcessSpec, ProcessStatus, RuntimeError};
+
+    fn config() -> HealthCheckConfig {
+        HealthCheckConfig { path: "/health".to_string(), interval_secs: 5, timeout_secs: 1, healthy_threshold: 2, unhealthy_threshold: 2, deploy_timeout_secs: 60 }
+    }
+
+    fn spec(index: u32) -> ProcessSpec {
+        ProcessSpec {
+            instance: ProcessInstance { application_id: 1, release_version: 1, process_type: "web".to_string(), index },
+            command: "./app".to_string(),
+            working_dir: PathBuf::from("/tmp"),
+            env: ConfigVarMap::new(),
+            size: ProcessSize::Small,
+        }
+    }
+
+    fn running_runtime() -> MockRuntimeBackend {
+        let mut runtime = MockRuntimeBackend::new();
+        runtime.expect_status().returning(|_| Ok(ProcessStatus::Running { pid: 42 }));
+        runtime
+    }
+
+    fn probe(healthy: bool) -> MockHealthProbe {
+        let mut probe = MockHealthProbe::new();
+        probe.expect_probe().returning(move |_, _| if healthy { Ok(()) } else { Err(ProbeFailure { message: "503".to_string() }) });
+        probe
+    }
+
+    fn check_intervals(checker: &HealthChecker, start: Instant, intervals: u32) -> Vec<ProcessInstance> {
+        (1..=intervals).flat_map(|i| checker.check_due(start + Duration::from_secs(5 * i as u64))).collect()
+    }
+
+    #[test]
+    fn should_validate_config() {
+        assert!(HealthCheckConfig::default().validate().is_ok());
+
+        let invalid = [
+            HealthCheckConfig { path: "health".to_string(), ..config() },
+            HealthCheckConfig { path: "/he alth".to_string(), ..config() },
+            HealthCheckConfig { interval_secs: 0, ..config() },
+            HealthCheckConfig { timeout_secs: 6, ..config() },
+            HealthCheckConfig { healthy_threshold: 0, ..config() },
+            HealthCheckConfig { unhealthy_threshold: 11, ..config() },
+            HealthCheckConfig { deploy_timeout_secs: 4, ..config() },
+            HealthCheckConfig { deploy_timeout_secs: 3601, ..config() },
+        ];
+        for config in invalid {
+            assert!(matches!(config.validate(), Err(ApplicationError::InvalidHealthCheck { .. })), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn should_wait_an_interval_before_first_probe() {
+        let checker = HealthChecker::new(Arc::new(MockRuntimeBackend::new()), Arc::new(MockHealthProbe::new()), RestartPolicy { max_restarts: 1 });
+        let now = Instant::now();
+        checker.watch(spec(0), "127.0.0.1:5000".to_string(), config(), now);
+
+        assert!(checker.check_due(now + Duration::from_secs(4)).is_empty());
+        assert_eq!(HealthStatus::Starting, checker.application_health(1).status);
+    }
+
+    #[test]
+    fn should_turn_healthy_after_threshold() {
+        let checker = HealthChecker::new(Arc::new(running_runtime()), Arc::new(probe(true)), RestartPolicy { max_restarts: 1 });
+        let now = Instant::now();
+        checker.watch(spec(0), "127.0.0.1:5000".to_string(), config(), now);
+
+        check_intervals(&checker, now, 1);
+        assert_eq!(HealthStatus::Starting, checker.application_health(1).status);
+        check_intervals(&checker, now + Duration::from_secs(5), 1);
+
+        let health = checker.application_health(1);
+        assert_eq!(HealthStatus::Healthy, health.status);
+        assert_eq!(2, health.instances[0].consecutive_successes);
+    }
+
+    #[test]
+    fn should_restart_unhealthy_instance_until_policy_gives_up() {
+        let mut runtime = running_runtime();
+        runtime.expect_stop().times(2).returning(|_| Ok(()));
+        runtime.expect_start().withf(|s| s.command == "./app").times(2).returning(|_| Ok(ProcessStatus::Running { pid: 43 }));
+        let checker = HealthChecker::new(Arc::new(runtime), Arc::new(probe(false)), RestartPolicy { max_restarts: 2 });
+        let now = Instant::now();
+        checker.watch(spec(0), "127.0.0.1:5000".to_string(), config(), now);
+
+        let restarted = check_intervals(&checker, now, 8);
+
+        assert_eq!(2, restarted.len());
+        let health = checker.application_health(1);
+        assert_eq!(HealthStatus::Unhealthy, health.status);
+        assert_eq!((2, Some("503".to_string())), (health.instances[0].restarts, health.instances[0].last_error.clone()));
+    }
+
+    #[test]
+    fn should_count_exited_process_as_failure_without_probing() {
+        let mut runtime = MockRuntimeBackend::new();
+        runtime.expect_status().returning(|_| Ok(ProcessStatus::Exited { code: Some(1) }));
+        let mut probe = MockHealthProbe::new();
+        probe.expect_probe().never();
+        let checker = HealthChecker::new(Arc::new(runtime), Arc::new(probe), RestartPolicy { max_restarts: 0 });
+        let now = Instant::now();
+        checker.watch(spec(0), "127.0.0.1:5000".to_string(), config(), now);
+
+        assert!(check_intervals(&checker, now, 2).is_empty());
+
+        let health = checker.application_health(1);
+        assert_eq!(HealthState::Unhealthy, health.instances[0].state);
+        assert_eq!(Some("process is exited".to_string()), health.instances[0].last_error);
+    }
+
+    #[test]
+    fn should_keep_unhealthy_state_if_restart_failed() {
+        let mut runtime = running_runtime();
+        runtime.expect_stop().returning(|_| Ok(()));
+        runtime.expect_start().returning(|_| Err(RuntimeError { message: "no such file".to_string() }));
+        let checker = HealthChecker::new(Arc::new(runtime), Arc::new(probe(false)), RestartPolicy { max_restarts: 1 });
+        let now = Instant::now();
+        checker.watch(spec(0), "127.0.0.1:5000".to_string(), config(), now);
+
+        check_intervals(&checker, now, 2);
+
+        let health = checker.application_health(1);
+        assert_eq!(Some("runtime error no such file".to_string()), health.instances[0].last_error);
+    }
+
+    #[test]
+    fn should_report_degraded_application_with_some_unhealthy_instances() {
+        let mut probe = MockHealthProbe::new();
+        probe.expect_probe().returning(|address, _| if address.ends_with("5000") { Ok(()) } else { Err(ProbeFailure { message: "timeout".to_string() }) });
+        let checker = HealthChecker::new(Arc::new(running_runtime()), Arc::new(probe), RestartPolicy { max_restarts: 0 });
+        let now = Instant::now();
+        checker.watch(spec(0), "127.0.0.1:5000".to_string(), config(), now);
+        checker.watch(spec(1), "127.0.0.1:5001".to_string(), config(), now);
+
+        check_intervals(&checker, now, 2);
+
+        assert_eq!(HealthStatus::Degraded, checker.application_health(1).status);
+        assert_eq!(vec!["127.0.0.1:5000".to_string()], checker.healthy_addresses(1));
+        assert_eq!(HealthStatus::NotRunning, checker.application_health(2).status);
+        checker.unwatch(&spec(1).instance);
+        assert_eq!(HealthStatus::Healthy, checker.application_health(1).status);
+    }
+
+    #[test]
+    fn should_apply_changed_config() {
+        let checker = HealthChecker::new(Arc::new(running_runtime()), Arc::new(probe(true)), RestartPolicy { max_restarts: 0 });
+        let now = Instant::now();
+        checker.watch(spec(0), "127.0.0.1:5000".to_string(), config(), now);
+
+        checker.configure(1, &HealthCheckConfig { healthy_threshold: 1, ..config() });
+        check_intervals(&checker, now, 1);
+
+        assert_eq!(HealthStatus::Healthy, checker.application_health(1).status);
+    }
+}
@@ -0,0 +1,761 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Just enough of the DNS wire format for RFC 2136 updates signed with RFC 8945 TSIG.
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::{Sha256, Sha512};
+use crypto::util::fixed_time_eq;
+use derive_more::{Display, Error};
+
+use crate::application::RecordType;
+
+pub(crate) const TYPE_A: u16 = 1;
+pub(crate) const TYPE_CNAME: u16 = 5;
+pub(crate) const TYPE_SOA: u16 = 6;
+pub(crate) const TYPE_TXT: u16 = 16;
+pub(crate) const TYPE_AAAA: u16 = 28;
+pub(crate) const TYPE_TSIG: u16 = 250;
+pub(crate) const TYPE_AXFR: u16 = 252;
+pub(crate) const CLASS_IN: u16 = 1;
+pub(crate) const CLASS_NONE: u16 = 254;
+pub(crate) const CLASS_ANY: u16 = 255;
+
+pub(crate) const FLAGS_UPDATE: u16 = 5 << 11;
+pub(crate) const FLAG_RESPONSE: u16 = 0x8000;
+pub(crate) const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const OPCODE_MASK: u16 = 0x7800;
+
+pub(crate) const FLAGS_QUERY: u16 = 0;
+
+pub(crate) const RCODE_NOERROR: u16 = 0;
+pub(crate) const RCODE_NXDOMAIN: u16 = 3;
+pub(crate) const RCODE_NXRRSET: u16 = 8;
+#[cfg(test)]
+pub(crate) const RCODE_NOTAUTH: u16 = 9;
+#[cfg(test)]
+pub(crate) const RCODE_NOTZONE: u16 = 10;
+pub(crate) const TSIG_BADSIG: u16 = 16;
+pub(crate) const TSIG_BADKEY: u16 = 17;
+pub(crate) const TSIG_BADTIME: u16 = 18;
+
+/// Seconds the signing time may differ from the clock of the receiver.
+const FUDGE: u16 = 300;
+const ARCOUNT_OFFSET: usize = 10;
+
+pub(crate) fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        6 => "YXDOMAIN".to_string(),
+        7 => "YXRRSET".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        TSIG_BADSIG => "BADSIG".to_string(),
+        TSIG_BADKEY => "BADKEY".to_string(),
+        TSIG_BADTIME => "BADTIME".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+#[derive(Debug, Display, Error, PartialEq)]
+pub enum TsigError {
+    #[display(fmt = "message is not signed")]
+    Unsigned,
+    #[display(fmt = "message is signed with another key")]
+    UnknownKey,
+    #[display(fmt = "signature does not match")]
+    BadSignature,
+    #[display(fmt = "signing time is off by more than {} seconds", FUDGE)]
+    BadTime,
+    #[display(fmt = "signature was rejected with {}", "rcode_name(*error)")]
+    Rejected { error: u16 },
+    #[display(fmt = "malformed message: {}", message)]
+    Malformed { message: String },
+}
+
+impl TsigError {
+    /// The TSIG error a server answers with.
+    #[cfg(test)]
+    pub(crate) fn error_code(&self) -> u16 {
+        match self {
+            TsigError::UnknownKey => TSIG_BADKEY,
+            TsigError::BadTime => TSIG_BADTIME,
+            _ => TSIG_BADSIG,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            TsigAlgorithm::HmacSha256 => "hmac-sha256",
+            TsigAlgorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+}
+
+impl FromStr for TsigAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Ok(TsigAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(TsigAlgorithm::HmacSha512),
+            _ => Err(format!("unsupported tsig algorithm {}, expected hmac-sha256 or hmac-sha512", s)),
+        }
+    }
+}
+
+/// A key shared with the name server, like one generated by `tsig-keygen`.
+#[derive(Debug, Clone)]
+pub struct TsigKey {
+    pub name: String,
+    pub algorithm: TsigAlgorithm,
+    pub secret: Vec<u8>,
+}
+
+impl TsigKey {
+    /// Takes the secret base64 encoded, the way it appears in the key file of the name server.
+    pub fn from_base64(name: &str, algorithm: TsigAlgorithm, secret: &str) -> Result<Self, String> {
+        let secret = base64::decode(secret.trim()).map_err(|e| format!("tsig secret of {} is not base64: {}", name, e))?;
+        Ok(TsigKey { name: name.to_string(), algorithm, secret })
+    }
+
+    fn mac(&self, data: &[u8]) -> Vec<u8> {
+        match self.algorithm {
+            TsigAlgorithm::HmacSha256 => {
+                let mut hmac = Hmac::new(Sha256::new(), &self.secret);
+                hmac.input(data);
+                hmac.result().code().to_vec()
+            }
+            TsigAlgorithm::HmacSha512 => {
+                let mut hmac = Hmac::new(Sha512::new(), &self.secret);
+                hmac.input(data);
+                hmac.result().code().to_vec()
+            }
+        }
+    }
+
+    /// Signs `message` by appending a TSIG record, a response covers the MAC of its request.
+    /// Returns the MAC so the response to this message can be verified.
+    pub(crate) fn sign(&self, message: &mut Vec<u8>, request_mac: Option<&[u8]>, time_signed: u64) -> Result<Vec<u8>, String> {
+        self.sign_with_error(message, request_mac, time_signed, RCODE_NOERROR)
+    }
+
+    /// A server rejecting a signature answers with the TSIG error and an empty MAC.
+    pub(crate) fn sign_with_error(&self, message: &mut Vec<u8>, request_mac: Option<&[u8]>, time_signed: u64, error: u16) -> Result<Vec<u8>, String> {
+        self.sign_digest(message, &mac_prefix(request_mac, &[]), false, time_signed, error)
+    }
+
+    /// Signs a later message of a zone transfer, see `verify_continuation`.
+    #[cfg(test)]
+    pub(crate) fn sign_continuation(&self, message: &mut Vec<u8>, prior_mac: &[u8], unsigned: &[u8], time_signed: u64) -> Result<Vec<u8>, String> {
+        self.sign_digest(message, &mac_prefix(Some(prior_mac), unsigned), true, time_signed, RCODE_NOERROR)
+    }
+
+    fn sign_digest(&self, message: &mut Vec<u8>, prefix: &[u8], timers_only: bool, time_signed: u64, error: u16) -> Result<Vec<u8>, String> {
+        let key_name = encode_name(&self.name)?;
+        let algorithm = encode_name(self.algorithm.name())?;
+        let original_id = [message[0], message[1]];
+
+        let mac = if error == TSIG_BADSIG || error == TSIG_BADKEY {
+            Vec::new()
+        } else {
+            let variables = TsigVariables { key_name: &key_name, algorithm: &algorithm, time_signed, fudge: FUDGE, error, timers_only };
+            self.mac(&digest_input(prefix, message, &variables))
+        };
+
+        let mut rdata = algorithm;
+        put_u48(&mut rdata, time_signed);
+        put_u16(&mut rdata, FUDGE);
+        put_u16(&mut rdata, mac.len() as u16);
+        rdata.extend_from_slice(&mac);
+        rdata.extend_from_slice(&original_id);
+        put_u16(&mut rdata, error);
+        put_u16(&mut rdata, 0);
+
+        message.extend_from_slice(&key_name);
+        put_u16(message, TYPE_TSIG);
+        put_u16(message, CLASS_ANY);
+        put_u32(message, 0);
+        put_u16(message, rdata.len() as u16);
+        message.extend_from_slice(&rdata);
+        increment_count(message, ARCOUNT_OFFSET);
+
+        Ok(mac)
+    }
+
+    /// Checks the TSIG record ending `message`, returning its MAC.
+    pub(crate) fn verify(&self, message: &[u8], request_mac: Option<&[u8]>, now: u64) -> Result<Vec<u8>, TsigError> {
+        self.verify_digest(message, &mac_prefix(request_mac, &[]), false, now)
+    }
+
+    /// Checks a later message of a zone transfer. Its MAC covers the MAC of the prior signed
+    /// message, the `unsigned` messages received since and only the time of the TSIG variables
+    /// (RFC 8945 section 5.3.1).
+    pub(crate) fn verify_continuation(&self, message: &[u8], prior_mac: &[u8], unsigned: &[u8], now: u64) -> Result<Vec<u8>, TsigError> {
+        self.verify_digest(message, &mac_prefix(Some(prior_mac), unsigned), true, now)
+    }
+
+    fn verify_digest(&self, message: &[u8], prefix: &[u8], timers_only: bool, now: u64) -> Result<Vec<u8>, TsigError> {
+        let malformed = |message: String| TsigError::Malformed { message };
+        let parsed = ParsedMessage::parse(message).map_err(malformed)?;
+        let tsig = match parsed.additional.last() {
+            Some(record) if record.rtype == TYPE_TSIG => record,
+            _ => return Err(TsigError::Unsigned),
+        };
+
+        let mut reader = Reader { bytes: message, pos: tsig.rdata_start };
+        let algorithm = reader.name().map_err(malformed)?;
+        let time_signed = reader.u48().map_err(malformed)?;
+        let fudge = reader.u16().map_err(malformed)?;
+        let mac_len = reader.u16().map_err(malformed)? as usize;
+        let mac = reader.take(mac_len).map_err(malformed)?.to_vec();
+        let original_id = reader.take(2).map_err(malformed)?.to_vec();
+        let error = reader.u16().map_err(malformed)?;
+
+        if error != RCODE_NOERROR {
+            return Err(TsigError::Rejected { error });
+        }
+        if !same_name(&tsig.name, &self.name) || !same_name(&algorithm, self.algorithm.name()) {
+            return Err(TsigError::UnknownKey);
+        }
+
+        let mut unsigned = message[..tsig.start].to_vec();
+        unsigned[..2].copy_from_slice(&original_id);
+        let arcount = u16::from_be_bytes([unsigned[ARCOUNT_OFFSET], unsigned[ARCOUNT_OFFSET + 1]]) - 1;
+        unsigned[ARCOUNT_OFFSET..ARCOUNT_OFFSET + 2].copy_from_slice(&arcount.to_be_bytes());
+
+        let key_name = encode_name(&self.name).map_err(malformed)?;
+        let algorithm = encode_name(self.algorithm.name()).map_err(malformed)?;
+        let variables = TsigVariables { key_name: &key_name, algorithm: &algorithm, time_signed, fudge, error, timers_only };
+        let expected = self.mac(&digest_input(prefix, &unsigned, &variables));
+        if !fixed_time_eq(&expected, &mac) {
+            return Err(TsigError::BadSignature);
+        }
+        if now.abs_diff(time_signed) > fudge as u64 {
+            return Err(TsigError::BadTime);
+        }
+
+        Ok(mac)
+    }
+}
+
+/// What a MAC covers ahead of the message itself.
+fn mac_prefix(prior_mac: Option<&[u8]>, unsigned: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    if let Some(prior_mac) = prior_mac {
+        put_u16(&mut prefix, prior_mac.len() as u16);
+        prefix.extend_from_slice(prior_mac);
+    }
+    prefix.extend_from_slice(unsigned);
+    prefix
+}
+
+struct TsigVariables<'a> {
+    key_name: &'a [u8],
+    algorithm: &'a [u8],
+    time_signed: u64,
+    fudge: u16,
+    error: u16,
+    timers_only: bool,
+}
+
+/// RFC 8945 section 4.3.3, names are in canonical form and other data is always empty.
+fn digest_input(prefix: &[u8], message: &[u8], variables: &TsigVariables) -> Vec<u8> {
+    let mut input = Vec::with_capacity(prefix.len() + message.len() + 128);
+    input.extend_from_slice(prefix);
+    input.extend_from_slice(message);
+    if !variables.timers_only {
+        input.extend_from_slice(variables.key_name);
+        put_u16(&mut input, CLASS_ANY);
+        put_u32(&mut input, 0);
+        input.extend_from_slice(variables.algorithm);
+    }
+    put_u48(&mut input, variables.time_signed);
+    put_u16(&mut input, variables.fudge);
+    if !variables.timers_only {
+        put_u16(&mut input, variables.error);
+        put_u16(&mut input, 0);
+    }
+    input
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.').eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub rdata: Vec<u8>,
+}
+
+/// Encodes a message with a single zone (or question) entry, `answers` (or prerequisites) and
+/// `authority` records (or updates). Names are lower cased and never compressed.
+pub(crate) fn encode_message(id: u16, flags: u16, zone: &str, zone_type: u16, answers: &[Record], authority: &[Record]) -> Result<Vec<u8>, String> {
+    let mut message = Vec::with_capacity(512);
+    put_u16(&mut message, id);
+    put_u16(&mut message, flags);
+    put_u16(&mut message, 1);
+    put_u16(&mut message, answers.len() as u16);
+    put_u16(&mut message, authority.len() as u16);
+    put_u16(&mut message, 0);
+
+    message.extend_from_slice(&encode_name(zone)?);
+    put_u16(&mut message, zone_type);
+    put_u16(&mut message, CLASS_IN);
+
+    for record in answers.iter().chain(authority) {
+        message.extend_from_slice(&encode_name(&record.name)?);
+        put_u16(&mut message, record.rtype);
+        put_u16(&mut message, record.class);
+        put_u32(&mut message, record.ttl);
+        put_u16(&mut message, record.rdata.len() as u16);
+        message.extend_from_slice(&record.rdata);
+    }
+
+    Ok(message)
+}
+
+pub(crate) fn type_code(record_type: RecordType) -> u16 {
+    match record_type {
+        RecordType::Cname => TYPE_CNAME,
+        RecordType::A => TYPE_A,
+        RecordType::Aaaa => TYPE_AAAA,
+        RecordType::Txt => TYPE_TXT,
+    }
+}
+
+pub(crate) fn record_type(code: u16) -> Option<RecordType> {
+    match code {
+        TYPE_CNAME => Some(RecordType::Cname),
+        TYPE_A => Some(RecordType::A),
+        TYPE_AAAA => Some(RecordType::Aaaa),
+        TYPE_TXT => Some(RecordType::Txt),
+        _ => None,
+    }
+}
+
+/// The target of a validated record in wire format, long texts are split in strings of 255 octets.
+pub(crate) fn encode_rdata(record_type: RecordType, target: &str) -> Result<Vec<u8>, String> {
+    match record_type {
+        RecordType::Cname => encode_name(target),
+        RecordType::A => target.parse::<Ipv4Addr>().map(|a| a.octets().to_vec()).map_err(|e| e.to_string()),
+        RecordType::Aaaa => target.parse::<Ipv6Addr>().map(|a| a.octets().to_vec()).map_err(|e| e.to_string()),
+        RecordType::Txt => Ok(target.as_bytes().chunks(255).flat_map(|chunk| [&[chunk.len() as u8], chunk].concat()).collect()),
+    }
+}
+
+/// Messages over TCP are preceded by their length.
+pub(crate) fn write_tcp_message(stream: &mut impl Write, message: &[u8]) -> std::io::Result<()> {
+    let mut framed = (message.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(message);
+    stream.write_all(&framed)
+}
+
+pub(crate) fn read_tcp_message(stream: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut message = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+pub(crate) fn encode_name(name: &str) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::with_capacity(name.len() + 2);
+    let trimmed = name.trim_end_matches('.');
+    if !trimmed.is_empty() {
+        for label in trimmed.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(format!("{} is not a valid domain name", name));
+            }
+            encoded.push(label.len() as u8);
+            encoded.extend(label.bytes().map(|b| b.to_ascii_lowercase()));
+        }
+    }
+    encoded.push(0);
+
+    if encoded.len() > 255 {
+        return Err(format!("{} is longer than 255 octets", name));
+    }
+    Ok(encoded)
+}
+
+fn put_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u48(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_be_bytes()[2..]);
+}
+
+fn increment_count(message: &mut [u8], offset: usize) {
+    let count = u16::from_be_bytes([message[offset], message[offset + 1]]) + 1;
+    message[offset..offset + 2].copy_from_slice(&count.to_be_bytes());
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Question {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub(crate) struct ParsedRecord {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    /// Offset of the record in the message.
+    pub start: usize,
+    pub rdata_start: usize,
+    pub rdata: Vec<u8>,
+}
+
+/// In an update the sections are zone, prerequisite, update and additional data. Only the name
+/// server in the tests reads all of them.
+#[allow(dead_code)]
+pub(crate) struct ParsedMessage<'a> {
+    bytes: &'a [u8],
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<ParsedRecord>,
+    pub authority: Vec<ParsedRecord>,
+    pub additional: Vec<ParsedRecord>,
+}
+
+impl<'a> ParsedMessage<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        let counts = [reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?];
+
+        let mut questions = Vec::with_capacity(counts[0] as usize);
+        for _ in 0..counts[0] {
+            questions.push(Question { name: reader.name()?, rtype: reader.u16()?, class: reader.u16()? });
+        }
+        let answers = reader.records(counts[1])?;
+        let authority = reader.records(counts[2])?;
+        let additional = reader.records(counts[3])?;
+
+        Ok(ParsedMessage { bytes, id, flags, questions, answers, authority, additional })
+    }
+
+    pub fn rcode(&self) -> u16 {
+        self.flags & 0x000f
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_RESPONSE != 0
+    }
+
+    pub fn is_update(&self) -> bool {
+        self.flags & OPCODE_MASK == FLAGS_UPDATE
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.flags & FLAG_TRUNCATED != 0
+    }
+
+    /// The target of a record of a supported type, as `encode_rdata` takes it.
+    pub fn rdata_text(&self, record: &ParsedRecord) -> Result<String, String> {
+        let rdata = &record.rdata;
+        match record.rtype {
+            TYPE_CNAME => self.name_at(record.rdata_start),
+            TYPE_A if rdata.len() == 4 => Ok(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]).to_string()),
+            TYPE_AAAA if rdata.len() == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                Ok(Ipv6Addr::from(octets).to_string())
+            }
+            TYPE_TXT => {
+                let mut text = Vec::with_capacity(rdata.len());
+                let mut reader = Reader { bytes: rdata, pos: 0 };
+                while reader.pos < rdata.len() {
+                    let len = reader.take(1)?[0] as usize;
+                    text.extend_from_slice(reader.take(len)?);
+                }
+                String::from_utf8(text).map_err(|e| e.to_string())
+            }
+            other => Err(format!("rdata of type {} with {} octets is not supported", other, rdata.len())),
+        }
+    }
+
+    /// Reads a name in the rdata of a record, it may point to names elsewhere in the message.
+    pub fn name_at(&self, offset: usize) -> Result<String, String> {
+        Reader { bytes: self.bytes, pos: offset }.name()
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        if end > self.bytes.len() {
+            return Err(format!("message ends at {} reading {} octets at {}", self.bytes.len(), len, self.pos));
+        }
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u48(&mut self) -> Result<u64, String> {
+        let bytes = self.take(6)?;
+        Ok(bytes.iter().fold(0, |value, b| value << 8 | *b as u64))
+    }
+
+    /// Reads a possibly compressed name, lower cased and without the trailing dot.
+    fn name(&mut self) -> Result<String, String> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut pos = self.pos;
+        let mut end = None;
+        for _ in 0..128 {
+            let len = *self.bytes.get(pos).ok_or("name runs past the end of the message")? as usize;
+            match len {
+                0 => {
+                    self.pos = end.unwrap_or(pos + 1);
+                    return Ok(labels.join("."));
+                }
+                l if l & 0xc0 == 0xc0 => {
+                    let low = *self.bytes.get(pos + 1).ok_or("name pointer runs past the end of the message")? as usize;
+                    end.get_or_insert(pos + 2);
+                    pos = (l & 0x3f) << 8 | low;
+                }
+                l if l <= 63 => {
+                    let label = self.bytes.get(pos + 1..pos + 1 + l).ok_or("label runs past the end of the message")?;
+                    labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+                    pos += 1 + l;
+                }
+                _ => return Err(format!("invalid label length {} at {}", len, pos)),
+            }
+        }
+        Err("name pointers loop".to_string())
+    }
+
+    fn records(&mut self, count: u16) -> Result<Vec<ParsedRecord>, String> {
+        let mut records = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let start = self.pos;
+            let name = self.name()?;
+            let rtype = self.u16()?;
+            let class = self.u16()?;
+            let ttl = self.u32()?;
+            let rdata_len = self.u16()? as usize;
+            let rdata_start = self.pos;
+            let rdata = self.take(rdata_len)?.to_vec();
+            records.push(ParsedRecord { name, rtype, class, ttl, start, rdata_start, rdata });
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::application::RecordType;
+    use crate::application::implementation::dns_message::{CLASS_ANY, CLASS_IN, encode_message, encode_name, encode_rdata, FLAG_RESPONSE, FLAGS_QUERY, FLAGS_UPDATE, ParsedMessage, Record, record_type, TsigAlgorithm, TsigError, TsigKey, type_code, TYPE_AXFR, TYPE_CNAME, TYPE_SOA, TYPE_TSIG};
+
+    const NOW: u64 = 1_653_811_200;
+
+    fn key() -> TsigKey {
+        TsigKey::from_base64("capsule-key", TsigAlgorithm::HmacSha256, "c2VjcmV0LW9mLXRoZS1jYXBzdWxlLWtleQ==").unwrap()
+    }
+
+    fn update() -> Vec<u8> {
+        let cname = Record { name: "app.capsuleapp.cyou".to_string(), rtype: TYPE_CNAME, class: CLASS_IN, ttl: 300, rdata: encode_name("router.capsuleapp.cyou.").unwrap() };
+        encode_message(0x1234, FLAGS_UPDATE, "capsuleapp.cyou", TYPE_SOA, &[], &[cname]).unwrap()
+    }
+
+    #[test]
+    fn should_encode_names_lower_cased_without_compression() {
+        assert_eq!(b"\x03app\x0acapsuleapp\x04cyou\x00".to_vec(), encode_name("App.CapsuleApp.cyou.").unwrap());
+        assert_eq!(vec![0], encode_name(".").unwrap());
+        assert!(encode_name("a..b").is_err());
+        assert!(encode_name(&"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn should_parse_encoded_update() {
+        let message = update();
+
+        let parsed = ParsedMessage::parse(&message).unwrap();
+
+        assert_eq!(0x1234, parsed.id);
+        assert!(parsed.is_update() && !parsed.is_response());
+        assert_eq!(("capsuleapp.cyou", TYPE_SOA), (parsed.questions[0].name.as_str(), parsed.questions[0].rtype));
+        assert_eq!(("app.capsuleapp.cyou", TYPE_CNAME, 300), (parsed.authority[0].name.as_str(), parsed.authority[0].rtype, parsed.authority[0].ttl));
+        assert_eq!("router.capsuleapp.cyou", parsed.name_at(parsed.authority[0].rdata_start).unwrap());
+    }
+
+    #[test]
+    fn should_follow_compressed_names() {
+        let mut message = update();
+        let rdata_start = ParsedMessage::parse(&message).unwrap().authority[0].rdata_start;
+        // a record named "www" below the zone name at offset 12.
+        message[9] = 2;
+        message.extend_from_slice(b"\x03www\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x02\xc0\x0c");
+
+        let parsed = ParsedMessage::parse(&message).unwrap();
+
+        assert_eq!("www.capsuleapp.cyou", parsed.authority[1].name);
+        assert_eq!("capsuleapp.cyou", parsed.name_at(parsed.authority[1].rdata_start).unwrap());
+        assert_eq!("router.capsuleapp.cyou", parsed.name_at(rdata_start).unwrap());
+    }
+
+    #[test]
+    fn should_verify_signed_message() {
+        let mut message = update();
+
+        let mac = key().sign(&mut message, None, NOW).unwrap();
+
+        let parsed = ParsedMessage::parse(&message).unwrap();
+        assert_eq!(1, parsed.additional.len());
+        assert_eq!((TYPE_TSIG, CLASS_ANY, "capsule-key"), (parsed.additional[0].rtype, parsed.additional[0].class, parsed.additional[0].name.as_str()));
+        assert_eq!(32, mac.len());
+        assert_eq!(Ok(mac), key().verify(&message, None, NOW + 10));
+    }
+
+    #[test]
+    fn should_cover_request_mac_in_response_signature() {
+        let mut request = update();
+        let request_mac = key().sign(&mut request, None, NOW).unwrap();
+        let mut response = encode_message(0x1234, FLAGS_UPDATE | 0x8000, "capsuleapp.cyou", TYPE_SOA, &[], &[]).unwrap();
+
+        key().sign(&mut response, Some(&request_mac), NOW).unwrap();
+
+        assert!(key().verify(&response, Some(&request_mac), NOW).is_ok());
+        assert_eq!(Err(TsigError::BadSignature), key().verify(&response, Some(&[0; 32]), NOW));
+    }
+
+    #[test]
+    fn should_reject_tampered_or_foreign_messages() {
+        let mut message = update();
+        key().sign(&mut message, None, NOW).unwrap();
+
+        let mut tampered = message.clone();
+        tampered[40] ^= 1;
+        assert_eq!(Err(TsigError::BadSignature), key().verify(&tampered, None, NOW));
+
+        let other_secret = TsigKey { secret: b"another secret".to_vec(), ..key() };
+        assert_eq!(Err(TsigError::BadSignature), other_secret.verify(&message, None, NOW));
+
+        let other_name = TsigKey { name: "other-key".to_string(), ..key() };
+        assert_eq!(Err(TsigError::UnknownKey), other_name.verify(&message, None, NOW));
+
+        let other_algorithm = TsigKey { algorithm: TsigAlgorithm::HmacSha512, ..key() };
+        assert_eq!(Err(TsigError::UnknownKey), other_algorithm.verify(&message, None, NOW));
+
+        assert_eq!(Err(TsigError::BadTime), key().verify(&message, None, NOW + 301));
+        assert_eq!(Err(TsigError::Unsigned), key().verify(&update(), None, NOW));
+    }
+
+    #[test]
+    fn should_sign_with_hmac_sha512() {
+        let key = TsigKey { algorithm: TsigAlgorithm::HmacSha512, ..key() };
+        let mut message = update();
+
+        let mac = key.sign(&mut message, None, NOW).unwrap();
+
+        assert_eq!(64, mac.len());
+        assert!(key.verify(&message, None, NOW).is_ok());
+    }
+
+    #[test]
+    fn should_read_encoded_rdata() {
+        let long_text = "v".repeat(300);
+        let targets = [
+            (RecordType::Cname, "router.capsuleapp.cyou"),
+            (RecordType::A, "203.0.113.10"),
+            (RecordType::Aaaa, "2001:db8::10"),
+            (RecordType::Txt, "gfj9Xq...Rg85nM"),
+            (RecordType::Txt, long_text.as_str()),
+        ];
+        let answers: Vec<Record> = targets.iter()
+            .map(|(t, target)| Record { name: "app.capsuleapp.cyou".to_string(), rtype: type_code(*t), class: CLASS_IN, ttl: 60, rdata: encode_rdata(*t, target).unwrap() })
+            .collect();
+
+        let message = encode_message(1, FLAGS_QUERY | FLAG_RESPONSE, "app.capsuleapp.cyou", TYPE_CNAME, &answers, &[]).unwrap();
+
+        let parsed = ParsedMessage::parse(&message).unwrap();
+        let read: Vec<(RecordType, String)> = parsed.answers.iter().map(|a| (record_type(a.rtype).unwrap(), parsed.rdata_text(a).unwrap())).collect();
+        assert_eq!(targets.iter().map(|(t, target)| (*t, target.to_string())).collect::<Vec<_>>(), read);
+        assert_eq!(302, parsed.answers[4].rdata.len());
+    }
+
+    #[test]
+    fn should_verify_messages_of_zone_transfer() {
+        let mut request = encode_message(7, FLAGS_QUERY, "capsuleapp.cyou", TYPE_AXFR, &[], &[]).unwrap();
+        let request_mac = key().sign(&mut request, None, NOW).unwrap();
+        let response = || encode_message(7, FLAGS_QUERY | FLAG_RESPONSE, "capsuleapp.cyou", TYPE_AXFR, &[], &[]).unwrap();
+        let mut first = response();
+        let first_mac = key().sign(&mut first, Some(&request_mac), NOW).unwrap();
+        let unsigned = response();
+        let mut last = response();
+
+        key().sign_continuation(&mut last, &first_mac, &unsigned, NOW + 1).unwrap();
+
+        assert_eq!(Ok(first_mac.clone()), key().verify(&first, Some(&request_mac), NOW));
+        assert!(key().verify_continuation(&last, &first_mac, &unsigned, NOW).is_ok());
+        assert_eq!(Err(TsigError::BadSignature), key().verify_continuation(&last, &first_mac, &[], NOW));
+        assert_eq!(Err(TsigError::BadSignature), key().verify(&last, Some(&first_mac), NOW));
+    }
+
+    #[test]
+    fn should_parse_tsig_algorithm() {
+        assert_eq!(Ok(TsigAlgorithm::HmacSha256), "hmac-sha256".parse());
+        assert_eq!(Ok(TsigAlgorithm::HmacSha512), "HMAC-SHA512.".parse());
+        assert!("hmac-md5.sig-alg.reg.int".parse::<TsigAlgorithm>().is_err());
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::Duration;
+
+use crate::application::{ApplicationError, TxtResolver};
+use crate::application::implementation::dns_message::{encode_message, FLAG_RECURSION_DESIRED, FLAGS_QUERY, ParsedMessage, rcode_name, RCODE_NOERROR, RCODE_NXDOMAIN, read_tcp_message, TYPE_CNAME, TYPE_TXT, write_tcp_message};
+
+/// Asks a recursive resolver, like `1.1.1.1:53`, for TXT records, so they are seen the way the
+/// rest of the internet sees them.
+pub struct DnsTxtResolver {
+    pub server: SocketAddr,
+    pub timeout: Duration,
+}
+
+fn dns_error(message: impl ToString) -> ApplicationError {
+    ApplicationError::DomainNameError { message: message.to_string() }
+}
+
+impl DnsTxtResolver {
+    fn exchange_udp(&self, id: u16, request: &[u8]) -> Result<Vec<u8>, ApplicationError> {
+        let local: SocketAddr = if self.server.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let socket = UdpSocket::bind(local).map_err(dns_error)?;
+        socket.set_read_timeout(Some(self.timeout)).map_err(dns_error)?;
+        socket.connect(self.server).map_err(dns_error)?;
+        socket.send(request).map_err(dns_error)?;
+
+        let mut buffer = [0u8; 4096];
+        loop {
+            let len = socket.recv(&mut buffer).map_err(|e| dns_error(format!("no answer from resolver {}: {}", self.server, e)))?;
+            match ParsedMessage::parse(&buffer[..len]) {
+                Ok(parsed) if parsed.id == id && parsed.is_response() => return Ok(buffer[..len].to_vec()),
+                _ => continue,
+            }
+        }
+    }
+
+    fn exchange_tcp(&self, request: &[u8]) -> Result<Vec<u8>, ApplicationError> {
+        let mut stream = TcpStream::connect_timeout(&self.server, self.timeout).map_err(|e| dns_error(format!("connect to resolver {} error: {}", self.server, e)))?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(dns_error)?;
+        write_tcp_message(&mut stream, request).map_err(dns_error)?;
+        read_tcp_message(&mut stream).map_err(dns_error)
+    }
+}
+
+impl TxtResolver for DnsTxtResolver {
+    fn resolve_txt(&self, name: &str) -> Result<Vec<String>, ApplicationError> {
+        let id = rand::random::<u16>();
+        let request = encode_message(id, FLAGS_QUERY | FLAG_RECURSION_DESIRED, name, TYPE_TXT, &[], &[]).map_err(dns_error)?;
+
+        let mut response = self.exchange_udp(id, &request)?;
+        if ParsedMessage::parse(&response).map_err(dns_error)?.is_truncated() {
+            response = self.exchange_tcp(&request)?;
+        }
+        let parsed = ParsedMessage::parse(&response).map_err(dns_error)?;
+        if parsed.id != id {
+            return Err(dns_error(format!("resolver {} answered another query", self.server)));
+        }
+
+        match parsed.rcode() {
+            RCODE_NOERROR => {}
+            RCODE_NXDOMAIN => return Ok(Vec::new()),
+            rcode => return Err(dns_error(format!("resolving TXT records of {} failed with {}", name, rcode_name(rcode)))),
+        }
+
+        // the answer may lead to the records through CNAME records.
+        let mut names = vec![name.trim_end_matches('.').to_ascii_lowercase()];
+        let mut texts = Vec::new();
+        for answer in &parsed.answers {
+            if !names.contains(&answer.name) {
+                continue;
+            }
+            match answer.rtype {
+                TYPE_CNAME => names.push(parsed.name_at(answer.rdata_start).map_err(dns_error)?),
+                TYPE_TXT => texts.push(parsed.rdata_text(answer).map_err(dns_error)?),
+                _ => {}
+            }
+        }
+
+        Ok(texts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener, UdpSocket};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::application::{ApplicationError, RecordType, TxtResolver};
+    use crate::application::implementation::dns_message::{CLASS_IN, encode_message, encode_rdata, FLAG_RECURSION_DESIRED, FLAG_RESPONSE, ParsedMessage, RCODE_NOERROR, RCODE_NXDOMAIN, read_tcp_message, Record, type_code, TYPE_TXT, write_tcp_message};
+    use crate::application::implementation::dns_txt_resolver::DnsTxtResolver;
+
+    const TRUNCATED: u16 = 0x0200;
+    const SERVFAIL: u16 = 2;
+
+    fn record(name: &str, record_type: RecordType, target: &str) -> Record {
+        Record { name: name.to_string(), rtype: type_code(record_type), class: CLASS_IN, ttl: 60, rdata: encode_rdata(record_type, target).unwrap() }
+    }
+
+    /// Answers every query over UDP with `udp_flags` and `answers`, and over TCP with all of them.
+    fn start_resolver(udp_flags: u16, answers: Vec<Record>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let listener = TcpListener::bind(address).unwrap();
+
+        let respond = move |request: &[u8], flags: u16, answers: &[Record]| {
+            let parsed = ParsedMessage::parse(request).unwrap();
+            assert_eq!(FLAG_RECURSION_DESIRED, parsed.flags & FLAG_RECURSION_DESIRED);
+            assert_eq!(TYPE_TXT, parsed.questions[0].rtype);
+            encode_message(parsed.id, parsed.flags | FLAG_RESPONSE | flags, &parsed.questions[0].name, TYPE_TXT, answers, &[]).unwrap()
+        };
+        let udp_answers = if udp_flags & TRUNCATED != 0 { Vec::new() } else { answers.clone() };
+        thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buffer) {
+                socket.send_to(&respond(&buffer[..len], udp_flags, &udp_answers), peer).unwrap();
+            }
+        });
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = read_tcp_message(&mut stream).unwrap();
+                write_tcp_message(&mut stream, &respond(&request, RCODE_NOERROR, &answers)).unwrap();
+            }
+        });
+
+        address
+    }
+
+    fn resolver(server: SocketAddr) -> DnsTxtResolver {
+        DnsTxtResolver { server, timeout: Duration::from_secs(2) }
+    }
+
+    #[test]
+    fn should_resolve_txt_records_through_cname_records() {
+        let server = start_resolver(RCODE_NOERROR, vec![
+            record("_capsule-verification.www.theirshop.com", RecordType::Cname, "verify.dns-host.net"),
+            record("verify.dns-host.net", RecordType::Txt, "first-token"),
+            record("verify.dns-host.net", RecordType::Txt, "second-token"),
+            record("unrelated.dns-host.net", RecordType::Txt, "unrelated-token"),
+        ]);
+
+        let texts = resolver(server).resolve_txt("_capsule-verification.WWW.theirshop.com.").expect("resolve txt failed");
+
+        assert_eq!(vec!["first-token", "second-token"], texts);
+    }
+
+    #[test]
+    fn should_resolve_no_txt_records_for_missing_name() {
+        let server = start_resolver(RCODE_NXDOMAIN, vec![]);
+
+        assert!(resolver(server).resolve_txt("_capsule-verification.www.theirshop.com").unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_retry_over_tcp_when_answer_was_truncated() {
+        let long_token = "t".repeat(400);
+        let server = start_resolver(RCODE_NOERROR | TRUNCATED, vec![record("_capsule-verification.www.theirshop.com", RecordType::Txt, &long_token)]);
+
+        assert_eq!(vec![long_token], resolver(server).resolve_txt("_capsule-verification.www.theirshop.com").unwrap());
+    }
+
+    #[test]
+    fn should_get_domain_name_error_when_resolver_failed() {
+        let server = start_resolver(SERVFAIL, vec![]);
+
+        let result = resolver(server).resolve_txt("_capsule-verification.www.theirshop.com");
+
+        match result {
+            Err(ApplicationError::DomainNameError { message }) => assert_eq!("resolving TXT records of _capsule-verification.www.theirshop.com failed with SERVFAIL", message),
+            _ => panic!("expected a domain name error"),
+        }
+    }
+
+    #[test]
+    fn should_get_domain_name_error_when_resolver_does_not_answer() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = DnsTxtResolver { server: silent.local_addr().unwrap(), timeout: Duration::from_millis(200) };
+
+        assert!(matches!(resolver.resolve_txt("_capsule-verification.www.theirshop.com"), Err(ApplicationError::DomainNameError { .. })));
+    }
+}
@@ -11,12 +11,519 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use crate::application::{ApplicationError, CnameRecord, DomainNameService};
+use std::sync::{Arc, Mutex, MutexGuard};
 
-pub struct NameCheapDomainNameService;
+use isahc::{ReadResponseExt, Request, RequestExt};
+use isahc::http::StatusCode;
+
+use crate::application::{ApplicationError, CnameRecord, DnsRecord, DomainNameService, RecordType};
+
+const GET_HOSTS: &str = "namecheap.domains.dns.getHosts";
+const SET_HOSTS: &str = "namecheap.domains.dns.setHosts";
+
+/// A host record of the domain, as `domains.dns.getHosts` lists it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostRecord {
+    pub name: String,
+    pub record_type: String,
+    pub address: String,
+    pub mx_pref: Option<String>,
+    pub ttl: String,
+}
+
+/// Keeps a CNAME record per application below `domain`, pointing at `cname_target`.
+///
+/// `domains.dns.setHosts` replaces every host record of the domain, so each change reads the
+/// current records first and writes them back with the change applied, holding `hosts_lock`. All
+/// services changing the domain must share the lock, or they overwrite each other's changes.
+pub struct NameCheapDomainNameService {
+    /// Like `https://api.namecheap.com/xml.response`.
+    pub api_uri: String,
+    pub api_user: String,
+    pub api_key: String,
+    pub user_name: String,
+    /// The address requests come from, it must be whitelisted for the API key.
+    pub client_ip: String,
+    pub domain: String,
+    pub cname_target: String,
+    pub ttl: u32,
+    pub hosts_lock: Arc<Mutex<()>>,
+}
+
+fn dns_error(message: impl ToString) -> ApplicationError {
+    ApplicationError::DomainNameError { message: message.to_string() }
+}
+
+impl NameCheapDomainNameService {
+    /// `SLD` and `TLD` parameters of the domain, `capsuleapp` and `cyou` for `capsuleapp.cyou`.
+    fn domain_params(&self) -> Result<(&str, &str), ApplicationError> {
+        self.domain.split_once('.').ok_or_else(|| dns_error(format!("domain {} has no top level domain", self.domain)))
+    }
+
+    fn params(&self, command: &str) -> Result<form_urlencoded::Serializer<'static, String>, ApplicationError> {
+        let (sld, tld) = self.domain_params()?;
+        let mut params = form_urlencoded::Serializer::new(String::new());
+        params.append_pair("ApiUser", &self.api_user)
+            .append_pair("ApiKey", &self.api_key)
+            .append_pair("UserName", &self.user_name)
+            .append_pair("ClientIp", &self.client_ip)
+            .append_pair("Command", command)
+            .append_pair("SLD", sld)
+            .append_pair("TLD", tld);
+        Ok(params)
+    }
+
+    fn lock_hosts(&self) -> MutexGuard<'_, ()> {
+        // the lock guards no data, a panic while holding it leaves nothing inconsistent behind.
+        self.hosts_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get_hosts(&self) -> Result<Vec<HostRecord>, ApplicationError> {
+        let uri = format!("{}?{}", self.api_uri, self.params(GET_HOSTS)?.finish());
+
+        let mut response = Request::get(uri.as_str()).body(()).map_err(dns_error)?.send().map_err(dns_error)?;
+        let body = read_body(&mut response, GET_HOSTS)?;
+
+        let document = roxmltree::Document::parse(&body).map_err(dns_error)?;
+        check_status(&document)?;
+
+        Ok(document.descendants()
+            .filter(|n| n.tag_name().name() == "host")
+            .map(|host| HostRecord {
+                name: host.attribute("Name").unwrap_or_default().to_string(),
+                record_type: host.attribute("Type").unwrap_or_default().to_string(),
+                address: host.attribute("Address").unwrap_or_default().to_string(),
+                mx_pref: host.attribute("MXPref").filter(|_| host.attribute("Type") == Some("MX")).map(|p| p.to_string()),
+                ttl: host.attribute("TTL").unwrap_or_default().to_string(),
+            })
+            .collect())
+    }
+
+    pub fn set_hosts(&self, hosts: &[HostRecord]) -> Result<(), ApplicationError> {
+        let mut params = self.params(SET_HOSTS)?;
+        for (i, host) in hosts.iter().enumerate().map(|(i, h)| (i + 1, h)) {
+            params.append_pair(&format!("HostName{}", i), &host.name)
+                .append_pair(&format!("RecordType{}", i), &host.record_type)
+                .append_pair(&format!("Address{}", i), &host.address)
+                .append_pair(&format!("TTL{}", i), &host.ttl);
+            if let Some(mx_pref) = &host.mx_pref {
+                params.append_pair(&format!("MXPref{}", i), mx_pref);
+            }
+        }
+        // without it, mail settings of the domain are reset along with the records.
+        if hosts.iter().any(|h| h.record_type == "MX") {
+            params.append_pair("EmailType", "MX");
+        }
+
+        let mut response = Request::post(self.api_uri.as_str())
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(params.finish())
+            .map_err(dns_error)?
+            .send()
+            .map_err(dns_error)?;
+        let body = read_body(&mut response, SET_HOSTS)?;
+
+        let document = roxmltree::Document::parse(&body).map_err(dns_error)?;
+        check_status(&document)?;
+        let succeeded = document.descendants()
+            .find(|n| n.tag_name().name() == "DomainDNSSetHostsResult")
+            .and_then(|n| n.attribute("IsSuccess"))
+            .is_some_and(|s| s.eq_ignore_ascii_case("true"));
+        if !succeeded {
+            return Err(dns_error(format!("{} did not succeed for {}", SET_HOSTS, self.domain)));
+        }
+
+        Ok(())
+    }
+
+    fn domain_name(&self, cname: &str) -> String {
+        format!("{}.{}", cname, self.domain)
+    }
+}
+
+fn read_body(response: &mut isahc::Response<isahc::Body>, command: &str) -> Result<String, ApplicationError> {
+    if response.status() != StatusCode::OK {
+        return Err(dns_error(format!("{} responded with status {}", command, response.status())));
+    }
+
+    response.text().map_err(dns_error)
+}
+
+/// The API answers errors with status 200 too, telling them apart by the `Status` attribute.
+fn check_status(document: &roxmltree::Document) -> Result<(), ApplicationError> {
+    let root = document.root_element();
+    if root.attribute("Status") == Some("OK") {
+        return Ok(());
+    }
+
+    let errors: Vec<String> = root.descendants()
+        .filter(|n| n.tag_name().name() == "Error")
+        .map(|n| format!("{} ({})", n.text().unwrap_or_default().trim(), n.attribute("Number").unwrap_or_default()))
+        .collect();
+    Err(dns_error(format!("namecheap api error: {}", errors.join(", "))))
+}
+
+impl HostRecord {
+    fn to_record(&self) -> Option<DnsRecord> {
+        let record_type = self.record_type.parse().ok()?;
+        let ttl = self.ttl.parse().unwrap_or_default();
+        Some(DnsRecord::new(&self.name, record_type, &self.address, ttl))
+    }
+
+    fn from_record(record: &DnsRecord) -> Self {
+        HostRecord {
+            name: record.name.clone(),
+            record_type: record.record_type.to_string(),
+            address: record.target.clone(),
+            mx_pref: None,
+            ttl: record.ttl.to_string(),
+        }
+    }
+
+    fn is_record(&self, record: &DnsRecord) -> bool {
+        self.to_record().is_some_and(|r| r.same_record(record))
+    }
+
+    fn has_name(&self, name: &str, record_type: RecordType) -> bool {
+        self.to_record().is_some_and(|r| r.has_name(name, record_type))
+    }
+}
 
 impl DomainNameService for NameCheapDomainNameService {
-    fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
-        todo!()
+    fn add_cname_record(&self, cname: &str) -> Result<CnameRecord, ApplicationError> {
+        self.add_record(&DnsRecord::new(cname, RecordType::Cname, &self.cname_target, self.ttl))?;
+
+        Ok(CnameRecord { domain_name: self.domain_name(cname) })
+    }
+
+    fn remove_cname_record(&self, cname: &str) -> Result<(), ApplicationError> {
+        let _hosts_lock = self.lock_hosts();
+        let hosts = self.get_hosts()?;
+
+        let kept: Vec<HostRecord> = hosts.iter().filter(|h| !h.has_name(cname, RecordType::Cname)).cloned().collect();
+        if kept.len() == hosts.len() {
+            return Ok(());
+        }
+
+        self.set_hosts(&kept)
+    }
+
+    fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+        Ok(self.get_hosts()?.iter().filter_map(HostRecord::to_record).collect())
+    }
+
+    fn get_records(&self, name: &str, record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+        Ok(self.list_records()?.into_iter().filter(|r| r.has_name(name, record_type)).collect())
+    }
+
+    fn add_record(&self, record: &DnsRecord) -> Result<(), ApplicationError> {
+        record.validate()?;
+        let _hosts_lock = self.lock_hosts();
+        let mut hosts = self.get_hosts()?;
+
+        if hosts.iter().any(|h| h.is_record(record) && h.ttl == record.ttl.to_string()) {
+            return Ok(());
+        }
+
+        let replaced_cname = |h: &HostRecord| record.record_type == RecordType::Cname && h.has_name(&record.name, RecordType::Cname);
+        hosts.retain(|h| !(h.is_record(record) || replaced_cname(h)));
+        hosts.push(HostRecord::from_record(record));
+        self.set_hosts(&hosts)
+    }
+
+    fn update_record(&self, record: &DnsRecord) -> Result<(), ApplicationError> {
+        record.validate()?;
+        let _hosts_lock = self.lock_hosts();
+        let mut hosts = self.get_hosts()?;
+
+        if !hosts.iter().any(|h| h.has_name(&record.name, record.record_type)) {
+            return Err(ApplicationError::DnsRecordNotFound { name: record.name.clone(), record_type: record.record_type });
+        }
+
+        hosts.retain(|h| !h.has_name(&record.name, record.record_type));
+        hosts.push(HostRecord::from_record(record));
+        self.set_hosts(&hosts)
+    }
+
+    fn delete_record(&self, record: &DnsRecord) -> Result<(), ApplicationError> {
+        let _hosts_lock = self.lock_hosts();
+        let hosts = self.get_hosts()?;
+
+        let kept: Vec<HostRecord> = hosts.iter().filter(|h| !h.is_record(record)).cloned().collect();
+        if kept.len() == hosts.len() {
+            return Ok(());
+        }
+
+        self.set_hosts(&kept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{body_string_contains, method, path, query_param};
+
+    use crate::application::{ApplicationError, DnsRecord, DomainNameService, RecordType};
+    use crate::application::implementation::domain_name_service::NameCheapDomainNameService;
+
+    const HOSTS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ApiResponse Status="OK" xmlns="http://api.namecheap.com/xml.response">
+  <Errors />
+  <RequestedCommand>namecheap.domains.dns.getHosts</RequestedCommand>
+  <CommandResponse Type="namecheap.domains.dns.getHosts">
+    <DomainDNSGetHostsResult Domain="capsuleapp.cyou" IsUsingOurDNS="true">
+      <host HostId="1" Name="@" Type="A" Address="203.0.113.10" MXPref="10" TTL="1800" />
+      <host HostId="2" Name="@" Type="MX" Address="mail.capsuleapp.cyou." MXPref="20" TTL="1800" />
+      <host HostId="3" Name="existing-app" Type="CNAME" Address="router.capsuleapp.cyou." MXPref="10" TTL="300" />
+    </DomainDNSGetHostsResult>
+  </CommandResponse>
+</ApiResponse>"#;
+
+    const SET_HOSTS_OK: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ApiResponse Status="OK" xmlns="http://api.namecheap.com/xml.response">
+  <Errors />
+  <CommandResponse Type="namecheap.domains.dns.setHosts">
+    <DomainDNSSetHostsResult Domain="capsuleapp.cyou" IsSuccess="true" />
+  </CommandResponse>
+</ApiResponse>"#;
+
+    const API_ERROR: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ApiResponse Status="ERROR" xmlns="http://api.namecheap.com/xml.response">
+  <Errors>
+    <Error Number="1011150">Parameter RequestIP is invalid</Error>
+  </Errors>
+</ApiResponse>"#;
+
+    fn service(mock_server: &MockServer) -> NameCheapDomainNameService {
+        NameCheapDomainNameService {
+            api_uri: format!("{}/xml.response", mock_server.uri()),
+            api_user: "capsule".to_string(),
+            api_key: "namecheap-api-key".to_string(),
+            user_name: "capsule".to_string(),
+            client_ip: "203.0.113.1".to_string(),
+            domain: "capsuleapp.cyou".to_string(),
+            cname_target: "router.capsuleapp.cyou.".to_string(),
+            ttl: 300,
+            hosts_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    async fn mock_get_hosts(mock_server: &MockServer, body: &str) {
+        Mock::given(method("GET"))
+            .and(path("/xml.response"))
+            .and(query_param("Command", "namecheap.domains.dns.getHosts"))
+            .and(query_param("ApiKey", "namecheap-api-key"))
+            .and(query_param("SLD", "capsuleapp"))
+            .and(query_param("TLD", "cyou"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(mock_server)
+            .await;
+    }
+
+    #[async_std::test]
+    async fn should_add_cname_record_keeping_existing_hosts() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        Mock::given(method("POST"))
+            .and(path("/xml.response"))
+            .and(body_string_contains("Command=namecheap.domains.dns.setHosts"))
+            .and(body_string_contains("HostName1=%40&RecordType1=A&Address1=203.0.113.10&TTL1=1800&"))
+            .and(body_string_contains("HostName2=%40&RecordType2=MX&Address2=mail.capsuleapp.cyou.&TTL2=1800&MXPref2=20&"))
+            .and(body_string_contains("HostName3=existing-app&RecordType3=CNAME"))
+            .and(body_string_contains("HostName4=first-capsule-application&RecordType4=CNAME&Address4=router.capsuleapp.cyou.&TTL4=300"))
+            .and(body_string_contains("EmailType=MX"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SET_HOSTS_OK))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let record = service(&mock_server).add_cname_record("first-capsule-application").expect("add cname record failed");
+
+        assert_eq!("first-capsule-application.capsuleapp.cyou", record.domain_name);
+    }
+
+    #[async_std::test]
+    async fn should_not_set_hosts_when_cname_record_exists() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(200).set_body_string(SET_HOSTS_OK)).expect(0).mount(&mock_server).await;
+
+        let record = service(&mock_server).add_cname_record("existing-app").unwrap();
+
+        assert_eq!("existing-app.capsuleapp.cyou", record.domain_name);
+    }
+
+    #[async_std::test]
+    async fn should_remove_only_cname_record_of_application() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("HostName2=%40&RecordType2=MX"))
+            .and(body_string_contains("TLD=cyou&HostName1=%40"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SET_HOSTS_OK))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        service(&mock_server).remove_cname_record("existing-app").expect("remove cname record failed");
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let set_hosts = String::from_utf8(requests.last().unwrap().body.clone()).unwrap();
+        assert!(!set_hosts.contains("existing-app"));
+        assert!(!set_hosts.contains("HostName3"));
+    }
+
+    #[async_std::test]
+    async fn should_not_set_hosts_when_removed_record_does_not_exist() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(200).set_body_string(SET_HOSTS_OK)).expect(0).mount(&mock_server).await;
+
+        assert!(service(&mock_server).remove_cname_record("gone-app").is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_get_domain_name_error_when_api_reported_error() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, API_ERROR).await;
+
+        let result = service(&mock_server).add_cname_record("first-capsule-application");
+
+        match result {
+            Err(ApplicationError::DomainNameError { message }) => assert_eq!("namecheap api error: Parameter RequestIP is invalid (1011150)", message),
+            _ => panic!("expected a domain name error"),
+        }
+    }
+
+    #[async_std::test]
+    async fn should_get_domain_name_error_when_set_hosts_did_not_succeed() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SET_HOSTS_OK.replace("IsSuccess=\"true\"", "IsSuccess=\"false\"")))
+            .mount(&mock_server)
+            .await;
+
+        assert!(matches!(service(&mock_server).add_cname_record("first-capsule-application"), Err(ApplicationError::DomainNameError { .. })));
+    }
+
+    #[async_std::test]
+    async fn should_get_domain_name_error_when_status_code_was_not_200() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(502)).mount(&mock_server).await;
+
+        assert!(matches!(service(&mock_server).remove_cname_record("first-capsule-application"), Err(ApplicationError::DomainNameError { .. })));
+    }
+
+    async fn mock_set_hosts(mock_server: &MockServer, times: u64) {
+        Mock::given(method("POST"))
+            .and(body_string_contains("Command=namecheap.domains.dns.setHosts"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SET_HOSTS_OK))
+            .expect(times)
+            .mount(mock_server)
+            .await;
+    }
+
+    async fn set_hosts_body(mock_server: &MockServer) -> String {
+        let requests = mock_server.received_requests().await.unwrap();
+        String::from_utf8(requests.last().unwrap().body.clone()).unwrap()
+    }
+
+    #[async_std::test]
+    async fn should_list_records_of_supported_types() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+
+        let records = service(&mock_server).list_records().expect("list records failed");
+
+        assert_eq!(vec![
+            DnsRecord::new("@", RecordType::A, "203.0.113.10", 1800),
+            DnsRecord::new("existing-app", RecordType::Cname, "router.capsuleapp.cyou.", 300),
+        ], records);
+    }
+
+    #[async_std::test]
+    async fn should_get_records_of_name_and_type() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        let service = service(&mock_server);
+
+        assert_eq!(vec![DnsRecord::new("existing-app", RecordType::Cname, "router.capsuleapp.cyou.", 300)], service.get_records("Existing-App", RecordType::Cname).unwrap());
+        assert!(service.get_records("existing-app", RecordType::A).unwrap().is_empty());
+    }
+
+    #[async_std::test]
+    async fn should_not_set_hosts_when_added_record_exists() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        mock_set_hosts(&mock_server, 0).await;
+
+        assert!(service(&mock_server).add_record(&DnsRecord::new("@", RecordType::A, "203.0.113.10", 1800)).is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_add_record_next_to_records_of_same_name() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        mock_set_hosts(&mock_server, 1).await;
+
+        service(&mock_server).add_record(&DnsRecord::new("@", RecordType::A, "203.0.113.11", 600)).expect("add record failed");
+
+        let body = set_hosts_body(&mock_server).await;
+        assert!(body.contains("HostName1=%40&RecordType1=A&Address1=203.0.113.10&TTL1=1800"));
+        assert!(body.contains("HostName4=%40&RecordType4=A&Address4=203.0.113.11&TTL4=600"));
+    }
+
+    #[async_std::test]
+    async fn should_update_every_record_of_name_and_type() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        mock_set_hosts(&mock_server, 1).await;
+
+        service(&mock_server).update_record(&DnsRecord::new("@", RecordType::A, "203.0.113.20", 60)).expect("update record failed");
+
+        let body = set_hosts_body(&mock_server).await;
+        assert!(!body.contains("203.0.113.10"));
+        assert!(body.contains("HostName3=%40&RecordType3=A&Address3=203.0.113.20&TTL3=60"));
+        assert!(body.contains("RecordType1=MX"));
+    }
+
+    #[async_std::test]
+    async fn should_get_not_found_updating_missing_record() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        mock_set_hosts(&mock_server, 0).await;
+
+        let result = service(&mock_server).update_record(&DnsRecord::new("first-app", RecordType::Aaaa, "2001:db8::10", 60));
+
+        assert!(matches!(result, Err(ApplicationError::DnsRecordNotFound { name, record_type: RecordType::Aaaa }) if name == "first-app"));
+    }
+
+    #[async_std::test]
+    async fn should_delete_only_given_record() {
+        let mock_server = MockServer::start().await;
+        mock_get_hosts(&mock_server, HOSTS).await;
+        mock_set_hosts(&mock_server, 1).await;
+        let service = service(&mock_server);
+
+        service.delete_record(&DnsRecord::new("existing-app", RecordType::Cname, "other.capsuleapp.cyou", 300)).unwrap();
+        service.delete_record(&DnsRecord::new("existing-app", RecordType::Cname, "router.capsuleapp.cyou", 300)).unwrap();
+
+        let body = set_hosts_body(&mock_server).await;
+        assert!(!body.contains("existing-app"));
+        assert!(body.contains("HostName1=%40&RecordType1=A"));
+    }
+
+    #[async_std::test]
+    async fn should_reject_invalid_record_without_calling_api() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200).set_body_string(HOSTS)).expect(0).mount(&mock_server).await;
+
+        let result = service(&mock_server).add_record(&DnsRecord::new("first app", RecordType::Txt, "token", 60));
+
+        assert!(matches!(result, Err(ApplicationError::InvalidDnsRecord { .. })));
     }
-}
\ No newline at end of file
+
+}
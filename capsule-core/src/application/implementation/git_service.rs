@@ -30,6 +30,21 @@ pub struct CreateGitRepoResponse {
     pub uri: String,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct RenameGitRepoRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TransferGitRepoRequest {
+    pub user: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RenameGitRepoResponse {
+    pub git_repo_uri: String,
+}
+
 pub struct DefaultGitService {
     pub host_uri: String,
 }
@@ -54,19 +69,19 @@ impl From<isahc::Error> for ApplicationError {
 
 impl From<Error> for GitError {
     fn from(_: Error) -> Self {
-        todo!()
+        GitError {}
     }
 }
 
 impl From<isahc::Error> for GitError {
     fn from(_: isahc::Error) -> Self {
-        todo!()
+        GitError {}
     }
 }
 
 impl From<isahc::http::Error> for GitError {
     fn from(_: isahc::http::Error) -> Self {
-        todo!()
+        GitError {}
     }
 }
 
@@ -92,6 +107,57 @@ impl GitService for DefaultGitService {
 
         Ok(GitRepository { uri: api_response.uri })
     }
+
+    fn rename_repo(&self, owner: &str, app_name: &str, new_app_name: &str) -> Result<GitRepository, GitError> {
+        let host = &self.host_uri;
+        let uri = format!("{}/repositories/{}/{}", host, owner, app_name);
+
+        let mut response = Request::patch(uri.as_str())
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(&RenameGitRepoRequest { name: new_app_name.to_string() })?)?
+            .send()?;
+
+        if response.status() != StatusCode::OK {
+            return Err(GitError {});
+        }
+
+        let api_response = response.json::<RenameGitRepoResponse>()?;
+
+        Ok(GitRepository { uri: api_response.git_repo_uri })
+    }
+
+    fn transfer_repo(&self, owner: &str, app_name: &str, new_owner: &str) -> Result<GitRepository, GitError> {
+        let host = &self.host_uri;
+        let uri = format!("{}/repositories/{}/{}/transfer", host, owner, app_name);
+
+        let mut response = Request::post(uri.as_str())
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(&TransferGitRepoRequest { user: new_owner.to_string() })?)?
+            .send()?;
+
+        if response.status() != StatusCode::OK {
+            return Err(GitError {});
+        }
+
+        let api_response = response.json::<RenameGitRepoResponse>()?;
+
+        Ok(GitRepository { uri: api_response.git_repo_uri })
+    }
+
+    fn delete_repo(&self, owner: &str, app_name: &str) -> Result<(), GitError> {
+        let host = &self.host_uri;
+        let uri = format!("{}/repositories/{}/{}", host, owner, app_name);
+
+        let response = Request::delete(uri.as_str())
+            .body(())?
+            .send()?;
+
+        // a repository that is already gone is what the caller asked for.
+        match response.status() {
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND => Ok(()),
+            _ => Err(GitError {}),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -100,7 +166,7 @@ mod tests {
     use wiremock::matchers::{body_json, method, path};
 
     use crate::application::{DefaultGitService, GitService};
-    use crate::application::implementation::git_service::{CreateGitRepoRequest, CreateGitRepoResponse};
+    use crate::application::implementation::git_service::{CreateGitRepoRequest, CreateGitRepoResponse, RenameGitRepoRequest, RenameGitRepoResponse, TransferGitRepoRequest};
 
     #[async_std::test]
     async fn should_send_git_repository_request_to_git_server() {
@@ -151,4 +217,115 @@ mod tests {
         let error = result.err().unwrap();
         // assert_eq!("git service error response status 400 Bad Request", error.to_string())
     }
-}
\ No newline at end of file
+
+    #[async_std::test]
+    async fn should_send_rename_repository_request_to_git_server() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("PATCH"))
+            .and(path("/repositories/first_capsule_user/first-capsule-application"))
+            .and(body_json(RenameGitRepoRequest { name: "new-name".to_string() }))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(RenameGitRepoResponse {
+                    git_repo_uri: "https://git.capsuleapp.cyou/first_capsule_user/new-name.git".to_string()
+                }))
+            .mount(&mock_server)
+            .await;
+
+        let git_service = DefaultGitService { host_uri: mock_server.uri() };
+
+        let git_repo = git_service.rename_repo("first_capsule_user", "first-capsule-application", "new-name").expect("rename git repo failed");
+
+        assert_eq!("https://git.capsuleapp.cyou/first_capsule_user/new-name.git", git_repo.uri)
+    }
+
+    #[async_std::test]
+    async fn should_get_git_error_when_rename_repository_failed() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("PATCH"))
+            .and(path("/repositories/first_capsule_user/first-capsule-application"))
+            .respond_with(ResponseTemplate::new(422))
+            .mount(&mock_server)
+            .await;
+
+        let git_service = DefaultGitService { host_uri: mock_server.uri() };
+
+        let result = git_service.rename_repo("first_capsule_user", "first-capsule-application", "new-name");
+
+        assert!(result.is_err());
+    }
+
+    #[async_std::test]
+    async fn should_send_transfer_repository_request_to_git_server() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/repositories/first_capsule_user/first-capsule-application/transfer"))
+            .and(body_json(TransferGitRepoRequest { user: "capsule-team".to_string() }))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(RenameGitRepoResponse {
+                    git_repo_uri: "https://git.capsuleapp.cyou/capsule-team/first-capsule-application.git".to_string()
+                }))
+            .mount(&mock_server)
+            .await;
+
+        let git_service = DefaultGitService { host_uri: mock_server.uri() };
+
+        let git_repo = git_service.transfer_repo("first_capsule_user", "first-capsule-application", "capsule-team").expect("transfer git repo failed");
+
+        assert_eq!("https://git.capsuleapp.cyou/capsule-team/first-capsule-application.git", git_repo.uri)
+    }
+
+    #[async_std::test]
+    async fn should_send_delete_repository_request_to_git_server() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/repositories/first_capsule_user/first-capsule-application"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let git_service = DefaultGitService { host_uri: mock_server.uri() };
+
+        let result = git_service.delete_repo("first_capsule_user", "first-capsule-application");
+
+        assert!(result.is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_treat_missing_repository_as_deleted() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/repositories/first_capsule_user/first-capsule-application"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let git_service = DefaultGitService { host_uri: mock_server.uri() };
+
+        let result = git_service.delete_repo("first_capsule_user", "first-capsule-application");
+
+        assert!(result.is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_get_git_error_when_delete_repository_failed() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/repositories/first_capsule_user/first-capsule-application"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let git_service = DefaultGitService { host_uri: mock_server.uri() };
+
+        let result = git_service.delete_repo("first_capsule_user", "first-capsule-application");
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::Duration;
+
+use isahc::{Request, RequestExt};
+use isahc::config::Configurable;
+
+use crate::application::health::{HealthCheckConfig, HealthProbe, ProbeFailure};
+
+/// Probes instances with a plain `GET`, redirects are not followed and count as passed.
+pub struct HttpHealthProbe;
+
+impl HealthProbe for HttpHealthProbe {
+    fn probe(&self, address: &str, config: &HealthCheckConfig) -> Result<(), ProbeFailure> {
+        let response = Request::get(format!("http://{}{}", address, config.path))
+            .timeout(Duration::from_secs(config.timeout_secs as u64))
+            .body(())
+            .map_err(|e| ProbeFailure { message: e.to_string() })?
+            .send()
+            .map_err(|e| ProbeFailure { message: e.to_string() })?;
+
+        let status = response.status();
+        if status.is_success() || status.is_redirection() {
+            Ok(())
+        } else {
+            Err(ProbeFailure { message: format!("health check responded with status {}", status) })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{method, path};
+
+    use crate::application::health::{HealthCheckConfig, HealthProbe};
+    use crate::application::implementation::http_health_probe::HttpHealthProbe;
+
+    fn config() -> HealthCheckConfig {
+        HealthCheckConfig { path: "/health".to_string(), timeout_secs: 1, ..HealthCheckConfig::default() }
+    }
+
+    async fn mock_server(response: ResponseTemplate) -> MockServer {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(response)
+            .mount(&mock_server)
+            .await;
+        mock_server
+    }
+
+    #[async_std::test]
+    async fn should_pass_when_instance_responded_with_success() {
+        let mock_server = mock_server(ResponseTemplate::new(200)).await;
+
+        assert!(HttpHealthProbe.probe(&mock_server.address().to_string(), &config()).is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_pass_when_instance_responded_with_redirect() {
+        let mock_server = mock_server(ResponseTemplate::new(302).insert_header("Location", "/login")).await;
+
+        assert!(HttpHealthProbe.probe(&mock_server.address().to_string(), &config()).is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_fail_when_instance_responded_with_error() {
+        let mock_server = mock_server(ResponseTemplate::new(503)).await;
+
+        let failure = HttpHealthProbe.probe(&mock_server.address().to_string(), &config()).unwrap_err();
+
+        assert_eq!("health check responded with status 503 Service Unavailable", failure.message);
+    }
+
+    #[async_std::test]
+    async fn should_fail_when_instance_responded_too_late() {
+        let mock_server = mock_server(ResponseTemplate::new(200).set_delay(Duration::from_secs(3))).await;
+
+        assert!(HttpHealthProbe.probe(&mock_server.address().to_string(), &config()).is_err());
+    }
+
+    #[test]
+    fn should_fail_when_nothing_listens_on_address() {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        assert!(HttpHealthProbe.probe(&address.to_string(), &config()).is_err());
+    }
+}
@@ -0,0 +1,414 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File, OpenOptions, read_to_string, remove_dir, write};
+use std::io::{BufRead, BufReader, pipe, PipeReader, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+use crate::application::logs::{LogSink, LogSource};
+use crate::application::runtime::{ProcessInstance, ProcessSpec, ProcessStatus, RuntimeBackend, RuntimeError};
+
+/// Length of the cgroup `cpu.max` period, in microseconds.
+const CPU_PERIOD: u64 = 100_000;
+/// Leaf cgroup below the cgroup root the server itself runs in.
+const SERVER_CGROUP: &str = "server";
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Runs instances as local processes, one process group each, logging to `{log_dir}/{instance}.log`
+/// and to the log sink, if any.
+///
+/// With a cgroup root every instance also gets its own cgroup v2 below it, limited to the memory
+/// and CPU of its size. The root must be a cgroup delegated to the capsule server, e.g. by systemd.
+pub struct LocalRuntimeBackend {
+    log_dir: PathBuf,
+    cgroup_root: Option<PathBuf>,
+    stop_timeout: Duration,
+    log_sink: Option<Arc<dyn LogSink>>,
+    children: Mutex<HashMap<ProcessInstance, Child>>,
+}
+
+impl LocalRuntimeBackend {
+    /// Instances get `stop_timeout` to exit after SIGTERM before they are killed.
+    pub fn new<P: AsRef<Path>>(log_dir: P, stop_timeout: Duration) -> Result<Self, RuntimeError> {
+        create_dir_all(log_dir.as_ref())?;
+
+        Ok(Self { log_dir: log_dir.as_ref().to_path_buf(), cgroup_root: None, stop_timeout, log_sink: None, children: Mutex::new(HashMap::new()) })
+    }
+
+    /// Moves the server into `{cgroup_root}/server` and enables the cpu and memory controllers for
+    /// the cgroups of instances below `cgroup_root`, failing unless it is a writable cgroup v2
+    /// directory offering both.
+    pub fn with_cgroup_root<P: AsRef<Path>>(mut self, cgroup_root: P) -> Result<Self, RuntimeError> {
+        let root = cgroup_root.as_ref();
+        let controllers = read_to_string(root.join("cgroup.controllers"))
+            .map_err(|e| RuntimeError { message: format!("{} is not a cgroup v2 directory: {}", root.display(), e) })?;
+        if let Some(missing) = ["cpu", "memory"].iter().find(|c| !controllers.split_whitespace().any(|available| available == **c)) {
+            return Err(RuntimeError { message: format!("cgroup {} does not offer the {} controller", root.display(), missing) });
+        }
+
+        // cgroup v2 only enables controllers for the children of a cgroup without processes of its
+        // own, and a delegated root starts out holding the server.
+        let server = root.join(SERVER_CGROUP);
+        create_dir_all(&server)
+            .and_then(|_| write(server.join("cgroup.procs"), std::process::id().to_string()))
+            .map_err(|e| RuntimeError { message: format!("move server into cgroup {}: {}", server.display(), e) })?;
+
+        write(root.join("cgroup.subtree_control"), "+cpu +memory")
+            .map_err(|e| RuntimeError {
+                message: format!("enable cgroup controllers in {}: {} (other processes than the server must not run in it)", root.display(), e)
+            })?;
+
+        self.cgroup_root = Some(cgroup_root.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    /// Also sends every line instances write to `log_sink`.
+    pub fn with_log_sink(mut self, log_sink: Arc<dyn LogSink>) -> Self {
+        self.log_sink = Some(log_sink);
+        self
+    }
+
+    fn log_file(&self, instance: &ProcessInstance) -> PathBuf {
+        self.log_dir.join(format!("{}.log", instance))
+    }
+
+    fn cgroup(&self, instance: &ProcessInstance) -> Option<PathBuf> {
+        self.cgroup_root.as_ref().map(|root| root.join(instance.to_string()))
+    }
+
+    /// Creates the cgroup of the instance and returns the script that moves the shell into it
+    /// before running `command`, so nothing the command starts escapes the limits.
+    fn limited(&self, spec: &ProcessSpec, cgroup: &Path) -> Result<String, RuntimeError> {
+        create_dir_all(cgroup)?;
+        write(cgroup.join("memory.max"), (spec.size.memory_mb() as u64 * 1024 * 1024).to_string())?;
+        write(cgroup.join("cpu.max"), format!("{} {}", spec.size.cpu_millis() as u64 * CPU_PERIOD / 1000, CPU_PERIOD))?;
+
+        let procs = cgroup.join("cgroup.procs");
+        Ok(format!("echo $$ > {} && exec sh -c {}", shell_quote(&procs.to_string_lossy()), shell_quote(&spec.command)))
+    }
+
+    fn stop_child(&self, instance: &ProcessInstance, child: &mut Child) -> Result<(), RuntimeError> {
+        let process_group = format!("-{}", child.id());
+
+        if child.try_wait()?.is_none() {
+            signal(&process_group, "TERM");
+            let deadline = Instant::now() + self.stop_timeout;
+            while child.try_wait()?.is_none() && Instant::now() < deadline {
+                sleep(STOP_POLL_INTERVAL);
+            }
+        }
+
+        // whatever is left of the instance, including processes it started, gets killed.
+        signal(&process_group, "KILL");
+        if let Some(cgroup) = self.cgroup(instance) {
+            let _ = write(cgroup.join("cgroup.kill"), "1");
+        }
+        let _ = child.kill();
+        child.wait()?;
+
+        if let Some(cgroup) = self.cgroup(instance) {
+            // a cgroup still holding dying processes is reused by the next start of the instance.
+            let _ = remove_dir(cgroup);
+        }
+        Ok(())
+    }
+}
+
+/// Copies the output of an instance line by line to its log file and the log sink, until the
+/// instance and everything it started closed the pipe.
+fn pump_output(output: PipeReader, mut log: File, log_sink: Option<Arc<dyn LogSink>>, instance: &ProcessInstance) {
+    let (application_id, process) = (instance.application_id, instance.process_name());
+
+    spawn(move || {
+        let mut reader = BufReader::new(output);
+        let mut line = vec![];
+        while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+            let _ = log.write_all(&line);
+            if let Some(sink) = &log_sink {
+                sink.append(application_id, LogSource::App, &process, String::from_utf8_lossy(&line).trim_end_matches(['\n', '\r']));
+            }
+            line.clear();
+        }
+    });
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn signal(process_group: &str, signal: &str) {
+    let _ = Command::new("kill").args(["-s", signal, "--", process_group])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+fn status_of(child: &mut Child) -> Result<ProcessStatus, RuntimeError> {
+    Ok(match child.try_wait()? {
+        None => ProcessStatus::Running { pid: child.id() },
+        Some(exit_status) => ProcessStatus::Exited { code: exit_status.code() },
+    })
+}
+
+impl RuntimeBackend for LocalRuntimeBackend {
+    fn start(&self, spec: &ProcessSpec) -> Result<ProcessStatus, RuntimeError> {
+        let mut children = self.children.lock().unwrap();
+        if let Some(child) = children.get_mut(&spec.instance) {
+            if let ProcessStatus::Running { .. } = status_of(child)? {
+                return Err(RuntimeError { message: format!("{} is already running", spec.instance) });
+            }
+        }
+
+        let script = match self.cgroup(&spec.instance) {
+            Some(cgroup) => self.limited(spec, &cgroup)?,
+            None => spec.command.clone(),
+        };
+        let log: File = OpenOptions::new().create(true).append(true).open(self.log_file(&spec.instance))?;
+        // one pipe for stdout and stderr keeps their lines in the order they were written.
+        let (output, output_writer) = pipe()?;
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script)
+            .current_dir(&spec.working_dir)
+            .env_clear()
+            .envs(std::env::var_os("PATH").map(|path| ("PATH", path)))
+            .envs(&spec.env)
+            .stdin(Stdio::null())
+            .stdout(output_writer.try_clone()?)
+            .stderr(output_writer)
+            .process_group(0);
+        let spawned = command.spawn();
+        // the writing ends must be closed here, or the pump never sees the instance finish.
+        drop(command);
+        let mut child = spawned.map_err(|e| RuntimeError { message: format!("start {}: {}", spec.instance, e) })?;
+        pump_output(output, log, self.log_sink.clone(), &spec.instance);
+
+        let status = status_of(&mut child)?;
+        children.insert(spec.instance.clone(), child);
+        Ok(status)
+    }
+
+    fn stop(&self, instance: &ProcessInstance) -> Result<(), RuntimeError> {
+        let child = self.children.lock().unwrap().remove(instance);
+
+        match child {
+            Some(mut child) => self.stop_child(instance, &mut child),
+            None => Ok(()),
+        }
+    }
+
+    fn status(&self, instance: &ProcessInstance) -> Result<ProcessStatus, RuntimeError> {
+        match self.children.lock().unwrap().get_mut(instance) {
+            Some(child) => status_of(child),
+            None => Ok(ProcessStatus::Stopped),
+        }
+    }
+
+    fn logs(&self, instance: &ProcessInstance, lines: usize) -> Result<Vec<String>, RuntimeError> {
+        let content = match read_to_string(self.log_file(instance)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let all: Vec<&str> = content.lines().collect();
+        Ok(all[all.len().saturating_sub(lines)..].iter().map(|l| l.to_string()).collect())
+    }
+
+    fn instances(&self, application_id: i64) -> Result<Vec<ProcessInstance>, RuntimeError> {
+        let mut instances: Vec<ProcessInstance> = self.children.lock().unwrap().keys()
+            .filter(|i| i.application_id == application_id)
+            .cloned()
+            .collect();
+        instances.sort();
+        Ok(instances)
+    }
+}
+
+impl Drop for LocalRuntimeBackend {
+    fn drop(&mut self) {
+        let children: Vec<(ProcessInstance, Child)> = self.children.get_mut().unwrap().drain().collect();
+        for (instance, mut child) in children {
+            let _ = self.stop_child(&instance, &mut child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use std::sync::Arc;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    use tempdir::TempDir;
+
+    use crate::application::config_vars::ConfigVarMap;
+    use crate::application::formation::ProcessSize;
+    use crate::application::implementation::local_runtime::LocalRuntimeBackend;
+    use crate::application::logs::{LogBuffer, LogFilter, LogSource};
+    use crate::application::runtime::{ProcessInstance, ProcessSpec, ProcessStatus, RuntimeBackend};
+
+    fn spec(work_dir: &TempDir, command: &str) -> ProcessSpec {
+        ProcessSpec {
+            instance: ProcessInstance { application_id: 1, release_version: 1, process_type: "web".to_string(), index: 0 },
+            command: command.to_string(),
+            working_dir: work_dir.path().to_path_buf(),
+            env: ConfigVarMap::from([("GREETING".to_string(), "hello capsule".to_string())]),
+            size: ProcessSize::Small,
+        }
+    }
+
+    fn wait_for_exit(runtime: &LocalRuntimeBackend, instance: &ProcessInstance) -> ProcessStatus {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let status = runtime.status(instance).unwrap();
+            if !matches!(status, ProcessStatus::Running { .. }) || Instant::now() > deadline {
+                return status;
+            }
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn wait_for_logs(runtime: &LocalRuntimeBackend, instance: &ProcessInstance, count: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let logs = runtime.logs(instance, usize::MAX).unwrap();
+            if logs.len() >= count || Instant::now() > deadline {
+                return logs;
+            }
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn should_run_command_with_env_in_working_dir_and_keep_logs() {
+        let dir = TempDir::new("runtime").unwrap();
+        let runtime = LocalRuntimeBackend::new(dir.path().join("logs"), Duration::from_secs(1)).unwrap();
+        let spec = spec(&dir, "echo \"$GREETING\"; pwd; echo oops >&2; exit 3");
+
+        runtime.start(&spec).unwrap();
+
+        assert_eq!(ProcessStatus::Exited { code: Some(3) }, wait_for_exit(&runtime, &spec.instance));
+        let logs = wait_for_logs(&runtime, &spec.instance, 3);
+        assert_eq!(vec!["hello capsule".to_string(), dir.path().to_string_lossy().to_string(), "oops".to_string()], logs);
+        assert_eq!(vec!["oops".to_string()], runtime.logs(&spec.instance, 1).unwrap());
+    }
+
+    #[test]
+    fn should_stop_running_instance() {
+        let dir = TempDir::new("runtime").unwrap();
+        let runtime = LocalRuntimeBackend::new(dir.path().join("logs"), Duration::from_secs(5)).unwrap();
+        let spec = spec(&dir, "sleep 30");
+
+        assert!(matches!(runtime.start(&spec).unwrap(), ProcessStatus::Running { .. }));
+        assert!(runtime.start(&spec).is_err());
+        assert_eq!(vec![spec.instance.clone()], runtime.instances(1).unwrap());
+        assert!(runtime.instances(2).unwrap().is_empty());
+
+        let started = Instant::now();
+        runtime.stop(&spec.instance).unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(ProcessStatus::Stopped, runtime.status(&spec.instance).unwrap());
+        assert!(runtime.instances(1).unwrap().is_empty());
+        runtime.stop(&spec.instance).expect("stopping a stopped instance should succeed");
+    }
+
+    #[test]
+    fn should_kill_instance_ignoring_sigterm() {
+        let dir = TempDir::new("runtime").unwrap();
+        let runtime = LocalRuntimeBackend::new(dir.path().join("logs"), Duration::from_millis(200)).unwrap();
+        let spec = spec(&dir, "trap '' TERM; sleep 30 & wait");
+
+        runtime.start(&spec).unwrap();
+        let started = Instant::now();
+        runtime.stop(&spec.instance).unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn should_have_no_logs_for_unknown_instance() {
+        let dir = TempDir::new("runtime").unwrap();
+        let runtime = LocalRuntimeBackend::new(dir.path().join("logs"), Duration::from_secs(1)).unwrap();
+        let instance = ProcessInstance { application_id: 2, release_version: 1, process_type: "worker".to_string(), index: 1 };
+
+        assert!(runtime.logs(&instance, 10).unwrap().is_empty());
+        assert_eq!(ProcessStatus::Stopped, runtime.status(&instance).unwrap());
+    }
+
+    #[test]
+    fn should_send_output_to_log_sink() {
+        let dir = TempDir::new("runtime").unwrap();
+        let logs = Arc::new(LogBuffer::new(10));
+        let runtime = LocalRuntimeBackend::new(dir.path().join("logs"), Duration::from_secs(1)).unwrap()
+            .with_log_sink(logs.clone());
+        let spec = spec(&dir, "echo started; echo failed >&2");
+
+        runtime.start(&spec).unwrap();
+        wait_for_logs(&runtime, &spec.instance, 2);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while logs.recent(1, &LogFilter::default(), 10).len() < 2 && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        let lines = logs.recent(1, &LogFilter::default(), 10);
+        assert_eq!(vec![("web.0", "started"), ("web.0", "failed")],
+                   lines.iter().map(|l| (l.process.as_str(), l.message.as_str())).collect::<Vec<_>>());
+        assert!(lines.iter().all(|l| l.source == LogSource::App));
+    }
+
+    /// A plain directory stands in for the cgroup file system, which only records what is written.
+    #[test]
+    fn should_limit_instance_with_cgroup() {
+        let dir = TempDir::new("runtime").unwrap();
+        let cgroup_root = dir.path().join("cgroup");
+        std::fs::create_dir_all(&cgroup_root).unwrap();
+        std::fs::write(cgroup_root.join("cgroup.controllers"), "cpuset cpu io memory pids").unwrap();
+        let runtime = LocalRuntimeBackend::new(dir.path().join("logs"), Duration::from_secs(1)).unwrap()
+            .with_cgroup_root(&cgroup_root).unwrap();
+        let spec = ProcessSpec { size: ProcessSize::Medium, ..spec(&dir, "echo \"it's $GREETING\"") };
+
+        runtime.start(&spec).unwrap();
+        let status = wait_for_exit(&runtime, &spec.instance);
+
+        let cgroup = cgroup_root.join("app-1-v1-web-0");
+        assert_eq!(std::process::id().to_string(), read_to_string(cgroup_root.join("server/cgroup.procs")).unwrap());
+        assert_eq!("+cpu +memory", read_to_string(cgroup_root.join("cgroup.subtree_control")).unwrap());
+        assert_eq!("1073741824", read_to_string(cgroup.join("memory.max")).unwrap());
+        assert_eq!("100000 100000", read_to_string(cgroup.join("cpu.max")).unwrap());
+        assert!(!read_to_string(cgroup.join("cgroup.procs")).unwrap().trim().is_empty());
+        assert_eq!(ProcessStatus::Exited { code: Some(0) }, status);
+        assert_eq!(vec!["it's hello capsule".to_string()], wait_for_logs(&runtime, &spec.instance, 1));
+    }
+
+    #[test]
+    fn should_reject_cgroup_root_without_cpu_and_memory_controllers() {
+        let dir = TempDir::new("runtime").unwrap();
+        let runtime = || LocalRuntimeBackend::new(dir.path().join("logs"), Duration::from_secs(1)).unwrap();
+
+        let error = runtime().with_cgroup_root(dir.path()).err().expect("plain directory should be rejected");
+        assert!(error.message.contains("is not a cgroup v2 directory"), "{}", error.message);
+
+        std::fs::write(dir.path().join("cgroup.controllers"), "cpuset cpu pids").unwrap();
+        let error = runtime().with_cgroup_root(dir.path()).err().expect("cgroup without memory controller should be rejected");
+        assert!(error.message.ends_with("does not offer the memory controller"), "{}", error.message);
+    }
+}
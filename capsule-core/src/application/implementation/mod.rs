@@ -14,3 +14,10 @@
 pub(crate) mod postgres;
 pub mod git_service;
 pub mod domain_name_service;
+pub mod local_runtime;
+pub mod http_health_probe;
+
+pub(crate) mod dns_message;
+pub mod rfc2136_domain_name_service;
+pub mod acme_client;
+pub mod dns_txt_resolver;
@@ -13,4 +13,14 @@
 // limitations under the License.
 mod schema;
 mod models;
-mod postgres_applications;
\ No newline at end of file
+pub(crate) mod postgres_applications;
+pub(crate) mod postgres_redirects;
+pub(crate) mod postgres_collaborators;
+pub(crate) mod postgres_config_vars;
+pub(crate) mod postgres_releases;
+pub(crate) mod postgres_builds;
+pub(crate) mod postgres_deploys;
+pub(crate) mod postgres_formations;
+pub(crate) mod postgres_health_checks;
+pub(crate) mod postgres_custom_domains;
+pub(crate) mod postgres_certificates;
@@ -1,6 +1,21 @@
 use std::time::SystemTime;
 
+use super::schema::capsule_acme_accounts;
+use super::schema::capsule_application_builds;
+use super::schema::capsule_application_certificates;
+use super::schema::capsule_application_collaborators;
+use super::schema::capsule_application_config_changes;
+use super::schema::capsule_application_config_vars;
+use super::schema::capsule_application_deploys;
+use super::schema::capsule_application_domains;
+use super::schema::capsule_application_formations;
+use super::schema::capsule_application_health_checks;
+use super::schema::capsule_application_redirects;
+use super::schema::capsule_application_releases;
 use super::schema::capsule_applications;
+use super::schema::capsule_deploy_steps;
+use super::schema::capsule_formation_events;
+use super::schema::capsule_owner_limits;
 
 #[derive(Queryable)]
 pub struct SavedApplication {
@@ -9,6 +24,7 @@ pub struct SavedApplication {
     pub application_name: String,
     pub owner: String,
     pub create_at: SystemTime,
+    pub version: i32,
 }
 
 #[derive(Insertable)]
@@ -19,3 +35,278 @@ pub struct NewApplication {
     pub owner: String,
     pub create_at: SystemTime,
 }
+
+#[derive(Queryable)]
+pub struct SavedRedirect {
+    pub id: i32,
+    pub from_name: String,
+    pub application_id: i64,
+    pub expire_at: SystemTime,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_redirects"]
+pub struct NewRedirect {
+    pub from_name: String,
+    pub application_id: i64,
+    pub expire_at: SystemTime,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedCollaborator {
+    pub id: i32,
+    pub application_id: i64,
+    pub user_name: String,
+    pub role: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_collaborators"]
+pub struct NewCollaborator {
+    pub application_id: i64,
+    pub user_name: String,
+    pub role: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedConfigVar {
+    pub var_key: String,
+    pub var_value: Vec<u8>,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_config_vars"]
+pub struct NewConfigVar {
+    pub application_id: i64,
+    pub var_key: String,
+    pub var_value: Vec<u8>,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedConfigChange {
+    pub application_id: i64,
+    pub user_name: String,
+    pub var_keys: Vec<String>,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_config_changes"]
+pub struct NewConfigChange {
+    pub application_id: i64,
+    pub user_name: String,
+    pub var_keys: Vec<String>,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedRelease {
+    pub application_id: i64,
+    pub version: i32,
+    pub build_artifact: String,
+    pub commit_sha: String,
+    pub config_vars: Vec<u8>,
+    pub description: String,
+    pub created_by: String,
+    pub create_at: SystemTime,
+    pub processes: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_releases"]
+pub struct NewRelease {
+    pub application_id: i64,
+    pub version: i32,
+    pub build_artifact: String,
+    pub commit_sha: String,
+    pub config_vars: Vec<u8>,
+    pub description: String,
+    pub created_by: String,
+    pub create_at: SystemTime,
+    pub processes: String,
+}
+
+#[derive(Queryable)]
+pub struct SavedBuild {
+    pub id: i32,
+    pub application_id: i64,
+    pub ref_name: String,
+    pub commit_sha: String,
+    pub pushed_by: String,
+    pub status: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_builds"]
+pub struct NewBuild {
+    pub application_id: i64,
+    pub ref_name: String,
+    pub commit_sha: String,
+    pub pushed_by: String,
+    pub status: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedFormation {
+    pub process_type: String,
+    pub quantity: i32,
+    pub size: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_formations"]
+pub struct NewFormation {
+    pub application_id: i64,
+    pub process_type: String,
+    pub quantity: i32,
+    pub size: String,
+    pub update_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedFormationEvent {
+    pub id: i32,
+    pub application_id: i64,
+    pub process_type: String,
+    pub quantity: i32,
+    pub size: String,
+    pub changed_by: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_formation_events"]
+pub struct NewFormationEvent {
+    pub application_id: i64,
+    pub process_type: String,
+    pub quantity: i32,
+    pub size: String,
+    pub changed_by: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "capsule_owner_limits"]
+pub struct SavedOwnerLimits {
+    pub owner: String,
+    pub max_instances: i32,
+    pub max_size: String,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "capsule_application_health_checks"]
+pub struct SavedHealthCheck {
+    pub application_id: i64,
+    pub path: String,
+    pub interval_secs: i32,
+    pub timeout_secs: i32,
+    pub healthy_threshold: i32,
+    pub unhealthy_threshold: i32,
+    pub update_at: SystemTime,
+    pub deploy_timeout_secs: i32,
+}
+
+#[derive(Queryable)]
+pub struct SavedDeploy {
+    pub id: i32,
+    pub application_id: i64,
+    pub release_version: i32,
+    pub status: String,
+    pub created_by: String,
+    pub create_at: SystemTime,
+    pub finish_at: Option<SystemTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_deploys"]
+pub struct NewDeploy {
+    pub application_id: i64,
+    pub release_version: i32,
+    pub status: String,
+    pub created_by: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedDeployStep {
+    pub deploy_id: i32,
+    pub kind: String,
+    pub succeeded: bool,
+    pub message: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_deploy_steps"]
+pub struct NewDeployStep {
+    pub deploy_id: i32,
+    pub kind: String,
+    pub succeeded: bool,
+    pub message: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedCustomDomain {
+    pub id: i32,
+    pub application_id: i64,
+    pub hostname: String,
+    pub verification_token: String,
+    pub verified_at: Option<SystemTime>,
+    pub last_checked_at: Option<SystemTime>,
+    pub last_error: Option<String>,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_domains"]
+pub struct NewCustomDomain {
+    pub application_id: i64,
+    pub hostname: String,
+    pub verification_token: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedCertificate {
+    pub application_id: i64,
+    pub hostname: String,
+    pub certificate_chain: Vec<u8>,
+    pub private_key: Vec<u8>,
+    pub not_after: SystemTime,
+    pub issued_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_application_certificates"]
+pub struct NewCertificate {
+    pub application_id: i64,
+    pub hostname: String,
+    pub certificate_chain: Vec<u8>,
+    pub private_key: Vec<u8>,
+    pub not_after: SystemTime,
+    pub issued_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedAcmeAccount {
+    pub directory_uri: String,
+    pub account_uri: String,
+    pub private_key: Vec<u8>,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_acme_accounts"]
+pub struct NewAcmeAccount {
+    pub directory_uri: String,
+    pub account_uri: String,
+    pub private_key: Vec<u8>,
+    pub create_at: SystemTime,
+}
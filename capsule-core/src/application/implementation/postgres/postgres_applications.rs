@@ -14,17 +14,18 @@
 use std::sync::Arc;
 use std::time::SystemTime;
 
-use diesel::{ExpressionMethods, insert_into, PgConnection, QueryDsl, RunQueryDsl};
-use diesel::result::Error;
+use diesel::{BoolExpressionMethods, ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use diesel::dsl::{exists, select};
+use diesel::result::{DatabaseErrorKind, Error};
 
-use crate::application::{Application, Updater};
-use crate::application::applications::Applications;
+use crate::application::{Application, ApplicationError, ApplicationName, Updater};
+use crate::application::applications::{Applications, Page};
 use crate::application::implementation::postgres::models::{NewApplication, SavedApplication};
 use crate::application::implementation::postgres::schema::capsule_applications;
 use crate::application::implementation::postgres::schema::capsule_applications::dsl::*;
 use crate::CoreError;
 
-struct PostgresApplications {
+pub struct PostgresApplications {
     connection: Arc<PgConnection>,
 }
 
@@ -32,11 +33,28 @@ impl PostgresApplications {
     pub fn new(connection: Arc<PgConnection>) -> PostgresApplications {
         PostgresApplications { connection }
     }
+
+    fn to_application(&self, saved_application: SavedApplication) -> Application {
+        Application {
+            id: saved_application.application_id,
+            name: ApplicationName::unchecked(saved_application.application_name),
+            owner: saved_application.owner,
+            create_at: saved_application.create_at,
+            version: saved_application.version,
+            updater: Some(Box::new(PgUpdater { connection: self.connection.clone() })),
+        }
+    }
 }
 
 impl From<Error> for CoreError {
-    fn from(_: Error) -> Self {
-        todo!()
+    fn from(e: Error) -> Self {
+        CoreError { message: e.to_string() }
+    }
+}
+
+impl From<Error> for ApplicationError {
+    fn from(e: Error) -> Self {
+        CoreError::from(e).into()
     }
 }
 
@@ -50,12 +68,68 @@ impl Applications for PostgresApplications {
 
         Ok(Application {
             id: new_application.application_id,
-            name: new_application.application_name.clone(),
+            name: application.name.clone(),
             owner: new_application.owner.clone(),
-            create_at: application.create_at.clone(),
+            create_at: application.create_at,
+            version: 0,
             updater: Some(Box::new(PgUpdater { connection: self.connection.clone() })),
         })
     }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Application>, CoreError> {
+        let saved_application = capsule_applications
+            .filter(application_name.eq(name))
+            .first::<SavedApplication>(self.connection.as_ref())
+            .optional()?;
+
+        Ok(saved_application.map(|a| self.to_application(a)))
+    }
+
+    fn find_by_id(&self, app_id: i64) -> Result<Option<Application>, CoreError> {
+        let saved_application = capsule_applications
+            .filter(application_id.eq(app_id))
+            .first::<SavedApplication>(self.connection.as_ref())
+            .optional()?;
+
+        Ok(saved_application.map(|a| self.to_application(a)))
+    }
+
+    fn list_by_owner(&self, app_owner: &str, page: &Page) -> Result<Vec<Application>, CoreError> {
+        let saved_applications = capsule_applications
+            .filter(owner.eq(app_owner))
+            .order(create_at.desc())
+            .then_order_by(application_id.desc())
+            .offset(page.offset())
+            .limit(page.limit())
+            .load::<SavedApplication>(self.connection.as_ref())?;
+
+        Ok(saved_applications.into_iter().map(|a| self.to_application(a)).collect())
+    }
+
+    fn list(&self, page: &Page) -> Result<Vec<Application>, CoreError> {
+        let saved_applications = capsule_applications
+            .order(create_at.asc())
+            .then_order_by(application_id.asc())
+            .offset(page.offset())
+            .limit(page.limit())
+            .load::<SavedApplication>(self.connection.as_ref())?;
+
+        Ok(saved_applications.into_iter().map(|a| self.to_application(a)).collect())
+    }
+
+    fn delete(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_applications.filter(application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn exists(&self, name: &str) -> Result<bool, CoreError> {
+        let found = select(exists(capsule_applications.filter(application_name.eq(name))))
+            .get_result::<bool>(self.connection.as_ref())?;
+
+        Ok(found)
+    }
 }
 
 fn new_application(app_id: i64, name: &str, app_owner: &str, app_create_at: SystemTime) -> NewApplication {
@@ -72,13 +146,22 @@ struct PgUpdater {
 }
 
 impl Updater for PgUpdater {
-    fn update(&self, application: &Application) {
+    fn update(&self, application: &Application) -> Result<(), ApplicationError> {
         let app_id = application.accept(PgUpdater::get_application_id);
+        let name = application.accept(PgUpdater::get_application_name);
 
-        diesel::update(capsule_applications.filter(application_id.eq(app_id)))
-            .set(application_name.eq(application.accept(PgUpdater::get_application_name)))
+        let updated = diesel::update(capsule_applications.filter(application_id.eq(app_id).and(version.eq(application.version()))))
+            .set((application_name.eq(&name), owner.eq(&application.owner), version.eq(application.version() + 1)))
             .execute(self.connection.as_ref())
-            .expect("");
+            .map_err(|e| match e {
+                Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => ApplicationError::ApplicationAlreadyExists { name: name.clone() },
+                e => ApplicationError::from(CoreError::from(e)),
+            })?;
+
+        match updated {
+            0 => Err(ApplicationError::ConcurrentModification { name }),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -94,14 +177,12 @@ impl PgUpdater {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
-
     use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
 
-    use test_tool::get_test_db_connection;
+    use test_tool::get_shared_test_db_connection;
 
-    use crate::application::Application;
-    use crate::application::applications::Applications;
+    use crate::application::{Application, ApplicationError, ApplicationName};
+    use crate::application::applications::{Applications, Page};
     use crate::application::implementation::postgres::models::SavedApplication;
     use crate::application::implementation::postgres::postgres_applications::PostgresApplications;
     use crate::application::implementation::postgres::schema::capsule_applications::application_name;
@@ -109,43 +190,161 @@ mod tests {
 
     #[test]
     fn should_save_application_to_db() {
-        let connection = Arc::new(get_test_db_connection());
+        let connection = get_shared_test_db_connection();
 
         let applications = PostgresApplications::new(connection.clone());
 
-        let application = Application::new(1, Some("first_capsule_application".to_string()), "first_capsule_user".to_string());
+        let application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string());
 
         applications.add(&application);
 
         let query_result = capsule_applications
-            .filter(application_name.eq("first_capsule_application"))
+            .filter(application_name.eq("first-capsule-application"))
             .first::<SavedApplication>(connection.as_ref());
 
         let saved_application = query_result.unwrap();
         assert_eq!(saved_application.application_id, 1);
-        assert_eq!(saved_application.application_name, "first_capsule_application".to_string());
+        assert_eq!(saved_application.application_name, "first-capsule-application".to_string());
         assert_eq!(saved_application.owner, "first_capsule_user".to_string());
     }
 
     #[test]
     fn should_update_db_if_application_rename() {
-        let connection = Arc::new(get_test_db_connection());
+        let connection = get_shared_test_db_connection();
 
         let applications = PostgresApplications::new(connection.clone());
 
-        let application = Application::new(1, Some("first_capsule_application".to_string()), "first_capsule_user".to_string());
+        let application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string());
 
         let mut application = applications.add(&application).expect("save application failed");
 
-        application.rename("new_name");
+        application.rename(ApplicationName::new("new-name").unwrap()).expect("rename application failed");
 
         let query_result = capsule_applications
-            .filter(application_name.eq("new_name"))
+            .filter(application_name.eq("new-name"))
             .first::<SavedApplication>(connection.clone().as_ref());
 
         let saved_application = query_result.unwrap();
         assert_eq!(saved_application.application_id, 1);
-        assert_eq!(saved_application.application_name, "new_name".to_string());
+        assert_eq!(saved_application.application_name, "new-name".to_string());
         assert_eq!(saved_application.owner, "first_capsule_user".to_string());
+        assert_eq!(saved_application.version, 1);
+    }
+
+    #[test]
+    fn should_update_db_if_application_transferred() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        let mut application = applications.add(&Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+
+        application.transfer_to("capsule-team").expect("transfer application failed");
+
+        assert_eq!(applications.find_by_id(1).unwrap().unwrap().owner, "capsule-team");
+    }
+
+    #[test]
+    fn should_reject_rename_of_stale_application() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        applications.add(&Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+        let mut first = applications.find_by_id(1).unwrap().unwrap();
+        let mut second = applications.find_by_id(1).unwrap().unwrap();
+
+        first.rename(ApplicationName::new("first-name").unwrap()).expect("rename application failed");
+        let result = second.rename(ApplicationName::new("second-name").unwrap());
+
+        assert!(matches!(result, Err(ApplicationError::ConcurrentModification { .. })));
+        assert_eq!(second.name.as_str(), "first-capsule-application");
+        assert_eq!(applications.find_by_id(1).unwrap().unwrap().name.as_str(), "first-name");
+    }
+
+    #[test]
+    fn should_report_taken_name_if_rename_to_existing_application() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        applications.add(&Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+        let mut application = applications.add(&Application::new(2, Some(ApplicationName::new("second-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+
+        let result = application.rename(ApplicationName::new("first-capsule-application").unwrap());
+
+        assert!(matches!(result, Err(ApplicationError::ApplicationAlreadyExists { .. })));
+    }
+
+    #[test]
+    fn should_find_application_by_name() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        applications.add(&Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+
+        let application = applications.find_by_name("first-capsule-application").expect("find application failed").unwrap();
+
+        assert_eq!(application.id, 1);
+        assert_eq!(application.owner, "first_capsule_user");
+        assert!(applications.find_by_name("not-exists").unwrap().is_none());
+    }
+
+    #[test]
+    fn should_find_application_by_id() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        applications.add(&Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+
+        let application = applications.find_by_id(1).expect("find application failed").unwrap();
+
+        assert_eq!(application.name.as_str(), "first-capsule-application");
+        assert!(applications.find_by_id(2).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_list_applications_of_owner_by_page() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        for app_id in 1..=3 {
+            let name = ApplicationName::new(format!("capsule-application-{}", app_id).as_str()).unwrap();
+            applications.add(&Application::new(app_id, Some(name), "first_capsule_user".to_string())).expect("save application failed");
+        }
+        applications.add(&Application::new(4, Some(ApplicationName::new("other-application").unwrap()), "other_capsule_user".to_string())).expect("save application failed");
+
+        let first_page = applications.list_by_owner("first_capsule_user", &Page::new(1, 2)).expect("list applications failed");
+        let second_page = applications.list_by_owner("first_capsule_user", &Page::new(2, 2)).expect("list applications failed");
+
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(second_page.len(), 1);
+        assert!(first_page.iter().chain(second_page.iter()).all(|a| a.owner == "first_capsule_user"));
+    }
+
+    #[test]
+    fn should_list_all_applications_by_page() {
+        let applications = PostgresApplications::new(get_shared_test_db_connection());
+        applications.add(&Application::new(1, Some(ApplicationName::new("first-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+        applications.add(&Application::new(2, Some(ApplicationName::new("other-application").unwrap()), "other_capsule_user".to_string())).expect("save application failed");
+
+        let first_page = applications.list(&Page::new(1, 1)).expect("list applications failed");
+        let second_page = applications.list(&Page::new(2, 1)).expect("list applications failed");
+
+        assert_eq!(first_page.len(), 1);
+        assert_eq!(second_page.len(), 1);
+        assert_ne!(first_page[0].id, second_page[0].id);
+    }
+
+    #[test]
+    fn should_delete_application() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        applications.add(&Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+
+        applications.delete(1).expect("delete application failed");
+
+        assert!(applications.find_by_id(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_check_application_exists() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        applications.add(&Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())).expect("save application failed");
+
+        assert!(applications.exists("first-capsule-application").unwrap());
+        assert!(!applications.exists("not-exists").unwrap());
     }
-}
\ No newline at end of file
+}
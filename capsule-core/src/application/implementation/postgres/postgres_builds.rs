@@ -0,0 +1,119 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{ExpressionMethods, insert_into, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::application::builds::{Build, Builds, BuildStatus, PushedRef};
+use crate::application::implementation::postgres::models::{NewBuild, SavedBuild};
+use crate::application::implementation::postgres::schema::capsule_application_builds;
+use crate::application::implementation::postgres::schema::capsule_application_builds::dsl::*;
+use crate::CoreError;
+
+pub struct PostgresBuilds {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresBuilds {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresBuilds {
+        PostgresBuilds { connection }
+    }
+}
+
+impl TryFrom<SavedBuild> for Build {
+    type Error = CoreError;
+
+    fn try_from(saved_build: SavedBuild) -> Result<Self, Self::Error> {
+        Ok(Build {
+            id: saved_build.id,
+            application_id: saved_build.application_id,
+            ref_name: saved_build.ref_name,
+            commit_sha: saved_build.commit_sha,
+            pushed_by: saved_build.pushed_by,
+            status: BuildStatus::from_str(saved_build.status.as_str())?,
+            create_at: saved_build.create_at,
+        })
+    }
+}
+
+impl Builds for PostgresBuilds {
+    fn add(&self, app_id: i64, pushed_ref: &PushedRef, user_name: &str) -> Result<Build, CoreError> {
+        let new_build = NewBuild {
+            application_id: app_id,
+            ref_name: pushed_ref.ref_name.clone(),
+            commit_sha: pushed_ref.new_sha.clone(),
+            pushed_by: user_name.to_string(),
+            status: BuildStatus::Pending.to_string(),
+            create_at: SystemTime::now(),
+        };
+
+        let saved_build = insert_into(capsule_application_builds::table)
+            .values(&new_build)
+            .get_result::<SavedBuild>(self.connection.as_ref())?;
+
+        Build::try_from(saved_build)
+    }
+
+    fn list(&self, app_id: i64) -> Result<Vec<Build>, CoreError> {
+        let saved_builds = capsule_application_builds
+            .filter(application_id.eq(app_id))
+            .order(id.desc())
+            .load::<SavedBuild>(self.connection.as_ref())?;
+
+        saved_builds.into_iter().map(Build::try_from).collect()
+    }
+
+    fn remove_all(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_builds.filter(application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::builds::{Builds, BuildStatus, PushedRef};
+    use crate::application::implementation::postgres::postgres_builds::PostgresBuilds;
+
+    fn pushed(new_sha: &str) -> PushedRef {
+        PushedRef { ref_name: "refs/heads/main".to_string(), old_sha: "0000000000000000000000000000000000000000".to_string(), new_sha: new_sha.to_string() }
+    }
+
+    #[test]
+    fn should_add_pending_build() {
+        let builds = PostgresBuilds::new(get_shared_test_db_connection());
+
+        let build = builds.add(1, &pushed("aaa"), "first_capsule_user").expect("add build failed");
+
+        assert_eq!(BuildStatus::Pending, build.status);
+        assert_eq!(("refs/heads/main", "aaa", "first_capsule_user"), (build.ref_name.as_str(), build.commit_sha.as_str(), build.pushed_by.as_str()));
+    }
+
+    #[test]
+    fn should_list_builds_latest_first() {
+        let builds = PostgresBuilds::new(get_shared_test_db_connection());
+        builds.add(1, &pushed("aaa"), "first_capsule_user").expect("add build failed");
+        builds.add(1, &pushed("bbb"), "first_capsule_user").expect("add build failed");
+        builds.add(2, &pushed("ccc"), "first_capsule_user").expect("add build failed");
+
+        let listed: Vec<String> = builds.list(1).unwrap().into_iter().map(|b| b.commit_sha).collect();
+
+        assert_eq!(vec!["bbb".to_string(), "aaa".to_string()], listed);
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::application::collaborators::{Collaborator, Collaborators, Role};
+use crate::application::implementation::postgres::models::{NewCollaborator, SavedCollaborator};
+use crate::application::implementation::postgres::schema::capsule_application_collaborators;
+use crate::application::implementation::postgres::schema::capsule_application_collaborators::dsl::*;
+use crate::CoreError;
+
+pub struct PostgresCollaborators {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresCollaborators {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresCollaborators {
+        PostgresCollaborators { connection }
+    }
+}
+
+impl TryFrom<SavedCollaborator> for Collaborator {
+    type Error = CoreError;
+
+    fn try_from(saved_collaborator: SavedCollaborator) -> Result<Self, Self::Error> {
+        let saved_role = Role::from_str(saved_collaborator.role.as_str())
+            .map_err(|e| CoreError { message: e.to_string() })?;
+
+        Ok(Collaborator {
+            application_id: saved_collaborator.application_id,
+            user_name: saved_collaborator.user_name,
+            role: saved_role,
+        })
+    }
+}
+
+impl Collaborators for PostgresCollaborators {
+    fn save(&self, collaborator: &Collaborator) -> Result<(), CoreError> {
+        let new_collaborator = NewCollaborator {
+            application_id: collaborator.application_id,
+            user_name: collaborator.user_name.clone(),
+            role: collaborator.role.to_string(),
+            create_at: SystemTime::now(),
+        };
+
+        insert_into(capsule_application_collaborators::table)
+            .values(&new_collaborator)
+            .on_conflict((application_id, user_name))
+            .do_update()
+            .set(role.eq(collaborator.role.to_string()))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn find(&self, app_id: i64, name: &str) -> Result<Option<Collaborator>, CoreError> {
+        let saved_collaborator = capsule_application_collaborators
+            .filter(application_id.eq(app_id))
+            .filter(user_name.eq(name))
+            .first::<SavedCollaborator>(self.connection.as_ref())
+            .optional()?;
+
+        saved_collaborator.map(Collaborator::try_from).transpose()
+    }
+
+    fn list(&self, app_id: i64) -> Result<Vec<Collaborator>, CoreError> {
+        let saved_collaborators = capsule_application_collaborators
+            .filter(application_id.eq(app_id))
+            .order(id.asc())
+            .load::<SavedCollaborator>(self.connection.as_ref())?;
+
+        saved_collaborators.into_iter().map(Collaborator::try_from).collect()
+    }
+
+    fn remove(&self, app_id: i64, name: &str) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_collaborators.filter(application_id.eq(app_id)).filter(user_name.eq(name)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn remove_all(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_collaborators.filter(application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::collaborators::{Collaborator, Collaborators, Role};
+    use crate::application::implementation::postgres::postgres_collaborators::PostgresCollaborators;
+
+    #[test]
+    fn should_find_saved_collaborator() {
+        let collaborators = PostgresCollaborators::new(get_shared_test_db_connection());
+        collaborators.save(&collaborator(1, "deploy_user", Role::Deployer)).expect("save collaborator failed");
+
+        let found = collaborators.find(1, "deploy_user").expect("find collaborator failed").unwrap();
+
+        assert_eq!(found, collaborator(1, "deploy_user", Role::Deployer));
+        assert!(collaborators.find(2, "deploy_user").unwrap().is_none());
+    }
+
+    #[test]
+    fn should_change_role_of_existing_collaborator() {
+        let collaborators = PostgresCollaborators::new(get_shared_test_db_connection());
+        collaborators.save(&collaborator(1, "deploy_user", Role::Deployer)).expect("save collaborator failed");
+
+        collaborators.save(&collaborator(1, "deploy_user", Role::Admin)).expect("save collaborator failed");
+
+        assert_eq!(collaborators.list(1).unwrap(), vec![collaborator(1, "deploy_user", Role::Admin)]);
+    }
+
+    #[test]
+    fn should_list_collaborators_of_application() {
+        let collaborators = PostgresCollaborators::new(get_shared_test_db_connection());
+        collaborators.save(&collaborator(1, "first_capsule_user", Role::Owner)).expect("save collaborator failed");
+        collaborators.save(&collaborator(1, "deploy_user", Role::Deployer)).expect("save collaborator failed");
+        collaborators.save(&collaborator(2, "other_user", Role::Owner)).expect("save collaborator failed");
+
+        let listed = collaborators.list(1).expect("list collaborators failed");
+
+        assert_eq!(listed, vec![collaborator(1, "first_capsule_user", Role::Owner), collaborator(1, "deploy_user", Role::Deployer)]);
+    }
+
+    #[test]
+    fn should_remove_collaborators() {
+        let collaborators = PostgresCollaborators::new(get_shared_test_db_connection());
+        collaborators.save(&collaborator(1, "first_capsule_user", Role::Owner)).expect("save collaborator failed");
+        collaborators.save(&collaborator(1, "deploy_user", Role::Deployer)).expect("save collaborator failed");
+
+        collaborators.remove(1, "deploy_user").expect("remove collaborator failed");
+        assert_eq!(collaborators.list(1).unwrap().len(), 1);
+
+        collaborators.remove_all(1).expect("remove collaborators failed");
+        assert!(collaborators.list(1).unwrap().is_empty());
+    }
+
+    fn collaborator(app_id: i64, name: &str, role: Role) -> Collaborator {
+        Collaborator { application_id: app_id, user_name: name.to_string(), role }
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{Connection, ExpressionMethods, insert_into, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::application::ApplicationError;
+use crate::application::config_vars::{ConfigChange, ConfigVarChanges, ConfigVarMap, ConfigVars, ConfigVarsCipher};
+use crate::application::implementation::postgres::models::{NewConfigChange, NewConfigVar, SavedConfigChange, SavedConfigVar};
+use crate::application::implementation::postgres::schema::{capsule_application_config_changes, capsule_application_config_vars};
+use crate::CoreError;
+
+pub struct PostgresConfigVars {
+    connection: Arc<PgConnection>,
+    cipher: ConfigVarsCipher,
+}
+
+impl PostgresConfigVars {
+    pub fn new(connection: Arc<PgConnection>, cipher: ConfigVarsCipher) -> PostgresConfigVars {
+        PostgresConfigVars { connection, cipher }
+    }
+}
+
+impl ConfigVars for PostgresConfigVars {
+    fn find_all(&self, app_id: i64) -> Result<ConfigVarMap, ApplicationError> {
+        let saved_vars = capsule_application_config_vars::table
+            .filter(capsule_application_config_vars::application_id.eq(app_id))
+            .select((capsule_application_config_vars::var_key, capsule_application_config_vars::var_value))
+            .load::<SavedConfigVar>(self.connection.as_ref())
+            .map_err(CoreError::from)?;
+
+        saved_vars.into_iter()
+            .map(|v| Ok((v.var_key.clone(), self.cipher.decrypt(app_id, &v.var_key, &v.var_value)?)))
+            .collect()
+    }
+
+    fn apply(&self, app_id: i64, changes: &ConfigVarChanges, changed_by: &str) -> Result<Vec<String>, ApplicationError> {
+        let current = self.find_all(app_id)?;
+        let changed: Vec<(&String, &Option<String>)> = changes.iter()
+            .filter(|(key, value)| current.get(*key) != value.as_ref())
+            .collect();
+        if changed.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let now = SystemTime::now();
+        self.connection.transaction::<_, diesel::result::Error, _>(|| {
+            for (key, value) in &changed {
+                match value {
+                    Some(value) => {
+                        let sealed = self.cipher.encrypt(app_id, key, value);
+                        insert_into(capsule_application_config_vars::table)
+                            .values(&NewConfigVar { application_id: app_id, var_key: key.to_string(), var_value: sealed.clone(), create_at: now })
+                            .on_conflict((capsule_application_config_vars::application_id, capsule_application_config_vars::var_key))
+                            .do_update()
+                            .set(capsule_application_config_vars::var_value.eq(sealed))
+                            .execute(self.connection.as_ref())?;
+                    }
+                    None => {
+                        diesel::delete(capsule_application_config_vars::table
+                            .filter(capsule_application_config_vars::application_id.eq(app_id))
+                            .filter(capsule_application_config_vars::var_key.eq(key.as_str())))
+                            .execute(self.connection.as_ref())?;
+                    }
+                }
+            }
+
+            let keys = changed.iter().map(|(key, _)| key.to_string()).collect();
+            insert_into(capsule_application_config_changes::table)
+                .values(&NewConfigChange { application_id: app_id, user_name: changed_by.to_string(), var_keys: keys, create_at: now })
+                .execute(self.connection.as_ref())?;
+
+            Ok(())
+        }).map_err(CoreError::from)?;
+
+        Ok(changed.into_iter().map(|(key, _)| key.clone()).collect())
+    }
+
+    fn history(&self, app_id: i64) -> Result<Vec<ConfigChange>, CoreError> {
+        let saved_changes = capsule_application_config_changes::table
+            .filter(capsule_application_config_changes::application_id.eq(app_id))
+            .order(capsule_application_config_changes::id.asc())
+            .select((
+                capsule_application_config_changes::application_id,
+                capsule_application_config_changes::user_name,
+                capsule_application_config_changes::var_keys,
+                capsule_application_config_changes::create_at,
+            ))
+            .load::<SavedConfigChange>(self.connection.as_ref())?;
+
+        Ok(saved_changes.into_iter()
+            .map(|c| ConfigChange { application_id: c.application_id, user_name: c.user_name, keys: c.var_keys, change_at: c.create_at })
+            .collect())
+    }
+
+    fn remove_all(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_config_vars::table.filter(capsule_application_config_vars::application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::config_vars::{ConfigVarChanges, ConfigVarMap, ConfigVars, ConfigVarsCipher};
+    use crate::application::implementation::postgres::postgres_config_vars::PostgresConfigVars;
+    use crate::application::implementation::postgres::schema::capsule_application_config_vars;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn config_vars() -> PostgresConfigVars {
+        PostgresConfigVars::new(get_shared_test_db_connection(), ConfigVarsCipher::from_hex(KEY).unwrap())
+    }
+
+    fn changes(entries: &[(&str, Option<&str>)]) -> ConfigVarChanges {
+        entries.iter().map(|(k, v)| (k.to_string(), v.map(|v| v.to_string()))).collect()
+    }
+
+    #[test]
+    fn should_find_applied_config_vars() {
+        let config_vars = config_vars();
+
+        config_vars.apply(1, &changes(&[("DATABASE_URL", Some("postgres://db")), ("PORT", Some("8080"))]), "first_capsule_user").expect("apply failed");
+        config_vars.apply(2, &changes(&[("PORT", Some("9090"))]), "first_capsule_user").expect("apply failed");
+
+        let expect = ConfigVarMap::from([("DATABASE_URL".to_string(), "postgres://db".to_string()), ("PORT".to_string(), "8080".to_string())]);
+        assert_eq!(expect, config_vars.find_all(1).unwrap());
+    }
+
+    #[test]
+    fn should_store_values_encrypted() {
+        let config_vars = config_vars();
+        config_vars.apply(1, &changes(&[("SECRET", Some("plain-secret"))]), "first_capsule_user").expect("apply failed");
+
+        let stored: Vec<Vec<u8>> = capsule_application_config_vars::table
+            .filter(capsule_application_config_vars::application_id.eq(1))
+            .select(capsule_application_config_vars::var_value)
+            .load(config_vars.connection.as_ref())
+            .unwrap();
+
+        assert_eq!(1, stored.len());
+        assert!(!stored[0].windows(12).any(|w| w == b"plain-secret"));
+    }
+
+    #[test]
+    fn should_update_and_unset_config_vars() {
+        let config_vars = config_vars();
+        config_vars.apply(1, &changes(&[("KEEP", Some("1")), ("DROP", Some("2"))]), "first_capsule_user").expect("apply failed");
+
+        config_vars.apply(1, &changes(&[("KEEP", Some("3")), ("DROP", None)]), "first_capsule_user").expect("apply failed");
+
+        assert_eq!(ConfigVarMap::from([("KEEP".to_string(), "3".to_string())]), config_vars.find_all(1).unwrap());
+    }
+
+    #[test]
+    fn should_record_changed_keys_only() {
+        let config_vars = config_vars();
+        config_vars.apply(1, &changes(&[("PORT", Some("8080")), ("SECRET", Some("a"))]), "first_capsule_user").expect("apply failed");
+
+        let changed = config_vars.apply(1, &changes(&[("PORT", Some("8080")), ("SECRET", Some("b")), ("MISSING", None)]), "deploy_user").expect("apply failed");
+        let unchanged = config_vars.apply(1, &changes(&[("PORT", Some("8080"))]), "deploy_user").expect("apply failed");
+
+        assert_eq!(vec!["SECRET".to_string()], changed);
+        assert!(unchanged.is_empty());
+        let history = config_vars.history(1).unwrap();
+        assert_eq!(2, history.len());
+        assert_eq!(("first_capsule_user", vec!["PORT".to_string(), "SECRET".to_string()]), (history[0].user_name.as_str(), history[0].keys.clone()));
+        assert_eq!(("deploy_user", vec!["SECRET".to_string()]), (history[1].user_name.as_str(), history[1].keys.clone()));
+    }
+
+    #[test]
+    fn should_remove_all_config_vars_of_application() {
+        let config_vars = config_vars();
+        config_vars.apply(1, &changes(&[("PORT", Some("8080"))]), "first_capsule_user").expect("apply failed");
+        config_vars.apply(2, &changes(&[("PORT", Some("9090"))]), "first_capsule_user").expect("apply failed");
+
+        config_vars.remove_all(1).expect("remove failed");
+
+        assert!(config_vars.find_all(1).unwrap().is_empty());
+        assert_eq!(1, config_vars.find_all(2).unwrap().len());
+    }
+}
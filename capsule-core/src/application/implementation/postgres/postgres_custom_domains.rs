@@ -0,0 +1,250 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{Connection, ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use diesel::dsl::{exists, select};
+use diesel::PgSortExpressionMethods;
+use diesel::result::{DatabaseErrorKind, Error};
+
+use crate::application::{ApplicationError, CustomDomain, CustomDomains};
+use crate::application::implementation::postgres::models::{NewCustomDomain, SavedCustomDomain};
+use crate::application::implementation::postgres::schema::capsule_application_domains;
+use crate::application::implementation::postgres::schema::capsule_application_domains::dsl::*;
+use crate::CoreError;
+
+pub struct PostgresCustomDomains {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresCustomDomains {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresCustomDomains {
+        PostgresCustomDomains { connection }
+    }
+}
+
+impl From<SavedCustomDomain> for CustomDomain {
+    fn from(saved_domain: SavedCustomDomain) -> Self {
+        CustomDomain {
+            application_id: saved_domain.application_id,
+            hostname: saved_domain.hostname,
+            verification_token: saved_domain.verification_token,
+            create_at: saved_domain.create_at,
+            verified_at: saved_domain.verified_at,
+            last_checked_at: saved_domain.last_checked_at,
+            last_error: saved_domain.last_error,
+        }
+    }
+}
+
+impl CustomDomains for PostgresCustomDomains {
+    fn add(&self, domain: &CustomDomain) -> Result<(), ApplicationError> {
+        let new_domain = NewCustomDomain {
+            application_id: domain.application_id,
+            hostname: domain.hostname.clone(),
+            verification_token: domain.verification_token.clone(),
+            create_at: domain.create_at,
+        };
+
+        let already_exists = || ApplicationError::CustomDomainAlreadyExists { hostname: domain.hostname.clone() };
+        self.connection.transaction::<_, ApplicationError, _>(|| {
+            // unverified claims do not hit the unique index on the host name.
+            let verified = select(exists(capsule_application_domains.filter(hostname.eq(&domain.hostname)).filter(verified_at.is_not_null())))
+                .get_result::<bool>(self.connection.as_ref())?;
+            if verified {
+                return Err(already_exists());
+            }
+
+            insert_into(capsule_application_domains::table)
+                .values(&new_domain)
+                .execute(self.connection.as_ref())
+                .map_err(|e| match e {
+                    Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => already_exists(),
+                    e => ApplicationError::from(CoreError::from(e)),
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn find_verified(&self, name: &str) -> Result<Option<CustomDomain>, CoreError> {
+        let saved_domain = capsule_application_domains
+            .filter(hostname.eq(name))
+            .filter(verified_at.is_not_null())
+            .first::<SavedCustomDomain>(self.connection.as_ref())
+            .optional()?;
+
+        Ok(saved_domain.map(CustomDomain::from))
+    }
+
+    fn list(&self, app_id: i64) -> Result<Vec<CustomDomain>, CoreError> {
+        let saved_domains = capsule_application_domains
+            .filter(application_id.eq(app_id))
+            .order(hostname.asc())
+            .load::<SavedCustomDomain>(self.connection.as_ref())?;
+
+        Ok(saved_domains.into_iter().map(CustomDomain::from).collect())
+    }
+
+    fn unverified(&self) -> Result<Vec<CustomDomain>, CoreError> {
+        let saved_domains = capsule_application_domains
+            .filter(verified_at.is_null())
+            .order((last_checked_at.asc().nulls_first(), id.asc()))
+            .load::<SavedCustomDomain>(self.connection.as_ref())?;
+
+        Ok(saved_domains.into_iter().map(CustomDomain::from).collect())
+    }
+
+    fn verified(&self) -> Result<Vec<CustomDomain>, CoreError> {
+        let saved_domains = capsule_application_domains
+            .filter(verified_at.is_not_null())
+            .order(id.asc())
+            .load::<SavedCustomDomain>(self.connection.as_ref())?;
+
+        Ok(saved_domains.into_iter().map(CustomDomain::from).collect())
+    }
+
+    fn mark_verified(&self, app_id: i64, name: &str, at: SystemTime) -> Result<bool, CoreError> {
+        self.connection.transaction::<_, CoreError, _>(|| {
+            let claims = || capsule_application_domains.filter(hostname.eq(name));
+            let taken = select(exists(claims().filter(verified_at.is_not_null()).filter(application_id.ne(app_id))))
+                .get_result::<bool>(self.connection.as_ref())?;
+            if taken {
+                diesel::delete(claims().filter(application_id.eq(app_id)))
+                    .execute(self.connection.as_ref())?;
+                return Ok(false);
+            }
+
+            diesel::update(claims().filter(application_id.eq(app_id)))
+                .set((verified_at.eq(Some(at)), last_checked_at.eq(Some(at)), last_error.eq(None::<String>)))
+                .execute(self.connection.as_ref())?;
+            diesel::delete(claims().filter(application_id.ne(app_id)).filter(verified_at.is_null()))
+                .execute(self.connection.as_ref())?;
+
+            Ok(true)
+        })
+    }
+
+    fn record_failed_check(&self, app_id: i64, name: &str, at: SystemTime, error: &str) -> Result<(), CoreError> {
+        diesel::update(capsule_application_domains.filter(application_id.eq(app_id)).filter(hostname.eq(name)))
+            .set((last_checked_at.eq(Some(at)), last_error.eq(Some(error))))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn remove(&self, app_id: i64, name: &str) -> Result<bool, CoreError> {
+        let deleted = diesel::delete(capsule_application_domains.filter(application_id.eq(app_id)).filter(hostname.eq(name)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(deleted > 0)
+    }
+
+    fn remove_all(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_domains.filter(application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::{ApplicationError, CustomDomain, CustomDomains};
+    use crate::application::implementation::postgres::postgres_custom_domains::PostgresCustomDomains;
+
+    #[test]
+    fn should_add_and_find_custom_domain() {
+        let domains = PostgresCustomDomains::new(get_shared_test_db_connection());
+        let domain = domain(1, "www.theirshop.com");
+        domains.add(&domain).expect("add custom domain failed");
+
+        let found = domains.list(1).expect("list custom domains failed");
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].verification_token, domain.verification_token);
+        assert!(!found[0].is_verified());
+        assert!(domains.list(2).unwrap().is_empty());
+        assert!(domains.find_verified("www.theirshop.com").unwrap().is_none());
+    }
+
+    #[test]
+    fn should_list_least_recently_checked_unverified_domains_first() {
+        let domains = PostgresCustomDomains::new(get_shared_test_db_connection());
+        let now = SystemTime::now();
+        for name in ["checked.theirshop.com", "new.theirshop.com", "verified.theirshop.com"] {
+            domains.add(&domain(1, name)).expect("add custom domain failed");
+        }
+        domains.record_failed_check(1, "checked.theirshop.com", now, "no TXT record found").expect("record failed check failed");
+        assert!(domains.mark_verified(1, "verified.theirshop.com", now + Duration::from_secs(1)).expect("mark verified failed"));
+
+        let unverified: Vec<String> = domains.unverified().unwrap().into_iter().map(|d| d.hostname).collect();
+
+        assert_eq!(unverified, vec!["new.theirshop.com", "checked.theirshop.com"]);
+        assert_eq!(domains.verified().unwrap().into_iter().map(|d| d.hostname).collect::<Vec<_>>(), vec!["verified.theirshop.com"]);
+        let checked = domains.list(1).unwrap().into_iter().find(|d| d.hostname == "checked.theirshop.com").unwrap();
+        assert_eq!(checked.last_error, Some("no TXT record found".to_string()));
+        assert!(domains.find_verified("verified.theirshop.com").unwrap().unwrap().is_verified());
+    }
+
+    #[test]
+    fn should_remove_custom_domains_of_application() {
+        let domains = PostgresCustomDomains::new(get_shared_test_db_connection());
+        domains.add(&domain(1, "www.theirshop.com")).expect("add custom domain failed");
+        domains.add(&domain(1, "shop.theirshop.com")).expect("add custom domain failed");
+
+        assert!(!domains.remove(2, "www.theirshop.com").unwrap());
+        assert!(domains.remove(1, "www.theirshop.com").unwrap());
+        domains.remove_all(1).expect("remove custom domains failed");
+
+        assert!(domains.list(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_not_add_custom_domain_twice() {
+        let domains = PostgresCustomDomains::new(get_shared_test_db_connection());
+        domains.add(&domain(1, "www.theirshop.com")).expect("add custom domain failed");
+
+        let result = domains.add(&domain(1, "www.theirshop.com"));
+
+        assert!(matches!(result, Err(ApplicationError::CustomDomainAlreadyExists { .. })));
+    }
+
+    #[test]
+    fn should_let_applications_claim_unverified_domain_until_one_verified_it() {
+        let domains = PostgresCustomDomains::new(get_shared_test_db_connection());
+        let now = SystemTime::now();
+        for app_id in [1, 2, 3] {
+            domains.add(&domain(app_id, "www.theirshop.com")).expect("add custom domain failed");
+        }
+
+        assert!(domains.mark_verified(2, "www.theirshop.com", now).unwrap());
+        // checked before the claim was removed, like by a verifier working off an older list.
+        assert!(!domains.mark_verified(1, "www.theirshop.com", now).unwrap());
+
+        assert_eq!(Some(2), domains.find_verified("www.theirshop.com").unwrap().map(|d| d.application_id));
+        assert!(domains.list(1).unwrap().is_empty());
+        assert!(domains.list(3).unwrap().is_empty());
+        assert!(matches!(domains.add(&domain(4, "www.theirshop.com")), Err(ApplicationError::CustomDomainAlreadyExists { .. })));
+    }
+
+    fn domain(application_id: i64, hostname: &str) -> CustomDomain {
+        CustomDomain::new(application_id, hostname, "capsuleapp.cyou", SystemTime::now()).unwrap()
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::application::deploy::{Deploy, Deploys, DeployStatus, DeployStep, DeployStepKind};
+use crate::application::implementation::postgres::models::{NewDeploy, NewDeployStep, SavedDeploy, SavedDeployStep};
+use crate::application::implementation::postgres::schema::{capsule_application_deploys, capsule_deploy_steps};
+use crate::CoreError;
+
+pub struct PostgresDeploys {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresDeploys {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresDeploys {
+        PostgresDeploys { connection }
+    }
+
+    /// Loads the steps of the saved deploys, keeping their order.
+    fn with_steps(&self, saved_deploys: Vec<SavedDeploy>) -> Result<Vec<Deploy>, CoreError> {
+        let ids: Vec<i32> = saved_deploys.iter().map(|d| d.id).collect();
+        let saved_steps = capsule_deploy_steps::table
+            .filter(capsule_deploy_steps::deploy_id.eq_any(ids))
+            .order(capsule_deploy_steps::id.asc())
+            .select((capsule_deploy_steps::deploy_id, capsule_deploy_steps::kind, capsule_deploy_steps::succeeded, capsule_deploy_steps::message, capsule_deploy_steps::create_at))
+            .load::<SavedDeployStep>(self.connection.as_ref())?;
+
+        saved_deploys.into_iter()
+            .map(|d| {
+                let steps = saved_steps.iter()
+                    .filter(|s| s.deploy_id == d.id)
+                    .map(|s| Ok(DeployStep { kind: DeployStepKind::from_str(&s.kind)?, succeeded: s.succeeded, message: s.message.clone(), create_at: s.create_at }))
+                    .collect::<Result<Vec<_>, CoreError>>()?;
+
+                Ok(Deploy {
+                    id: d.id,
+                    application_id: d.application_id,
+                    release_version: d.release_version,
+                    status: DeployStatus::from_str(&d.status)?,
+                    steps,
+                    created_by: d.created_by,
+                    create_at: d.create_at,
+                    finish_at: d.finish_at,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Deploys for PostgresDeploys {
+    fn create(&self, app_id: i64, release_version: i32, created_by: &str) -> Result<Deploy, CoreError> {
+        let new_deploy = NewDeploy {
+            application_id: app_id,
+            release_version,
+            status: DeployStatus::Pending.to_string(),
+            created_by: created_by.to_string(),
+            create_at: SystemTime::now(),
+        };
+
+        let saved_deploy = insert_into(capsule_application_deploys::table)
+            .values(&new_deploy)
+            .get_result::<SavedDeploy>(self.connection.as_ref())?;
+
+        Ok(self.with_steps(vec![saved_deploy])?.remove(0))
+    }
+
+    fn find(&self, deploy_id: i32) -> Result<Option<Deploy>, CoreError> {
+        let saved_deploy = capsule_application_deploys::table
+            .find(deploy_id)
+            .first::<SavedDeploy>(self.connection.as_ref())
+            .optional()?;
+
+        Ok(self.with_steps(saved_deploy.into_iter().collect())?.pop())
+    }
+
+    fn list(&self, app_id: i64, limit: i64) -> Result<Vec<Deploy>, CoreError> {
+        let saved_deploys = capsule_application_deploys::table
+            .filter(capsule_application_deploys::application_id.eq(app_id))
+            .order(capsule_application_deploys::id.desc())
+            .limit(limit)
+            .load::<SavedDeploy>(self.connection.as_ref())?;
+
+        self.with_steps(saved_deploys)
+    }
+
+    fn pending(&self) -> Result<Vec<Deploy>, CoreError> {
+        let saved_deploys = capsule_application_deploys::table
+            .filter(capsule_application_deploys::status.eq(DeployStatus::Pending.to_string()))
+            .order(capsule_application_deploys::id.asc())
+            .load::<SavedDeploy>(self.connection.as_ref())?;
+
+        self.with_steps(saved_deploys)
+    }
+
+    fn update_status(&self, deploy_id: i32, status: DeployStatus) -> Result<(), CoreError> {
+        let finish_at = matches!(status, DeployStatus::Succeeded | DeployStatus::Failed).then(SystemTime::now);
+
+        diesel::update(capsule_application_deploys::table.find(deploy_id))
+            .set((capsule_application_deploys::status.eq(status.to_string()), capsule_application_deploys::finish_at.eq(finish_at)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn add_step(&self, deploy_id: i32, step: &DeployStep) -> Result<(), CoreError> {
+        let new_step = NewDeployStep {
+            deploy_id,
+            kind: step.kind.to_string(),
+            succeeded: step.succeeded,
+            message: step.message.clone(),
+            create_at: step.create_at,
+        };
+
+        insert_into(capsule_deploy_steps::table)
+            .values(&new_step)
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn remove_all(&self, app_id: i64) -> Result<(), CoreError> {
+        let deploy_ids: Vec<i32> = capsule_application_deploys::table
+            .filter(capsule_application_deploys::application_id.eq(app_id))
+            .select(capsule_application_deploys::id)
+            .load(self.connection.as_ref())?;
+
+        diesel::delete(capsule_deploy_steps::table.filter(capsule_deploy_steps::deploy_id.eq_any(deploy_ids)))
+            .execute(self.connection.as_ref())?;
+        diesel::delete(capsule_application_deploys::table.filter(capsule_application_deploys::application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::deploy::{Deploys, DeployStatus, DeployStep, DeployStepKind};
+    use crate::application::implementation::postgres::postgres_deploys::PostgresDeploys;
+
+    fn step(kind: DeployStepKind, succeeded: bool) -> DeployStep {
+        DeployStep { kind, succeeded, message: format!("{} done", kind), create_at: SystemTime::now() }
+    }
+
+    #[test]
+    fn should_create_pending_deploy() {
+        let deploys = PostgresDeploys::new(get_shared_test_db_connection());
+
+        let deploy = deploys.create(1, 3, "first_capsule_user").unwrap();
+
+        assert_eq!((1, 3, DeployStatus::Pending, "first_capsule_user"), (deploy.application_id, deploy.release_version, deploy.status, deploy.created_by.as_str()));
+        assert_eq!((true, None), (deploy.steps.is_empty(), deploy.finish_at));
+        assert_eq!(vec![deploy.clone()], deploys.pending().unwrap());
+        assert_eq!(Some(deploy.clone()), deploys.find(deploy.id).unwrap());
+    }
+
+    #[test]
+    fn should_record_steps_and_finish_deploy() {
+        let deploys = PostgresDeploys::new(get_shared_test_db_connection());
+        let deploy = deploys.create(1, 3, "first_capsule_user").unwrap();
+
+        deploys.update_status(deploy.id, DeployStatus::Running).unwrap();
+        deploys.add_step(deploy.id, &step(DeployStepKind::ReleasePhase, true)).unwrap();
+        deploys.add_step(deploy.id, &step(DeployStepKind::StartProcesses, false)).unwrap();
+        assert!(deploys.find(deploy.id).unwrap().unwrap().finish_at.is_none());
+        deploys.update_status(deploy.id, DeployStatus::Failed).unwrap();
+
+        let found = deploys.find(deploy.id).unwrap().unwrap();
+        assert_eq!(DeployStatus::Failed, found.status);
+        assert!(found.finish_at.is_some());
+        assert_eq!(vec![(DeployStepKind::ReleasePhase, true, "release_phase done"), (DeployStepKind::StartProcesses, false, "start_processes done")],
+                   found.steps.iter().map(|s| (s.kind, s.succeeded, s.message.as_str())).collect::<Vec<_>>());
+        assert!(deploys.pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_list_latest_deploys_first() {
+        let deploys = PostgresDeploys::new(get_shared_test_db_connection());
+        for version in 1..=3 {
+            deploys.create(1, version, "first_capsule_user").unwrap();
+        }
+        deploys.create(2, 1, "first_capsule_user").unwrap();
+
+        assert_eq!(vec![3, 2], deploys.list(1, 2).unwrap().iter().map(|d| d.release_version).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_remove_deploys_with_steps() {
+        let deploys = PostgresDeploys::new(get_shared_test_db_connection());
+        let deploy = deploys.create(1, 1, "first_capsule_user").unwrap();
+        deploys.add_step(deploy.id, &step(DeployStepKind::ReleasePhase, true)).unwrap();
+        let other = deploys.create(2, 1, "first_capsule_user").unwrap();
+
+        deploys.remove_all(1).unwrap();
+
+        assert!(deploys.list(1, 10).unwrap().is_empty());
+        assert_eq!(None, deploys.find(deploy.id).unwrap());
+        assert_eq!(Some(other.clone()), deploys.find(other.id).unwrap());
+    }
+}
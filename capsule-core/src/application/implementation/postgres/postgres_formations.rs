@@ -0,0 +1,297 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{Connection, ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::application::ApplicationError;
+use crate::application::formation::{Formation, FormationChange, FormationEvent, Formations, OwnerLimits, OwnerLimitsRepository, ProcessScale, ProcessSize, ScaledProcess};
+use crate::application::implementation::postgres::models::{NewFormation, NewFormationEvent, SavedFormation, SavedFormationEvent, SavedOwnerLimits};
+use crate::application::implementation::postgres::schema::{capsule_application_formations, capsule_applications, capsule_formation_events, capsule_owner_limits};
+use crate::buildpack::ProcessTypes;
+use crate::CoreError;
+
+pub struct PostgresFormations {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresFormations {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresFormations {
+        PostgresFormations { connection }
+    }
+}
+
+fn to_size(size: &str) -> Result<ProcessSize, CoreError> {
+    ProcessSize::from_str(size).map_err(|e| CoreError { message: e.to_string() })
+}
+
+impl TryFrom<SavedFormationEvent> for FormationEvent {
+    type Error = CoreError;
+
+    fn try_from(saved_event: SavedFormationEvent) -> Result<Self, Self::Error> {
+        Ok(FormationEvent {
+            id: saved_event.id,
+            application_id: saved_event.application_id,
+            process_type: saved_event.process_type,
+            scale: ProcessScale { quantity: saved_event.quantity, size: to_size(&saved_event.size)? },
+            changed_by: saved_event.changed_by,
+            create_at: saved_event.create_at,
+        })
+    }
+}
+
+impl Formations for PostgresFormations {
+    fn find(&self, app_id: i64) -> Result<Formation, CoreError> {
+        let saved_formations = capsule_application_formations::table
+            .filter(capsule_application_formations::application_id.eq(app_id))
+            .select((capsule_application_formations::process_type, capsule_application_formations::quantity, capsule_application_formations::size))
+            .load::<SavedFormation>(self.connection.as_ref())?;
+
+        let processes = saved_formations.into_iter()
+            .map(|f| Ok((f.process_type, ProcessScale { quantity: f.quantity, size: to_size(&f.size)? })))
+            .collect::<Result<BTreeMap<_, _>, CoreError>>()?;
+
+        Ok(Formation::new(app_id, processes))
+    }
+
+    fn count_instances(&self, owner: &str, except_application_id: i64) -> Result<i32, CoreError> {
+        let owned: Vec<i64> = capsule_applications::table
+            .filter(capsule_applications::owner.eq(owner))
+            .filter(capsule_applications::application_id.ne(except_application_id))
+            .select(capsule_applications::application_id)
+            .load(self.connection.as_ref())?;
+
+        let instances: Option<i64> = capsule_application_formations::table
+            .filter(capsule_application_formations::application_id.eq_any(owned))
+            .select(diesel::dsl::sum(capsule_application_formations::quantity))
+            .first(self.connection.as_ref())?;
+
+        Ok(instances.unwrap_or(0) as i32)
+    }
+
+    fn scale(&self, app_id: i64, owner: &str, changes: &BTreeMap<String, FormationChange>, process_types: &ProcessTypes,
+             limits: &OwnerLimits, changed_by: &str) -> Result<Formation, ApplicationError> {
+        self.connection.transaction::<_, ApplicationError, _>(|| {
+            // the rows of the owner's applications serialize scalings counting against the same limits.
+            capsule_applications::table
+                .filter(capsule_applications::owner.eq(owner))
+                .order(capsule_applications::application_id)
+                .select(capsule_applications::application_id)
+                .for_update()
+                .load::<i64>(self.connection.as_ref())?;
+
+            let other_instances = self.count_instances(owner, app_id)?;
+            let mut formation = self.find(app_id)?;
+            let scaled = formation.scale(changes, process_types, limits, other_instances)?;
+            self.save(&formation, &scaled, changed_by)?;
+
+            Ok(formation)
+        })
+    }
+
+    fn save(&self, formation: &Formation, scaled: &[ScaledProcess], changed_by: &str) -> Result<Vec<FormationEvent>, CoreError> {
+        let now = SystemTime::now();
+        let app_id = formation.application_id();
+
+        let saved_events = self.connection.transaction::<_, diesel::result::Error, _>(|| {
+            let mut saved_events = vec![];
+            for process in scaled {
+                let size = process.scale.size.to_string();
+                insert_into(capsule_application_formations::table)
+                    .values(&NewFormation { application_id: app_id, process_type: process.process_type.clone(), quantity: process.scale.quantity, size: size.clone(), update_at: now })
+                    .on_conflict((capsule_application_formations::application_id, capsule_application_formations::process_type))
+                    .do_update()
+                    .set((
+                        capsule_application_formations::quantity.eq(process.scale.quantity),
+                        capsule_application_formations::size.eq(size.clone()),
+                        capsule_application_formations::update_at.eq(now),
+                    ))
+                    .execute(self.connection.as_ref())?;
+
+                let new_event = NewFormationEvent {
+                    application_id: app_id,
+                    process_type: process.process_type.clone(),
+                    quantity: process.scale.quantity,
+                    size,
+                    changed_by: changed_by.to_string(),
+                    create_at: now,
+                };
+                saved_events.push(insert_into(capsule_formation_events::table)
+                    .values(&new_event)
+                    .get_result::<SavedFormationEvent>(self.connection.as_ref())?);
+            }
+
+            Ok(saved_events)
+        })?;
+
+        saved_events.into_iter().map(FormationEvent::try_from).collect()
+    }
+
+    fn events_after(&self, after_id: i32, limit: i64) -> Result<Vec<FormationEvent>, CoreError> {
+        let saved_events = capsule_formation_events::table
+            .filter(capsule_formation_events::id.gt(after_id))
+            .order(capsule_formation_events::id.asc())
+            .limit(limit)
+            .load::<SavedFormationEvent>(self.connection.as_ref())?;
+
+        saved_events.into_iter().map(FormationEvent::try_from).collect()
+    }
+
+    fn remove_all(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_formations::table.filter(capsule_application_formations::application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+pub struct PostgresOwnerLimits {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresOwnerLimits {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresOwnerLimits {
+        PostgresOwnerLimits { connection }
+    }
+}
+
+impl OwnerLimitsRepository for PostgresOwnerLimits {
+    fn find(&self, owner_name: &str) -> Result<Option<OwnerLimits>, CoreError> {
+        let saved_limits = capsule_owner_limits::table
+            .find(owner_name)
+            .first::<SavedOwnerLimits>(self.connection.as_ref())
+            .optional()?;
+
+        saved_limits.map(|l| Ok(OwnerLimits { max_instances: l.max_instances, max_size: to_size(&l.max_size)? })).transpose()
+    }
+
+    fn save(&self, owner_name: &str, limits: &OwnerLimits) -> Result<(), CoreError> {
+        let saved_limits = SavedOwnerLimits { owner: owner_name.to_string(), max_instances: limits.max_instances, max_size: limits.max_size.to_string() };
+
+        insert_into(capsule_owner_limits::table)
+            .values(&saved_limits)
+            .on_conflict(capsule_owner_limits::owner)
+            .do_update()
+            .set((capsule_owner_limits::max_instances.eq(limits.max_instances), capsule_owner_limits::max_size.eq(limits.max_size.to_string())))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::formation::{Formation, FormationChange, Formations, OwnerLimits, OwnerLimitsRepository, ProcessScale, ProcessSize};
+    use crate::application::implementation::postgres::postgres_applications::PostgresApplications;
+    use crate::application::implementation::postgres::postgres_formations::{PostgresFormations, PostgresOwnerLimits};
+    use crate::application::{Application, ApplicationError, ApplicationName, Applications};
+    use crate::buildpack::ProcessTypes;
+
+    const LIMITS: OwnerLimits = OwnerLimits { max_instances: 10, max_size: ProcessSize::Large };
+
+    fn scale(formations: &PostgresFormations, app_id: i64, process_type: &str, quantity: i32) {
+        let mut formation = formations.find(app_id).unwrap();
+        let process_types = ProcessTypes::from([(process_type.to_string(), "./app".to_string())]);
+        let changes = BTreeMap::from([(process_type.to_string(), FormationChange { quantity: Some(quantity), size: Some(ProcessSize::Medium) })]);
+
+        let scaled = formation.scale(&changes, &process_types, &LIMITS, 0).unwrap();
+        formations.save(&formation, &scaled, "first_capsule_user").unwrap();
+    }
+
+    #[test]
+    fn should_save_formation_and_record_events() {
+        let formations = PostgresFormations::new(get_shared_test_db_connection());
+
+        scale(&formations, 1, "web", 2);
+        scale(&formations, 1, "web", 3);
+        scale(&formations, 1, "worker", 1);
+
+        let formation = formations.find(1).unwrap();
+        assert_eq!(BTreeMap::from([
+            ("web".to_string(), ProcessScale { quantity: 3, size: ProcessSize::Medium }),
+            ("worker".to_string(), ProcessScale { quantity: 1, size: ProcessSize::Medium }),
+        ]), *formation.processes());
+
+        let events = formations.events_after(0, 10).unwrap();
+        assert_eq!(vec![("web", 2), ("web", 3), ("worker", 1)],
+                   events.iter().map(|e| (e.process_type.as_str(), e.scale.quantity)).collect::<Vec<_>>());
+        assert_eq!(vec![events[2].clone()], formations.events_after(events[1].id, 10).unwrap());
+        assert_eq!(1, formations.events_after(0, 1).unwrap().len());
+    }
+
+    #[test]
+    fn should_count_instances_of_other_applications_of_owner() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        let formations = PostgresFormations::new(connection);
+        for (app_id, name, owner) in [(1, "first-app", "first_capsule_user"), (2, "second-app", "first_capsule_user"), (3, "third-app", "other_user")] {
+            applications.add(&Application::new(app_id, Some(ApplicationName::new(name).unwrap()), owner.to_string())).unwrap();
+        }
+        scale(&formations, 1, "web", 2);
+        scale(&formations, 2, "web", 3);
+        scale(&formations, 3, "web", 4);
+
+        assert_eq!(3, formations.count_instances("first_capsule_user", 1).unwrap());
+        assert_eq!(0, formations.count_instances("nobody", 1).unwrap());
+    }
+
+    #[test]
+    fn should_scale_within_limits_of_owner() {
+        let connection = get_shared_test_db_connection();
+        let applications = PostgresApplications::new(connection.clone());
+        let formations = PostgresFormations::new(connection);
+        for (app_id, name) in [(1, "first-app"), (2, "second-app")] {
+            applications.add(&Application::new(app_id, Some(ApplicationName::new(name).unwrap()), "first_capsule_user".to_string())).unwrap();
+        }
+        scale(&formations, 2, "web", 8);
+        let process_types = ProcessTypes::from([("web".to_string(), "./app".to_string())]);
+        let changes = |quantity| BTreeMap::from([("web".to_string(), FormationChange { quantity: Some(quantity), size: None })]);
+
+        let scaled = formations.scale(1, "first_capsule_user", &changes(2), &process_types, &LIMITS, "first_capsule_user").unwrap();
+        let rejected = formations.scale(1, "first_capsule_user", &changes(3), &process_types, &LIMITS, "first_capsule_user");
+
+        assert_eq!(2, scaled.instances());
+        assert!(matches!(rejected, Err(ApplicationError::LimitExceeded { .. })));
+        assert_eq!(2, formations.find(1).unwrap().instances());
+    }
+
+    #[test]
+    fn should_remove_formation_but_keep_events() {
+        let formations = PostgresFormations::new(get_shared_test_db_connection());
+        scale(&formations, 1, "web", 2);
+
+        formations.remove_all(1).unwrap();
+
+        assert_eq!(Formation::new(1, BTreeMap::new()), formations.find(1).unwrap());
+        assert_eq!(1, formations.events_after(0, 10).unwrap().len());
+    }
+
+    #[test]
+    fn should_save_owner_limits() {
+        let limits = PostgresOwnerLimits::new(get_shared_test_db_connection());
+        assert_eq!(None, limits.find("first_capsule_user").unwrap());
+
+        limits.save("first_capsule_user", &OwnerLimits { max_instances: 2, max_size: ProcessSize::Small }).unwrap();
+        limits.save("first_capsule_user", &LIMITS).unwrap();
+
+        assert_eq!(Some(LIMITS), limits.find("first_capsule_user").unwrap());
+    }
+}
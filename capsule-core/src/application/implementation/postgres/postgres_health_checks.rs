@@ -0,0 +1,123 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::application::health::{HealthCheckConfig, HealthChecks};
+use crate::application::implementation::postgres::models::SavedHealthCheck;
+use crate::application::implementation::postgres::schema::capsule_application_health_checks;
+use crate::CoreError;
+
+pub struct PostgresHealthChecks {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresHealthChecks {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresHealthChecks {
+        PostgresHealthChecks { connection }
+    }
+}
+
+impl From<SavedHealthCheck> for HealthCheckConfig {
+    fn from(saved_check: SavedHealthCheck) -> Self {
+        HealthCheckConfig {
+            path: saved_check.path,
+            interval_secs: saved_check.interval_secs as u32,
+            timeout_secs: saved_check.timeout_secs as u32,
+            healthy_threshold: saved_check.healthy_threshold as u32,
+            unhealthy_threshold: saved_check.unhealthy_threshold as u32,
+            deploy_timeout_secs: saved_check.deploy_timeout_secs as u32,
+        }
+    }
+}
+
+impl HealthChecks for PostgresHealthChecks {
+    fn find(&self, app_id: i64) -> Result<Option<HealthCheckConfig>, CoreError> {
+        let saved_check = capsule_application_health_checks::table
+            .find(app_id)
+            .first::<SavedHealthCheck>(self.connection.as_ref())
+            .optional()?;
+
+        Ok(saved_check.map(HealthCheckConfig::from))
+    }
+
+    fn save(&self, app_id: i64, config: &HealthCheckConfig) -> Result<(), CoreError> {
+        let saved_check = SavedHealthCheck {
+            application_id: app_id,
+            path: config.path.clone(),
+            interval_secs: config.interval_secs as i32,
+            timeout_secs: config.timeout_secs as i32,
+            healthy_threshold: config.healthy_threshold as i32,
+            unhealthy_threshold: config.unhealthy_threshold as i32,
+            update_at: SystemTime::now(),
+            deploy_timeout_secs: config.deploy_timeout_secs as i32,
+        };
+
+        insert_into(capsule_application_health_checks::table)
+            .values(&saved_check)
+            .on_conflict(capsule_application_health_checks::application_id)
+            .do_update()
+            .set((
+                capsule_application_health_checks::path.eq(&saved_check.path),
+                capsule_application_health_checks::interval_secs.eq(saved_check.interval_secs),
+                capsule_application_health_checks::timeout_secs.eq(saved_check.timeout_secs),
+                capsule_application_health_checks::healthy_threshold.eq(saved_check.healthy_threshold),
+                capsule_application_health_checks::unhealthy_threshold.eq(saved_check.unhealthy_threshold),
+                capsule_application_health_checks::update_at.eq(saved_check.update_at),
+                capsule_application_health_checks::deploy_timeout_secs.eq(saved_check.deploy_timeout_secs),
+            ))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn remove(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_health_checks::table.find(app_id))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::health::{HealthCheckConfig, HealthChecks};
+    use crate::application::implementation::postgres::postgres_health_checks::PostgresHealthChecks;
+
+    #[test]
+    fn should_save_and_update_health_check() {
+        let health_checks = PostgresHealthChecks::new(get_shared_test_db_connection());
+        assert_eq!(None, health_checks.find(1).unwrap());
+
+        health_checks.save(1, &HealthCheckConfig::default()).unwrap();
+        let config = HealthCheckConfig { path: "/health".to_string(), interval_secs: 30, timeout_secs: 5, healthy_threshold: 1, unhealthy_threshold: 5, deploy_timeout_secs: 120 };
+        health_checks.save(1, &config).unwrap();
+
+        assert_eq!(Some(config), health_checks.find(1).unwrap());
+    }
+
+    #[test]
+    fn should_remove_health_check() {
+        let health_checks = PostgresHealthChecks::new(get_shared_test_db_connection());
+        health_checks.save(1, &HealthCheckConfig::default()).unwrap();
+
+        health_checks.remove(1).unwrap();
+
+        assert_eq!(None, health_checks.find(1).unwrap());
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::application::implementation::postgres::models::{NewRedirect, SavedRedirect};
+use crate::application::implementation::postgres::schema::capsule_application_redirects;
+use crate::application::implementation::postgres::schema::capsule_application_redirects::dsl::*;
+use crate::application::redirects::{Redirect, Redirects};
+use crate::CoreError;
+
+pub struct PostgresRedirects {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresRedirects {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresRedirects {
+        PostgresRedirects { connection }
+    }
+}
+
+impl From<SavedRedirect> for Redirect {
+    fn from(saved_redirect: SavedRedirect) -> Self {
+        Redirect {
+            from_name: saved_redirect.from_name,
+            application_id: saved_redirect.application_id,
+            expire_at: saved_redirect.expire_at,
+        }
+    }
+}
+
+impl Redirects for PostgresRedirects {
+    fn add(&self, redirect: &Redirect) -> Result<(), CoreError> {
+        let new_redirect = NewRedirect {
+            from_name: redirect.from_name.clone(),
+            application_id: redirect.application_id,
+            expire_at: redirect.expire_at,
+            create_at: SystemTime::now(),
+        };
+
+        // renaming back and forth within the grace period replaces the older redirect.
+        insert_into(capsule_application_redirects::table)
+            .values(&new_redirect)
+            .on_conflict(from_name)
+            .do_update()
+            .set((application_id.eq(redirect.application_id), expire_at.eq(redirect.expire_at)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn find_active(&self, name: &str, now: SystemTime) -> Result<Option<Redirect>, CoreError> {
+        let saved_redirect = capsule_application_redirects
+            .filter(from_name.eq(name))
+            .filter(expire_at.gt(now))
+            .first::<SavedRedirect>(self.connection.as_ref())
+            .optional()?;
+
+        Ok(saved_redirect.map(Redirect::from))
+    }
+
+    fn find_expired(&self, now: SystemTime) -> Result<Vec<Redirect>, CoreError> {
+        let saved_redirects = capsule_application_redirects
+            .filter(expire_at.le(now))
+            .load::<SavedRedirect>(self.connection.as_ref())?;
+
+        Ok(saved_redirects.into_iter().map(Redirect::from).collect())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_redirects.filter(from_name.eq(name)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::implementation::postgres::postgres_redirects::PostgresRedirects;
+    use crate::application::redirects::{Redirect, Redirects};
+
+    #[test]
+    fn should_find_active_redirect() {
+        let redirects = PostgresRedirects::new(get_shared_test_db_connection());
+        let now = SystemTime::now();
+        redirects.add(&redirect("old-name", now + Duration::from_secs(60))).expect("add redirect failed");
+
+        let redirect = redirects.find_active("old-name", now).expect("find redirect failed").unwrap();
+
+        assert_eq!(redirect.application_id, 1);
+        assert!(redirects.find_active("other-name", now).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_not_find_expired_redirect_as_active() {
+        let redirects = PostgresRedirects::new(get_shared_test_db_connection());
+        let now = SystemTime::now();
+        redirects.add(&redirect("old-name", now - Duration::from_secs(60))).expect("add redirect failed");
+
+        assert!(redirects.find_active("old-name", now).unwrap().is_none());
+        assert_eq!(redirects.find_expired(now).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn should_replace_redirect_of_same_name() {
+        let redirects = PostgresRedirects::new(get_shared_test_db_connection());
+        let now = SystemTime::now();
+        redirects.add(&redirect("old-name", now - Duration::from_secs(60))).expect("add redirect failed");
+        redirects.add(&redirect("old-name", now + Duration::from_secs(60))).expect("add redirect failed");
+
+        assert!(redirects.find_active("old-name", now).unwrap().is_some());
+        assert!(redirects.find_expired(now).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_delete_redirect() {
+        let redirects = PostgresRedirects::new(get_shared_test_db_connection());
+        let now = SystemTime::now();
+        redirects.add(&redirect("old-name", now + Duration::from_secs(60))).expect("add redirect failed");
+
+        redirects.delete("old-name").expect("delete redirect failed");
+
+        assert!(redirects.find_active("old-name", now).unwrap().is_none());
+    }
+
+    fn redirect(from_name: &str, expire_at: SystemTime) -> Redirect {
+        Redirect { from_name: from_name.to_string(), application_id: 1, expire_at }
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{Connection, ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use diesel::result::{DatabaseErrorKind, Error};
+
+use crate::application::ApplicationError;
+use crate::application::config_vars::{ConfigVarMap, ConfigVarsCipher};
+use crate::application::implementation::postgres::models::{NewRelease, SavedRelease};
+use crate::application::implementation::postgres::schema::capsule_application_releases;
+use crate::application::implementation::postgres::schema::capsule_application_releases::dsl::*;
+use crate::application::releases::{Release, Releases, ReleaseSpec};
+use crate::buildpack::ProcessTypes;
+use crate::CoreError;
+
+/// Creating a release races with other releases of the same application for the next version.
+const CREATE_ATTEMPTS: usize = 3;
+
+const RELEASE_COLUMNS: (application_id, version, build_artifact, commit_sha, config_vars, description, created_by, create_at, processes) =
+    (application_id, version, build_artifact, commit_sha, config_vars, description, created_by, create_at, processes);
+
+/// Stores releases with their config vars encrypted like the config vars themselves.
+pub struct PostgresReleases {
+    connection: Arc<PgConnection>,
+    cipher: ConfigVarsCipher,
+}
+
+impl PostgresReleases {
+    pub fn new(connection: Arc<PgConnection>, cipher: ConfigVarsCipher) -> PostgresReleases {
+        PostgresReleases { connection, cipher }
+    }
+
+    fn to_release(&self, saved_release: SavedRelease) -> Result<Release, ApplicationError> {
+        let config_json = self.cipher.decrypt(saved_release.application_id, &config_vars_key(saved_release.version), &saved_release.config_vars)?;
+        let saved_config_vars: ConfigVarMap = serde_json::from_str(&config_json)
+            .map_err(|e| ApplicationError::InternalError { message: e.to_string() })?;
+        let saved_processes: ProcessTypes = serde_json::from_str(&saved_release.processes)
+            .map_err(|e| ApplicationError::InternalError { message: e.to_string() })?;
+
+        Ok(Release {
+            application_id: saved_release.application_id,
+            version: saved_release.version,
+            spec: ReleaseSpec {
+                build_artifact: saved_release.build_artifact,
+                commit_sha: saved_release.commit_sha,
+                config_vars: saved_config_vars,
+                processes: saved_processes,
+                description: saved_release.description,
+                created_by: saved_release.created_by,
+            },
+            create_at: saved_release.create_at,
+        })
+    }
+
+    fn insert_next(&self, app_id: i64, spec: &ReleaseSpec, config_json: &str, processes_json: &str) -> Result<i32, Error> {
+        self.connection.transaction(|| {
+            let latest: Option<i32> = capsule_application_releases
+                .filter(application_id.eq(app_id))
+                .select(diesel::dsl::max(version))
+                .first(self.connection.as_ref())?;
+            let next_version = latest.unwrap_or(0) + 1;
+
+            let new_release = NewRelease {
+                application_id: app_id,
+                version: next_version,
+                build_artifact: spec.build_artifact.clone(),
+                commit_sha: spec.commit_sha.clone(),
+                config_vars: self.cipher.encrypt(app_id, &config_vars_key(next_version), config_json),
+                processes: processes_json.to_string(),
+                description: spec.description.clone(),
+                created_by: spec.created_by.clone(),
+                create_at: SystemTime::now(),
+            };
+            insert_into(capsule_application_releases::table)
+                .values(&new_release)
+                .execute(self.connection.as_ref())?;
+
+            Ok(next_version)
+        })
+    }
+}
+
+fn config_vars_key(release_version: i32) -> String {
+    format!("release/{}", release_version)
+}
+
+impl Releases for PostgresReleases {
+    fn create(&self, app_id: i64, spec: &ReleaseSpec) -> Result<Release, ApplicationError> {
+        let config_json = serde_json::to_string(&spec.config_vars)
+            .map_err(|e| ApplicationError::InternalError { message: e.to_string() })?;
+        let processes_json = serde_json::to_string(&spec.processes)
+            .map_err(|e| ApplicationError::InternalError { message: e.to_string() })?;
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.insert_next(app_id, spec, &config_json, &processes_json) {
+                Ok(created_version) => {
+                    return self.find(app_id, created_version)?
+                        .ok_or_else(|| ApplicationError::InternalError { message: format!("release v{} vanished", created_version) });
+                }
+                Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) if attempts < CREATE_ATTEMPTS => continue,
+                Err(e) => return Err(CoreError::from(e).into()),
+            }
+        }
+    }
+
+    fn find(&self, app_id: i64, release_version: i32) -> Result<Option<Release>, ApplicationError> {
+        let saved_release = capsule_application_releases
+            .filter(application_id.eq(app_id))
+            .filter(version.eq(release_version))
+            .select(RELEASE_COLUMNS)
+            .first::<SavedRelease>(self.connection.as_ref())
+            .optional()
+            .map_err(CoreError::from)?;
+
+        saved_release.map(|r| self.to_release(r)).transpose()
+    }
+
+    fn latest(&self, app_id: i64) -> Result<Option<Release>, ApplicationError> {
+        let saved_release = capsule_application_releases
+            .filter(application_id.eq(app_id))
+            .order(version.desc())
+            .select(RELEASE_COLUMNS)
+            .first::<SavedRelease>(self.connection.as_ref())
+            .optional()
+            .map_err(CoreError::from)?;
+
+        saved_release.map(|r| self.to_release(r)).transpose()
+    }
+
+    fn list(&self, app_id: i64) -> Result<Vec<Release>, ApplicationError> {
+        let saved_releases = capsule_application_releases
+            .filter(application_id.eq(app_id))
+            .order(version.desc())
+            .select(RELEASE_COLUMNS)
+            .load::<SavedRelease>(self.connection.as_ref())
+            .map_err(CoreError::from)?;
+
+        saved_releases.into_iter().map(|r| self.to_release(r)).collect()
+    }
+
+    fn remove_all(&self, app_id: i64) -> Result<(), CoreError> {
+        diesel::delete(capsule_application_releases.filter(application_id.eq(app_id)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::application::config_vars::{ConfigVarMap, ConfigVarsCipher};
+    use crate::application::implementation::postgres::postgres_releases::PostgresReleases;
+    use crate::application::releases::{Releases, ReleaseSpec, rollback};
+    use crate::buildpack::ProcessTypes;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn releases() -> PostgresReleases {
+        PostgresReleases::new(get_shared_test_db_connection(), ConfigVarsCipher::from_hex(KEY).unwrap())
+    }
+
+    fn spec(commit_sha: &str) -> ReleaseSpec {
+        ReleaseSpec {
+            build_artifact: format!("registry.capsuleapp.cyou/app:{}", commit_sha),
+            commit_sha: commit_sha.to_string(),
+            config_vars: ConfigVarMap::from([("SECRET".to_string(), commit_sha.to_string())]),
+            processes: ProcessTypes::from([("web".to_string(), format!("./app --commit {}", commit_sha))]),
+            description: format!("Deploy {}", commit_sha),
+            created_by: "first_capsule_user".to_string(),
+        }
+    }
+
+    #[test]
+    fn should_number_releases_per_application() {
+        let releases = releases();
+
+        let first = releases.create(1, &spec("aaa")).expect("create release failed");
+        let second = releases.create(1, &spec("bbb")).expect("create release failed");
+        let other = releases.create(2, &spec("ccc")).expect("create release failed");
+
+        assert_eq!((1, 2, 1), (first.version, second.version, other.version));
+        assert_eq!(spec("bbb"), second.spec);
+    }
+
+    #[test]
+    fn should_list_releases_latest_first() {
+        let releases = releases();
+        releases.create(1, &spec("aaa")).expect("create release failed");
+        releases.create(1, &spec("bbb")).expect("create release failed");
+
+        let listed: Vec<(i32, String)> = releases.list(1).unwrap().into_iter().map(|r| (r.version, r.spec.commit_sha)).collect();
+
+        assert_eq!(vec![(2, "bbb".to_string()), (1, "aaa".to_string())], listed);
+    }
+
+    #[test]
+    fn should_find_latest_release() {
+        let releases = releases();
+        assert_eq!(None, releases.latest(1).unwrap());
+
+        releases.create(1, &spec("aaa")).expect("create release failed");
+        releases.create(1, &spec("bbb")).expect("create release failed");
+        releases.create(2, &spec("ccc")).expect("create release failed");
+
+        let latest = releases.latest(1).unwrap().unwrap();
+        assert_eq!((2, spec("bbb")), (latest.version, latest.spec));
+    }
+
+    #[test]
+    fn should_roll_back_to_older_release_as_new_release() {
+        let releases = releases();
+        releases.create(1, &spec("aaa")).expect("create release failed");
+        releases.create(1, &spec("bbb")).expect("create release failed");
+
+        let rolled_back = rollback(&releases, 1, 1, "deploy_user").unwrap().unwrap();
+
+        assert_eq!(3, rolled_back.version);
+        assert_eq!(("aaa", "Rollback to v1"), (rolled_back.spec.commit_sha.as_str(), rolled_back.spec.description.as_str()));
+        assert_eq!(spec("aaa").config_vars, releases.find(1, 3).unwrap().unwrap().spec.config_vars);
+        assert_eq!(spec("bbb"), releases.find(1, 2).unwrap().unwrap().spec);
+    }
+
+    #[test]
+    fn should_remove_all_releases_of_application() {
+        let releases = releases();
+        releases.create(1, &spec("aaa")).expect("create release failed");
+        releases.create(2, &spec("bbb")).expect("create release failed");
+
+        releases.remove_all(1).expect("remove releases failed");
+
+        assert!(releases.list(1).unwrap().is_empty());
+        assert_eq!(1, releases.list(2).unwrap().len());
+    }
+}
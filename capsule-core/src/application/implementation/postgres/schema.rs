@@ -5,5 +5,175 @@ table! {
         application_name -> Varchar,
         owner -> Varchar,
         create_at -> Timestamp,
+        version -> Int4,
+    }
+}
+
+table! {
+    capsule_application_health_checks (application_id) {
+        application_id -> BigInt,
+        path -> Varchar,
+        interval_secs -> Int4,
+        timeout_secs -> Int4,
+        healthy_threshold -> Int4,
+        unhealthy_threshold -> Int4,
+        update_at -> Timestamp,
+        deploy_timeout_secs -> Int4,
+    }
+}
+
+table! {
+    capsule_application_redirects (id) {
+        id -> Int4,
+        from_name -> Varchar,
+        application_id -> BigInt,
+        expire_at -> Timestamp,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_application_collaborators (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        user_name -> Varchar,
+        role -> Varchar,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_application_config_vars (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        var_key -> Varchar,
+        var_value -> Bytea,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_application_config_changes (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        user_name -> Varchar,
+        var_keys -> Array<Text>,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_application_releases (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        version -> Int4,
+        build_artifact -> Varchar,
+        commit_sha -> Varchar,
+        config_vars -> Bytea,
+        description -> Varchar,
+        created_by -> Varchar,
+        create_at -> Timestamp,
+        processes -> Text,
+    }
+}
+
+table! {
+    capsule_application_builds (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        ref_name -> Varchar,
+        commit_sha -> Varchar,
+        pushed_by -> Varchar,
+        status -> Varchar,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_application_deploys (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        release_version -> Int4,
+        status -> Varchar,
+        created_by -> Varchar,
+        create_at -> Timestamp,
+        finish_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    capsule_application_formations (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        process_type -> Varchar,
+        quantity -> Int4,
+        size -> Varchar,
+        update_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_formation_events (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        process_type -> Varchar,
+        quantity -> Int4,
+        size -> Varchar,
+        changed_by -> Varchar,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_owner_limits (owner) {
+        owner -> Varchar,
+        max_instances -> Int4,
+        max_size -> Varchar,
+    }
+}
+
+table! {
+    capsule_deploy_steps (id) {
+        id -> Int4,
+        deploy_id -> Int4,
+        kind -> Varchar,
+        succeeded -> Bool,
+        message -> Text,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_application_domains (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        hostname -> Varchar,
+        verification_token -> Varchar,
+        verified_at -> Nullable<Timestamp>,
+        last_checked_at -> Nullable<Timestamp>,
+        last_error -> Nullable<Text>,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_application_certificates (id) {
+        id -> Int4,
+        application_id -> BigInt,
+        hostname -> Varchar,
+        certificate_chain -> Bytea,
+        private_key -> Bytea,
+        not_after -> Timestamp,
+        issued_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_acme_accounts (id) {
+        id -> Int4,
+        directory_uri -> Varchar,
+        account_uri -> Varchar,
+        private_key -> Bytea,
+        create_at -> Timestamp,
     }
 }
@@ -0,0 +1,567 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::application::{ApplicationError, CnameRecord, DnsRecord, DomainNameService, RecordType, ZONE_APEX};
+use crate::application::implementation::dns_message::{CLASS_ANY, CLASS_IN, CLASS_NONE, encode_message, encode_rdata, FLAGS_QUERY, FLAGS_UPDATE, ParsedMessage, rcode_name, RCODE_NOERROR, RCODE_NXDOMAIN, RCODE_NXRRSET, read_tcp_message, Record, record_type, TsigError, TsigKey, type_code, TYPE_AXFR, TYPE_SOA, TYPE_TSIG, write_tcp_message};
+
+/// Keeps the records of `zone` by sending RFC 2136 updates, signed with a TSIG key, to the
+/// primary name server of the zone, such as BIND or Knot. Listing the records transfers the
+/// zone, which the key must be allowed to do as well.
+pub struct Rfc2136DomainNameService {
+    /// Like `192.0.2.53:53`.
+    pub server: SocketAddr,
+    pub zone: String,
+    pub key: TsigKey,
+    pub cname_target: String,
+    pub ttl: u32,
+    /// How long to wait for the answer to an update.
+    pub timeout: Duration,
+}
+
+fn dns_error(message: impl ToString) -> ApplicationError {
+    ApplicationError::DomainNameError { message: message.to_string() }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+impl Rfc2136DomainNameService {
+    fn zone(&self) -> &str {
+        self.zone.trim_end_matches('.')
+    }
+
+    fn domain_name(&self, cname: &str) -> String {
+        format!("{}.{}", cname, self.zone())
+    }
+
+    fn owner_name(&self, name: &str) -> String {
+        if name == ZONE_APEX { self.zone().to_string() } else { self.domain_name(name) }
+    }
+
+    /// Name of a record relative to the zone, `None` for names outside of it.
+    fn relative_name(&self, owner: &str) -> Option<String> {
+        let zone = self.zone().to_ascii_lowercase();
+        if owner == zone {
+            return Some(ZONE_APEX.to_string());
+        }
+        owner.strip_suffix(&zone).and_then(|n| n.strip_suffix('.')).map(|n| n.to_string())
+    }
+
+    fn record(&self, record: &DnsRecord, class: u16) -> Result<Record, ApplicationError> {
+        let rdata = encode_rdata(record.record_type, &record.target).map_err(|message| ApplicationError::InvalidDnsRecord { message })?;
+        let ttl = if class == CLASS_IN { record.ttl } else { 0 };
+        Ok(Record { name: self.owner_name(&record.name), rtype: type_code(record.record_type), class, ttl, rdata })
+    }
+
+    /// Deletes every record of the name and type in an update, or requires one to exist in a
+    /// prerequisite.
+    fn rrset(&self, name: &str, record_type: RecordType) -> Record {
+        Record { name: self.owner_name(name), rtype: type_code(record_type), class: CLASS_ANY, ttl: 0, rdata: Vec::new() }
+    }
+
+    fn signed_request(&self, flags: u16, name: &str, qtype: u16, prerequisites: &[Record], updates: &[Record]) -> Result<(u16, Vec<u8>, Vec<u8>), ApplicationError> {
+        let id = rand::random::<u16>();
+        let mut request = encode_message(id, flags, name, qtype, prerequisites, updates).map_err(dns_error)?;
+        let request_mac = self.key.sign(&mut request, None, unix_time()).map_err(dns_error)?;
+        Ok((id, request, request_mac))
+    }
+
+    /// Returns the response code, `NXRRSET` when a prerequisite did not hold.
+    fn update(&self, prerequisites: &[Record], updates: &[Record]) -> Result<u16, ApplicationError> {
+        let (id, request, request_mac) = self.signed_request(FLAGS_UPDATE, self.zone(), TYPE_SOA, prerequisites, updates)?;
+
+        let local: SocketAddr = if self.server.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let socket = UdpSocket::bind(local).map_err(dns_error)?;
+        socket.set_read_timeout(Some(self.timeout)).map_err(dns_error)?;
+        socket.connect(self.server).map_err(dns_error)?;
+        socket.send(&request).map_err(dns_error)?;
+
+        let mut buffer = [0u8; 4096];
+        loop {
+            let len = socket.recv(&mut buffer).map_err(|e| dns_error(format!("no answer from {} to update of {}: {}", self.server, self.zone(), e)))?;
+            let response = &buffer[..len];
+            // anything else is a late answer to an earlier update.
+            match ParsedMessage::parse(response) {
+                Ok(parsed) if parsed.id == id && parsed.is_response() && parsed.is_update() => {
+                    if parsed.is_truncated() {
+                        return Err(dns_error(format!("answer of {} to update of {} was truncated", self.server, self.zone())));
+                    }
+                    let verified = self.key.verify(response, Some(&request_mac), unix_time());
+                    return self.check(&parsed, verified, &[RCODE_NOERROR, RCODE_NXRRSET]).map(|(rcode, _)| rcode);
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Returns the response code and MAC of a response, if it is one of `accepted` and signed.
+    fn check(&self, parsed: &ParsedMessage, verified: Result<Vec<u8>, TsigError>, accepted: &[u16]) -> Result<(u16, Vec<u8>), ApplicationError> {
+        if !accepted.contains(&parsed.rcode()) {
+            let reason = match verified {
+                Err(TsigError::Rejected { error }) => format!(" ({})", rcode_name(error)),
+                _ => String::new(),
+            };
+            return Err(dns_error(format!("request to {} for {} failed with {}{}", self.server, self.zone(), rcode_name(parsed.rcode()), reason)));
+        }
+
+        let mac = verified.map_err(|e| dns_error(format!("answer of {} for {} is not trusted: {}", self.server, self.zone(), e)))?;
+        Ok((parsed.rcode(), mac))
+    }
+
+    fn connect(&self) -> Result<TcpStream, ApplicationError> {
+        let stream = TcpStream::connect_timeout(&self.server, self.timeout).map_err(|e| dns_error(format!("connect to {} error: {}", self.server, e)))?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(dns_error)?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(dns_error)?;
+        Ok(stream)
+    }
+
+    fn records(&self, parsed: &ParsedMessage) -> Result<Vec<DnsRecord>, ApplicationError> {
+        let mut records = Vec::new();
+        for answer in parsed.answers.iter().filter(|a| a.class == CLASS_IN) {
+            if let (Some(record_type), Some(name)) = (record_type(answer.rtype), self.relative_name(&answer.name)) {
+                let target = parsed.rdata_text(answer).map_err(dns_error)?;
+                records.push(DnsRecord::new(&name, record_type, &target, answer.ttl));
+            }
+        }
+        Ok(records)
+    }
+
+    fn query(&self, name: &str, record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+        let (id, request, request_mac) = self.signed_request(FLAGS_QUERY, &self.owner_name(name), type_code(record_type), &[], &[])?;
+        let mut stream = self.connect()?;
+        write_tcp_message(&mut stream, &request).map_err(dns_error)?;
+
+        let response = read_tcp_message(&mut stream).map_err(dns_error)?;
+        let parsed = ParsedMessage::parse(&response).map_err(dns_error)?;
+        if parsed.id != id || !parsed.is_response() {
+            return Err(dns_error(format!("{} answered another query", self.server)));
+        }
+        self.check(&parsed, self.key.verify(&response, Some(&request_mac), unix_time()), &[RCODE_NOERROR, RCODE_NXDOMAIN])?;
+
+        Ok(self.records(&parsed)?.into_iter().filter(|r| r.has_name(name, record_type)).collect())
+    }
+
+    /// Transfers the zone, which ends with the SOA record it starts with. A server may sign only
+    /// every few messages, the last one is always signed.
+    fn transfer(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+        let (id, request, request_mac) = self.signed_request(FLAGS_QUERY, self.zone(), TYPE_AXFR, &[], &[])?;
+        let mut stream = self.connect()?;
+        write_tcp_message(&mut stream, &request).map_err(dns_error)?;
+
+        let mut records = Vec::new();
+        let mut prior_mac: Option<Vec<u8>> = None;
+        let mut unsigned = Vec::new();
+        let mut soa_records = 0;
+        while soa_records < 2 {
+            let response = read_tcp_message(&mut stream).map_err(dns_error)?;
+            let parsed = ParsedMessage::parse(&response).map_err(dns_error)?;
+            if parsed.id != id || !parsed.is_response() {
+                return Err(dns_error(format!("{} answered another query", self.server)));
+            }
+
+            let signed = parsed.additional.last().is_some_and(|r| r.rtype == TYPE_TSIG);
+            match &prior_mac {
+                None => {
+                    let verified = self.key.verify(&response, Some(&request_mac), unix_time());
+                    prior_mac = Some(self.check(&parsed, verified, &[RCODE_NOERROR])?.1);
+                }
+                Some(mac) if signed => {
+                    let verified = self.key.verify_continuation(&response, mac, &unsigned, unix_time());
+                    prior_mac = Some(self.check(&parsed, verified, &[RCODE_NOERROR])?.1);
+                    unsigned.clear();
+                }
+                Some(_) => {
+                    if parsed.rcode() != RCODE_NOERROR {
+                        return Err(dns_error(format!("transfer of {} failed with {}", self.zone(), rcode_name(parsed.rcode()))));
+                    }
+                    unsigned.extend_from_slice(&response);
+                }
+            }
+
+            soa_records += parsed.answers.iter().filter(|a| a.rtype == TYPE_SOA).count();
+            records.extend(self.records(&parsed)?);
+        }
+
+        if !unsigned.is_empty() {
+            return Err(dns_error(format!("transfer of {} from {} ended with an unsigned message", self.zone(), self.server)));
+        }
+        Ok(records)
+    }
+}
+
+impl DomainNameService for Rfc2136DomainNameService {
+    fn add_cname_record(&self, cname: &str) -> Result<CnameRecord, ApplicationError> {
+        self.add_record(&DnsRecord::new(cname, RecordType::Cname, &self.cname_target, self.ttl))?;
+
+        Ok(CnameRecord { domain_name: self.domain_name(cname) })
+    }
+
+    fn remove_cname_record(&self, cname: &str) -> Result<(), ApplicationError> {
+        self.update(&[], &[self.rrset(cname, RecordType::Cname)]).map(|_| ())
+    }
+
+    fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+        self.transfer()
+    }
+
+    fn get_records(&self, name: &str, record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+        self.query(name, record_type)
+    }
+
+    /// Name servers ignore records added twice, so the update alone keeps adds idempotent.
+    fn add_record(&self, record: &DnsRecord) -> Result<(), ApplicationError> {
+        record.validate()?;
+
+        let mut updates = Vec::with_capacity(2);
+        if record.record_type == RecordType::Cname {
+            updates.push(self.rrset(&record.name, RecordType::Cname));
+        }
+        updates.push(self.record(record, CLASS_IN)?);
+
+        self.update(&[], &updates).map(|_| ())
+    }
+
+    fn update_record(&self, record: &DnsRecord) -> Result<(), ApplicationError> {
+        record.validate()?;
+
+        let prerequisites = [self.rrset(&record.name, record.record_type)];
+        let updates = [self.rrset(&record.name, record.record_type), self.record(record, CLASS_IN)?];
+        match self.update(&prerequisites, &updates)? {
+            RCODE_NXRRSET => Err(ApplicationError::DnsRecordNotFound { name: record.name.clone(), record_type: record.record_type }),
+            _ => Ok(()),
+        }
+    }
+
+    fn delete_record(&self, record: &DnsRecord) -> Result<(), ApplicationError> {
+        self.update(&[], &[self.record(record, CLASS_NONE)?]).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::application::{ApplicationError, DnsRecord, DomainNameService, RecordType};
+    use crate::application::implementation::dns_message::{CLASS_ANY, CLASS_IN, CLASS_NONE, encode_message, encode_rdata, FLAG_RESPONSE, ParsedMessage, RCODE_NOERROR, RCODE_NOTAUTH, RCODE_NOTZONE, RCODE_NXDOMAIN, RCODE_NXRRSET, Record, record_type, TsigAlgorithm, TsigKey, type_code, TYPE_AXFR, TYPE_SOA};
+    use crate::application::implementation::rfc2136_domain_name_service::Rfc2136DomainNameService;
+
+    const SECRET: &str = "c2VjcmV0LW9mLXRoZS1jYXBzdWxlLWtleQ==";
+    const ZONE: &str = "capsuleapp.cyou";
+
+    type Zone = Arc<Mutex<BTreeMap<(String, RecordType), Vec<(u32, String)>>>>;
+
+    /// Serves `capsuleapp.cyou` like an authoritative name server: updates over UDP, queries and
+    /// zone transfers over TCP on the same port.
+    struct TestNameServer {
+        address: SocketAddr,
+        zone: Zone,
+    }
+
+    impl TestNameServer {
+        fn start(records: &[(&str, RecordType, &str)]) -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let address = socket.local_addr().unwrap();
+            let listener = TcpListener::bind(address).unwrap();
+            let zone: Zone = Arc::new(Mutex::new(BTreeMap::new()));
+            for (name, record_type, target) in records {
+                zone.lock().unwrap().entry((name.to_string(), *record_type)).or_default().push((300, target.to_string()));
+            }
+
+            let served = zone.clone();
+            thread::spawn(move || {
+                let mut buffer = [0u8; 4096];
+                while let Ok((len, peer)) = socket.recv_from(&mut buffer) {
+                    for response in answer(&served, &buffer[..len]) {
+                        socket.send_to(&response, peer).unwrap();
+                    }
+                }
+            });
+            let served = zone.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream: TcpStream = stream.unwrap();
+                    let mut len = [0u8; 2];
+                    stream.read_exact(&mut len).unwrap();
+                    let mut request = vec![0u8; u16::from_be_bytes(len) as usize];
+                    stream.read_exact(&mut request).unwrap();
+                    for response in answer(&served, &request) {
+                        stream.write_all(&(response.len() as u16).to_be_bytes()).unwrap();
+                        stream.write_all(&response).unwrap();
+                    }
+                }
+            });
+
+            TestNameServer { address, zone }
+        }
+
+        fn records(&self, name: &str, record_type: RecordType) -> Vec<(u32, String)> {
+            self.zone.lock().unwrap().get(&(name.to_string(), record_type)).cloned().unwrap_or_default()
+        }
+
+        fn names(&self) -> Vec<String> {
+            self.zone.lock().unwrap().keys().map(|(name, _)| name.clone()).collect()
+        }
+    }
+
+    fn key() -> TsigKey {
+        TsigKey::from_base64("capsule-key", TsigAlgorithm::HmacSha256, SECRET).unwrap()
+    }
+
+    fn answer(zone: &Zone, request: &[u8]) -> Vec<Vec<u8>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let parsed = ParsedMessage::parse(request).unwrap();
+        let question = &parsed.questions[0];
+        let respond = |rcode: u16, answers: &[Record]| encode_message(parsed.id, parsed.flags | FLAG_RESPONSE | rcode, &question.name, question.rtype, answers, &[]).unwrap();
+
+        let request_mac = match key().verify(request, None, now) {
+            Ok(mac) => mac,
+            Err(e) => {
+                let mut response = respond(RCODE_NOTAUTH, &[]);
+                key().sign_with_error(&mut response, None, now, e.error_code()).unwrap();
+                return vec![response];
+            }
+        };
+        if !question.name.ends_with(ZONE) {
+            let mut response = respond(if parsed.is_update() { RCODE_NOTZONE } else { RCODE_NOTAUTH }, &[]);
+            key().sign(&mut response, Some(&request_mac), now).unwrap();
+            return vec![response];
+        }
+
+        let mut records = zone.lock().unwrap();
+        let as_record = |(name, record_type): &(String, RecordType), (ttl, target): &(u32, String)| Record {
+            name: name.clone(),
+            rtype: type_code(*record_type),
+            class: CLASS_IN,
+            ttl: *ttl,
+            rdata: encode_rdata(*record_type, target).unwrap(),
+        };
+
+        if question.rtype == TYPE_AXFR {
+            let soa = Record { name: ZONE.to_string(), rtype: TYPE_SOA, class: CLASS_IN, ttl: 300, rdata: vec![0; 22] };
+            let all: Vec<Record> = records.iter().flat_map(|(key, values)| values.iter().map(move |value| as_record(key, value))).collect();
+            let mut first = respond(RCODE_NOERROR, std::slice::from_ref(&soa));
+            let first_mac = key().sign(&mut first, Some(&request_mac), now).unwrap();
+            let unsigned = respond(RCODE_NOERROR, &all);
+            let mut last = respond(RCODE_NOERROR, &[soa]);
+            key().sign_continuation(&mut last, &first_mac, &unsigned, now).unwrap();
+            return vec![first, unsigned, last];
+        }
+
+        let mut response = if parsed.is_update() {
+            let missing = parsed.answers.iter().any(|p| record_type(p.rtype).is_none_or(|t| !records.contains_key(&(p.name.clone(), t))));
+            if missing {
+                respond(RCODE_NXRRSET, &[])
+            } else {
+                for update in &parsed.authority {
+                    let key = (update.name.clone(), record_type(update.rtype).unwrap());
+                    let target = if update.rdata.is_empty() { String::new() } else { parsed.rdata_text(update).unwrap() };
+                    let values = records.entry(key.clone()).or_default();
+                    match update.class {
+                        CLASS_ANY => values.clear(),
+                        CLASS_NONE => values.retain(|(_, t)| *t != target),
+                        _ => {
+                            values.retain(|(_, t)| *t != target);
+                            values.push((update.ttl, target));
+                        }
+                    }
+                    if values.is_empty() {
+                        records.remove(&key);
+                    }
+                }
+                respond(RCODE_NOERROR, &[])
+            }
+        } else {
+            let key = (question.name.clone(), record_type(question.rtype).unwrap());
+            match records.get(&key) {
+                Some(values) => respond(RCODE_NOERROR, &values.iter().map(|value| as_record(&key, value)).collect::<Vec<_>>()),
+                None if records.keys().any(|(name, _)| *name == question.name) => respond(RCODE_NOERROR, &[]),
+                None => respond(RCODE_NXDOMAIN, &[]),
+            }
+        };
+        key().sign(&mut response, Some(&request_mac), now).unwrap();
+        vec![response]
+    }
+
+    fn service(server: SocketAddr, secret: &str) -> Rfc2136DomainNameService {
+        Rfc2136DomainNameService {
+            server,
+            zone: "capsuleapp.cyou.".to_string(),
+            key: TsigKey::from_base64("capsule-key", TsigAlgorithm::HmacSha256, secret).unwrap(),
+            cname_target: "router.capsuleapp.cyou.".to_string(),
+            ttl: 300,
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn should_add_cname_record() {
+        let server = TestNameServer::start(&[("existing-app.capsuleapp.cyou", RecordType::Cname, "router.capsuleapp.cyou")]);
+
+        let record = service(server.address, SECRET).add_cname_record("first-capsule-application").expect("add cname record failed");
+
+        assert_eq!("first-capsule-application.capsuleapp.cyou", record.domain_name);
+        assert_eq!(vec![(300, "router.capsuleapp.cyou".to_string())], server.records("first-capsule-application.capsuleapp.cyou", RecordType::Cname));
+        assert_eq!(vec!["existing-app.capsuleapp.cyou", "first-capsule-application.capsuleapp.cyou"], server.names());
+    }
+
+    #[test]
+    fn should_replace_existing_cname_record() {
+        let server = TestNameServer::start(&[("first-capsule-application.capsuleapp.cyou", RecordType::Cname, "old-router.capsuleapp.cyou")]);
+
+        service(server.address, SECRET).add_cname_record("first-capsule-application").unwrap();
+
+        assert_eq!(vec![(300, "router.capsuleapp.cyou".to_string())], server.records("first-capsule-application.capsuleapp.cyou", RecordType::Cname));
+    }
+
+    #[test]
+    fn should_remove_only_cname_record_of_application() {
+        let server = TestNameServer::start(&[
+            ("first-capsule-application.capsuleapp.cyou", RecordType::Cname, "router.capsuleapp.cyou"),
+            ("existing-app.capsuleapp.cyou", RecordType::Cname, "router.capsuleapp.cyou"),
+        ]);
+
+        service(server.address, SECRET).remove_cname_record("first-capsule-application").expect("remove cname record failed");
+
+        assert_eq!(vec!["existing-app.capsuleapp.cyou"], server.names());
+    }
+
+    #[test]
+    fn should_succeed_removing_missing_cname_record() {
+        let server = TestNameServer::start(&[]);
+
+        assert!(service(server.address, SECRET).remove_cname_record("gone-app").is_ok());
+    }
+
+    #[test]
+    fn should_list_records_of_zone() {
+        let server = TestNameServer::start(&[
+            ("capsuleapp.cyou", RecordType::A, "203.0.113.10"),
+            ("first-app.capsuleapp.cyou", RecordType::Cname, "router.capsuleapp.cyou"),
+            ("_acme-challenge.first-app.capsuleapp.cyou", RecordType::Txt, "gfj9Xq...Rg85nM"),
+        ]);
+
+        let records = service(server.address, SECRET).list_records().expect("list records failed");
+
+        assert_eq!(vec![
+            DnsRecord::new("_acme-challenge.first-app", RecordType::Txt, "gfj9Xq...Rg85nM", 300),
+            DnsRecord::new("@", RecordType::A, "203.0.113.10", 300),
+            DnsRecord::new("first-app", RecordType::Cname, "router.capsuleapp.cyou", 300),
+        ], records);
+    }
+
+    #[test]
+    fn should_get_records_of_name_and_type() {
+        let server = TestNameServer::start(&[
+            ("first-app.capsuleapp.cyou", RecordType::Txt, "first"),
+            ("first-app.capsuleapp.cyou", RecordType::Txt, "second"),
+            ("first-app.capsuleapp.cyou", RecordType::A, "203.0.113.10"),
+        ]);
+        let service = service(server.address, SECRET);
+
+        let texts = service.get_records("first-app", RecordType::Txt).unwrap();
+
+        assert_eq!(vec![DnsRecord::new("first-app", RecordType::Txt, "first", 300), DnsRecord::new("first-app", RecordType::Txt, "second", 300)], texts);
+        assert!(service.get_records("first-app", RecordType::Aaaa).unwrap().is_empty());
+        assert!(service.get_records("gone-app", RecordType::A).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_add_record_once() {
+        let server = TestNameServer::start(&[]);
+        let service = service(server.address, SECRET);
+        let record = DnsRecord::new("first-app", RecordType::Aaaa, "2001:db8::10", 120);
+
+        service.add_record(&record).unwrap();
+        service.add_record(&record).unwrap();
+
+        assert_eq!(vec![(120, "2001:db8::10".to_string())], server.records("first-app.capsuleapp.cyou", RecordType::Aaaa));
+    }
+
+    #[test]
+    fn should_update_every_record_of_name_and_type() {
+        let server = TestNameServer::start(&[("first-app.capsuleapp.cyou", RecordType::A, "203.0.113.10"), ("first-app.capsuleapp.cyou", RecordType::A, "203.0.113.11")]);
+
+        service(server.address, SECRET).update_record(&DnsRecord::new("first-app", RecordType::A, "203.0.113.20", 60)).expect("update record failed");
+
+        assert_eq!(vec![(60, "203.0.113.20".to_string())], server.records("first-app.capsuleapp.cyou", RecordType::A));
+    }
+
+    #[test]
+    fn should_get_not_found_updating_missing_record() {
+        let server = TestNameServer::start(&[]);
+
+        let result = service(server.address, SECRET).update_record(&DnsRecord::new("first-app", RecordType::A, "203.0.113.20", 60));
+
+        assert!(matches!(result, Err(ApplicationError::DnsRecordNotFound { name, record_type: RecordType::A }) if name == "first-app"));
+        assert!(server.names().is_empty());
+    }
+
+    #[test]
+    fn should_delete_only_given_record() {
+        let server = TestNameServer::start(&[("_acme-challenge.capsuleapp.cyou", RecordType::Txt, "first"), ("_acme-challenge.capsuleapp.cyou", RecordType::Txt, "second")]);
+        let service = service(server.address, SECRET);
+
+        service.delete_record(&DnsRecord::new("_acme-challenge", RecordType::Txt, "first", 60)).unwrap();
+        service.delete_record(&DnsRecord::new("_acme-challenge", RecordType::Txt, "gone", 60)).unwrap();
+
+        assert_eq!(vec![(300, "second".to_string())], server.records("_acme-challenge.capsuleapp.cyou", RecordType::Txt));
+    }
+
+    #[test]
+    fn should_reject_invalid_record_without_update() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let result = service(silent.local_addr().unwrap(), SECRET).add_record(&DnsRecord::new("first-app", RecordType::A, "2001:db8::10", 60));
+
+        assert!(matches!(result, Err(ApplicationError::InvalidDnsRecord { .. })));
+    }
+
+    #[test]
+    fn should_get_domain_name_error_when_key_is_rejected() {
+        let server = TestNameServer::start(&[]);
+
+        let result = service(server.address, "YW5vdGhlci1zZWNyZXQ=").add_cname_record("first-capsule-application");
+
+        match result {
+            Err(ApplicationError::DomainNameError { message }) => assert_eq!(format!("request to {} for capsuleapp.cyou failed with NOTAUTH (BADSIG)", server.address), message),
+            _ => panic!("expected a domain name error"),
+        }
+        assert!(server.names().is_empty());
+    }
+
+    #[test]
+    fn should_get_domain_name_error_when_zone_is_not_served() {
+        let server = TestNameServer::start(&[]);
+        let service = Rfc2136DomainNameService { zone: "example.org".to_string(), ..service(server.address, SECRET) };
+
+        assert!(matches!(service.add_cname_record("first-capsule-application"), Err(ApplicationError::DomainNameError { message }) if message.contains("NOTZONE")));
+        assert!(matches!(service.list_records(), Err(ApplicationError::DomainNameError { message }) if message.contains("NOTAUTH")));
+    }
+
+    #[test]
+    fn should_get_domain_name_error_when_server_does_not_answer() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let service = Rfc2136DomainNameService { timeout: Duration::from_millis(200), ..service(silent.local_addr().unwrap(), SECRET) };
+
+        assert!(matches!(service.add_cname_record("first-capsule-application"), Err(ApplicationError::DomainNameError { .. })));
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use derive_more::Display;
+
+use crate::application::ApplicationError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum LogSource {
+    #[display(fmt = "build")]
+    Build,
+    #[display(fmt = "app")]
+    App,
+}
+
+impl FromStr for LogSource {
+    type Err = ApplicationError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "build" => Ok(LogSource::Build),
+            "app" => Ok(LogSource::App),
+            _ => Err(ApplicationError::InvalidLogFilter { message: format!("unknown log source {}", source) }),
+        }
+    }
+}
+
+/// A line of output. `process` names what wrote it, like `web.0` for the first `web` instance or
+/// `build.12` for build 12. `seq` increases with every line of every application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub seq: u64,
+    pub application_id: i64,
+    pub source: LogSource,
+    pub process: String,
+    pub timestamp: SystemTime,
+    pub message: String,
+}
+
+impl LogLine {
+    pub fn process_type(&self) -> &str {
+        self.process.split('.').next().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogFilter {
+    pub source: Option<LogSource>,
+    pub process_type: Option<String>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, line: &LogLine) -> bool {
+        self.source.is_none_or(|s| s == line.source)
+            && self.process_type.as_ref().is_none_or(|t| t == line.process_type())
+    }
+}
+
+/// Where builds and running processes send their output.
+pub trait LogSink: Send + Sync {
+    fn append(&self, application_id: i64, source: LogSource, process: &str, message: &str);
+}
+
+struct Lines {
+    next_seq: u64,
+    by_application: HashMap<i64, VecDeque<LogLine>>,
+}
+
+/// Keeps the latest `capacity` lines of every application in memory, dropping the oldest first.
+pub struct LogBuffer {
+    capacity: usize,
+    lines: Mutex<Lines>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, lines: Mutex::new(Lines { next_seq: 1, by_application: HashMap::new() }) }
+    }
+
+    /// The last `count` lines passing the filter, oldest first.
+    pub fn recent(&self, application_id: i64, filter: &LogFilter, count: usize) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap();
+        let mut recent: Vec<LogLine> = lines.by_application.get(&application_id).into_iter()
+            .flat_map(|l| l.iter().rev())
+            .filter(|l| filter.matches(l))
+            .take(count)
+            .cloned()
+            .collect();
+        recent.reverse();
+        recent
+    }
+
+    /// Lines passing the filter appended after line `after_seq`, oldest first. Lines dropped from
+    /// the buffer in between are lost to the caller.
+    pub fn since(&self, application_id: i64, filter: &LogFilter, after_seq: u64) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap();
+        lines.by_application.get(&application_id).into_iter()
+            .flat_map(|l| l.iter())
+            .filter(|l| l.seq > after_seq && filter.matches(l))
+            .cloned()
+            .collect()
+    }
+
+    /// Seq of the latest line of any application, lines appended later have a greater one.
+    pub fn last_seq(&self) -> u64 {
+        self.lines.lock().unwrap().next_seq - 1
+    }
+
+    pub fn remove_all(&self, application_id: i64) {
+        self.lines.lock().unwrap().by_application.remove(&application_id);
+    }
+}
+
+impl LogSink for LogBuffer {
+    fn append(&self, application_id: i64, source: LogSource, process: &str, message: &str) {
+        let mut lines = self.lines.lock().unwrap();
+        let seq = lines.next_seq;
+        lines.next_seq += 1;
+
+        let capacity = self.capacity;
+        let application_lines = lines.by_application.entry(application_id).or_default();
+        if application_lines.len() >= capacity {
+            application_lines.pop_front();
+        }
+        application_lines.push_back(LogLine {
+            seq,
+            application_id,
+            source,
+            process: process.to_string(),
+            timestamp: SystemTime::now(),
+            message: message.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::application::logs::{LogBuffer, LogFilter, LogSink, LogSource};
+
+    fn messages(lines: Vec<super::LogLine>) -> Vec<String> {
+        lines.into_iter().map(|l| l.message).collect()
+    }
+
+    fn buffer() -> LogBuffer {
+        let buffer = LogBuffer::new(3);
+        buffer.append(1, LogSource::Build, "build.1", "compiling");
+        buffer.append(1, LogSource::App, "web.0", "listening");
+        buffer.append(2, LogSource::App, "web.0", "other application");
+        buffer.append(1, LogSource::App, "worker.0", "working");
+        buffer
+    }
+
+    #[test]
+    fn should_keep_recent_lines_per_application() {
+        let buffer = buffer();
+        buffer.append(1, LogSource::App, "web.1", "listening too");
+
+        assert_eq!(vec!["listening", "working", "listening too"], messages(buffer.recent(1, &LogFilter::default(), 10)));
+        assert_eq!(vec!["working", "listening too"], messages(buffer.recent(1, &LogFilter::default(), 2)));
+        assert_eq!(vec!["other application"], messages(buffer.recent(2, &LogFilter::default(), 10)));
+    }
+
+    #[test]
+    fn should_filter_by_source_and_process_type() {
+        let buffer = buffer();
+
+        let app = LogFilter { source: Some(LogSource::App), process_type: None };
+        let worker = LogFilter { source: None, process_type: Some("worker".to_string()) };
+        let build_web = LogFilter { source: Some(LogSource::Build), process_type: Some("web".to_string()) };
+
+        assert_eq!(vec!["listening", "working"], messages(buffer.recent(1, &app, 10)));
+        assert_eq!(vec!["working"], messages(buffer.recent(1, &worker, 10)));
+        assert!(buffer.recent(1, &build_web, 10).is_empty());
+    }
+
+    #[test]
+    fn should_list_lines_since_seq() {
+        let buffer = buffer();
+        let seen = buffer.last_seq();
+        buffer.append(1, LogSource::App, "web.0", "request");
+        buffer.append(2, LogSource::App, "web.0", "other request");
+
+        assert_eq!(vec!["request"], messages(buffer.since(1, &LogFilter::default(), seen)));
+        assert!(buffer.since(1, &LogFilter::default(), buffer.last_seq()).is_empty());
+    }
+
+    #[test]
+    fn should_remove_lines_of_application() {
+        let buffer = buffer();
+
+        buffer.remove_all(1);
+
+        assert!(buffer.recent(1, &LogFilter::default(), 10).is_empty());
+        assert_eq!(1, buffer.recent(2, &LogFilter::default(), 10).len());
+    }
+
+    #[test]
+    fn should_parse_source() {
+        assert_eq!(LogSource::Build, "build".parse().unwrap());
+        assert!("router".parse::<LogSource>().is_err());
+    }
+}
@@ -13,19 +13,74 @@
 // limitations under the License.
 use std::time::SystemTime;
 
-use anarchist_readable_name_generator_lib::readable_name;
 use derive_more::{Display, Error};
-use rand::Rng;
-
-pub use crate::application::domain_name::{CnameRecord, DomainNameService};
+#[cfg(test)]
+use mockall::automock;
+
+use crate::CoreError;
+
+pub use crate::application::application_name::ApplicationName;
+pub use crate::application::applications::{Applications, Page};
+pub use crate::application::certificates::{AcmeAccount, Certificate, CertificateIssuer, CertificateManager, CertificateRenewal, Certificates, CertificateTarget, ChallengeResponder, ChallengeType, DnsChallengeResponder, HttpChallenges, IssuedCertificate};
+pub use crate::application::custom_domains::{CustomDomain, CustomDomains, DomainVerifier, TxtResolver, validate_hostname, VERIFICATION_PREFIX};
+pub use crate::application::config_vars::{ConfigChange, ConfigVarChanges, ConfigVarMap, ConfigVars, ConfigVarsCipher, replacement_changes, validate_key};
+pub use crate::application::builds::{Build, Builds, BuildStatus, dispatch_builds, PushedRef};
+pub use crate::application::collaborators::{ApplicationAccess, Collaborator, Collaborators, OwnerCollaboratorHook, Role};
+pub use crate::application::deploy::{Deploy, DeployCoordinator, Deploys, DeployStatus, DeployStep, DeployStepKind, DeployTarget};
+pub use crate::application::domain_name::{CnameRecord, DnsRecord, DomainNameService, RecordType, ZONE_APEX};
+pub use crate::application::formation::{Formation, FormationChange, FormationEvent, Formations, OwnerLimits, OwnerLimitsRepository, ProcessScale, ProcessSize, ScaledProcess};
 pub use crate::application::git::{GitError, GitRepository, GitService};
+pub use crate::application::health::{ApplicationHealth, HealthCheckConfig, HealthChecker, HealthChecks, HealthProbe, HealthState, HealthStatus, InstanceHealth, ProbeFailure, RestartPolicy};
+pub use crate::application::implementation::acme_client::AcmeClient;
 pub use crate::application::implementation::domain_name_service::NameCheapDomainNameService;
+pub use crate::application::implementation::dns_message::{TsigAlgorithm, TsigKey};
+pub use crate::application::implementation::dns_txt_resolver::DnsTxtResolver;
 pub use crate::application::implementation::git_service::DefaultGitService;
+pub use crate::application::implementation::http_health_probe::HttpHealthProbe;
+pub use crate::application::implementation::local_runtime::LocalRuntimeBackend;
+pub use crate::application::implementation::postgres::postgres_applications::PostgresApplications;
+pub use crate::application::implementation::postgres::postgres_builds::PostgresBuilds;
+pub use crate::application::implementation::postgres::postgres_certificates::PostgresCertificates;
+pub use crate::application::implementation::postgres::postgres_collaborators::PostgresCollaborators;
+pub use crate::application::implementation::postgres::postgres_config_vars::PostgresConfigVars;
+pub use crate::application::implementation::postgres::postgres_custom_domains::PostgresCustomDomains;
+pub use crate::application::implementation::postgres::postgres_deploys::PostgresDeploys;
+pub use crate::application::implementation::postgres::postgres_formations::{PostgresFormations, PostgresOwnerLimits};
+pub use crate::application::implementation::postgres::postgres_health_checks::PostgresHealthChecks;
+pub use crate::application::implementation::postgres::postgres_redirects::PostgresRedirects;
+pub use crate::application::implementation::postgres::postgres_releases::PostgresReleases;
+pub use crate::application::implementation::rfc2136_domain_name_service::Rfc2136DomainNameService;
+pub use crate::application::logs::{LogBuffer, LogFilter, LogLine, LogSink, LogSource};
+pub use crate::application::provisioning::{ApplicationProvisioner, ProvisionedApplication, ProvisioningHook};
+pub use crate::application::redirects::{Redirect, Redirects};
+pub use crate::application::releases::{Release, Releases, ReleaseSpec, rollback};
+pub use crate::application::renaming::{ApplicationRenamer, RenamedApplication};
+pub use crate::application::routing::{HostRouter, Route};
+pub use crate::application::runtime::{ProcessInstance, ProcessSpec, ProcessStatus, RuntimeBackend, RuntimeError};
+pub use crate::application::transfer::{OwnershipTransfer, TransferredApplication};
 
 mod implementation;
 mod git;
 mod domain_name;
 mod applications;
+mod application_name;
+mod provisioning;
+mod compensation;
+mod redirects;
+mod renaming;
+mod routing;
+mod collaborators;
+mod transfer;
+mod config_vars;
+mod releases;
+mod builds;
+mod formation;
+mod runtime;
+mod logs;
+mod health;
+mod deploy;
+mod custom_domains;
+mod certificates;
 
 #[derive(Debug, Error, Display)]
 pub enum ApplicationError {
@@ -35,18 +90,54 @@ pub enum ApplicationError {
     DomainNameError { message: String },
     #[display(fmt = "internal error {}", message)]
     InternalError { message: String },
+    #[display(fmt = "invalid application name: {}", message)]
+    InvalidApplicationName { message: String },
+    #[display(fmt = "application name {} is already taken", name)]
+    ApplicationAlreadyExists { name: String },
+    #[display(fmt = "application {} was modified concurrently, reload and try again", name)]
+    ConcurrentModification { name: String },
+    #[display(fmt = "invalid role: {}", message)]
+    InvalidRole { message: String },
+    #[display(fmt = "invalid owner: {}", message)]
+    InvalidOwner { message: String },
+    #[display(fmt = "permission denied: {}", message)]
+    PermissionDenied { message: String },
+    #[display(fmt = "invalid config var: {}", message)]
+    InvalidConfigVar { message: String },
+    #[display(fmt = "invalid formation: {}", message)]
+    InvalidFormation { message: String },
+    #[display(fmt = "limit exceeded: {}", message)]
+    LimitExceeded { message: String },
+    #[display(fmt = "invalid log filter: {}", message)]
+    InvalidLogFilter { message: String },
+    #[display(fmt = "invalid health check: {}", message)]
+    InvalidHealthCheck { message: String },
+    #[display(fmt = "invalid dns record: {}", message)]
+    InvalidDnsRecord { message: String },
+    #[display(fmt = "no {} record named {}", record_type, name)]
+    DnsRecordNotFound { name: String, record_type: RecordType },
+    #[display(fmt = "invalid custom domain: {}", message)]
+    InvalidCustomDomain { message: String },
+    #[display(fmt = "custom domain {} is already taken", hostname)]
+    CustomDomainAlreadyExists { hostname: String },
+    #[display(fmt = "certificate error: {}", message)]
+    CertificateError { message: String },
 }
 
 pub struct Application {
-    name: String,
+    name: ApplicationName,
     id: i64,
     owner: String,
     create_at: SystemTime,
+    version: i32,
     updater: Option<Box<dyn Updater>>,
 }
 
+/// Persists changes made to an application. Implementations must only apply the change if the
+/// stored version still equals `application.version()`, and report `ConcurrentModification` otherwise.
+#[cfg_attr(test, automock)]
 pub trait Updater {
-    fn update(&self, application: &Application);
+    fn update(&self, application: &Application) -> Result<(), ApplicationError>;
 }
 
 pub type ApplicationVisitor<T> = fn(id: i64, &str, &str, create_at: SystemTime) -> T;
@@ -57,21 +148,26 @@ impl From<GitError> for ApplicationError {
     }
 }
 
+impl From<CoreError> for ApplicationError {
+    fn from(e: CoreError) -> Self {
+        ApplicationError::InternalError { message: e.to_string() }
+    }
+}
+
 impl Application {
-    pub fn new(id: i64, new_app_name: Option<String>, owner: String) -> Self {
+    pub fn new(id: i64, new_app_name: Option<ApplicationName>, owner: String) -> Self {
         let name = match new_app_name {
             Some(app_name) => app_name,
-            _ => Self::random_name(),
+            _ => ApplicationName::random(),
         };
 
-        Self { name, owner, updater: None, id, create_at: SystemTime::now() }
+        Self { name, owner, updater: None, id, create_at: SystemTime::now(), version: 0 }
     }
 
-    fn random_name() -> String {
-        let random_name = readable_name();
-        let random_number: u32 = rand::thread_rng().gen();
-
-        format!("{}_{}", random_name, random_number)
+    /// Lets an `Applications` implementation persist changes made through this application.
+    pub fn with_updater(mut self, updater: Box<dyn Updater>) -> Self {
+        self.updater = Some(updater);
+        self
     }
 
     pub fn create_git_repository(&self, git_service: &dyn GitService) -> Result<GitRepository, ApplicationError> {
@@ -82,16 +178,52 @@ impl Application {
         Ok(domain_name_service.add_cname_record(self.name.as_str())?)
     }
 
+    pub fn delete_git_repository(&self, git_service: &dyn GitService) -> Result<(), ApplicationError> {
+        Ok(git_service.delete_repo(self.owner.as_str(), self.name.as_str())?)
+    }
+
+    pub fn remove_cname_record(&self, domain_name_service: &dyn DomainNameService) -> Result<(), ApplicationError> {
+        domain_name_service.remove_cname_record(self.name.as_str())
+    }
+
     pub fn accept<T>(&self, visitor: ApplicationVisitor<T>) -> T {
         visitor(self.id, self.name.as_str(), self.owner.as_str(), self.create_at)
     }
 
-    pub fn rename(&mut self, new_name: &str) {
-        self.name = new_name.to_string();
+    /// Version the application was loaded with, bumped by every successful update.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    pub fn rename(&mut self, new_name: ApplicationName) -> Result<(), ApplicationError> {
+        let previous_name = std::mem::replace(&mut self.name, new_name);
+
+        if let Err(e) = self.save() {
+            self.name = previous_name;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    pub fn transfer_to(&mut self, new_owner: &str) -> Result<(), ApplicationError> {
+        let previous_owner = std::mem::replace(&mut self.owner, new_owner.to_string());
 
+        if let Err(e) = self.save() {
+            self.owner = previous_owner;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<(), ApplicationError> {
         if let Some(u) = &self.updater {
-            u.update(self)
+            u.update(self)?;
         }
+
+        self.version += 1;
+        Ok(())
     }
 }
 
@@ -101,7 +233,7 @@ mod tests {
 
     use mockall::predicate::eq;
 
-    use crate::application::{Application, GitRepository};
+    use crate::application::{Application, ApplicationError, ApplicationName, GitRepository, MockUpdater};
     use crate::application::domain_name::{CnameRecord, MockDomainNameService};
     use crate::application::git::MockGitService;
 
@@ -110,24 +242,24 @@ mod tests {
         let application = Application::new(1, None, "first_capsule_user".to_string());
 
         println!("{}", &application.name);
-        assert_eq!(application.name.is_empty(), false);
+        assert_eq!(application.name.as_str().is_empty(), false);
     }
 
     #[test]
     fn should_use_given_application_name_if_give_application_name() {
-        let name = Some("first_capsule_application".to_string());
+        let name = Some(ApplicationName::new("first-capsule-application").unwrap());
         let application = Application::new(1, name, "first_capsule_user".to_string());
 
-        assert_eq!(application.name, "first_capsule_application");
+        assert_eq!(application.name.as_str(), "first-capsule-application");
     }
 
     #[test]
     fn should_call_git_service_to_create_git_repo() {
-        let application = Application::new(1, Some("first_capsule_application".to_string()), "first_capsule_user".to_string());
+        let application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string());
         let mut git_service = MockGitService::new();
 
         git_service.expect_create_repo()
-            .with(eq("first_capsule_user"), eq("first_capsule_application"))
+            .with(eq("first_capsule_user"), eq("first-capsule-application"))
             .times(1)
             .returning(|_, _| Ok(GitRepository { uri: "https://git.test.com".to_string() }));
 
@@ -138,35 +270,63 @@ mod tests {
 
     #[test]
     fn should_call_domain_name_service_to_create_cname_record() {
-        let application = Application::new(1, Some("first_capsule_application".to_string()), "first_capsule_user".to_string());
+        let application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string());
         let mut domain_name_service = MockDomainNameService::new();
 
         domain_name_service.expect_add_cname_record()
-            .with(eq("first_capsule_application"))
+            .with(eq("first-capsule-application"))
             .times(1)
             .returning(|cname| Ok(CnameRecord { domain_name: format!("{}.capsuleapp.cyou", cname) }));
 
         let cname_record = application.add_cname_record(&domain_name_service).expect("add cname record failed.");
 
-        assert_eq!(cname_record.domain_name, "first_capsule_application.capsuleapp.cyou");
+        assert_eq!(cname_record.domain_name, "first-capsule-application.capsuleapp.cyou");
     }
 
     #[test]
     fn should_call_application_visitor() {
-        let application = Application::new(1, Some("first_capsule_application".to_string()), "first_capsule_user".to_string());
+        let application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string());
 
         let result = application.accept(test_saver).save();
 
-        assert_eq!(("first_capsule_application".to_string(), "first_capsule_user".to_string()), result)
+        assert_eq!(("first-capsule-application".to_string(), "first_capsule_user".to_string()), result)
     }
 
     #[test]
     fn should_rename_application() {
-        let mut application = Application::new(1, Some("first_capsule_application".to_string()), "first_capsule_user".to_string());
+        let mut application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string());
+
+        application.rename(ApplicationName::new("new-name").unwrap()).expect("rename failed");
+
+        assert_eq!("new-name", application.name.as_str());
+        assert_eq!(1, application.version());
+    }
+
+    #[test]
+    fn should_transfer_application() {
+        let mut application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string());
+
+        application.transfer_to("capsule-team").expect("transfer failed");
+
+        assert_eq!("capsule-team", application.owner);
+        assert_eq!(1, application.version());
+    }
+
+    #[test]
+    fn should_keep_name_and_version_if_update_failed() {
+        let mut updater = MockUpdater::new();
+        updater.expect_update()
+            .withf(|a| a.name.as_str() == "new-name" && a.version() == 0)
+            .times(1)
+            .returning(|_| Err(ApplicationError::ConcurrentModification { name: "new-name".to_string() }));
+        let mut application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())
+            .with_updater(Box::new(updater));
 
-        application.rename("new_name");
+        let result = application.rename(ApplicationName::new("new-name").unwrap());
 
-        assert_eq!("new_name", application.name)
+        assert!(matches!(result, Err(ApplicationError::ConcurrentModification { .. })));
+        assert_eq!("first-capsule-application", application.name.as_str());
+        assert_eq!(0, application.version());
     }
 
     struct Saver {
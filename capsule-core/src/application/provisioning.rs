@@ -0,0 +1,388 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::SystemTime;
+
+#[cfg(test)]
+use mockall::{automock, predicate::*};
+
+use crate::application::{Application, ApplicationError, CnameRecord, DomainNameService, GitRepository, GitService};
+use crate::application::applications::Applications;
+use crate::application::redirects::Redirects;
+use crate::application::compensation::Compensations;
+
+/// An extra provisioning step run after the application row, git repository and DNS record exist.
+#[cfg_attr(test, automock)]
+pub trait ProvisioningHook {
+    fn name(&self) -> String;
+
+    fn provision(&self, application: &Application) -> Result<(), ApplicationError>;
+
+    fn rollback(&self, application: &Application) -> Result<(), ApplicationError>;
+}
+
+pub struct ProvisionedApplication {
+    pub application: Application,
+    pub git_repository: GitRepository,
+    pub cname_record: CnameRecord,
+}
+
+/// Creates everything an application needs, one step at a time. Every completed step registers
+/// its undo action, so a failure part way through unwinds the earlier steps in reverse order.
+pub struct ApplicationProvisioner<'a> {
+    applications: &'a dyn Applications,
+    redirects: &'a dyn Redirects,
+    git_service: &'a dyn GitService,
+    domain_name_service: &'a dyn DomainNameService,
+    hooks: Vec<&'a dyn ProvisioningHook>,
+}
+
+impl<'a> ApplicationProvisioner<'a> {
+    pub fn new(applications: &'a dyn Applications, redirects: &'a dyn Redirects, git_service: &'a dyn GitService, domain_name_service: &'a dyn DomainNameService) -> Self {
+        Self { applications, redirects, git_service, domain_name_service, hooks: vec![] }
+    }
+
+    pub fn with_hook(mut self, hook: &'a dyn ProvisioningHook) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn provision(&self, application: &Application) -> Result<ProvisionedApplication, ApplicationError> {
+        let mut compensations = Compensations::new();
+
+        match self.run_steps(application, &mut compensations) {
+            Ok(provisioned) => Ok(provisioned),
+            Err(e) => Err(compensations.unwind(e)),
+        }
+    }
+
+    fn run_steps<'b>(&'b self, application: &'b Application, compensations: &mut Compensations<'b>) -> Result<ProvisionedApplication, ApplicationError> {
+        let saved_application = self.reserve_name(application)?;
+        compensations.push("persist application", move || Ok(self.applications.delete(application.id)?));
+
+        let git_repository = application.create_git_repository(self.git_service)?;
+        compensations.push("create git repository", move || application.delete_git_repository(self.git_service));
+
+        let cname_record = application.add_cname_record(self.domain_name_service)?;
+        compensations.push("add cname record", move || application.remove_cname_record(self.domain_name_service));
+
+        for hook in &self.hooks {
+            hook.provision(application)?;
+            compensations.push(hook.name().as_str(), move || hook.rollback(application));
+        }
+
+        Ok(ProvisionedApplication { application: saved_application, git_repository, cname_record })
+    }
+
+    /// Removes an application in the reverse order of provisioning. The row goes last, so when
+    /// any backend fails the application can still be found and the deletion retried.
+    pub fn deprovision(&self, application: &Application) -> Result<(), ApplicationError> {
+        for hook in self.hooks.iter().rev() {
+            hook.rollback(application)?;
+        }
+
+        application.remove_cname_record(self.domain_name_service)?;
+        application.delete_git_repository(self.git_service)?;
+
+        Ok(self.applications.delete(application.id)?)
+    }
+
+    // the row is the reservation of the name, it must exist before any external service is called.
+    // a previous name in its grace period is still taken, its host and CNAME record belong to the
+    // renamed application.
+    fn reserve_name(&self, application: &Application) -> Result<Application, ApplicationError> {
+        let already_exists = || ApplicationError::ApplicationAlreadyExists { name: application.name.to_string() };
+
+        if self.applications.exists(application.name.as_str())? || self.redirects.find_active(application.name.as_str(), SystemTime::now())?.is_some() {
+            return Err(already_exists());
+        }
+
+        match self.applications.add(application) {
+            Ok(saved_application) => Ok(saved_application),
+            // someone else took the name between the check and the insert.
+            Err(_) if self.applications.exists(application.name.as_str())? => Err(already_exists()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mockall::predicate::{always, eq};
+
+    use crate::application::{Application, ApplicationError, ApplicationName, CnameRecord, GitError, GitRepository};
+    use crate::application::applications::MockApplications;
+    use crate::application::domain_name::MockDomainNameService;
+    use crate::application::git::MockGitService;
+    use crate::application::provisioning::{ApplicationProvisioner, MockProvisioningHook};
+    use crate::application::redirects::{MockRedirects, Redirect};
+    use crate::CoreError;
+
+    struct Services {
+        applications: MockApplications,
+        redirects: MockRedirects,
+        git_service: MockGitService,
+        domain_name_service: MockDomainNameService,
+    }
+
+    impl Services {
+        fn new() -> Self {
+            Self {
+                applications: MockApplications::new(),
+                redirects: MockRedirects::new(),
+                git_service: MockGitService::new(),
+                domain_name_service: MockDomainNameService::new(),
+            }
+        }
+
+        fn add_succeeds(mut self) -> Self {
+            self.applications.expect_exists().returning(|_| Ok(false));
+            self.redirects.expect_find_active().returning(|_, _| Ok(None));
+            self.applications.expect_add()
+                .times(1)
+                .returning(|a| Ok(Application::new(a.id, Some(a.name.clone()), a.owner.clone())));
+            self
+        }
+
+        fn create_repo_succeeds(mut self) -> Self {
+            self.git_service.expect_create_repo()
+                .times(1)
+                .returning(|_, _| Ok(GitRepository { uri: "https://git.capsuleapp.cyou/first_capsule_user/first-capsule-application.git".to_string() }));
+            self
+        }
+
+        fn add_cname_succeeds(mut self) -> Self {
+            self.domain_name_service.expect_add_cname_record()
+                .times(1)
+                .returning(|cname| Ok(CnameRecord { domain_name: format!("{}.capsuleapp.cyou", cname) }));
+            self
+        }
+
+        fn expect_delete_row(mut self) -> Self {
+            self.applications.expect_delete().with(eq(1)).times(1).returning(|_| Ok(()));
+            self
+        }
+
+        fn expect_delete_repo(mut self) -> Self {
+            self.git_service.expect_delete_repo()
+                .with(eq("first_capsule_user"), eq("first-capsule-application"))
+                .times(1)
+                .returning(|_, _| Ok(()));
+            self
+        }
+
+        fn expect_remove_cname(mut self) -> Self {
+            self.domain_name_service.expect_remove_cname_record()
+                .with(eq("first-capsule-application"))
+                .times(1)
+                .returning(|_| Ok(()));
+            self
+        }
+
+        fn provisioner(&self) -> ApplicationProvisioner<'_> {
+            ApplicationProvisioner::new(&self.applications, &self.redirects, &self.git_service, &self.domain_name_service)
+        }
+    }
+
+    fn application() -> Application {
+        Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())
+    }
+
+    fn hook(provision_result: Result<(), ApplicationError>, expect_rollback: bool) -> MockProvisioningHook {
+        let mut hook = MockProvisioningHook::new();
+        hook.expect_name().returning(|| "test hook".to_string());
+        hook.expect_provision().times(1).return_once(move |_| provision_result);
+        hook.expect_rollback().times(if expect_rollback { 1 } else { 0 }).returning(|_| Ok(()));
+        hook
+    }
+
+    #[test]
+    fn should_provision_application() {
+        let services = Services::new().add_succeeds().create_repo_succeeds().add_cname_succeeds();
+        let hook = hook(Ok(()), false);
+
+        let provisioned = services.provisioner().with_hook(&hook).provision(&application()).expect("provision failed");
+
+        assert_eq!(provisioned.application.name.as_str(), "first-capsule-application");
+        assert_eq!(provisioned.git_repository.uri, "https://git.capsuleapp.cyou/first_capsule_user/first-capsule-application.git");
+        assert_eq!(provisioned.cname_record.domain_name, "first-capsule-application.capsuleapp.cyou");
+    }
+
+    #[test]
+    fn should_not_call_external_services_if_application_name_taken() {
+        let mut services = Services::new();
+        services.applications.expect_exists().with(eq("first-capsule-application")).returning(|_| Ok(true));
+        services.applications.expect_add().times(0);
+        services.git_service.expect_create_repo().times(0);
+        services.domain_name_service.expect_add_cname_record().times(0);
+
+        let result = services.provisioner().provision(&application());
+
+        match result {
+            Err(ApplicationError::ApplicationAlreadyExists { name }) => assert_eq!(name, "first-capsule-application"),
+            _ => panic!("expect application already exists"),
+        }
+    }
+
+    #[test]
+    fn should_not_take_previous_name_in_its_grace_period() {
+        let mut services = Services::new();
+        services.applications.expect_exists().returning(|_| Ok(false));
+        services.redirects.expect_find_active()
+            .with(eq("first-capsule-application"), always())
+            .returning(|name, now| Ok(Some(Redirect { from_name: name.to_string(), application_id: 2, expire_at: now + Duration::from_secs(3600) })));
+        services.applications.expect_add().times(0);
+        services.git_service.expect_create_repo().times(0);
+        services.domain_name_service.expect_add_cname_record().times(0);
+        services.domain_name_service.expect_remove_cname_record().times(0);
+
+        let result = services.provisioner().provision(&application());
+
+        assert!(matches!(result, Err(ApplicationError::ApplicationAlreadyExists { name }) if name == "first-capsule-application"));
+    }
+
+    #[test]
+    fn should_report_name_taken_if_insert_lost_the_race() {
+        let mut services = Services::new();
+        let mut checked = false;
+        services.applications.expect_exists().times(2).returning(move |_| {
+            let exists = checked;
+            checked = true;
+            Ok(exists)
+        });
+        services.redirects.expect_find_active().returning(|_, _| Ok(None));
+        services.applications.expect_add().times(1).returning(|_| Err(CoreError { message: "duplicate key value violates unique constraint".to_string() }));
+        services.git_service.expect_create_repo().times(0);
+
+        let result = services.provisioner().provision(&application());
+
+        assert!(matches!(result, Err(ApplicationError::ApplicationAlreadyExists { .. })));
+    }
+
+    #[test]
+    fn should_not_call_external_services_if_persist_failed() {
+        let mut services = Services::new();
+        services.applications.expect_exists().returning(|_| Ok(false));
+        services.redirects.expect_find_active().returning(|_, _| Ok(None));
+        services.applications.expect_add().times(1).returning(|_| Err(CoreError { message: "duplicate".to_string() }));
+        services.applications.expect_delete().times(0);
+        services.git_service.expect_create_repo().times(0);
+        services.domain_name_service.expect_add_cname_record().times(0);
+
+        let result = services.provisioner().provision(&application());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_delete_application_row_if_create_git_repository_failed() {
+        let mut services = Services::new().add_succeeds().expect_delete_row();
+        services.git_service.expect_create_repo().times(1).returning(|_, _| Err(GitError {}));
+        services.git_service.expect_delete_repo().times(0);
+        services.domain_name_service.expect_add_cname_record().times(0);
+
+        let result = services.provisioner().provision(&application());
+
+        assert!(matches!(result, Err(ApplicationError::GitError { .. })));
+    }
+
+    #[test]
+    fn should_delete_git_repository_and_row_if_add_cname_record_failed() {
+        let mut services = Services::new().add_succeeds().create_repo_succeeds().expect_delete_repo().expect_delete_row();
+        services.domain_name_service.expect_add_cname_record()
+            .times(1)
+            .returning(|_| Err(ApplicationError::DomainNameError { message: "add application domain record failed.".to_string() }));
+        services.domain_name_service.expect_remove_cname_record().times(0);
+
+        let result = services.provisioner().provision(&application());
+
+        assert!(matches!(result, Err(ApplicationError::DomainNameError { .. })));
+    }
+
+    #[test]
+    fn should_unwind_all_steps_if_hook_failed() {
+        let services = Services::new().add_succeeds().create_repo_succeeds().add_cname_succeeds()
+            .expect_remove_cname().expect_delete_repo().expect_delete_row();
+        let hook = hook(Err(ApplicationError::InternalError { message: "hook failed".to_string() }), false);
+
+        let result = services.provisioner().with_hook(&hook).provision(&application());
+
+        assert!(matches!(result, Err(ApplicationError::InternalError { .. })));
+    }
+
+    #[test]
+    fn should_rollback_earlier_hooks_if_later_hook_failed() {
+        let services = Services::new().add_succeeds().create_repo_succeeds().add_cname_succeeds()
+            .expect_remove_cname().expect_delete_repo().expect_delete_row();
+        let first_hook = hook(Ok(()), true);
+        let second_hook = hook(Err(ApplicationError::InternalError { message: "hook failed".to_string() }), false);
+
+        let result = services.provisioner().with_hook(&first_hook).with_hook(&second_hook).provision(&application());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_report_steps_which_could_not_be_rolled_back() {
+        let mut services = Services::new().add_succeeds().create_repo_succeeds().expect_delete_row();
+        services.domain_name_service.expect_add_cname_record()
+            .times(1)
+            .returning(|_| Err(ApplicationError::DomainNameError { message: "add application domain record failed.".to_string() }));
+        services.git_service.expect_delete_repo().times(1).returning(|_, _| Err(GitError {}));
+
+        let result = services.provisioner().provision(&application());
+
+        match result {
+            Err(ApplicationError::InternalError { message }) => assert!(message.contains("create git repository")),
+            _ => panic!("expect internal error"),
+        }
+    }
+
+    #[test]
+    fn should_deprovision_application() {
+        let services = Services::new().expect_remove_cname().expect_delete_repo().expect_delete_row();
+        let mut hook = MockProvisioningHook::new();
+        hook.expect_rollback().times(1).returning(|_| Ok(()));
+
+        let result = services.provisioner().with_hook(&hook).deprovision(&application());
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn should_keep_application_row_if_delete_git_repository_failed() {
+        let mut services = Services::new().expect_remove_cname();
+        services.git_service.expect_delete_repo().times(1).returning(|_, _| Err(GitError {}));
+        services.applications.expect_delete().times(0);
+
+        let result = services.provisioner().deprovision(&application());
+
+        assert!(matches!(result, Err(ApplicationError::GitError { .. })));
+    }
+
+    #[test]
+    fn should_keep_application_row_if_remove_cname_record_failed() {
+        let mut services = Services::new();
+        services.domain_name_service.expect_remove_cname_record()
+            .times(1)
+            .returning(|_| Err(ApplicationError::DomainNameError { message: "dns is down".to_string() }));
+        services.git_service.expect_delete_repo().times(0);
+        services.applications.expect_delete().times(0);
+
+        let result = services.provisioner().deprovision(&application());
+
+        assert!(matches!(result, Err(ApplicationError::DomainNameError { .. })));
+    }
+}
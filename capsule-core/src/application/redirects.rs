@@ -0,0 +1,38 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::SystemTime;
+
+#[cfg(test)]
+use mockall::{automock, predicate::*};
+
+use crate::CoreError;
+
+/// Keeps a previous application name pointing at the renamed application until `expire_at`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub from_name: String,
+    pub application_id: i64,
+    pub expire_at: SystemTime,
+}
+
+#[cfg_attr(test, automock)]
+pub trait Redirects {
+    fn add(&self, redirect: &Redirect) -> Result<(), CoreError>;
+
+    fn find_active(&self, from_name: &str, now: SystemTime) -> Result<Option<Redirect>, CoreError>;
+
+    fn find_expired(&self, now: SystemTime) -> Result<Vec<Redirect>, CoreError>;
+
+    fn delete(&self, from_name: &str) -> Result<(), CoreError>;
+}
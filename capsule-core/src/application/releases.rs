@@ -0,0 +1,131 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::SystemTime;
+
+#[cfg(test)]
+use mockall::automock;
+
+use crate::application::ApplicationError;
+use crate::application::config_vars::ConfigVarMap;
+use crate::buildpack::ProcessTypes;
+use crate::CoreError;
+
+/// What an application runs: the build artifact made from a commit, started as the process types of
+/// the commit's `Procfile` with a set of config vars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleaseSpec {
+    pub build_artifact: String,
+    pub commit_sha: String,
+    pub config_vars: ConfigVarMap,
+    pub processes: ProcessTypes,
+    pub description: String,
+    pub created_by: String,
+}
+
+/// A numbered snapshot of a `ReleaseSpec`. Releases are never changed after creation, going back
+/// to an older one creates a new release.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    pub application_id: i64,
+    pub version: i32,
+    pub spec: ReleaseSpec,
+    pub create_at: SystemTime,
+}
+
+#[cfg_attr(test, automock)]
+pub trait Releases {
+    /// Stores the spec as the release following the latest one, starting at 1.
+    fn create(&self, application_id: i64, spec: &ReleaseSpec) -> Result<Release, ApplicationError>;
+
+    fn find(&self, application_id: i64, version: i32) -> Result<Option<Release>, ApplicationError>;
+
+    fn latest(&self, application_id: i64) -> Result<Option<Release>, ApplicationError>;
+
+    /// Releases of the application, latest first.
+    fn list(&self, application_id: i64) -> Result<Vec<Release>, ApplicationError>;
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError>;
+}
+
+/// Creates a new release with the build artifact, commit, config vars and processes of release `version`.
+/// Returns `None` if the application has no such release.
+pub fn rollback(releases: &dyn Releases, application_id: i64, version: i32, user_name: &str) -> Result<Option<Release>, ApplicationError> {
+    let target = match releases.find(application_id, version)? {
+        Some(release) => release,
+        None => return Ok(None),
+    };
+
+    let spec = ReleaseSpec {
+        description: format!("Rollback to v{}", target.version),
+        created_by: user_name.to_string(),
+        ..target.spec
+    };
+
+    Ok(Some(releases.create(application_id, &spec)?))
+}
+
+#[cfg(test)]
+pub(crate) fn release_spec(commit_sha: &str, processes: &[(&str, &str)]) -> ReleaseSpec {
+    ReleaseSpec {
+        build_artifact: format!("registry.capsuleapp.cyou/first-capsule-application:{}", commit_sha),
+        commit_sha: commit_sha.to_string(),
+        config_vars: ConfigVarMap::new(),
+        processes: processes.iter().map(|(t, c)| (t.to_string(), c.to_string())).collect(),
+        description: format!("Deploy {}", commit_sha),
+        created_by: "first_capsule_user".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use mockall::predicate::eq;
+
+    use crate::application::config_vars::ConfigVarMap;
+    use crate::application::releases::{MockReleases, Release, release_spec, ReleaseSpec, rollback};
+
+    fn spec(commit_sha: &str) -> ReleaseSpec {
+        ReleaseSpec {
+            config_vars: ConfigVarMap::from([("PORT".to_string(), "8080".to_string())]),
+            ..release_spec(commit_sha, &[("web", "./app")])
+        }
+    }
+
+    #[test]
+    fn should_create_release_copying_older_release() {
+        let mut releases = MockReleases::new();
+        releases.expect_find()
+            .with(eq(1), eq(2))
+            .returning(|_, _| Ok(Some(Release { application_id: 1, version: 2, spec: spec("abc123"), create_at: SystemTime::now() })));
+        releases.expect_create()
+            .withf(|app_id, s| *app_id == 1 && s.commit_sha == "abc123" && s.build_artifact == spec("abc123").build_artifact
+                && s.config_vars == spec("abc123").config_vars && s.processes == spec("abc123").processes && s.description == "Rollback to v2" && s.created_by == "deploy_user")
+            .times(1)
+            .returning(|app_id, s| Ok(Release { application_id: app_id, version: 4, spec: s.clone(), create_at: SystemTime::now() }));
+
+        let release = rollback(&releases, 1, 2, "deploy_user").unwrap().unwrap();
+
+        assert_eq!(4, release.version);
+    }
+
+    #[test]
+    fn should_not_create_release_if_rollback_target_not_exists() {
+        let mut releases = MockReleases::new();
+        releases.expect_find().returning(|_, _| Ok(None));
+        releases.expect_create().never();
+
+        assert!(rollback(&releases, 1, 7, "deploy_user").unwrap().is_none());
+    }
+}
@@ -0,0 +1,309 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, SystemTime};
+
+use crate::application::{Application, ApplicationError, ApplicationName, CnameRecord, DomainNameService, GitRepository, GitService};
+use crate::application::applications::Applications;
+use crate::application::compensation::Compensations;
+use crate::application::redirects::{Redirect, Redirects};
+
+pub struct RenamedApplication {
+    pub git_repository: GitRepository,
+    pub cname_record: CnameRecord,
+    pub redirect: Redirect,
+}
+
+/// Renames an application everywhere it is known by name. The previous name keeps its DNS record
+/// and redirects to the new one for a grace period, so existing links do not break right away.
+pub struct ApplicationRenamer<'a> {
+    applications: &'a dyn Applications,
+    redirects: &'a dyn Redirects,
+    git_service: &'a dyn GitService,
+    domain_name_service: &'a dyn DomainNameService,
+    grace_period: Duration,
+}
+
+impl<'a> ApplicationRenamer<'a> {
+    pub fn new(applications: &'a dyn Applications,
+               redirects: &'a dyn Redirects,
+               git_service: &'a dyn GitService,
+               domain_name_service: &'a dyn DomainNameService,
+               grace_period: Duration) -> Self {
+        Self { applications, redirects, git_service, domain_name_service, grace_period }
+    }
+
+    pub fn rename(&self, application: &mut Application, new_name: ApplicationName) -> Result<RenamedApplication, ApplicationError> {
+        if application.name == new_name {
+            return Err(ApplicationError::InvalidApplicationName { message: format!("'{}' is the current name", new_name) });
+        }
+
+        let now = SystemTime::now();
+
+        if self.applications.exists(new_name.as_str())? || self.redirects.find_active(new_name.as_str(), now)?.is_some() {
+            return Err(ApplicationError::ApplicationAlreadyExists { name: new_name.to_string() });
+        }
+
+        let owner = application.owner.clone();
+        let old_name = application.name.clone();
+        let redirect = Redirect { from_name: old_name.to_string(), application_id: application.id, expire_at: now + self.grace_period };
+
+        let mut compensations = Compensations::new();
+
+        let result = self.run_steps(&owner, &old_name, &new_name, &redirect, &mut compensations)
+            .and_then(|(git_repository, cname_record)| {
+                application.rename(new_name.clone())?;
+
+                Ok(RenamedApplication { git_repository, cname_record, redirect: redirect.clone() })
+            });
+
+        result.map_err(|e| compensations.unwind(e))
+    }
+
+    fn run_steps<'b>(&'b self,
+                     owner: &'b str,
+                     old_name: &'b ApplicationName,
+                     new_name: &'b ApplicationName,
+                     redirect: &'b Redirect,
+                     compensations: &mut Compensations<'b>) -> Result<(GitRepository, CnameRecord), ApplicationError> {
+        let git_repository = self.git_service.rename_repo(owner, old_name.as_str(), new_name.as_str())?;
+        compensations.push("rename git repository", move || {
+            Ok(self.git_service.rename_repo(owner, new_name.as_str(), old_name.as_str()).map(|_| ())?)
+        });
+
+        let cname_record = self.domain_name_service.add_cname_record(new_name.as_str())?;
+        compensations.push("add cname record", move || self.domain_name_service.remove_cname_record(new_name.as_str()));
+
+        self.redirects.add(redirect)?;
+        compensations.push("add redirect", move || Ok(self.redirects.delete(redirect.from_name.as_str())?));
+
+        Ok((git_repository, cname_record))
+    }
+
+    /// Drops the DNS records of previous names whose grace period is over, unless a new
+    /// application has taken the name in the meantime. Returns how many redirects expired.
+    pub fn expire_redirects(&self, now: SystemTime) -> Result<usize, ApplicationError> {
+        let expired = self.redirects.find_expired(now)?;
+
+        for redirect in &expired {
+            if !self.applications.exists(redirect.from_name.as_str())? {
+                self.domain_name_service.remove_cname_record(redirect.from_name.as_str())?;
+            }
+
+            self.redirects.delete(redirect.from_name.as_str())?;
+        }
+
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use mockall::predicate::eq;
+
+    use crate::application::{Application, ApplicationError, ApplicationName, CnameRecord, GitError, GitRepository, MockUpdater};
+    use crate::application::applications::MockApplications;
+    use crate::application::domain_name::MockDomainNameService;
+    use crate::application::git::MockGitService;
+    use crate::application::redirects::{MockRedirects, Redirect};
+    use crate::application::renaming::ApplicationRenamer;
+
+    struct Services {
+        applications: MockApplications,
+        redirects: MockRedirects,
+        git_service: MockGitService,
+        domain_name_service: MockDomainNameService,
+    }
+
+    impl Services {
+        fn new() -> Self {
+            Self {
+                applications: MockApplications::new(),
+                redirects: MockRedirects::new(),
+                git_service: MockGitService::new(),
+                domain_name_service: MockDomainNameService::new(),
+            }
+        }
+
+        fn name_is_free(mut self) -> Self {
+            self.applications.expect_exists().with(eq("new-name")).returning(|_| Ok(false));
+            self.redirects.expect_find_active().returning(|_, _| Ok(None));
+            self
+        }
+
+        fn rename_repo_succeeds(mut self) -> Self {
+            self.git_service.expect_rename_repo()
+                .with(eq("first_capsule_user"), eq("first-capsule-application"), eq("new-name"))
+                .times(1)
+                .returning(|owner, _, new_name| Ok(GitRepository { uri: format!("https://git.capsuleapp.cyou/{}/{}.git", owner, new_name) }));
+            self
+        }
+
+        fn add_cname_succeeds(mut self) -> Self {
+            self.domain_name_service.expect_add_cname_record()
+                .with(eq("new-name"))
+                .times(1)
+                .returning(|cname| Ok(CnameRecord { domain_name: format!("{}.capsuleapp.cyou", cname) }));
+            self
+        }
+
+        fn renamer(&self) -> ApplicationRenamer<'_> {
+            ApplicationRenamer::new(&self.applications, &self.redirects, &self.git_service, &self.domain_name_service, Duration::from_secs(3600))
+        }
+    }
+
+    fn application() -> Application {
+        Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())
+    }
+
+    fn new_name() -> ApplicationName {
+        ApplicationName::new("new-name").unwrap()
+    }
+
+    #[test]
+    fn should_rename_git_repository_and_dns_and_keep_redirect_of_old_name() {
+        let mut services = Services::new().name_is_free().rename_repo_succeeds().add_cname_succeeds();
+        services.redirects.expect_add()
+            .withf(|r| r.from_name == "first-capsule-application" && r.application_id == 1 && r.expire_at > SystemTime::now())
+            .times(1)
+            .returning(|_| Ok(()));
+        services.domain_name_service.expect_remove_cname_record().times(0);
+        let mut application = application();
+
+        let renamed = services.renamer().rename(&mut application, new_name()).expect("rename failed");
+
+        assert_eq!(application.name.as_str(), "new-name");
+        assert_eq!(renamed.git_repository.uri, "https://git.capsuleapp.cyou/first_capsule_user/new-name.git");
+        assert_eq!(renamed.cname_record.domain_name, "new-name.capsuleapp.cyou");
+        assert_eq!(renamed.redirect.from_name, "first-capsule-application");
+    }
+
+    #[test]
+    fn should_not_rename_to_taken_name() {
+        let mut services = Services::new();
+        services.applications.expect_exists().with(eq("new-name")).returning(|_| Ok(true));
+        services.redirects.expect_find_active().returning(|_, _| Ok(None));
+        services.git_service.expect_rename_repo().times(0);
+        let mut application = application();
+
+        let result = services.renamer().rename(&mut application, new_name());
+
+        assert!(matches!(result, Err(ApplicationError::ApplicationAlreadyExists { .. })));
+        assert_eq!(application.name.as_str(), "first-capsule-application");
+    }
+
+    #[test]
+    fn should_not_rename_to_name_still_redirecting() {
+        let mut services = Services::new();
+        services.applications.expect_exists().returning(|_| Ok(false));
+        services.redirects.expect_find_active()
+            .returning(|name, _| Ok(Some(Redirect { from_name: name.to_string(), application_id: 2, expire_at: SystemTime::now() })));
+        services.git_service.expect_rename_repo().times(0);
+
+        let result = services.renamer().rename(&mut application(), new_name());
+
+        assert!(matches!(result, Err(ApplicationError::ApplicationAlreadyExists { .. })));
+    }
+
+    #[test]
+    fn should_rename_git_repository_back_if_add_cname_record_failed() {
+        let mut services = Services::new().name_is_free().rename_repo_succeeds();
+        services.domain_name_service.expect_add_cname_record()
+            .returning(|_| Err(ApplicationError::DomainNameError { message: "dns is down".to_string() }));
+        services.git_service.expect_rename_repo()
+            .with(eq("first_capsule_user"), eq("new-name"), eq("first-capsule-application"))
+            .times(1)
+            .returning(|_, _, _| Ok(GitRepository { uri: "".to_string() }));
+        services.redirects.expect_add().times(0);
+        let mut application = application();
+
+        let result = services.renamer().rename(&mut application, new_name());
+
+        assert!(matches!(result, Err(ApplicationError::DomainNameError { .. })));
+        assert_eq!(application.name.as_str(), "first-capsule-application");
+    }
+
+    #[test]
+    fn should_undo_git_and_dns_if_add_redirect_failed() {
+        let mut services = Services::new().name_is_free().rename_repo_succeeds().add_cname_succeeds();
+        services.redirects.expect_add().returning(|_| Err(crate::CoreError { message: "db is down".to_string() }));
+        services.domain_name_service.expect_remove_cname_record().with(eq("new-name")).times(1).returning(|_| Ok(()));
+        services.git_service.expect_rename_repo()
+            .with(eq("first_capsule_user"), eq("new-name"), eq("first-capsule-application"))
+            .times(1)
+            .returning(|_, _, _| Ok(GitRepository { uri: "".to_string() }));
+
+        let result = services.renamer().rename(&mut application(), new_name());
+
+        assert!(matches!(result, Err(ApplicationError::InternalError { .. })));
+    }
+
+    #[test]
+    fn should_undo_everything_if_application_was_modified_concurrently() {
+        let mut services = Services::new().name_is_free().rename_repo_succeeds().add_cname_succeeds();
+        services.redirects.expect_add().times(1).returning(|_| Ok(()));
+        services.redirects.expect_delete().with(eq("first-capsule-application")).times(1).returning(|_| Ok(()));
+        services.domain_name_service.expect_remove_cname_record().with(eq("new-name")).times(1).returning(|_| Ok(()));
+        services.git_service.expect_rename_repo()
+            .with(eq("first_capsule_user"), eq("new-name"), eq("first-capsule-application"))
+            .times(1)
+            .returning(|_, _, _| Ok(GitRepository { uri: "".to_string() }));
+        let mut updater = MockUpdater::new();
+        updater.expect_update().returning(|a| Err(ApplicationError::ConcurrentModification { name: a.name.to_string() }));
+        let mut application = application().with_updater(Box::new(updater));
+
+        let result = services.renamer().rename(&mut application, new_name());
+
+        assert!(matches!(result, Err(ApplicationError::ConcurrentModification { .. })));
+        assert_eq!(application.name.as_str(), "first-capsule-application");
+    }
+
+    #[test]
+    fn should_not_touch_dns_if_rename_git_repository_failed() {
+        let mut services = Services::new().name_is_free();
+        services.git_service.expect_rename_repo().times(1).returning(|_, _, _| Err(GitError {}));
+        services.domain_name_service.expect_add_cname_record().times(0);
+
+        let result = services.renamer().rename(&mut application(), new_name());
+
+        assert!(matches!(result, Err(ApplicationError::GitError { .. })));
+    }
+
+    #[test]
+    fn should_remove_dns_record_of_expired_redirect() {
+        let mut services = Services::new();
+        services.redirects.expect_find_expired()
+            .returning(|now| Ok(vec![Redirect { from_name: "old-name".to_string(), application_id: 1, expire_at: now }]));
+        services.applications.expect_exists().with(eq("old-name")).returning(|_| Ok(false));
+        services.domain_name_service.expect_remove_cname_record().with(eq("old-name")).times(1).returning(|_| Ok(()));
+        services.redirects.expect_delete().with(eq("old-name")).times(1).returning(|_| Ok(()));
+
+        let expired = services.renamer().expire_redirects(SystemTime::now()).expect("expire redirects failed");
+
+        assert_eq!(expired, 1);
+    }
+
+    #[test]
+    fn should_keep_dns_record_of_expired_redirect_if_name_taken_again() {
+        let mut services = Services::new();
+        services.redirects.expect_find_expired()
+            .returning(|now| Ok(vec![Redirect { from_name: "old-name".to_string(), application_id: 1, expire_at: now }]));
+        services.applications.expect_exists().with(eq("old-name")).returning(|_| Ok(true));
+        services.domain_name_service.expect_remove_cname_record().times(0);
+        services.redirects.expect_delete().with(eq("old-name")).times(1).returning(|_| Ok(()));
+
+        services.renamer().expire_redirects(SystemTime::now()).expect("expire redirects failed");
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::SystemTime;
+
+use crate::application::ApplicationError;
+use crate::application::applications::Applications;
+use crate::application::custom_domains::CustomDomains;
+use crate::application::redirects::Redirects;
+
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    Application { application_id: i64, name: String },
+    /// The host is a previous name of the application, `location` keeps the path and query.
+    Redirect { location: String },
+    NotFound,
+}
+
+/// Decides what to do with a request for `{name}.{domain}` or for a custom domain of an application.
+pub struct HostRouter<'a> {
+    applications: &'a dyn Applications,
+    redirects: &'a dyn Redirects,
+    custom_domains: &'a dyn CustomDomains,
+    domain: String,
+}
+
+impl<'a> HostRouter<'a> {
+    pub fn new(applications: &'a dyn Applications, redirects: &'a dyn Redirects, custom_domains: &'a dyn CustomDomains, domain: &str) -> Self {
+        Self { applications, redirects, custom_domains, domain: domain.to_string() }
+    }
+
+    /// Routes a request for `host`, as in the `Host` header, with the port if any. `path_and_query`
+    /// is kept by redirects.
+    pub fn route(&self, host: &str, path_and_query: &str) -> Result<Route, ApplicationError> {
+        let host = without_port(host).trim_end_matches('.').to_ascii_lowercase();
+
+        if host != self.domain && !host.ends_with(&format!(".{}", self.domain)) {
+            return self.route_custom_domain(&host);
+        }
+
+        let name = match host.strip_suffix(&format!(".{}", self.domain)) {
+            Some(name) if !name.contains('.') => name,
+            _ => return Ok(Route::NotFound),
+        };
+
+        if let Some(application) = self.applications.find_by_name(name)? {
+            return Ok(Route::Application { application_id: application.id, name: name.to_string() });
+        }
+
+        if let Some(redirect) = self.redirects.find_active(name, SystemTime::now())? {
+            if let Some(application) = self.applications.find_by_id(redirect.application_id)? {
+                return Ok(Route::Redirect { location: format!("https://{}.{}{}", application.name, self.domain, path_and_query) });
+            }
+        }
+
+        Ok(Route::NotFound)
+    }
+
+    /// Only serves custom domains whose ownership has been verified.
+    fn route_custom_domain(&self, host: &str) -> Result<Route, ApplicationError> {
+        match self.custom_domains.find_verified(host)? {
+            Some(domain) => match self.applications.find_by_id(domain.application_id)? {
+                Some(application) => Ok(Route::Application { application_id: application.id, name: application.name.to_string() }),
+                None => Ok(Route::NotFound),
+            },
+            None => Ok(Route::NotFound),
+        }
+    }
+}
+
+/// IPv6 literals keep their brackets, like `[::1]` of `[::1]:8080`.
+fn without_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return match host.find(']') {
+            Some(end) => &host[..=end],
+            None => host,
+        };
+    }
+
+    host.split(':').next().unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use mockall::predicate::eq;
+
+    use crate::application::{Application, ApplicationName};
+    use crate::application::applications::MockApplications;
+    use crate::application::custom_domains::{CustomDomain, MockCustomDomains};
+    use crate::application::redirects::{MockRedirects, Redirect};
+    use crate::application::routing::{HostRouter, Route};
+
+    #[test]
+    fn should_route_to_application() {
+        let mut applications = MockApplications::new();
+        applications.expect_find_by_name().with(eq("first-capsule-application"))
+            .returning(|name| Ok(Some(Application::new(1, Some(ApplicationName::new(name).unwrap()), "first_capsule_user".to_string()))));
+        let redirects = MockRedirects::new();
+        let custom_domains = MockCustomDomains::new();
+
+        let route = HostRouter::new(&applications, &redirects, &custom_domains, "capsuleapp.cyou").route("first-capsule-application.capsuleapp.cyou:443", "/").unwrap();
+
+        assert_eq!(route, Route::Application { application_id: 1, name: "first-capsule-application".to_string() });
+    }
+
+    #[test]
+    fn should_strip_port_but_keep_ipv6_literal() {
+        let applications = MockApplications::new();
+        let redirects = MockRedirects::new();
+        let mut custom_domains = MockCustomDomains::new();
+        custom_domains.expect_find_verified().with(eq("[::1]")).times(2).returning(|_| Ok(None));
+        let router = HostRouter::new(&applications, &redirects, &custom_domains, "capsuleapp.cyou");
+
+        assert_eq!(router.route("[::1]:8080", "/").unwrap(), Route::NotFound);
+        assert_eq!(router.route("[::1]", "/").unwrap(), Route::NotFound);
+    }
+
+    #[test]
+    fn should_redirect_previous_name_to_current_name() {
+        let mut applications = MockApplications::new();
+        applications.expect_find_by_name().returning(|_| Ok(None));
+        applications.expect_find_by_id().with(eq(1))
+            .returning(|id| Ok(Some(Application::new(id, Some(ApplicationName::new("new-name").unwrap()), "first_capsule_user".to_string()))));
+        let mut redirects = MockRedirects::new();
+        redirects.expect_find_active().with(eq("old-name"), mockall::predicate::always())
+            .returning(|name, _| Ok(Some(Redirect { from_name: name.to_string(), application_id: 1, expire_at: SystemTime::now() })));
+        let custom_domains = MockCustomDomains::new();
+
+        let route = HostRouter::new(&applications, &redirects, &custom_domains, "capsuleapp.cyou").route("old-name.capsuleapp.cyou", "/orders?page=2").unwrap();
+
+        assert_eq!(route, Route::Redirect { location: "https://new-name.capsuleapp.cyou/orders?page=2".to_string() });
+    }
+
+    #[test]
+    fn should_not_route_unknown_host() {
+        let mut applications = MockApplications::new();
+        applications.expect_find_by_name().returning(|_| Ok(None));
+        let mut redirects = MockRedirects::new();
+        redirects.expect_find_active().returning(|_, _| Ok(None));
+        let mut custom_domains = MockCustomDomains::new();
+        custom_domains.expect_find_verified().with(eq("www.example.com")).returning(|_| Ok(None));
+        let router = HostRouter::new(&applications, &redirects, &custom_domains, "capsuleapp.cyou");
+
+        assert_eq!(router.route("unknown.capsuleapp.cyou", "/").unwrap(), Route::NotFound);
+        assert_eq!(router.route("www.example.com", "/").unwrap(), Route::NotFound);
+        assert_eq!(router.route("a.b.capsuleapp.cyou", "/").unwrap(), Route::NotFound);
+    }
+
+    #[test]
+    fn should_route_verified_custom_domain_to_application() {
+        let mut applications = MockApplications::new();
+        applications.expect_find_by_id().with(eq(1))
+            .returning(|id| Ok(Some(Application::new(id, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string()))));
+        let redirects = MockRedirects::new();
+        let mut custom_domains = MockCustomDomains::new();
+        custom_domains.expect_find_verified().with(eq("www.theirshop.com")).returning(|hostname| {
+            let mut domain = CustomDomain::new(1, hostname, "capsuleapp.cyou", SystemTime::now()).unwrap();
+            domain.verified_at = Some(SystemTime::now());
+            Ok(Some(domain))
+        });
+
+        let route = HostRouter::new(&applications, &redirects, &custom_domains, "capsuleapp.cyou").route("WWW.theirshop.com:443", "/").unwrap();
+
+        assert_eq!(route, Route::Application { application_id: 1, name: "first-capsule-application".to_string() });
+    }
+}
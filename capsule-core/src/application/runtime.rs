@@ -0,0 +1,91 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::path::PathBuf;
+
+use derive_more::{Display, Error};
+#[cfg(test)]
+use mockall::automock;
+
+use crate::application::config_vars::ConfigVarMap;
+use crate::application::formation::ProcessSize;
+
+#[derive(Debug, Error, Display)]
+#[display(fmt = "runtime error {}", message)]
+pub struct RuntimeError {
+    pub message: String,
+}
+
+impl From<std::io::Error> for RuntimeError {
+    fn from(e: std::io::Error) -> Self {
+        RuntimeError { message: e.to_string() }
+    }
+}
+
+/// One running copy of a process type, the formation decides how many there are.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Display)]
+#[display(fmt = "app-{}-v{}-{}-{}", application_id, release_version, process_type, index)]
+pub struct ProcessInstance {
+    pub application_id: i64,
+    /// Instances of the next release run next to the current ones while it is deployed.
+    pub release_version: i32,
+    pub process_type: String,
+    pub index: u32,
+}
+
+impl ProcessInstance {
+    /// Name of the instance among the instances of its application, like `web.0`.
+    pub fn process_name(&self) -> String {
+        format!("{}.{}", self.process_type, self.index)
+    }
+}
+
+/// How to start an instance: the `Procfile` command, run in the build artifact with the release's config vars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessSpec {
+    pub instance: ProcessInstance,
+    pub command: String,
+    pub working_dir: PathBuf,
+    pub env: ConfigVarMap,
+    pub size: ProcessSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum ProcessStatus {
+    #[display(fmt = "running")]
+    Running { pid: u32 },
+    /// The process ended on its own, `code` is absent if it was killed by a signal.
+    #[display(fmt = "exited")]
+    Exited { code: Option<i32> },
+    /// Stopped through the backend, or never started.
+    #[display(fmt = "stopped")]
+    Stopped,
+}
+
+/// Runs the processes of applications, the way `GitService` hosts their repositories.
+#[cfg_attr(test, automock)]
+pub trait RuntimeBackend: Send + Sync {
+    /// Fails if the instance is already running.
+    fn start(&self, spec: &ProcessSpec) -> Result<ProcessStatus, RuntimeError>;
+
+    /// Stopping an instance that does not run succeeds, so callers can safely retry.
+    fn stop(&self, instance: &ProcessInstance) -> Result<(), RuntimeError>;
+
+    fn status(&self, instance: &ProcessInstance) -> Result<ProcessStatus, RuntimeError>;
+
+    /// The last `lines` lines the instance wrote to stdout and stderr.
+    fn logs(&self, instance: &ProcessInstance, lines: usize) -> Result<Vec<String>, RuntimeError>;
+
+    /// Instances of the application started and not stopped yet, including the exited ones.
+    fn instances(&self, application_id: i64) -> Result<Vec<ProcessInstance>, RuntimeError>;
+}
@@ -0,0 +1,203 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::application::{Application, ApplicationError, GitRepository, GitService};
+use crate::application::collaborators::{Collaborator, Collaborators, Role};
+use crate::application::compensation::Compensations;
+use crate::organization::Organizations;
+
+pub struct TransferredApplication {
+    pub git_repository: GitRepository,
+}
+
+/// Hands an application over to another user or organization. The git repository moves to the
+/// namespace of the new owner; a previous owning user stays on as admin, a previous owning
+/// organization leaves, its members keep access only through their own collaborator roles.
+pub struct OwnershipTransfer<'a> {
+    git_service: &'a dyn GitService,
+    collaborators: &'a dyn Collaborators,
+    organizations: &'a dyn Organizations,
+}
+
+impl<'a> OwnershipTransfer<'a> {
+    pub fn new(git_service: &'a dyn GitService, collaborators: &'a dyn Collaborators, organizations: &'a dyn Organizations) -> Self {
+        Self { git_service, collaborators, organizations }
+    }
+
+    pub fn transfer(&self, application: &mut Application, new_owner: &str) -> Result<TransferredApplication, ApplicationError> {
+        if application.owner == new_owner {
+            return Err(ApplicationError::InvalidOwner { message: format!("{} already owns application {}", new_owner, application.name) });
+        }
+
+        let mut compensations = Compensations::new();
+
+        let result = self.run_steps(application, new_owner, &mut compensations)
+            .and_then(|git_repository| {
+                application.transfer_to(new_owner)?;
+
+                Ok(TransferredApplication { git_repository })
+            });
+
+        result.map_err(|e| compensations.unwind(e))
+    }
+
+    fn run_steps(&self, application: &Application, new_owner: &str, compensations: &mut Compensations<'a>) -> Result<GitRepository, ApplicationError> {
+        let (app_id, app_name, old_owner) = (application.id, application.name.to_string(), application.owner.clone());
+        let (git_service, collaborators) = (self.git_service, self.collaborators);
+
+        let git_repository = git_service.transfer_repo(&old_owner, &app_name, new_owner)?;
+        let (from, to, name) = (new_owner.to_string(), old_owner.clone(), app_name.clone());
+        compensations.push("transfer git repository", move || Ok(git_service.transfer_repo(&from, &name, &to).map(|_| ())?));
+
+        let previous = collaborators.find(app_id, new_owner)?;
+        collaborators.save(&Collaborator { application_id: app_id, user_name: new_owner.to_string(), role: Role::Owner })?;
+        let user_name = new_owner.to_string();
+        compensations.push("add new owner", move || {
+            match previous {
+                Some(previous) => collaborators.save(&previous)?,
+                None => collaborators.remove(app_id, &user_name)?,
+            }
+            Ok(())
+        });
+
+        let old_owner_collaborator = Collaborator { application_id: app_id, user_name: old_owner.clone(), role: Role::Owner };
+        if self.organizations.find_by_name(&old_owner)?.is_some() {
+            collaborators.remove(app_id, &old_owner)?;
+        } else {
+            collaborators.save(&Collaborator { role: Role::Admin, ..old_owner_collaborator.clone() })?;
+        }
+        compensations.push("step down previous owner", move || Ok(collaborators.save(&old_owner_collaborator)?));
+
+        Ok(git_repository)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::eq;
+
+    use crate::application::{Application, ApplicationError, ApplicationName, GitError, GitRepository, MockUpdater};
+    use crate::application::collaborators::{Collaborator, MockCollaborators, Role};
+    use crate::application::git::MockGitService;
+    use crate::application::transfer::OwnershipTransfer;
+    use crate::organization::{MockOrganizations, Organization};
+
+    fn application() -> Application {
+        Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "first_capsule_user".to_string())
+    }
+
+    fn collaborator(user_name: &str, role: Role) -> Collaborator {
+        Collaborator { application_id: 1, user_name: user_name.to_string(), role }
+    }
+
+    fn transfer_repo_succeeds(git_service: &mut MockGitService) {
+        git_service.expect_transfer_repo()
+            .with(eq("first_capsule_user"), eq("first-capsule-application"), eq("capsule-team"))
+            .times(1)
+            .returning(|_, name, owner| Ok(GitRepository { uri: format!("https://git.capsuleapp.cyou/{}/{}.git", owner, name) }));
+    }
+
+    fn no_organizations() -> MockOrganizations {
+        let mut organizations = MockOrganizations::new();
+        organizations.expect_find_by_name().returning(|_| Ok(None));
+        organizations
+    }
+
+    #[test]
+    fn should_move_git_repository_and_owner_role() {
+        let mut git_service = MockGitService::new();
+        transfer_repo_succeeds(&mut git_service);
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find().returning(|_, _| Ok(None));
+        collaborators.expect_save().with(eq(collaborator("capsule-team", Role::Owner))).times(1).returning(|_| Ok(()));
+        collaborators.expect_save().with(eq(collaborator("first_capsule_user", Role::Admin))).times(1).returning(|_| Ok(()));
+        let organizations = no_organizations();
+        let mut application = application();
+
+        let transferred = OwnershipTransfer::new(&git_service, &collaborators, &organizations)
+            .transfer(&mut application, "capsule-team")
+            .expect("transfer failed");
+
+        assert_eq!(application.owner, "capsule-team");
+        assert_eq!(transferred.git_repository.uri, "https://git.capsuleapp.cyou/capsule-team/first-capsule-application.git");
+    }
+
+    #[test]
+    fn should_remove_previous_owning_organization() {
+        let mut git_service = MockGitService::new();
+        git_service.expect_transfer_repo().returning(|_, _, _| Ok(GitRepository { uri: "".to_string() }));
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find().returning(|_, _| Ok(None));
+        collaborators.expect_save().with(eq(collaborator("first_capsule_user", Role::Owner))).times(1).returning(|_| Ok(()));
+        collaborators.expect_remove().with(eq(1), eq("capsule-team")).times(1).returning(|_, _| Ok(()));
+        let mut organizations = MockOrganizations::new();
+        organizations.expect_find_by_name().with(eq("capsule-team")).returning(|name| Ok(Some(Organization::new(name).unwrap())));
+        let mut application = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "capsule-team".to_string());
+
+        OwnershipTransfer::new(&git_service, &collaborators, &organizations)
+            .transfer(&mut application, "first_capsule_user")
+            .expect("transfer failed");
+
+        assert_eq!(application.owner, "first_capsule_user");
+    }
+
+    #[test]
+    fn should_not_transfer_to_current_owner() {
+        let git_service = MockGitService::new();
+        let collaborators = MockCollaborators::new();
+        let organizations = MockOrganizations::new();
+
+        let result = OwnershipTransfer::new(&git_service, &collaborators, &organizations).transfer(&mut application(), "first_capsule_user");
+
+        assert!(matches!(result, Err(ApplicationError::InvalidOwner { .. })));
+    }
+
+    #[test]
+    fn should_keep_collaborators_if_git_transfer_failed() {
+        let mut git_service = MockGitService::new();
+        git_service.expect_transfer_repo().times(1).returning(|_, _, _| Err(GitError {}));
+        let collaborators = MockCollaborators::new();
+        let organizations = MockOrganizations::new();
+        let mut application = application();
+
+        let result = OwnershipTransfer::new(&git_service, &collaborators, &organizations).transfer(&mut application, "capsule-team");
+
+        assert!(matches!(result, Err(ApplicationError::GitError { .. })));
+        assert_eq!(application.owner, "first_capsule_user");
+    }
+
+    #[test]
+    fn should_undo_everything_if_application_update_failed() {
+        let mut git_service = MockGitService::new();
+        transfer_repo_succeeds(&mut git_service);
+        git_service.expect_transfer_repo()
+            .with(eq("capsule-team"), eq("first-capsule-application"), eq("first_capsule_user"))
+            .times(1)
+            .returning(|_, _, _| Ok(GitRepository { uri: "".to_string() }));
+        let mut collaborators = MockCollaborators::new();
+        collaborators.expect_find().with(eq(1), eq("capsule-team")).returning(|_, _| Ok(Some(collaborator("capsule-team", Role::Viewer))));
+        collaborators.expect_save().with(eq(collaborator("capsule-team", Role::Owner))).times(1).returning(|_| Ok(()));
+        collaborators.expect_save().with(eq(collaborator("first_capsule_user", Role::Admin))).times(1).returning(|_| Ok(()));
+        collaborators.expect_save().with(eq(collaborator("first_capsule_user", Role::Owner))).times(1).returning(|_| Ok(()));
+        collaborators.expect_save().with(eq(collaborator("capsule-team", Role::Viewer))).times(1).returning(|_| Ok(()));
+        let organizations = no_organizations();
+        let mut updater = MockUpdater::new();
+        updater.expect_update().returning(|a| Err(ApplicationError::ConcurrentModification { name: a.name.to_string() }));
+        let mut application = application().with_updater(Box::new(updater));
+
+        let result = OwnershipTransfer::new(&git_service, &collaborators, &organizations).transfer(&mut application, "capsule-team");
+
+        assert!(matches!(result, Err(ApplicationError::ConcurrentModification { .. })));
+        assert_eq!(application.owner, "first_capsule_user");
+    }
+}
@@ -0,0 +1,252 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cmp::Reverse;
+use std::path::Path;
+
+use git2::{ObjectType, Oid, Repository, Tree};
+
+use crate::buildpack::{BuildpackError, Detection, parse_procfile, PROCFILE, ProcessTypes, Stack};
+
+/// A file whose presence suggests a stack. Lock files only add to a stack found by its manifest.
+struct Marker {
+    path: &'static str,
+    confidence: u8,
+    lock_file: bool,
+}
+
+const fn manifest(path: &'static str, confidence: u8) -> Marker {
+    Marker { path, confidence, lock_file: false }
+}
+
+const fn lock_file(path: &'static str) -> Marker {
+    Marker { path, confidence: LOCK_FILE_BONUS, lock_file: true }
+}
+
+const LOCK_FILE_BONUS: u8 = 5;
+
+/// A Dockerfile states how to build outright, so it wins over everything guessed from manifests.
+const RULES: &[(Stack, &[Marker])] = &[
+    (Stack::Dockerfile, &[manifest("Dockerfile", 100)]),
+    (Stack::Rust, &[manifest("Cargo.toml", 90), lock_file("Cargo.lock")]),
+    (Stack::Node, &[manifest("package.json", 90), lock_file("package-lock.json"), lock_file("yarn.lock")]),
+    (Stack::Python, &[
+        manifest("requirements.txt", 90), manifest("Pipfile", 90), manifest("pyproject.toml", 90), manifest("setup.py", 80),
+        lock_file("Pipfile.lock"), lock_file("poetry.lock"),
+    ]),
+    (Stack::Go, &[manifest("go.mod", 90), manifest("main.go", 60), lock_file("go.sum")]),
+    (Stack::Static, &[manifest("index.html", 60), manifest("public/index.html", 50)]),
+];
+
+/// Reads the tree of a commit in a bare repository and tells which stacks it looks like.
+pub struct Detector<'a> {
+    repository: &'a Repository,
+}
+
+impl<'a> Detector<'a> {
+    pub fn new(repository: &'a Repository) -> Self {
+        Self { repository }
+    }
+
+    /// Opens the bare repository at `path`, as created by the git server.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Repository, BuildpackError> {
+        Ok(Repository::open_bare(path)?)
+    }
+
+    /// Detected stacks of the commit, most confident first. Empty if nothing looks buildable.
+    pub fn detect(&self, commit: &str) -> Result<Vec<Detection>, BuildpackError> {
+        let tree = self.tree_of(commit)?;
+
+        let mut detections: Vec<Detection> = RULES.iter()
+            .filter_map(|(stack, markers)| detect_stack(&tree, *stack, markers))
+            .collect();
+        // stable, so equally confident stacks keep the order of the rules.
+        detections.sort_by_key(|d| Reverse(d.confidence));
+
+        Ok(detections)
+    }
+
+    /// Process types declared by the `Procfile` of the commit, empty if it has none.
+    pub fn process_types(&self, commit: &str) -> Result<ProcessTypes, BuildpackError> {
+        let tree = self.tree_of(commit)?;
+        let entry = match tree.get_path(Path::new(PROCFILE)) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Blob) => entry,
+            _ => return Ok(ProcessTypes::new()),
+        };
+        let blob = self.repository.find_blob(entry.id())?;
+
+        let content = std::str::from_utf8(blob.content()).map_err(|e| BuildpackError::InvalidProcfile {
+            line: blob.content()[..e.valid_up_to()].iter().filter(|b| **b == b'\n').count() + 1,
+            message: "not valid UTF-8".to_string(),
+        })?;
+        parse_procfile(content)
+    }
+
+    fn tree_of(&self, commit: &str) -> Result<Tree<'a>, BuildpackError> {
+        let not_found = || BuildpackError::CommitNotFound { commit: commit.to_string() };
+        let oid = Oid::from_str(commit).map_err(|_| not_found())?;
+        let object = self.repository.find_object(oid, None).map_err(|_| not_found())?;
+
+        Ok(object.peel_to_commit().map_err(|_| not_found())?.tree()?)
+    }
+}
+
+fn detect_stack(tree: &Tree, stack: Stack, markers: &[Marker]) -> Option<Detection> {
+    let found: Vec<&Marker> = markers.iter().filter(|m| is_file(tree, m.path)).collect();
+    let confidence = found.iter().filter(|m| !m.lock_file).map(|m| m.confidence).max()?;
+    let bonus = found.iter().filter(|m| m.lock_file).map(|m| m.confidence).max().unwrap_or(0);
+
+    Some(Detection {
+        stack,
+        confidence: confidence.saturating_add(bonus).min(100),
+        evidence: found.iter().map(|m| m.path.to_string()).collect(),
+    })
+}
+
+fn is_file(tree: &Tree, path: &str) -> bool {
+    matches!(tree.get_path(Path::new(path)), Ok(entry) if entry.kind() == Some(ObjectType::Blob))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs::{read, read_dir};
+    use std::path::Path;
+
+    use git2::{Oid, Repository, Signature};
+    use tempdir::TempDir;
+
+    use crate::buildpack::{BuildpackError, Detector, Stack};
+
+    /// Commits the files of `_fixture/repos/{name}` to a new bare repository, returning the repository and the commit.
+    pub(crate) fn fixture_repo(name: &str) -> (TempDir, Repository, String) {
+        let repo_dir = TempDir::new("detector").unwrap();
+        let repository = Repository::init_bare(repo_dir.path().join("capsule").join(format!("{}.git", name))).unwrap();
+
+        let tree_id = write_tree(&repository, &Path::new("./_fixture/repos").join(name));
+        let commit_id = {
+            let tree = repository.find_tree(tree_id).unwrap();
+            let signature = Signature::now("capsule", "capsule@capsuleapp.cyou").unwrap();
+            repository.commit(Some("refs/heads/main"), &signature, &signature, "fixture", &tree, &[]).unwrap()
+        };
+
+        (repo_dir, repository, commit_id.to_string())
+    }
+
+    fn write_tree(repository: &Repository, dir: &Path) -> Oid {
+        let mut builder = repository.treebuilder(None).unwrap();
+        for entry in read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let name = entry.file_name();
+            if entry.file_type().unwrap().is_dir() {
+                builder.insert(&name, write_tree(repository, &entry.path()), 0o040000).unwrap();
+            } else {
+                builder.insert(&name, repository.blob(&read(entry.path()).unwrap()).unwrap(), 0o100644).unwrap();
+            }
+        }
+
+        builder.write().unwrap()
+    }
+
+    fn detected(name: &str) -> Vec<(Stack, u8)> {
+        let (_dir, repository, commit) = fixture_repo(name);
+
+        Detector::new(&repository).detect(&commit).unwrap().into_iter().map(|d| (d.stack, d.confidence)).collect()
+    }
+
+    #[test]
+    fn should_detect_rust_with_lock_file() {
+        assert_eq!(vec![(Stack::Rust, 95)], detected("rust"));
+    }
+
+    #[test]
+    fn should_rank_node_above_static_files_it_serves() {
+        assert_eq!(vec![(Stack::Node, 95), (Stack::Static, 50)], detected("node"));
+    }
+
+    #[test]
+    fn should_detect_python() {
+        assert_eq!(vec![(Stack::Python, 90)], detected("python"));
+    }
+
+    #[test]
+    fn should_detect_go() {
+        assert_eq!(vec![(Stack::Go, 90)], detected("go"));
+    }
+
+    #[test]
+    fn should_detect_static_site() {
+        assert_eq!(vec![(Stack::Static, 60)], detected("static"));
+    }
+
+    #[test]
+    fn should_prefer_dockerfile() {
+        assert_eq!(vec![(Stack::Dockerfile, 100), (Stack::Node, 90)], detected("dockerfile"));
+    }
+
+    #[test]
+    fn should_detect_nothing_in_unknown_repository() {
+        assert!(detected("empty").is_empty());
+    }
+
+    #[test]
+    fn should_name_evidence() {
+        let (_dir, repository, commit) = fixture_repo("node");
+
+        let detections = Detector::new(&repository).detect(&commit).unwrap();
+
+        assert_eq!(vec!["package.json".to_string(), "package-lock.json".to_string()], detections[0].evidence);
+    }
+
+    #[test]
+    fn should_read_process_types_from_procfile() {
+        let (_dir, repository, commit) = fixture_repo("node");
+
+        let process_types = Detector::new(&repository).process_types(&commit).unwrap();
+
+        assert_eq!(vec![("web", "node index.js"), ("worker", "node worker.js")],
+                   process_types.iter().map(|(t, c)| (t.as_str(), c.as_str())).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_have_no_process_types_without_procfile() {
+        let (_dir, repository, commit) = fixture_repo("go");
+
+        assert!(Detector::new(&repository).process_types(&commit).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_fail_for_invalid_procfile() {
+        let (_dir, repository, commit) = fixture_repo("python");
+
+        let result = Detector::new(&repository).process_types(&commit);
+
+        assert!(matches!(result, Err(BuildpackError::InvalidProcfile { line: 2, .. })));
+    }
+
+    #[test]
+    fn should_fail_for_unknown_commit() {
+        let (_dir, repository, _) = fixture_repo("rust");
+
+        let result = Detector::new(&repository).detect("0123456789012345678901234567890123456789");
+
+        assert!(matches!(result, Err(BuildpackError::CommitNotFound { .. })));
+    }
+
+    #[test]
+    fn should_open_bare_repository() {
+        let (dir, _, commit) = fixture_repo("go");
+        let repository = Detector::open(dir.path().join("capsule").join("go.git")).unwrap();
+
+        assert_eq!(Stack::Go, Detector::new(&repository).detect(&commit).unwrap()[0].stack);
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use derive_more::{Display, Error};
+
+pub use crate::buildpack::detector::Detector;
+pub use crate::buildpack::procfile::{parse_procfile, PROCFILE, ProcessTypes, RELEASE_PROCESS_TYPE, WEB_PROCESS_TYPE};
+
+mod detector;
+mod procfile;
+
+#[derive(Debug, Error, Display)]
+pub enum BuildpackError {
+    #[display(fmt = "git error {}", message)]
+    GitError { message: String },
+    #[display(fmt = "commit {} not found", commit)]
+    CommitNotFound { commit: String },
+    #[display(fmt = "invalid Procfile, line {}: {}", line, message)]
+    InvalidProcfile { line: usize, message: String },
+}
+
+impl From<git2::Error> for BuildpackError {
+    fn from(e: git2::Error) -> Self {
+        BuildpackError::GitError { message: e.message().to_string() }
+    }
+}
+
+/// The kinds of applications capsule knows how to build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+pub enum Stack {
+    #[display(fmt = "dockerfile")]
+    Dockerfile,
+    #[display(fmt = "rust")]
+    Rust,
+    #[display(fmt = "node")]
+    Node,
+    #[display(fmt = "python")]
+    Python,
+    #[display(fmt = "go")]
+    Go,
+    #[display(fmt = "static")]
+    Static,
+}
+
+/// A stack a commit looks like, with the files that gave it away. Confidence ranges from 0 to 100.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub stack: Stack,
+    pub confidence: u8,
+    pub evidence: Vec<String>,
+}
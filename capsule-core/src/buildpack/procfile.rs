@@ -0,0 +1,118 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+
+use crate::buildpack::BuildpackError;
+
+pub const PROCFILE: &str = "Procfile";
+/// The process type receiving HTTP traffic on `$PORT`.
+pub const WEB_PROCESS_TYPE: &str = "web";
+/// The process type run once before each release is deployed, never scaled.
+pub const RELEASE_PROCESS_TYPE: &str = "release";
+
+/// Commands of an application by process type, e.g. `web`, `worker` and `release`.
+pub type ProcessTypes = BTreeMap<String, String>;
+
+const MAX_PROCESS_TYPE_LENGTH: usize = 63;
+
+/// Parses `type: command` lines. Blank lines and lines starting with `#` are skipped.
+pub fn parse_procfile(content: &str) -> Result<ProcessTypes, BuildpackError> {
+    let mut process_types = ProcessTypes::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: String| BuildpackError::InvalidProcfile { line: index + 1, message };
+        let (process_type, command) = line.split_once(':')
+            .ok_or_else(|| invalid("expected `<process type>: <command>`".to_string()))?;
+        let (process_type, command) = (process_type.trim(), command.trim());
+
+        validate_process_type(process_type).map_err(invalid)?;
+        if command.is_empty() {
+            return Err(invalid(format!("process type {} has no command", process_type)));
+        }
+        if process_types.insert(process_type.to_string(), command.to_string()).is_some() {
+            return Err(invalid(format!("process type {} is declared twice", process_type)));
+        }
+    }
+
+    Ok(process_types)
+}
+
+fn validate_process_type(process_type: &str) -> Result<(), String> {
+    if process_type.is_empty() {
+        return Err("process type is empty".to_string());
+    }
+    if process_type.len() > MAX_PROCESS_TYPE_LENGTH {
+        return Err(format!("process type {} is longer than {} characters", process_type, MAX_PROCESS_TYPE_LENGTH));
+    }
+    if !process_type.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_') {
+        return Err(format!("process type {} may only contain lowercase letters, digits, '-' and '_'", process_type));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buildpack::BuildpackError;
+    use crate::buildpack::procfile::{parse_procfile, ProcessTypes};
+
+    #[test]
+    fn should_parse_process_types() {
+        let content = "# processes\nweb: ./target/release/app --port $PORT\n\nworker:bundle exec sidekiq\nrelease: ./migrate.sh\n";
+
+        let expected = ProcessTypes::from([
+            ("web".to_string(), "./target/release/app --port $PORT".to_string()),
+            ("worker".to_string(), "bundle exec sidekiq".to_string()),
+            ("release".to_string(), "./migrate.sh".to_string()),
+        ]);
+        assert_eq!(expected, parse_procfile(content).unwrap());
+    }
+
+    #[test]
+    fn should_keep_colons_in_command() {
+        let process_types = parse_procfile("web: gunicorn app:app --bind 0.0.0.0:$PORT").unwrap();
+
+        assert_eq!("gunicorn app:app --bind 0.0.0.0:$PORT", process_types["web"]);
+    }
+
+    #[test]
+    fn should_report_line_of_invalid_entry() {
+        let cases = [
+            ("web: node index.js\nworker node worker.js", 2),
+            ("\n\nweb:", 3),
+            ("Web: node index.js", 1),
+            (": node index.js", 1),
+            ("web: node index.js\nweb: node other.js", 2),
+        ];
+
+        for (content, expected_line) in cases {
+            match parse_procfile(content) {
+                Err(BuildpackError::InvalidProcfile { line, .. }) => assert_eq!(expected_line, line, "{:?}", content),
+                other => panic!("expected invalid Procfile for {:?}, got {:?}", content, other),
+            }
+        }
+    }
+
+    #[test]
+    fn should_describe_error() {
+        let error = parse_procfile("web node index.js").unwrap_err();
+
+        assert_eq!("invalid Procfile, line 1: expected `<process type>: <command>`", error.to_string());
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::CoreError;
+use crate::id::IdGenerator;
+
+// 2022-01-01T00:00:00Z
+const CAPSULE_EPOCH_MILLIS: u64 = 1_640_995_200_000;
+
+const NODE_ID_BITS: u8 = 10;
+const SEQUENCE_BITS: u8 = 12;
+
+const MAX_NODE_ID: u16 = (1 << NODE_ID_BITS) - 1;
+const MAX_SEQUENCE: u16 = (1 << SEQUENCE_BITS) - 1;
+const MAX_TIMESTAMP: u64 = (1 << 41) - 1;
+
+// clock drift we are willing to sit out before refusing to hand out ids.
+const MAX_BACKWARD_DRIFT_MILLIS: u64 = 10;
+
+/// Generates 63-bit ids made of 41 bits of milliseconds since the capsule epoch,
+/// 10 bits of node id and a 12 bits per-millisecond sequence.
+///
+/// Ids are unique across restarts as long as the wall clock does not go backwards,
+/// and across server instances as long as every instance runs with its own node id.
+pub struct SnowflakeIdGenerator {
+    node_id: u16,
+    state: Mutex<State>,
+    clock: Box<dyn Fn() -> u64 + Send + Sync>,
+}
+
+struct State {
+    last_timestamp: u64,
+    sequence: u16,
+}
+
+impl SnowflakeIdGenerator {
+    pub fn new(node_id: u16) -> Result<Self, CoreError> {
+        Self::with_clock(node_id, Box::new(current_millis))
+    }
+
+    fn with_clock(node_id: u16, clock: Box<dyn Fn() -> u64 + Send + Sync>) -> Result<Self, CoreError> {
+        if node_id > MAX_NODE_ID {
+            return Err(CoreError { message: format!("node id {} is out of range 0..={}", node_id, MAX_NODE_ID) });
+        }
+
+        Ok(Self { node_id, state: Mutex::new(State { last_timestamp: 0, sequence: 0 }), clock })
+    }
+
+    fn timestamp(&self) -> Result<u64, CoreError> {
+        let now = (self.clock)();
+
+        if now < CAPSULE_EPOCH_MILLIS {
+            return Err(CoreError { message: "system clock is earlier than capsule epoch".to_string() });
+        }
+
+        let timestamp = now - CAPSULE_EPOCH_MILLIS;
+
+        if timestamp > MAX_TIMESTAMP {
+            return Err(CoreError { message: "id generator timestamp overflow".to_string() });
+        }
+
+        Ok(timestamp)
+    }
+
+    fn wait_until_after(&self, last_timestamp: u64) -> Result<u64, CoreError> {
+        let mut timestamp = self.timestamp()?;
+
+        while timestamp <= last_timestamp {
+            thread::sleep(Duration::from_micros(100));
+            timestamp = self.timestamp()?;
+        }
+
+        Ok(timestamp)
+    }
+}
+
+impl IdGenerator for SnowflakeIdGenerator {
+    fn next_id(&self) -> Result<i64, CoreError> {
+        let mut state = self.state.lock()
+            .map_err(|_| CoreError { message: "id generator state poisoned".to_string() })?;
+
+        let mut timestamp = self.timestamp()?;
+
+        if timestamp < state.last_timestamp {
+            if state.last_timestamp - timestamp > MAX_BACKWARD_DRIFT_MILLIS {
+                return Err(CoreError { message: format!("clock moved backwards by {}ms", state.last_timestamp - timestamp) });
+            }
+
+            timestamp = self.wait_until_after(state.last_timestamp - 1)?;
+        }
+
+        if timestamp == state.last_timestamp {
+            if state.sequence == MAX_SEQUENCE {
+                timestamp = self.wait_until_after(state.last_timestamp)?;
+                state.sequence = 0;
+            } else {
+                state.sequence += 1;
+            }
+        } else {
+            state.sequence = 0;
+        }
+
+        state.last_timestamp = timestamp;
+
+        let id = (timestamp << (NODE_ID_BITS + SEQUENCE_BITS))
+            | ((self.node_id as u64) << SEQUENCE_BITS)
+            | state.sequence as u64;
+
+        Ok(id as i64)
+    }
+}
+
+fn current_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::thread;
+
+    use crate::id::IdGenerator;
+    use crate::id::snowflake::{CAPSULE_EPOCH_MILLIS, MAX_SEQUENCE, SnowflakeIdGenerator};
+
+    #[test]
+    fn should_generate_unique_ids() {
+        let generator = SnowflakeIdGenerator::new(1).unwrap();
+
+        let ids: HashSet<i64> = (0..10000).map(|_| generator.next_id().unwrap()).collect();
+
+        assert_eq!(ids.len(), 10000);
+    }
+
+    #[test]
+    fn should_generate_increasing_ids() {
+        let generator = SnowflakeIdGenerator::new(1).unwrap();
+
+        let first = generator.next_id().unwrap();
+        let second = generator.next_id().unwrap();
+
+        assert!(second > first);
+    }
+
+    #[test]
+    fn should_generate_unique_ids_across_threads() {
+        let generator = Arc::new(SnowflakeIdGenerator::new(1).unwrap());
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let generator = generator.clone();
+            thread::spawn(move || (0..2000).map(|_| generator.next_id().unwrap()).collect::<Vec<i64>>())
+        }).collect();
+
+        let ids: HashSet<i64> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+
+        assert_eq!(ids.len(), 8000);
+    }
+
+    #[test]
+    fn should_not_collide_between_nodes_at_same_millisecond() {
+        let first_node = SnowflakeIdGenerator::with_clock(1, Box::new(|| CAPSULE_EPOCH_MILLIS + 1000)).unwrap();
+        let second_node = SnowflakeIdGenerator::with_clock(2, Box::new(|| CAPSULE_EPOCH_MILLIS + 1000)).unwrap();
+
+        assert_ne!(first_node.next_id().unwrap(), second_node.next_id().unwrap());
+    }
+
+    #[test]
+    fn should_embed_timestamp_and_node_id() {
+        let generator = SnowflakeIdGenerator::with_clock(5, Box::new(|| CAPSULE_EPOCH_MILLIS + 1000)).unwrap();
+
+        let id = generator.next_id().unwrap();
+
+        assert_eq!(id >> 22, 1000);
+        assert_eq!((id >> 12) & 0x3ff, 5);
+        assert_eq!(id & 0xfff, 0);
+    }
+
+    #[test]
+    fn should_wait_for_next_millisecond_when_sequence_exhausted() {
+        let now = Arc::new(AtomicU64::new(CAPSULE_EPOCH_MILLIS + 1000));
+        let clock = now.clone();
+        let generator = SnowflakeIdGenerator::with_clock(1, Box::new(move || clock.load(Ordering::SeqCst))).unwrap();
+
+        for _ in 0..=MAX_SEQUENCE {
+            generator.next_id().unwrap();
+        }
+
+        let ticker = now.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(20));
+            ticker.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let id = generator.next_id().unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(id >> 22, 1001);
+        assert_eq!(id & 0xfff, 0);
+    }
+
+    #[test]
+    fn should_reject_ids_when_clock_moved_backwards() {
+        let now = Arc::new(AtomicU64::new(CAPSULE_EPOCH_MILLIS + 1000));
+        let clock = now.clone();
+        let generator = SnowflakeIdGenerator::with_clock(1, Box::new(move || clock.load(Ordering::SeqCst))).unwrap();
+
+        generator.next_id().unwrap();
+        now.store(CAPSULE_EPOCH_MILLIS + 500, Ordering::SeqCst);
+
+        assert!(generator.next_id().is_err());
+    }
+
+    #[test]
+    fn should_reject_out_of_range_node_id() {
+        assert!(SnowflakeIdGenerator::new(1024).is_err());
+    }
+}
@@ -17,10 +17,16 @@ extern crate diesel;
 #[macro_use]
 extern crate downcast_rs;
 
+use derive_more::Display;
+
 pub mod user;
 pub mod application;
+pub mod id;
+pub mod organization;
+pub mod buildpack;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Display)]
+#[display(fmt = "{}", message)]
 pub struct CoreError {
     message: String,
 
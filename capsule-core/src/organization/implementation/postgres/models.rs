@@ -0,0 +1,35 @@
+use std::time::SystemTime;
+
+use super::schema::capsule_organization_members;
+use super::schema::capsule_organizations;
+
+#[derive(Queryable)]
+pub struct SavedOrganization {
+    pub organization_name: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_organizations"]
+pub struct NewOrganization {
+    pub organization_name: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Queryable)]
+pub struct SavedMember {
+    pub id: i32,
+    pub organization_name: String,
+    pub user_name: String,
+    pub role: String,
+    pub create_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "capsule_organization_members"]
+pub struct NewMember {
+    pub organization_name: String,
+    pub user_name: String,
+    pub role: String,
+    pub create_at: SystemTime,
+}
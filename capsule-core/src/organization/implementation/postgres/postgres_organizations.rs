@@ -0,0 +1,195 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use diesel::{Connection, ExpressionMethods, insert_into, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use diesel::dsl::{exists, select};
+
+use crate::CoreError;
+use crate::organization::{Member, Organization, OrganizationError, OrganizationRole, Organizations};
+use crate::organization::implementation::postgres::models::{NewMember, NewOrganization, SavedMember, SavedOrganization};
+use crate::organization::implementation::postgres::schema::{capsule_organization_members, capsule_organizations};
+use crate::user::implementation::postgres::schema::capsule_users;
+
+pub struct PostgresOrganizations {
+    connection: Arc<PgConnection>,
+}
+
+impl PostgresOrganizations {
+    pub fn new(connection: Arc<PgConnection>) -> PostgresOrganizations {
+        PostgresOrganizations { connection }
+    }
+
+    fn name_taken(&self, name: &str) -> Result<bool, CoreError> {
+        let organization_found = select(exists(capsule_organizations::table.filter(capsule_organizations::organization_name.eq(name))))
+            .get_result::<bool>(self.connection.as_ref())?;
+        let user_found = select(exists(capsule_users::table.filter(capsule_users::user_name.eq(name))))
+            .get_result::<bool>(self.connection.as_ref())?;
+
+        Ok(organization_found || user_found)
+    }
+}
+
+impl TryFrom<SavedMember> for Member {
+    type Error = CoreError;
+
+    fn try_from(saved_member: SavedMember) -> Result<Self, Self::Error> {
+        let role = OrganizationRole::from_str(saved_member.role.as_str())
+            .map_err(|e| CoreError { message: e.to_string() })?;
+
+        Ok(Member { organization_name: saved_member.organization_name, user_name: saved_member.user_name, role })
+    }
+}
+
+impl Organizations for PostgresOrganizations {
+    fn add(&self, organization: &Organization, owner: &str) -> Result<(), OrganizationError> {
+        let already_exists = || OrganizationError::OrganizationAlreadyExists { name: organization.name.clone() };
+
+        if self.name_taken(&organization.name)? {
+            return Err(already_exists());
+        }
+
+        let new_organization = NewOrganization { organization_name: organization.name.clone(), create_at: organization.create_at };
+        let new_owner = NewMember {
+            organization_name: organization.name.clone(),
+            user_name: owner.to_string(),
+            role: OrganizationRole::Owner.to_string(),
+            create_at: organization.create_at,
+        };
+
+        let result = self.connection.transaction::<_, diesel::result::Error, _>(|| {
+            insert_into(capsule_organizations::table).values(&new_organization).execute(self.connection.as_ref())?;
+            insert_into(capsule_organization_members::table).values(&new_owner).execute(self.connection.as_ref())?;
+            Ok(())
+        });
+
+        match result {
+            Ok(_) => Ok(()),
+            // someone else took the name between the check and the insert.
+            Err(_) if self.name_taken(&organization.name)? => Err(already_exists()),
+            Err(e) => Err(CoreError::from(e).into()),
+        }
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Organization>, CoreError> {
+        let saved_organization = capsule_organizations::table
+            .filter(capsule_organizations::organization_name.eq(name))
+            .select((capsule_organizations::organization_name, capsule_organizations::create_at))
+            .first::<SavedOrganization>(self.connection.as_ref())
+            .optional()?;
+
+        Ok(saved_organization.map(|o| Organization { name: o.organization_name, create_at: o.create_at }))
+    }
+
+    fn save_member(&self, member: &Member) -> Result<(), CoreError> {
+        let new_member = NewMember {
+            organization_name: member.organization_name.clone(),
+            user_name: member.user_name.clone(),
+            role: member.role.to_string(),
+            create_at: SystemTime::now(),
+        };
+
+        insert_into(capsule_organization_members::table)
+            .values(&new_member)
+            .on_conflict((capsule_organization_members::organization_name, capsule_organization_members::user_name))
+            .do_update()
+            .set(capsule_organization_members::role.eq(member.role.to_string()))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+
+    fn find_member(&self, organization_name: &str, user_name: &str) -> Result<Option<Member>, CoreError> {
+        let saved_member = capsule_organization_members::table
+            .filter(capsule_organization_members::organization_name.eq(organization_name))
+            .filter(capsule_organization_members::user_name.eq(user_name))
+            .first::<SavedMember>(self.connection.as_ref())
+            .optional()?;
+
+        saved_member.map(Member::try_from).transpose()
+    }
+
+    fn list_members(&self, organization_name: &str) -> Result<Vec<Member>, CoreError> {
+        let saved_members = capsule_organization_members::table
+            .filter(capsule_organization_members::organization_name.eq(organization_name))
+            .order(capsule_organization_members::id.asc())
+            .load::<SavedMember>(self.connection.as_ref())?;
+
+        saved_members.into_iter().map(Member::try_from).collect()
+    }
+
+    fn remove_member(&self, organization_name: &str, user_name: &str) -> Result<(), CoreError> {
+        diesel::delete(capsule_organization_members::table
+            .filter(capsule_organization_members::organization_name.eq(organization_name))
+            .filter(capsule_organization_members::user_name.eq(user_name)))
+            .execute(self.connection.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::RunQueryDsl;
+
+    use test_tool::get_shared_test_db_connection;
+
+    use crate::organization::{Member, Organization, OrganizationError, OrganizationRole, Organizations};
+    use crate::organization::implementation::postgres::postgres_organizations::PostgresOrganizations;
+
+    fn member(user_name: &str, role: OrganizationRole) -> Member {
+        Member { organization_name: "capsule-team".to_string(), user_name: user_name.to_string(), role }
+    }
+
+    #[test]
+    fn should_add_organization_with_owner() {
+        let organizations = PostgresOrganizations::new(get_shared_test_db_connection());
+
+        organizations.add(&Organization::new("capsule-team").unwrap(), "first_capsule_user").expect("add organization failed");
+
+        assert_eq!(organizations.find_by_name("capsule-team").unwrap().unwrap().name, "capsule-team");
+        assert_eq!(organizations.list_members("capsule-team").unwrap(), vec![member("first_capsule_user", OrganizationRole::Owner)]);
+    }
+
+    #[test]
+    fn should_not_add_organization_with_taken_name() {
+        let connection = get_shared_test_db_connection();
+        let organizations = PostgresOrganizations::new(connection.clone());
+        organizations.add(&Organization::new("capsule-team").unwrap(), "first_capsule_user").expect("add organization failed");
+        diesel::sql_query("insert into capsule_users (user_name, create_at) values ('capsule-user', now())")
+            .execute(connection.as_ref())
+            .unwrap();
+
+        let same_organization = organizations.add(&Organization::new("capsule-team").unwrap(), "other_user");
+        let same_as_user = organizations.add(&Organization::new("capsule-user").unwrap(), "other_user");
+
+        assert!(matches!(same_organization, Err(OrganizationError::OrganizationAlreadyExists { .. })));
+        assert!(matches!(same_as_user, Err(OrganizationError::OrganizationAlreadyExists { .. })));
+    }
+
+    #[test]
+    fn should_save_find_and_remove_members() {
+        let organizations = PostgresOrganizations::new(get_shared_test_db_connection());
+        organizations.add(&Organization::new("capsule-team").unwrap(), "first_capsule_user").expect("add organization failed");
+
+        organizations.save_member(&member("new_user", OrganizationRole::Member)).expect("save member failed");
+        organizations.save_member(&member("new_user", OrganizationRole::Admin)).expect("save member failed");
+        assert_eq!(organizations.find_member("capsule-team", "new_user").unwrap(), Some(member("new_user", OrganizationRole::Admin)));
+
+        organizations.remove_member("capsule-team", "new_user").expect("remove member failed");
+        assert!(organizations.find_member("capsule-team", "new_user").unwrap().is_none());
+    }
+}
@@ -0,0 +1,17 @@
+table! {
+    capsule_organizations (id) {
+        id -> Int4,
+        organization_name -> Varchar,
+        create_at -> Timestamp,
+    }
+}
+
+table! {
+    capsule_organization_members (id) {
+        id -> Int4,
+        organization_name -> Varchar,
+        user_name -> Varchar,
+        role -> Varchar,
+        create_at -> Timestamp,
+    }
+}
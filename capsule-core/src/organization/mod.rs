@@ -0,0 +1,264 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use derive_more::{Display, Error};
+#[cfg(test)]
+use mockall::{automock, predicate::*};
+
+use crate::CoreError;
+pub use crate::organization::implementation::postgres::postgres_organizations::PostgresOrganizations;
+
+pub(crate) mod implementation;
+
+#[derive(Debug, Error, Display)]
+pub enum OrganizationError {
+    #[display(fmt = "invalid organization name: {}", message)]
+    InvalidOrganizationName { message: String },
+    #[display(fmt = "organization name {} is already taken", name)]
+    OrganizationAlreadyExists { name: String },
+    #[display(fmt = "invalid role: {}", message)]
+    InvalidRole { message: String },
+    #[display(fmt = "permission denied: {}", message)]
+    PermissionDenied { message: String },
+    #[display(fmt = "{}", message)]
+    InvalidMembership { message: String },
+    #[display(fmt = "internal error {}", message)]
+    InternalError { message: String },
+}
+
+impl From<CoreError> for OrganizationError {
+    fn from(e: CoreError) -> Self {
+        OrganizationError::InternalError { message: e.to_string() }
+    }
+}
+
+/// A group of users owning applications together. Organizations and users share one namespace,
+/// since both appear as the owner segment of git repository paths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Organization {
+    pub name: String,
+    pub create_at: SystemTime,
+}
+
+impl Organization {
+    pub fn new(name: &str) -> Result<Self, OrganizationError> {
+        validate_name(name)?;
+
+        Ok(Self { name: name.to_string(), create_at: SystemTime::now() })
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), OrganizationError> {
+    let invalid = |message: &str| Err(OrganizationError::InvalidOrganizationName { message: message.to_string() });
+
+    if name.len() < 3 || name.len() > 39 {
+        return invalid("must be 3 to 39 characters long");
+    }
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return invalid("must only contain lowercase letters, digits and hyphens");
+    }
+    if name.starts_with('-') || name.ends_with('-') {
+        return invalid("must not start or end with a hyphen");
+    }
+
+    Ok(())
+}
+
+/// What a member may do within an organization: owner > admin > member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display)]
+pub enum OrganizationRole {
+    #[display(fmt = "member")]
+    Member,
+    #[display(fmt = "admin")]
+    Admin,
+    #[display(fmt = "owner")]
+    Owner,
+}
+
+impl FromStr for OrganizationRole {
+    type Err = OrganizationError;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "owner" => Ok(OrganizationRole::Owner),
+            "admin" => Ok(OrganizationRole::Admin),
+            "member" => Ok(OrganizationRole::Member),
+            _ => Err(OrganizationError::InvalidRole { message: format!("'{}' is not one of owner, admin, member", role) }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub organization_name: String,
+    pub user_name: String,
+    pub role: OrganizationRole,
+}
+
+#[cfg_attr(test, automock)]
+pub trait Organizations {
+    /// Creates the organization with `owner` as its first member. Fails with
+    /// `OrganizationAlreadyExists` if an organization or a user already has the name.
+    fn add(&self, organization: &Organization, owner: &str) -> Result<(), OrganizationError>;
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Organization>, CoreError>;
+
+    /// Adds the member, or changes the role if the user is a member already.
+    fn save_member(&self, member: &Member) -> Result<(), CoreError>;
+
+    fn find_member(&self, organization_name: &str, user_name: &str) -> Result<Option<Member>, CoreError>;
+
+    fn list_members(&self, organization_name: &str) -> Result<Vec<Member>, CoreError>;
+
+    fn remove_member(&self, organization_name: &str, user_name: &str) -> Result<(), CoreError>;
+}
+
+/// Fails with `PermissionDenied` unless `user_name` is a member of the organization with at least `required`.
+pub fn require_member_role(organizations: &dyn Organizations, organization_name: &str, user_name: &str, required: OrganizationRole) -> Result<Member, OrganizationError> {
+    match organizations.find_member(organization_name, user_name)? {
+        Some(member) if member.role >= required => Ok(member),
+        _ => Err(OrganizationError::PermissionDenied {
+            message: format!("{} needs the {} role in organization {}", user_name, required, organization_name)
+        }),
+    }
+}
+
+/// Users own applications themselves, and on behalf of the organizations they administer.
+pub fn can_own_applications(organizations: &dyn Organizations, user_name: &str, owner: &str) -> Result<bool, CoreError> {
+    if user_name == owner {
+        return Ok(true);
+    }
+
+    Ok(matches!(organizations.find_member(owner, user_name)?, Some(member) if member.role >= OrganizationRole::Admin))
+}
+
+/// Changes the role of a member, or removes it with `None`, without ever leaving the organization
+/// without an owner. Only owners may hand out or take away the owner role.
+pub fn change_membership(organizations: &dyn Organizations, organization_name: &str, acting_user: &str, user_name: &str, role: Option<OrganizationRole>) -> Result<(), OrganizationError> {
+    let acting = require_member_role(organizations, organization_name, acting_user, OrganizationRole::Admin)?;
+    let current = organizations.find_member(organization_name, user_name)?;
+
+    let touches_owner = role == Some(OrganizationRole::Owner) || matches!(&current, Some(m) if m.role == OrganizationRole::Owner);
+    if touches_owner && acting.role != OrganizationRole::Owner {
+        return Err(OrganizationError::PermissionDenied { message: "only owners may change owners".to_string() });
+    }
+
+    let demotes_owner = matches!(&current, Some(m) if m.role == OrganizationRole::Owner) && role != Some(OrganizationRole::Owner);
+    if demotes_owner {
+        let owners = organizations.list_members(organization_name)?.iter().filter(|m| m.role == OrganizationRole::Owner).count();
+        if owners <= 1 {
+            return Err(OrganizationError::InvalidMembership { message: "an organization needs at least one owner".to_string() });
+        }
+    }
+
+    match role {
+        Some(role) => organizations.save_member(&Member { organization_name: organization_name.to_string(), user_name: user_name.to_string(), role })?,
+        None => organizations.remove_member(organization_name, user_name)?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use mockall::predicate::eq;
+
+    use crate::organization::{can_own_applications, change_membership, Member, MockOrganizations, Organization, OrganizationError, OrganizationRole};
+
+    fn member(user_name: &str, role: OrganizationRole) -> Member {
+        Member { organization_name: "capsule-team".to_string(), user_name: user_name.to_string(), role }
+    }
+
+    fn organizations_with(members: Vec<Member>) -> MockOrganizations {
+        let mut organizations = MockOrganizations::new();
+        let found = members.clone();
+        organizations.expect_find_member()
+            .returning(move |_, user| Ok(found.iter().find(|m| m.user_name == user).cloned()));
+        organizations.expect_list_members().returning(move |_| Ok(members.clone()));
+        organizations
+    }
+
+    #[test]
+    fn should_validate_organization_name() {
+        assert!(Organization::new("capsule-team").is_ok());
+        assert!(matches!(Organization::new("ab"), Err(OrganizationError::InvalidOrganizationName { .. })));
+        assert!(matches!(Organization::new("Capsule"), Err(OrganizationError::InvalidOrganizationName { .. })));
+        assert!(matches!(Organization::new("capsule_team"), Err(OrganizationError::InvalidOrganizationName { .. })));
+        assert!(matches!(Organization::new("-capsule"), Err(OrganizationError::InvalidOrganizationName { .. })));
+    }
+
+    #[test]
+    fn should_parse_organization_role() {
+        assert_eq!(OrganizationRole::from_str("admin").unwrap(), OrganizationRole::Admin);
+        assert!(OrganizationRole::from_str("deployer").is_err());
+        assert!(OrganizationRole::Owner > OrganizationRole::Admin);
+    }
+
+    #[test]
+    fn should_let_admins_own_applications_for_organization() {
+        let organizations = organizations_with(vec![member("admin_user", OrganizationRole::Admin), member("some_member", OrganizationRole::Member)]);
+
+        assert!(can_own_applications(&organizations, "some_user", "some_user").unwrap());
+        assert!(can_own_applications(&organizations, "admin_user", "capsule-team").unwrap());
+        assert!(!can_own_applications(&organizations, "some_member", "capsule-team").unwrap());
+        assert!(!can_own_applications(&organizations, "stranger", "capsule-team").unwrap());
+    }
+
+    #[test]
+    fn should_let_admin_add_member() {
+        let mut organizations = organizations_with(vec![member("admin_user", OrganizationRole::Admin)]);
+        organizations.expect_save_member().with(eq(member("new_user", OrganizationRole::Member))).times(1).returning(|_| Ok(()));
+
+        change_membership(&organizations, "capsule-team", "admin_user", "new_user", Some(OrganizationRole::Member)).expect("add member failed");
+    }
+
+    #[test]
+    fn should_not_let_admin_make_owner() {
+        let organizations = organizations_with(vec![member("admin_user", OrganizationRole::Admin)]);
+
+        let result = change_membership(&organizations, "capsule-team", "admin_user", "new_user", Some(OrganizationRole::Owner));
+
+        assert!(matches!(result, Err(OrganizationError::PermissionDenied { .. })));
+    }
+
+    #[test]
+    fn should_not_remove_last_owner() {
+        let organizations = organizations_with(vec![member("owner_user", OrganizationRole::Owner)]);
+
+        let result = change_membership(&organizations, "capsule-team", "owner_user", "owner_user", None);
+
+        assert!(matches!(result, Err(OrganizationError::InvalidMembership { .. })));
+    }
+
+    #[test]
+    fn should_remove_owner_if_another_owner_left() {
+        let mut organizations = organizations_with(vec![member("owner_user", OrganizationRole::Owner), member("other_owner", OrganizationRole::Owner)]);
+        organizations.expect_remove_member().with(eq("capsule-team"), eq("other_owner")).times(1).returning(|_, _| Ok(()));
+
+        change_membership(&organizations, "capsule-team", "owner_user", "other_owner", None).expect("remove owner failed");
+    }
+
+    #[test]
+    fn should_not_let_member_change_membership() {
+        let organizations = organizations_with(vec![member("some_member", OrganizationRole::Member)]);
+
+        let result = change_membership(&organizations, "capsule-team", "some_member", "new_user", Some(OrganizationRole::Member));
+
+        assert!(matches!(result, Err(OrganizationError::PermissionDenied { .. })));
+    }
+}
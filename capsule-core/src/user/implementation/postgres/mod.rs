@@ -16,7 +16,7 @@ use diesel::PgConnection;
 use crate::user::{User, UserFactory};
 use crate::user::implementation::postgres::postgres_credentials::PostgresCredentials;
 
-mod schema;
+pub(crate) mod schema;
 mod models;
 pub mod postgres_repository;
 pub(crate) mod postgres_credentials;
@@ -0,0 +1,201 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use isahc::{ReadResponseExt, Request, RequestExt};
+use isahc::http::StatusCode;
+use serde::Deserialize;
+
+const USER_HEADER: &str = "X-Capsule-User";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitAccess {
+    Fetch,
+    Push,
+}
+
+/// The repository and kind of access a git smart http request asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitRequest {
+    pub owner: String,
+    pub app_name: String,
+    pub access: GitAccess,
+}
+
+impl GitRequest {
+    /// Parses `/{owner}/{app_name}.git/...` as sent to git-http-backend. A push is either the
+    /// `git-receive-pack` endpoint itself or the ref advertisement asking for that service.
+    pub fn parse(uri: &str) -> Option<GitRequest> {
+        let (path, query) = match uri.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (uri, ""),
+        };
+
+        let mut segments = path.trim_start_matches('/').splitn(3, '/');
+        let owner = segments.next().filter(|s| !s.is_empty())?;
+        let app_name = segments.next()?.strip_suffix(".git").filter(|s| !s.is_empty())?;
+        let rest = segments.next().unwrap_or("");
+
+        let is_push = rest == "git-receive-pack" || query.split('&').any(|p| p == "service=git-receive-pack");
+        let access = if is_push { GitAccess::Push } else { GitAccess::Fetch };
+
+        Some(GitRequest { owner: owner.to_string(), app_name: app_name.to_string(), access })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizationError {
+    pub message: String,
+}
+
+/// Where the git server learns the role a user has on an application.
+pub trait RoleLookup {
+    fn role_of(&self, app_name: &str, user: &str) -> Result<Option<String>, AuthorizationError>;
+}
+
+/// Every collaborator may fetch, only deployers and above may push.
+pub fn is_allowed(request: &GitRequest, user: &str, roles: &dyn RoleLookup) -> Result<bool, AuthorizationError> {
+    let role = match roles.role_of(&request.app_name, user)? {
+        Some(role) => role,
+        None => return Ok(false),
+    };
+
+    Ok(match request.access {
+        GitAccess::Fetch => true,
+        GitAccess::Push => matches!(role.as_str(), "owner" | "admin" | "deployer"),
+    })
+}
+
+#[derive(Deserialize)]
+struct CollaboratorResponse {
+    role: String,
+}
+
+/// Asks the capsule server, acting as the user, for the user's own collaborator entry.
+pub struct CapsuleServerRoles {
+    pub host_uri: String,
+}
+
+impl RoleLookup for CapsuleServerRoles {
+    fn role_of(&self, app_name: &str, user: &str) -> Result<Option<String>, AuthorizationError> {
+        let error = |e: &dyn std::fmt::Display| AuthorizationError { message: e.to_string() };
+
+        let mut response = Request::get(format!("{}/applications/{}/collaborators/{}", self.host_uri, app_name, user))
+            .header(USER_HEADER, user)
+            .body(())
+            .map_err(|e| error(&e))?
+            .send()
+            .map_err(|e| error(&e))?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let collaborator: CollaboratorResponse = response.json().map_err(|e| error(&e))?;
+                Ok(Some(collaborator.role))
+            }
+            StatusCode::NOT_FOUND | StatusCode::FORBIDDEN => Ok(None),
+            status => Err(AuthorizationError { message: format!("capsule server answered {}", status) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{header, method, path};
+
+    use crate::authorization::{AuthorizationError, CapsuleServerRoles, GitAccess, GitRequest, is_allowed, RoleLookup};
+
+    struct RoleStub(Option<&'static str>);
+
+    impl RoleLookup for RoleStub {
+        fn role_of(&self, _app_name: &str, _user: &str) -> Result<Option<String>, AuthorizationError> {
+            Ok(self.0.map(|r| r.to_string()))
+        }
+    }
+
+    #[test]
+    fn should_parse_push_requests() {
+        let expect = Some(GitRequest { owner: "capsule".to_string(), app_name: "first-capsule-application".to_string(), access: GitAccess::Push });
+
+        assert_eq!(GitRequest::parse("/capsule/first-capsule-application.git/info/refs?service=git-receive-pack"), expect);
+        assert_eq!(GitRequest::parse("/capsule/first-capsule-application.git/git-receive-pack"), expect);
+    }
+
+    #[test]
+    fn should_parse_fetch_requests() {
+        let request = GitRequest::parse("/capsule/first-capsule-application.git/info/refs?service=git-upload-pack").unwrap();
+        assert_eq!(request.access, GitAccess::Fetch);
+
+        let request = GitRequest::parse("/capsule/first-capsule-application.git/git-upload-pack").unwrap();
+        assert_eq!(request.access, GitAccess::Fetch);
+    }
+
+    #[test]
+    fn should_not_parse_non_repository_paths() {
+        assert_eq!(GitRequest::parse("/"), None);
+        assert_eq!(GitRequest::parse("/capsule"), None);
+        assert_eq!(GitRequest::parse("/capsule/first-capsule-application/info/refs"), None);
+    }
+
+    #[test]
+    fn should_allow_push_for_deployer_and_above_only() {
+        let push = GitRequest::parse("/capsule/app.git/git-receive-pack").unwrap();
+
+        for role in ["owner", "admin", "deployer"] {
+            assert!(is_allowed(&push, "user", &RoleStub(Some(role))).unwrap());
+        }
+        assert!(!is_allowed(&push, "user", &RoleStub(Some("viewer"))).unwrap());
+        assert!(!is_allowed(&push, "user", &RoleStub(None)).unwrap());
+    }
+
+    #[test]
+    fn should_allow_fetch_for_every_collaborator() {
+        let fetch = GitRequest::parse("/capsule/app.git/git-upload-pack").unwrap();
+
+        assert!(is_allowed(&fetch, "user", &RoleStub(Some("viewer"))).unwrap());
+        assert!(!is_allowed(&fetch, "user", &RoleStub(None)).unwrap());
+    }
+
+    #[async_std::test]
+    async fn should_look_up_role_from_capsule_server() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/applications/first-capsule-application/collaborators/deploy_user"))
+            .and(header("X-Capsule-User", "deploy_user"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"user":"deploy_user","role":"deployer"}"#, "application/json"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/applications/first-capsule-application/collaborators/stranger"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let roles = CapsuleServerRoles { host_uri: mock_server.uri() };
+
+        assert_eq!(roles.role_of("first-capsule-application", "deploy_user").unwrap(), Some("deployer".to_string()));
+        assert_eq!(roles.role_of("first-capsule-application", "stranger").unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn should_fail_if_capsule_server_failed() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let roles = CapsuleServerRoles { host_uri: mock_server.uri() };
+
+        assert!(roles.role_of("first-capsule-application", "deploy_user").is_err());
+    }
+}
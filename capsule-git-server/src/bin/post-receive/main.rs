@@ -0,0 +1,63 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! The `post-receive` hook installed into every application repository. It reports the pushed
+//! refs to the capsule server, which queues the builds. A push can not be undone from here, so
+//! failures are only reported back to the pusher.
+use std::env;
+use std::fs::read_to_string;
+use std::io::{Read, stdin};
+use std::path::PathBuf;
+use std::process::exit;
+
+// shared with the git server, which installs the hook and writes its config.
+#[allow(dead_code)]
+#[path = "../../hook.rs"]
+mod hook;
+mod dispatch;
+
+use dispatch::{BuildDispatchRequest, dispatch_builds, parse_pushed_refs, repository_of};
+use hook::{HOOK_CONFIG_FILE, HookConfig};
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("capsule: {}", message);
+        exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    // hooks of bare repositories run inside the repository.
+    let git_dir = env::var("GIT_DIR").map(PathBuf::from).or_else(|_| env::current_dir()).map_err(|e| e.to_string())?;
+    let git_dir = git_dir.canonicalize().map_err(|e| e.to_string())?;
+    let (owner, app_name) = repository_of(&git_dir).ok_or_else(|| format!("{} is not an application repository", git_dir.display()))?;
+
+    let config_json = read_to_string(git_dir.join("hooks").join(HOOK_CONFIG_FILE)).map_err(|e| format!("read hook config error: {}", e))?;
+    let config: HookConfig = serde_json::from_str(&config_json).map_err(|e| format!("read hook config error: {}", e))?;
+
+    let mut input = String::new();
+    stdin().read_to_string(&mut input).map_err(|e| e.to_string())?;
+
+    let request = BuildDispatchRequest {
+        // set by git-http-backend for authenticated pushes.
+        pushed_by: env::var("REMOTE_USER").unwrap_or(owner),
+        refs: parse_pushed_refs(&input),
+    };
+    let builds = dispatch_builds(&config, &app_name, &request).map_err(|e| format!("build dispatch failed: {}", e.message))?;
+
+    for build in builds {
+        println!("capsule: queued build {} of {} at {}", build.id, build.ref_name, build.commit_sha);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use serde::{Deserialize, Serialize};
+
+/// Name of the hook binary in the hooks directory, and of the hook it becomes in every repository.
+pub const POST_RECEIVE_HOOK: &str = "post-receive";
+
+/// Written next to the hook in every repository, since hooks run with the environment of
+/// git-http-backend and can not see the settings of the git server.
+pub const HOOK_CONFIG_FILE: &str = "capsule-hook.json";
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct HookConfig {
+    pub capsule_server_uri: String,
+    pub build_dispatch_token: String,
+}
@@ -11,10 +11,38 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use actix_web::{App, HttpServer, middleware, web};
+
+use crate::context::GitServerContext;
+use crate::resources::repository;
+
 mod context;
 mod settings;
 mod repo;
+mod authorization;
+mod hook;
 mod resources;
 
-fn main() {}
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let context = web::Data::new(GitServerContext::new());
+
+    let bind_addr = context.settings.ctl_server.listen_addr.clone();
+    let bind_port = context.settings.ctl_server.listen_port;
+
+    HttpServer::new(move || App::new()
+        .app_data(context.clone())
+        .wrap(middleware::Logger::default())
+        .service(resources::authorization::authorize)
+        .service(repository::create_repository)
+        .service(repository::delete_repository)
+        .service(repository::rename_repository)
+        .service(repository::transfer_repository))
+        .bind((IpAddr::from_str(bind_addr.as_str()).unwrap(), bind_port))?
+        .run()
+        .await
+}
 
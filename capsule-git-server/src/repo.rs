@@ -11,12 +11,13 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use std::fs::copy;
+use std::fs::{copy, create_dir_all, remove_dir_all, rename, write};
 use std::path::{Path, PathBuf};
 
 use git2::{Error, Repository};
 
-use crate::repo::ErrorKind::{GitRepoAlreadyExists, GitRepoNotInitialized};
+use crate::hook::{HOOK_CONFIG_FILE, HookConfig};
+use crate::repo::ErrorKind::{GitRepoAlreadyExists, GitRepoNotFound, GitRepoNotInitialized};
 
 pub struct GitRepository {
     pub user: String,
@@ -28,6 +29,7 @@ pub struct GitRepository {
 pub enum ErrorKind {
     GitRepoAlreadyExists,
     GitRepoNotInitialized,
+    GitRepoNotFound,
     GitError(String),
 }
 
@@ -73,6 +75,46 @@ impl GitRepository {
         Ok(())
     }
 
+    pub fn delete_repository(&self) -> Result<(), GitRepoErr> {
+        if !self.repo_path().exists() {
+            return Err(GitRepoErr { error_kind: GitRepoNotFound });
+        }
+
+        remove_dir_all(self.repo_path())?;
+
+        Ok(())
+    }
+
+    pub fn rename_repository(&self, new_name: &str) -> Result<GitRepository, GitRepoErr> {
+        self.move_to(GitRepository::new(&self.user, new_name, &self.directory))
+    }
+
+    pub fn transfer_repository(&self, new_user: &str) -> Result<GitRepository, GitRepoErr> {
+        self.move_to(GitRepository::new(new_user, &self.name, &self.directory))
+    }
+
+    fn move_to(&self, target: GitRepository) -> Result<GitRepository, GitRepoErr> {
+        if !self.repo_path().exists() {
+            // a retried move finds the work already done.
+            if target.repo_path().exists() {
+                return Ok(target);
+            }
+
+            return Err(GitRepoErr { error_kind: GitRepoNotFound });
+        }
+
+        if target.repo_path().exists() {
+            return Err(GitRepoErr { error_kind: GitRepoAlreadyExists });
+        }
+
+        if let Some(parent) = target.repo_path().parent() {
+            create_dir_all(parent)?;
+        }
+        rename(self.repo_path(), target.repo_path())?;
+
+        Ok(target)
+    }
+
     pub fn repo_path(&self) -> PathBuf {
         let repo_path = format!("{}/{}/{}.git", self.directory, self.user, self.name);
 
@@ -93,6 +135,18 @@ impl GitRepository {
 
         Ok(())
     }
+
+    pub fn write_hook_config(&self, config: &HookConfig) -> Result<(), GitRepoErr> {
+        let hooks_dir = self.repo_path().join("hooks");
+        if !hooks_dir.exists() {
+            return Err(GitRepoErr { error_kind: GitRepoNotInitialized });
+        }
+
+        let config_json = serde_json::to_string(config).map_err(|e| GitRepoErr { error_kind: ErrorKind::GitError(e.to_string()) })?;
+        write(hooks_dir.join(HOOK_CONFIG_FILE), config_json)?;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -101,7 +155,7 @@ mod tests {
 
     use tempdir::TempDir;
 
-    use crate::repo::ErrorKind::{GitRepoAlreadyExists, GitRepoNotInitialized};
+    use crate::repo::ErrorKind::{GitRepoAlreadyExists, GitRepoNotFound, GitRepoNotInitialized};
     use crate::repo::GitRepository;
 
     #[test]
@@ -153,4 +207,84 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.err().unwrap().error_kind, GitRepoAlreadyExists);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_delete_git_repo() {
+        let repo_dir = TempDir::new("test").unwrap();
+
+        let git_repo = GitRepository::new("first_capsule_user", "first_capsule_application", repo_dir.path());
+        git_repo.init_bare_repository().expect("init bare repo failed");
+
+        git_repo.delete_repository().expect("delete repo failed");
+
+        assert!(!git_repo.repo_path().exists());
+    }
+
+    #[test]
+    fn should_return_error_when_delete_not_exists_git_repo() {
+        let repo_dir = TempDir::new("test").unwrap();
+
+        let git_repo = GitRepository::new("first_capsule_user", "first_capsule_application", repo_dir.path());
+
+        let result = git_repo.delete_repository();
+
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().error_kind, GitRepoNotFound);
+    }
+
+    #[test]
+    fn should_rename_git_repo() {
+        let repo_dir = TempDir::new("test").unwrap();
+
+        let git_repo = GitRepository::new("first_capsule_user", "first_capsule_application", repo_dir.path());
+        git_repo.init_bare_repository().expect("init bare repo failed");
+
+        let renamed = git_repo.rename_repository("new_name").expect("rename repo failed");
+
+        assert!(!git_repo.repo_path().exists());
+        assert!(renamed.repo_path().join("hooks").exists());
+        assert_eq!(renamed.name, "new_name");
+    }
+
+    #[test]
+    fn should_transfer_git_repo_to_new_user() {
+        let repo_dir = TempDir::new("test").unwrap();
+
+        let git_repo = GitRepository::new("first_capsule_user", "first_capsule_application", repo_dir.path());
+        git_repo.init_bare_repository().expect("init bare repo failed");
+
+        let transferred = git_repo.transfer_repository("capsule-team").expect("transfer repo failed");
+
+        assert!(!git_repo.repo_path().exists());
+        assert!(repo_dir.path().join("capsule-team").join("first_capsule_application.git").join("hooks").exists());
+        assert_eq!(transferred.user, "capsule-team");
+
+        assert!(git_repo.transfer_repository("capsule-team").is_ok());
+    }
+
+    #[test]
+    fn should_succeed_when_rename_already_renamed_git_repo() {
+        let repo_dir = TempDir::new("test").unwrap();
+
+        let git_repo = GitRepository::new("first_capsule_user", "first_capsule_application", repo_dir.path());
+        git_repo.init_bare_repository().expect("init bare repo failed");
+        git_repo.rename_repository("new_name").expect("rename repo failed");
+
+        let result = git_repo.rename_repository("new_name");
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn should_return_error_when_rename_to_existing_git_repo() {
+        let repo_dir = TempDir::new("test").unwrap();
+
+        let git_repo = GitRepository::new("first_capsule_user", "first_capsule_application", repo_dir.path());
+        git_repo.init_bare_repository().expect("init bare repo failed");
+        GitRepository::new("first_capsule_user", "new_name", repo_dir.path()).init_bare_repository().expect("init bare repo failed");
+
+        let result = git_repo.rename_repository("new_name");
+
+        assert_eq!(result.err().unwrap().error_kind, GitRepoAlreadyExists);
+    }
+}
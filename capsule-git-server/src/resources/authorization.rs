@@ -0,0 +1,126 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use actix_web::{get, HttpRequest, HttpResponse, web};
+
+use crate::authorization::{AuthorizationError, CapsuleServerRoles, GitRequest, is_allowed};
+use crate::context::GitServerContext;
+use crate::resources::ApiError;
+
+const ORIGINAL_URI_HEADER: &str = "X-Original-URI";
+const REMOTE_USER_HEADER: &str = "X-Remote-User";
+
+impl From<AuthorizationError> for ApiError {
+    fn from(e: AuthorizationError) -> Self {
+        ApiError::InternalError { message: e.message }
+    }
+}
+
+/// Target of nginx `auth_request`, answers whether the authenticated user may run the git request.
+#[get("/authorize")]
+pub async fn authorize(request: HttpRequest, context: web::Data<GitServerContext>) -> Result<HttpResponse, ApiError> {
+    let header = |name: &str| request.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+
+    let user = header(REMOTE_USER_HEADER);
+    if user.is_empty() {
+        return Err(ApiError::Unauthorized { message: "no authenticated user".to_string() });
+    }
+
+    let git_request = match GitRequest::parse(header(ORIGINAL_URI_HEADER).as_str()) {
+        Some(git_request) => git_request,
+        None => return Err(ApiError::Forbidden { message: "not a git repository".to_string() }),
+    };
+
+    let roles = CapsuleServerRoles { host_uri: context.settings.capsule_server.uri.clone() };
+    if !is_allowed(&git_request, &user, &roles)? {
+        return Err(ApiError::Forbidden { message: format!("{} may not access {}", user, git_request.app_name) });
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{method, path};
+
+    use crate::context::GitServerContext;
+    use crate::resources::authorization::authorize;
+
+    async fn capsule_server(role: &str) -> MockServer {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/applications/first-capsule-application/collaborators/some_user"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(format!(r#"{{"user":"some_user","role":"{}"}}"#, role), "application/json"))
+            .mount(&mock_server)
+            .await;
+        mock_server
+    }
+
+    fn context(capsule_server: &MockServer) -> GitServerContext {
+        env::set_var("CAPSULE_GIT_CTL_CONFIG_DIR", "./_fixture");
+
+        let mut context = GitServerContext::new();
+        context.settings.capsule_server.uri = capsule_server.uri();
+
+        context
+    }
+
+    #[actix_web::test]
+    async fn should_authorize_push_of_deployer() {
+        let capsule_server = capsule_server("deployer").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(context(&capsule_server))).service(authorize)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/authorize")
+            .insert_header(("X-Original-URI", "/capsule/first-capsule-application.git/git-receive-pack"))
+            .insert_header(("X-Remote-User", "some_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_push_of_viewer() {
+        let capsule_server = capsule_server("viewer").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(context(&capsule_server))).service(authorize)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/authorize")
+            .insert_header(("X-Original-URI", "/capsule/first-capsule-application.git/info/refs?service=git-receive-pack"))
+            .insert_header(("X-Remote-User", "some_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn should_reject_request_without_user() {
+        let capsule_server = capsule_server("owner").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(context(&capsule_server))).service(authorize)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/authorize")
+            .insert_header(("X-Original-URI", "/capsule/first-capsule-application.git/git-receive-pack"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+    }
+}
@@ -18,11 +18,15 @@ use actix_web::{error, HttpResponse};
 use actix_web::http::StatusCode;
 use derive_more::Error;
 
-mod repository;
+pub mod authorization;
+pub mod repository;
 
 #[derive(Debug, Error)]
 pub enum ApiError {
     GitRepoError { message: String },
+    Unauthorized { message: String },
+    Forbidden { message: String },
+    NotFound { message: String },
     InternalError { message: String },
 }
 
@@ -35,6 +39,24 @@ impl Display for ApiError {
 
                 serde_json::to_string(&response).unwrap()
             }
+            ApiError::Unauthorized { message } => {
+                let mut response = HashMap::new();
+                response.insert("message", message);
+
+                serde_json::to_string(&response).unwrap()
+            }
+            ApiError::Forbidden { message } => {
+                let mut response = HashMap::new();
+                response.insert("message", message);
+
+                serde_json::to_string(&response).unwrap()
+            }
+            ApiError::NotFound { message } => {
+                let mut response = HashMap::new();
+                response.insert("message", message);
+
+                serde_json::to_string(&response).unwrap()
+            }
             ApiError::InternalError { message } => {
                 let mut response = HashMap::new();
                 response.insert("message", message);
@@ -51,6 +73,9 @@ impl error::ResponseError for ApiError {
     fn status_code(&self) -> StatusCode {
         match self {
             ApiError::GitRepoError { message: _ } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized { message: _ } => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden { message: _ } => StatusCode::FORBIDDEN,
+            ApiError::NotFound { message: _ } => StatusCode::NOT_FOUND,
             ApiError::InternalError { message: _ } => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -11,12 +11,13 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use actix_web::{HttpRequest, HttpResponse, post, Responder, web};
+use actix_web::{delete, HttpRequest, HttpResponse, patch, post, Responder, web};
 use actix_web::{body::BoxBody, http::header::ContentType};
 use serde::{Deserialize, Serialize};
 
 use crate::context::GitServerContext;
-use crate::repo::GitRepository;
+use crate::hook::{HookConfig, POST_RECEIVE_HOOK};
+use crate::repo::{ErrorKind, GitRepoErr, GitRepository};
 use crate::resources::ApiError;
 
 #[derive(Deserialize, Serialize)]
@@ -30,6 +31,16 @@ pub struct GitRepositoryCreateResponse {
     git_repo_uri: String,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct GitRepoRenameRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GitRepoTransferRequest {
+    pub user: String,
+}
+
 impl Responder for GitRepositoryCreateResponse {
     type Body = BoxBody;
 
@@ -42,6 +53,17 @@ impl Responder for GitRepositoryCreateResponse {
     }
 }
 
+impl From<GitRepoErr> for ApiError {
+    fn from(e: GitRepoErr) -> Self {
+        match e.error_kind {
+            ErrorKind::GitRepoNotFound => ApiError::NotFound { message: "git repository not found".to_string() },
+            ErrorKind::GitRepoAlreadyExists => ApiError::GitRepoError { message: "git repository already exists".to_string() },
+            ErrorKind::GitRepoNotInitialized => ApiError::GitRepoError { message: "git repository not initialized".to_string() },
+            ErrorKind::GitError(message) => ApiError::InternalError { message },
+        }
+    }
+}
+
 #[post("/repositories")]
 pub async fn create_repository(request: web::Json<GitRepoCreateRequest>, context: web::Data<GitServerContext>) -> Result<GitRepositoryCreateResponse, ApiError> {
     let git_repo = GitRepository {
@@ -50,19 +72,90 @@ pub async fn create_repository(request: web::Json<GitRepoCreateRequest>, context
         directory: context.settings.git_repo.directory.clone(),
     };
 
-    git_repo.init_bare_repository();
+    git_repo.init_bare_repository()?;
+    // a half set up repository would fail every retry as already existing.
+    if let Err(e) = install_post_receive_hook(&git_repo, &context) {
+        let _ = git_repo.delete_repository();
+        return Err(e.into());
+    }
 
-    let git_repo_uri = context.settings.git_repo.url_template
-        .replace("{user_name}", &request.user)
-        .replace("{app_name}", &request.name);
+    let git_repo_uri = git_repo_uri(&context, &request.user, &request.name);
 
     Ok(GitRepositoryCreateResponse { git_repo_uri })
 }
 
+fn install_post_receive_hook(git_repo: &GitRepository, context: &GitServerContext) -> Result<(), GitRepoErr> {
+    git_repo.install_git_hooks(&context.settings.git_hooks.directory, &vec![POST_RECEIVE_HOOK])?;
+    git_repo.write_hook_config(&HookConfig {
+        capsule_server_uri: context.settings.capsule_server.uri.clone(),
+        build_dispatch_token: context.settings.capsule_server.build_dispatch_token.clone(),
+    })
+}
+
+#[patch("/repositories/{user}/{name}")]
+pub async fn rename_repository(path: web::Path<(String, String)>, request: web::Json<GitRepoRenameRequest>, context: web::Data<GitServerContext>) -> Result<HttpResponse, ApiError> {
+    let (user, name) = path.into_inner();
+
+    if !is_path_segment(&user) || !is_path_segment(&name) || !is_path_segment(&request.name) {
+        return Err(ApiError::GitRepoError { message: "invalid git repository path".to_string() });
+    }
+
+    let git_repo = GitRepository::new(&user, &name, &context.settings.git_repo.directory);
+
+    let renamed = git_repo.rename_repository(&request.name)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&GitRepositoryCreateResponse { git_repo_uri: git_repo_uri(&context, &renamed.user, &renamed.name) }).unwrap()))
+}
+
+#[post("/repositories/{user}/{name}/transfer")]
+pub async fn transfer_repository(path: web::Path<(String, String)>, request: web::Json<GitRepoTransferRequest>, context: web::Data<GitServerContext>) -> Result<HttpResponse, ApiError> {
+    let (user, name) = path.into_inner();
+
+    if !is_path_segment(&user) || !is_path_segment(&name) || !is_path_segment(&request.user) {
+        return Err(ApiError::GitRepoError { message: "invalid git repository path".to_string() });
+    }
+
+    let git_repo = GitRepository::new(&user, &name, &context.settings.git_repo.directory);
+
+    let transferred = git_repo.transfer_repository(&request.user)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&GitRepositoryCreateResponse { git_repo_uri: git_repo_uri(&context, &transferred.user, &transferred.name) }).unwrap()))
+}
+
+#[delete("/repositories/{user}/{name}")]
+pub async fn delete_repository(path: web::Path<(String, String)>, context: web::Data<GitServerContext>) -> Result<HttpResponse, ApiError> {
+    let (user, name) = path.into_inner();
+
+    if !is_path_segment(&user) || !is_path_segment(&name) {
+        return Err(ApiError::GitRepoError { message: "invalid git repository path".to_string() });
+    }
+
+    let git_repo = GitRepository::new(&user, &name, &context.settings.git_repo.directory);
+
+    git_repo.delete_repository()?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn git_repo_uri(context: &GitServerContext, user: &str, name: &str) -> String {
+    context.settings.git_repo.url_template
+        .replace("{user_name}", user)
+        .replace("{app_name}", name)
+}
+
+// user and application names end up in a file system path, never let them walk out of the git directory.
+fn is_path_segment(segment: &str) -> bool {
+    !segment.is_empty() && !segment.starts_with('.') && !segment.contains(['/', '\\'])
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
-    use std::fs::remove_dir_all;
+    use std::fs::{read_to_string, remove_dir_all};
     use std::path::PathBuf;
 
     use actix_web::{App, http::{self}, middleware, test, web};
@@ -70,7 +163,9 @@ mod tests {
     use tempdir::TempDir;
 
     use crate::context::GitServerContext;
-    use crate::resources::repository::{create_repository, GitRepoCreateRequest, GitRepositoryCreateResponse};
+    use crate::hook::{HOOK_CONFIG_FILE, HookConfig, POST_RECEIVE_HOOK};
+    use crate::repo::GitRepository;
+    use crate::resources::repository::{create_repository, delete_repository, GitRepoCreateRequest, GitRepoRenameRequest, GitRepositoryCreateResponse, GitRepoTransferRequest, rename_repository, transfer_repository};
 
     #[actix_web::test]
     async fn should_return_git_repository_information_if_create_successfully() {
@@ -102,6 +197,201 @@ mod tests {
         assert_eq!(expect_json, body);
     }
 
+    #[actix_web::test]
+    async fn should_install_post_receive_hook_into_created_repository() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context_with_directory(&repo_dir)))
+                .service(create_repository))
+                .await;
+
+        let req = test::TestRequest::post()
+            .uri("/repositories")
+            .set_json(GitRepoCreateRequest { name: "first-capsule-application".to_string(), user: "capsule".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+        let hooks_dir = repo_dir.path().join("capsule").join("first-capsule-application.git").join("hooks");
+        assert_eq!("this is a test post-receive hook.", read_to_string(hooks_dir.join(POST_RECEIVE_HOOK)).unwrap());
+        let config: HookConfig = serde_json::from_str(&read_to_string(hooks_dir.join(HOOK_CONFIG_FILE)).unwrap()).unwrap();
+        assert_eq!(HookConfig {
+            capsule_server_uri: "https://api.capsuleapp.cyou".to_string(),
+            build_dispatch_token: "capsule-build-dispatch-token".to_string(),
+        }, config);
+    }
+
+    #[actix_web::test]
+    async fn should_remove_repository_if_installing_hook_failed() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let mut context = context_with_directory(&repo_dir);
+        let hooks_directory = context.settings.git_hooks.directory.clone();
+        context.settings.git_hooks.directory = repo_dir.path().join("not-exists-hooks").to_str().unwrap().to_string();
+        let context = web::Data::new(context);
+        let app = test::init_service(App::new().app_data(context.clone()).service(create_repository)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/repositories")
+            .set_json(GitRepoCreateRequest { name: "first-capsule-application".to_string(), user: "capsule".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!repo_dir.path().join("capsule").join("first-capsule-application.git").exists());
+
+        let mut context = context_with_directory(&repo_dir);
+        context.settings.git_hooks.directory = hooks_directory;
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(create_repository)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/repositories")
+            .set_json(GitRepoCreateRequest { name: "first-capsule-application".to_string(), user: "capsule".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+
+    #[actix_web::test]
+    async fn should_delete_git_repository() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let context = context_with_directory(&repo_dir);
+        let git_repo = GitRepository::new("capsule", "first-capsule-application", &context.settings.git_repo.directory);
+        git_repo.init_bare_repository().expect("init bare repo failed");
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(delete_repository))
+                .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/repositories/capsule/first-capsule-application")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+        assert!(!git_repo.repo_path().exists());
+    }
+
+    #[actix_web::test]
+    async fn should_return_not_found_if_delete_not_exists_git_repository() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context_with_directory(&repo_dir)))
+                .wrap(middleware::Logger::default())
+                .service(delete_repository))
+                .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/repositories/capsule/not-exists-application")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn should_reject_git_repository_path_outside_git_directory() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context_with_directory(&repo_dir)))
+                .wrap(middleware::Logger::default())
+                .service(delete_repository))
+                .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/repositories/capsule/..")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn should_rename_git_repository() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let context = context_with_directory(&repo_dir);
+        let git_repo = GitRepository::new("capsule", "first-capsule-application", &context.settings.git_repo.directory);
+        git_repo.init_bare_repository().expect("init bare repo failed");
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(rename_repository))
+                .await;
+
+        let req = test::TestRequest::patch()
+            .uri("/repositories/capsule/first-capsule-application")
+            .set_json(GitRepoRenameRequest { name: "new-name".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        let expect = GitRepositoryCreateResponse {
+            git_repo_uri: "https://git.capsuleapp.cyou/capsule/new-name.git".to_string(),
+        };
+        assert_eq!(serde_json::to_string(&expect).unwrap(), body);
+        assert!(repo_dir.path().join("capsule").join("new-name.git").exists());
+    }
+
+    #[actix_web::test]
+    async fn should_transfer_git_repository() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let context = context_with_directory(&repo_dir);
+        let git_repo = GitRepository::new("capsule", "first-capsule-application", &context.settings.git_repo.directory);
+        git_repo.init_bare_repository().expect("init bare repo failed");
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(transfer_repository))
+                .await;
+
+        let req = test::TestRequest::post()
+            .uri("/repositories/capsule/first-capsule-application/transfer")
+            .set_json(GitRepoTransferRequest { user: "capsule-team".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        let expect = GitRepositoryCreateResponse {
+            git_repo_uri: "https://git.capsuleapp.cyou/capsule-team/first-capsule-application.git".to_string(),
+        };
+        assert_eq!(serde_json::to_string(&expect).unwrap(), body);
+        assert!(repo_dir.path().join("capsule-team").join("first-capsule-application.git").exists());
+    }
+
+    #[actix_web::test]
+    async fn should_return_not_found_if_rename_not_exists_git_repository() {
+        let repo_dir = TempDir::new("test").unwrap();
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context_with_directory(&repo_dir)))
+                .wrap(middleware::Logger::default())
+                .service(rename_repository))
+                .await;
+
+        let req = test::TestRequest::patch()
+            .uri("/repositories/capsule/first-capsule-application")
+            .set_json(GitRepoRenameRequest { name: "new-name".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+
     fn context() -> GitServerContext {
         env::set_var("CAPSULE_GIT_CTL_CONFIG_DIR", "./_fixture");
 
@@ -111,4 +401,13 @@ mod tests {
 
         context
     }
-}
\ No newline at end of file
+
+    fn context_with_directory(repo_dir: &TempDir) -> GitServerContext {
+        env::set_var("CAPSULE_GIT_CTL_CONFIG_DIR", "./_fixture");
+
+        let mut context = GitServerContext::new();
+        context.settings.git_repo.directory = repo_dir.path().to_str().unwrap().to_string();
+
+        context
+    }
+}
@@ -31,11 +31,27 @@ pub struct GitRepo {
     pub directory: String,
 }
 
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct CapsuleServer {
+    pub uri: String,
+    pub build_dispatch_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct GitHooks {
+    /// Where the `post-receive` hook binary is installed from.
+    pub directory: String,
+}
+
 #[derive(Debug, Deserialize)]
 #[allow(unused)]
 pub struct Settings {
     pub ctl_server: CtlServer,
     pub git_repo: GitRepo,
+    pub capsule_server: CapsuleServer,
+    pub git_hooks: GitHooks,
 }
 
 impl Settings {
@@ -70,4 +86,18 @@ mod tests {
         assert_eq!(settings.git_repo.url_template, "https://git.capsuleapp.cyou/{user_name}/{app_name}.git");
         assert_eq!(settings.git_repo.directory, "/tmp/capsule/git");
     }
+    #[test]
+    fn should_read_capsule_server_config() {
+        let settings = Settings::new("./_fixture").unwrap();
+
+        assert_eq!(settings.capsule_server.uri, "https://api.capsuleapp.cyou");
+        assert_eq!(settings.capsule_server.build_dispatch_token, "capsule-build-dispatch-token");
+    }
+
+    #[test]
+    fn should_read_git_hooks_config() {
+        let settings = Settings::new("./_fixture").unwrap();
+
+        assert_eq!(settings.git_hooks.directory, "./_fixture/git_hooks");
+    }
 }
\ No newline at end of file
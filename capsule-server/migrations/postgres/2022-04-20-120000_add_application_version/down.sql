@@ -0,0 +1 @@
+ALTER TABLE capsule_applications DROP COLUMN version;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE capsule_applications
+    ADD COLUMN version integer not null default 0;
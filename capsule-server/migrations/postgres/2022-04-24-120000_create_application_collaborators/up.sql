@@ -0,0 +1,15 @@
+CREATE TABLE capsule_application_collaborators
+(
+    id             serial primary key,
+    application_id bigint       not null,
+    user_name      varchar(200) not null,
+    role           varchar(20)  not null,
+    create_at      timestamp    not null
+);
+
+create unique index capsule_application_collaborators_application_user_uindex on capsule_application_collaborators (application_id, user_name);
+
+-- every existing application keeps its owner as the first collaborator.
+insert into capsule_application_collaborators (application_id, user_name, role, create_at)
+select application_id, owner, 'owner', create_at
+from capsule_applications;
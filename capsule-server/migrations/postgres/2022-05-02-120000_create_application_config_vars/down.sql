@@ -0,0 +1,2 @@
+DROP TABLE capsule_application_config_changes;
+DROP TABLE capsule_application_config_vars;
\ No newline at end of file
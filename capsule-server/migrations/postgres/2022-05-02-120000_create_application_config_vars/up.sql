@@ -0,0 +1,22 @@
+CREATE TABLE capsule_application_config_vars
+(
+    id             serial primary key,
+    application_id bigint       not null,
+    var_key        varchar(200) not null,
+    var_value      bytea        not null,
+    create_at      timestamp    not null
+);
+
+create unique index capsule_application_config_vars_application_key_uindex on capsule_application_config_vars (application_id, var_key);
+
+-- only the keys are recorded, values stay in the encrypted table above.
+CREATE TABLE capsule_application_config_changes
+(
+    id             serial primary key,
+    application_id bigint       not null,
+    user_name      varchar(200) not null,
+    var_keys       text[]       not null,
+    create_at      timestamp    not null
+);
+
+create index capsule_application_config_changes_application_index on capsule_application_config_changes (application_id);
@@ -0,0 +1,14 @@
+CREATE TABLE capsule_application_releases
+(
+    id             serial primary key,
+    application_id bigint        not null,
+    version        integer       not null,
+    build_artifact varchar(500)  not null,
+    commit_sha     varchar(40)   not null,
+    config_vars    bytea         not null,
+    description    varchar(500)  not null,
+    created_by     varchar(200)  not null,
+    create_at      timestamp     not null
+);
+
+create unique index capsule_application_releases_application_version_uindex on capsule_application_releases (application_id, version);
@@ -0,0 +1,12 @@
+CREATE TABLE capsule_application_builds
+(
+    id             serial primary key,
+    application_id bigint       not null,
+    ref_name       varchar(500) not null,
+    commit_sha     varchar(40)  not null,
+    pushed_by      varchar(200) not null,
+    status         varchar(20)  not null,
+    create_at      timestamp    not null
+);
+
+create index capsule_application_builds_application_index on capsule_application_builds (application_id);
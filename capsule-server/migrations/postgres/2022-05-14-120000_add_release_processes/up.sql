@@ -0,0 +1,2 @@
+ALTER TABLE capsule_application_releases
+    ADD COLUMN processes text not null default '{}';
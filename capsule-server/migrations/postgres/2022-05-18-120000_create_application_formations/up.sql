@@ -0,0 +1,29 @@
+CREATE TABLE capsule_application_formations
+(
+    id             serial primary key,
+    application_id bigint       not null,
+    process_type   varchar(63)  not null,
+    quantity       integer      not null,
+    size           varchar(20)  not null,
+    update_at      timestamp    not null
+);
+
+create unique index capsule_application_formations_application_process_uindex on capsule_application_formations (application_id, process_type);
+
+CREATE TABLE capsule_formation_events
+(
+    id             serial primary key,
+    application_id bigint       not null,
+    process_type   varchar(63)  not null,
+    quantity       integer      not null,
+    size           varchar(20)  not null,
+    changed_by     varchar(200) not null,
+    create_at      timestamp    not null
+);
+
+CREATE TABLE capsule_owner_limits
+(
+    owner          varchar(200) primary key,
+    max_instances  integer      not null,
+    max_size       varchar(20)  not null
+);
@@ -0,0 +1 @@
+DROP TABLE capsule_application_health_checks;
\ No newline at end of file
@@ -0,0 +1,10 @@
+CREATE TABLE capsule_application_health_checks
+(
+    application_id      bigint       primary key,
+    path                varchar(500) not null,
+    interval_secs       integer      not null,
+    timeout_secs        integer      not null,
+    healthy_threshold   integer      not null,
+    unhealthy_threshold integer      not null,
+    update_at           timestamp    not null
+);
@@ -0,0 +1,2 @@
+DROP TABLE capsule_deploy_steps;
+DROP TABLE capsule_application_deploys;
\ No newline at end of file
@@ -0,0 +1,22 @@
+CREATE TABLE capsule_application_deploys
+(
+    id              serial primary key,
+    application_id  bigint       not null,
+    release_version integer      not null,
+    status          varchar(20)  not null,
+    created_by      varchar(200) not null,
+    create_at       timestamp    not null,
+    finish_at       timestamp
+);
+
+create index capsule_application_deploys_application_id_index on capsule_application_deploys (application_id);
+
+CREATE TABLE capsule_deploy_steps
+(
+    id              serial primary key,
+    deploy_id       integer      not null,
+    kind            varchar(30)  not null,
+    succeeded       boolean      not null,
+    message         text         not null,
+    create_at       timestamp    not null
+);
@@ -0,0 +1 @@
+DROP TABLE capsule_application_domains;
\ No newline at end of file
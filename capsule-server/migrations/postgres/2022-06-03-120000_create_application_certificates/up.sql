@@ -0,0 +1,24 @@
+CREATE TABLE capsule_application_certificates
+(
+    id                serial primary key,
+    application_id    bigint       not null,
+    hostname          varchar(253) not null,
+    certificate_chain bytea        not null,
+    private_key       bytea        not null,
+    not_after         timestamp    not null,
+    issued_at         timestamp    not null
+);
+
+create unique index capsule_application_certificates_hostname_uindex on capsule_application_certificates (hostname);
+create index capsule_application_certificates_application_id_index on capsule_application_certificates (application_id);
+
+CREATE TABLE capsule_acme_accounts
+(
+    id            serial primary key,
+    directory_uri varchar(500) not null,
+    account_uri   varchar(500) not null,
+    private_key   bytea        not null,
+    create_at     timestamp    not null
+);
+
+create unique index capsule_acme_accounts_directory_uri_uindex on capsule_acme_accounts (directory_uri);
@@ -0,0 +1 @@
+ALTER TABLE capsule_application_health_checks DROP COLUMN deploy_timeout_secs;
\ No newline at end of file
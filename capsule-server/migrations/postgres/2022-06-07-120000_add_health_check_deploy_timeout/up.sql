@@ -0,0 +1,2 @@
+ALTER TABLE capsule_application_health_checks
+    ADD COLUMN deploy_timeout_secs integer not null default 300;
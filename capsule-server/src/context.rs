@@ -11,28 +11,145 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use std::sync::Arc;
+use std::net::ToSocketAddrs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use capsule_core::application::{DefaultGitService, DomainNameService, GitService, NameCheapDomainNameService};
+use diesel::{Connection, PgConnection};
 
-use crate::settings::Settings;
+use capsule_core::application::{Applications, Builds, Certificates, Deploys, ConfigVars, ConfigVarsCipher, CustomDomains, DefaultGitService, DomainNameService, Formations, GitService, HealthChecker, HttpChallenges, HealthChecks, HttpHealthProbe, LocalRuntimeBackend, LogBuffer, NameCheapDomainNameService, Rfc2136DomainNameService, TsigKey, Collaborators, OwnerLimitsRepository, PostgresApplications, PostgresBuilds, PostgresCertificates, PostgresCollaborators, PostgresConfigVars, PostgresCustomDomains, PostgresDeploys, PostgresFormations, PostgresHealthChecks, PostgresOwnerLimits, PostgresRedirects, PostgresReleases, Redirects, Releases, RestartPolicy, RuntimeBackend, RuntimeError};
+use capsule_core::id::IdGenerator;
+use capsule_core::organization::{Organizations, PostgresOrganizations};
+
+use crate::settings::{DnsProvider, Settings};
+
+/// State of the server process as a whole. Every worker gets its own `ServerContext`, these are
+/// created once and shared by all of them.
+#[derive(Clone)]
+pub struct SharedServices {
+    pub id_generator: Arc<dyn IdGenerator>,
+    pub logs: Arc<LogBuffer>,
+    pub runtime: Arc<dyn RuntimeBackend>,
+    pub health_checker: Arc<HealthChecker>,
+    pub acme_challenges: Arc<HttpChallenges>,
+    /// Held by every change of the Namecheap host records, see `NameCheapDomainNameService`.
+    pub dns_hosts_lock: Arc<Mutex<()>>,
+}
+
+impl SharedServices {
+    /// Fails when the runtime backend can not be set up, e.g. because `runtime.cgroup_root` is
+    /// not delegated to the server.
+    pub fn new(settings: &Settings, id_generator: Arc<dyn IdGenerator>) -> Result<Self, RuntimeError> {
+        let logs = Arc::new(LogBuffer::new(settings.logs.buffer_lines));
+
+        let runtime: Arc<dyn RuntimeBackend> = Arc::new(LocalRuntimeBackend::new(settings.runtime.log_dir.as_str(), Duration::from_secs(settings.runtime.stop_timeout_secs))?
+            .with_cgroup_root(settings.runtime.cgroup_root.as_str())?
+            .with_log_sink(logs.clone()));
+        let restart_policy = RestartPolicy { max_restarts: settings.health.max_restarts };
+        let health_checker = Arc::new(HealthChecker::new(runtime.clone(), Arc::new(HttpHealthProbe), restart_policy));
+
+        Ok(Self { id_generator, logs, runtime, health_checker, acme_challenges: Arc::new(HttpChallenges::new()), dns_hosts_lock: Arc::new(Mutex::new(())) })
+    }
+}
 
 pub struct ServerContext {
     pub settings: Arc<Settings>,
     pub git_service: Arc<dyn GitService>,
     pub domain_name_service: Arc<dyn DomainNameService>,
+    pub id_generator: Arc<dyn IdGenerator>,
+    pub applications: Arc<dyn Applications>,
+    pub redirects: Arc<dyn Redirects>,
+    pub collaborators: Arc<dyn Collaborators>,
+    pub organizations: Arc<dyn Organizations>,
+    pub config_vars: Arc<dyn ConfigVars>,
+    pub releases: Arc<dyn Releases>,
+    pub builds: Arc<dyn Builds>,
+    pub formations: Arc<dyn Formations>,
+    pub owner_limits: Arc<dyn OwnerLimitsRepository>,
+    pub logs: Arc<LogBuffer>,
+    pub health_checks: Arc<dyn HealthChecks>,
+    pub runtime: Arc<dyn RuntimeBackend>,
+    pub health_checker: Arc<HealthChecker>,
+    pub deploys: Arc<dyn Deploys>,
+    pub custom_domains: Arc<dyn CustomDomains>,
+    pub certificates: Arc<dyn Certificates>,
+    pub acme_challenges: Arc<HttpChallenges>,
+}
+
+fn domain_name_service(settings: &Settings, dns_hosts_lock: Arc<Mutex<()>>) -> Arc<dyn DomainNameService> {
+    match settings.dns.provider {
+        DnsProvider::Namecheap => {
+            let namecheap = settings.namecheap.as_ref().expect("dns provider is namecheap but [namecheap] is missing");
+            Arc::new(NameCheapDomainNameService {
+                api_uri: namecheap.api_uri.clone(),
+                api_user: namecheap.api_user.clone(),
+                api_key: namecheap.api_key.clone(),
+                user_name: namecheap.user_name.clone(),
+                client_ip: namecheap.client_ip.clone(),
+                domain: namecheap.domain.clone(),
+                cname_target: namecheap.cname_target.clone(),
+                ttl: namecheap.ttl,
+                hosts_lock: dns_hosts_lock,
+            })
+        }
+        DnsProvider::Rfc2136 => {
+            let rfc2136 = settings.rfc2136.as_ref().expect("dns provider is rfc2136 but [rfc2136] is missing");
+            let server = match rfc2136.server.to_socket_addrs().map(|mut addrs| addrs.next()) {
+                Ok(Some(addr)) => addr,
+                Ok(None) => panic!("rfc2136 server {} has no address", rfc2136.server),
+                Err(e) => panic!("resolve rfc2136 server {} error: {}", rfc2136.server, e),
+            };
+            let key = rfc2136.key_algorithm.parse()
+                .and_then(|algorithm| TsigKey::from_base64(&rfc2136.key_name, algorithm, &rfc2136.key_secret))
+                .unwrap_or_else(|e| panic!("rfc2136 key error: {}", e));
+            Arc::new(Rfc2136DomainNameService {
+                server,
+                zone: rfc2136.zone.clone(),
+                key,
+                cname_target: rfc2136.cname_target.clone(),
+                ttl: rfc2136.ttl,
+                timeout: Duration::from_secs(rfc2136.timeout_secs),
+            })
+        }
+    }
 }
 
 impl ServerContext {
-    pub fn new() -> Self {
+    // the connection and the repositories over it are not `Sync`; each worker owns its context,
+    // the `Arc`s only share them between the repositories on that worker's thread.
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(shared: SharedServices) -> Self {
         let settings = Settings::new();
 
         let git_service_uri = settings.git_service.uri.clone();
         let git_service = Arc::new(DefaultGitService { host_uri: git_service_uri });
 
-        let domain_name_service = Arc::new(NameCheapDomainNameService);
+        let domain_name_service = domain_name_service(&settings, shared.dns_hosts_lock.clone());
+
+        let connection = match PgConnection::establish(settings.database.url.as_str()) {
+            Ok(c) => Arc::new(c),
+            Err(e) => panic!("connect to database error: {}", e)
+        };
+        let applications = Arc::new(PostgresApplications::new(connection.clone()));
+        let redirects = Arc::new(PostgresRedirects::new(connection.clone()));
+        let collaborators = Arc::new(PostgresCollaborators::new(connection.clone()));
+        let organizations = Arc::new(PostgresOrganizations::new(connection.clone()));
+
+        let cipher = match ConfigVarsCipher::from_hex(settings.config_vars.encryption_key.as_str()) {
+            Ok(c) => c,
+            Err(e) => panic!("read config vars encryption key error: {}", e)
+        };
+        let config_vars = Arc::new(PostgresConfigVars::new(connection.clone(), cipher.clone()));
+        let releases = Arc::new(PostgresReleases::new(connection.clone(), cipher.clone()));
+        let builds = Arc::new(PostgresBuilds::new(connection.clone()));
+        let formations = Arc::new(PostgresFormations::new(connection.clone()));
+        let owner_limits = Arc::new(PostgresOwnerLimits::new(connection.clone()));
+        let health_checks = Arc::new(PostgresHealthChecks::new(connection.clone()));
+        let deploys = Arc::new(PostgresDeploys::new(connection.clone()));
+        let custom_domains = Arc::new(PostgresCustomDomains::new(connection.clone()));
+        let certificates = Arc::new(PostgresCertificates::new(connection, cipher));
 
-        Self { settings: Arc::new(settings), git_service, domain_name_service }
+        Self { settings: Arc::new(settings), git_service, domain_name_service, id_generator: shared.id_generator, applications, redirects, collaborators, organizations, config_vars, releases, builds, formations, owner_limits, logs: shared.logs, health_checks, runtime: shared.runtime, health_checker: shared.health_checker, deploys, custom_domains, certificates, acme_challenges: shared.acme_challenges }
     }
 
     pub fn settings(&self) -> Arc<Settings> {
@@ -46,4 +163,76 @@ impl ServerContext {
     pub fn domain_name_service(&self) -> Arc<dyn DomainNameService> {
         self.domain_name_service.clone()
     }
+
+    pub fn id_generator(&self) -> Arc<dyn IdGenerator> {
+        self.id_generator.clone()
+    }
+
+    pub fn applications(&self) -> Arc<dyn Applications> {
+        self.applications.clone()
+    }
+
+    pub fn redirects(&self) -> Arc<dyn Redirects> {
+        self.redirects.clone()
+    }
+
+    pub fn collaborators(&self) -> Arc<dyn Collaborators> {
+        self.collaborators.clone()
+    }
+
+    pub fn organizations(&self) -> Arc<dyn Organizations> {
+        self.organizations.clone()
+    }
+
+    pub fn config_vars(&self) -> Arc<dyn ConfigVars> {
+        self.config_vars.clone()
+    }
+
+    pub fn releases(&self) -> Arc<dyn Releases> {
+        self.releases.clone()
+    }
+
+    pub fn builds(&self) -> Arc<dyn Builds> {
+        self.builds.clone()
+    }
+
+    pub fn formations(&self) -> Arc<dyn Formations> {
+        self.formations.clone()
+    }
+
+    pub fn owner_limits(&self) -> Arc<dyn OwnerLimitsRepository> {
+        self.owner_limits.clone()
+    }
+
+    pub fn logs(&self) -> Arc<LogBuffer> {
+        self.logs.clone()
+    }
+
+    pub fn health_checks(&self) -> Arc<dyn HealthChecks> {
+        self.health_checks.clone()
+    }
+
+    pub fn runtime(&self) -> Arc<dyn RuntimeBackend> {
+        self.runtime.clone()
+    }
+
+    pub fn health_checker(&self) -> Arc<HealthChecker> {
+        self.health_checker.clone()
+    }
+
+    pub fn deploys(&self) -> Arc<dyn Deploys> {
+        self.deploys.clone()
+    }
+
+    pub fn custom_domains(&self) -> Arc<dyn CustomDomains> {
+        self.custom_domains.clone()
+    }
+
+    pub fn certificates(&self) -> Arc<dyn Certificates> {
+        self.certificates.clone()
+    }
+
+    pub fn acme_challenges(&self) -> Arc<HttpChallenges> {
+        self.acme_challenges.clone()
+    }
 }
@@ -13,32 +13,210 @@
 // limitations under the License.
 extern crate core;
 
-use std::net::IpAddr;
+use std::fmt::Display;
+use std::net::{IpAddr, ToSocketAddrs};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use actix_web::{App, HttpServer, middleware, web};
 
-use resources::application;
+use capsule_core::application::{AcmeClient, ApplicationRenamer, DnsChallengeResponder, DnsTxtResolver, DomainVerifier, LogSink, LogSource};
+use capsule_core::id::{IdGenerator, SnowflakeIdGenerator};
+use resources::{application, build, certificate, collaborator, config_var, deploy, domain, formation, health, log, organization, process, release};
 
-use crate::context::ServerContext;
+use crate::context::{ServerContext, SharedServices};
 use crate::settings::Settings;
 
 mod resources;
 mod context;
+mod router;
 pub mod settings;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let settings = Settings::new();
 
-    let bind_addr = settings.server.listen_addr;
+    let bind_addr = settings.server.listen_addr.clone();
     let bind_port = settings.server.listen_port;
 
-    HttpServer::new(|| App::new()
-        .app_data(web::Data::new(ServerContext::new()))
+    // one generator per process, shared by all workers, so they never hand out the same sequence.
+    let id_generator: Arc<dyn IdGenerator> = match SnowflakeIdGenerator::new(settings.id_generator.node_id) {
+        Ok(g) => Arc::new(g),
+        Err(e) => panic!("create id generator error: {}", e)
+    };
+
+    let shared = SharedServices::new(&settings, id_generator)
+        .map_err(|e| std::io::Error::other(format!("create runtime backend error: {}", e)))?;
+
+    spawn_redirect_expiry(shared.clone());
+    spawn_health_checks(shared.clone());
+    spawn_deploys(shared.clone());
+    spawn_domain_verification(shared.clone());
+    spawn_certificate_renewal(shared.clone());
+
+    HttpServer::new(move || App::new()
+        .app_data(web::Data::new(ServerContext::new(shared.clone())))
+        .wrap_fn(router::route_host)
         .wrap(middleware::Logger::default())
-        .service(application::create_application))
+        .service(application::create_application)
+        .service(application::delete_application)
+        .service(application::rename_application)
+        .service(application::transfer_application)
+        .service(build::dispatch_builds_for_push)
+        .service(certificate::find_acme_challenge)
+        .service(certificate::list_certificates)
+        .service(collaborator::list_collaborators)
+        .service(collaborator::find_collaborator)
+        .service(collaborator::save_collaborator)
+        .service(collaborator::remove_collaborator)
+        .service(config_var::find_config_vars)
+        .service(config_var::replace_config_vars)
+        .service(config_var::update_config_vars)
+        .service(config_var::remove_config_vars)
+        .service(deploy::create_deploy)
+        .service(deploy::list_deploys)
+        .service(domain::add_domain)
+        .service(domain::list_domains)
+        .service(domain::remove_domain)
+        .service(formation::find_formation)
+        .service(formation::update_formation)
+        .service(health::find_health)
+        .service(health::update_health_check)
+        .service(log::stream_logs)
+        .service(process::list_processes)
+        .service(release::list_releases)
+        .service(release::rollback_release)
+        .service(organization::create_organization)
+        .service(organization::list_members)
+        .service(organization::save_member)
+        .service(organization::remove_member))
         .bind((IpAddr::from_str(bind_addr.as_str()).unwrap(), bind_port))?
         .run()
         .await
 }
+
+/// Background loops have no request to answer with their errors, so they go to stderr.
+fn report_error(action: impl Display, e: impl Display) {
+    eprintln!("{} error: {}", action, e);
+}
+
+/// Releases the DNS records of previous application names once their rename grace period is over.
+fn spawn_redirect_expiry(shared: SharedServices) {
+    thread::spawn(move || {
+        let context = ServerContext::new(shared);
+        let grace_period = Duration::from_secs(context.settings().application.rename_grace_period_secs);
+
+        loop {
+            let applications = context.applications();
+            let redirects = context.redirects();
+            let git_service = context.git_service();
+            let domain_name_service = context.domain_name_service();
+            let renamer = ApplicationRenamer::new(applications.as_ref(), redirects.as_ref(), git_service.as_ref(), domain_name_service.as_ref(), grace_period);
+
+            if let Err(e) = renamer.expire_redirects(SystemTime::now()) {
+                report_error("expire application redirects", e);
+            }
+
+            thread::sleep(Duration::from_secs(3600));
+        }
+    });
+}
+
+/// Probes the watched web instances whose interval is up, logging the ones restarted for failing.
+fn spawn_health_checks(shared: SharedServices) {
+    thread::spawn(move || {
+        loop {
+            for instance in shared.health_checker.check_due(Instant::now()) {
+                shared.logs.append(instance.application_id, LogSource::App, &instance.process_name(), "restarted after failing health checks");
+            }
+
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+}
+
+/// Runs queued deploys one after another, so deploys of an application never overlap.
+fn spawn_deploys(shared: SharedServices) {
+    thread::spawn(move || {
+        let context = ServerContext::new(shared);
+
+        loop {
+            match context.deploys().pending() {
+                Ok(pending) => for queued in pending {
+                    if let Err(e) = deploy::run_deploy(&context, &queued) {
+                        report_error(format!("run deploy {}", queued.id), e);
+                    }
+                },
+                Err(e) => report_error("find pending deploys", e),
+            }
+
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+}
+
+/// Looks up the verification records of pending custom domains, so they are served once verified.
+fn spawn_domain_verification(shared: SharedServices) {
+    thread::spawn(move || {
+        let context = ServerContext::new(shared.clone());
+        let settings = context.settings();
+        let server = match settings.custom_domains.resolver.to_socket_addrs().map(|mut addrs| addrs.next()) {
+            Ok(Some(addr)) => addr,
+            Ok(None) => panic!("custom domains resolver {} has no address", settings.custom_domains.resolver),
+            Err(e) => panic!("resolve custom domains resolver {} error: {}", settings.custom_domains.resolver, e),
+        };
+        let resolver = DnsTxtResolver { server, timeout: Duration::from_secs(settings.custom_domains.resolver_timeout_secs) };
+        let interval = Duration::from_secs(settings.custom_domains.verify_interval_secs);
+
+        loop {
+            let custom_domains = context.custom_domains();
+            match DomainVerifier::new(custom_domains.as_ref(), &resolver).verify_unverified(SystemTime::now()) {
+                Ok(verified) => for domain in verified {
+                    shared.logs.append(domain.application_id, LogSource::App, "router", &format!("custom domain {} verified", domain.hostname));
+                },
+                Err(e) => report_error("verify custom domains", e),
+            }
+
+            thread::sleep(interval);
+        }
+    });
+}
+
+/// Obtains certificates for the host names of applications and their verified custom domains,
+/// renewing them before they expire.
+fn spawn_certificate_renewal(shared: SharedServices) {
+    thread::spawn(move || {
+        let context = ServerContext::new(shared.clone());
+        let settings = context.settings();
+        // the responder never leaves this thread, like the context it takes its DNS service from.
+        #[allow(clippy::arc_with_non_send_sync)]
+        let issuer = AcmeClient {
+            directory_uri: settings.acme.directory_uri.clone(),
+            contact_email: settings.acme.contact_email.clone(),
+            ca_certificate: settings.acme.ca_certificate.clone(),
+            accounts: context.certificates(),
+            http_01: shared.acme_challenges.clone(),
+            dns_01: Arc::new(DnsChallengeResponder { domain_name_service: context.domain_name_service(), domain: settings.platform_domain() }),
+            poll_interval: Duration::from_secs(2),
+            poll_timeout: Duration::from_secs(120),
+        };
+        let interval = Duration::from_secs(settings.acme.check_interval_secs);
+
+        loop {
+            match certificate::renew_certificates(&context, &issuer, SystemTime::now()) {
+                Ok(renewals) => for renewal in renewals {
+                    let message = match renewal.result {
+                        Ok(_) => format!("certificate for {} issued", renewal.hostname),
+                        Err(e) => format!("obtain certificate for {} error: {}", renewal.hostname, e),
+                    };
+                    shared.logs.append(renewal.application_id, LogSource::App, "router", &message);
+                },
+                Err(e) => report_error("renew certificates", e),
+            }
+
+            thread::sleep(interval);
+        }
+    });
+}
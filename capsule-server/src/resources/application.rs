@@ -1,4 +1,4 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 // Copyright 2022 the original author or authors.
 //
@@ -12,18 +12,22 @@ use std::time::SystemTime;
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use actix_web::{HttpRequest, HttpResponse, post, Responder, web};
+use actix_web::{delete, HttpRequest, HttpResponse, patch, post, Responder, web};
 use actix_web::{body::BoxBody, http::header::ContentType};
 use serde::{Deserialize, Serialize};
 
-use capsule_core::application::{Application, ApplicationError};
+use capsule_core::application::{Application, ApplicationAccess, ApplicationError, ApplicationName, ApplicationProvisioner, ApplicationRenamer, OwnershipTransfer, OwnerCollaboratorHook, Role};
+use capsule_core::organization::can_own_applications;
 
 use crate::context::ServerContext;
-use crate::resources::ApiError;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
 
 #[derive(Deserialize, Serialize)]
 pub struct ApplicationCreateRequest {
     name: Option<String>,
+    /// A user or an organization, the requesting user if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -45,10 +49,35 @@ impl Responder for ApplicationCreateResponse {
     }
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct ApplicationRenameRequest {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ApplicationRenameResponse {
+    name: String,
+    application_uri: String,
+    git_repo_uri: String,
+}
+
+impl Responder for ApplicationRenameResponse {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let body = serde_json::to_string(&self).unwrap();
+
+        HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(body)
+    }
+}
+
 impl Default for ApplicationCreateRequest {
     fn default() -> Self {
         Self {
-            name: None
+            name: None,
+            owner: None,
         }
     }
 }
@@ -65,81 +94,282 @@ impl From<ApplicationError> for ApiError {
             ApplicationError::InternalError { message } => {
                 ApiError::InternalError { message }
             }
+            ApplicationError::InvalidApplicationName { message } => {
+                ApiError::FieldValidationFailed { field: "name".to_string(), message }
+            }
+            e @ ApplicationError::ApplicationAlreadyExists { name: _ } => {
+                ApiError::Conflict { message: e.to_string() }
+            }
+            e @ ApplicationError::ConcurrentModification { name: _ } => {
+                ApiError::Conflict { message: e.to_string() }
+            }
+            ApplicationError::InvalidOwner { message } => {
+                ApiError::FieldValidationFailed { field: "owner".to_string(), message }
+            }
+            ApplicationError::InvalidRole { message } => {
+                ApiError::FieldValidationFailed { field: "role".to_string(), message }
+            }
+            ApplicationError::PermissionDenied { message } => {
+                ApiError::Forbidden { message }
+            }
+            ApplicationError::InvalidConfigVar { message } => {
+                ApiError::FieldValidationFailed { field: "config".to_string(), message }
+            }
+            ApplicationError::InvalidFormation { message } => {
+                ApiError::FieldValidationFailed { field: "formation".to_string(), message }
+            }
+            ApplicationError::LimitExceeded { message } => {
+                ApiError::Forbidden { message }
+            }
+            ApplicationError::InvalidLogFilter { message } => {
+                ApiError::FieldValidationFailed { field: "filter".to_string(), message }
+            }
+            ApplicationError::InvalidHealthCheck { message } => {
+                ApiError::FieldValidationFailed { field: "health_check".to_string(), message }
+            }
+            ApplicationError::InvalidDnsRecord { message } => {
+                ApiError::FieldValidationFailed { field: "record".to_string(), message }
+            }
+            e @ ApplicationError::DnsRecordNotFound { .. } => {
+                ApiError::NotFound { message: e.to_string() }
+            }
+            ApplicationError::InvalidCustomDomain { message } => {
+                ApiError::FieldValidationFailed { field: "hostname".to_string(), message }
+            }
+            e @ ApplicationError::CustomDomainAlreadyExists { .. } => {
+                ApiError::Conflict { message: e.to_string() }
+            }
+            ApplicationError::CertificateError { message } => {
+                ApiError::InternalError { message }
+            }
         }
     }
 }
 
 #[post("/applications")]
-pub async fn create_application(request: web::Json<ApplicationCreateRequest>, context: web::Data<ServerContext>) -> Result<ApplicationCreateResponse, ApiError> {
-    let user_name = "capsule".to_string();
+pub async fn create_application(request: web::Json<ApplicationCreateRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<ApplicationCreateResponse, ApiError> {
+    let owner = request.owner.clone().unwrap_or_else(|| user.name.clone());
+    if !can_own_applications(context.organizations().as_ref(), &user.name, &owner)? {
+        return Err(ApiError::Forbidden { message: format!("{} can not create applications for {}", user.name, owner) });
+    }
+
+    let application_name = match &request.name {
+        Some(name) => Some(ApplicationName::new(name)?),
+        _ => None,
+    };
+    let application_id = context.id_generator().next_id()?;
+    let application = Application::new(application_id, application_name, owner);
+
+    let applications = context.applications();
+    let redirects = context.redirects();
     let git_service = context.git_service();
-    let application = Application::new(1, request.name.clone(), user_name);
-    let git_repo = application.create_git_repository(git_service.as_ref())?;
-    let cname_record = application.add_cname_record(context.domain_name_service().as_ref())?;
+    let domain_name_service = context.domain_name_service();
+    let collaborators = context.collaborators();
+    let owner_collaborator = OwnerCollaboratorHook::new(collaborators.as_ref());
+    let provisioned = ApplicationProvisioner::new(applications.as_ref(), redirects.as_ref(), git_service.as_ref(), domain_name_service.as_ref())
+        .with_hook(&owner_collaborator)
+        .provision(&application)?;
 
     Ok(ApplicationCreateResponse {
+        name: provisioned.application.accept(get_application_name),
+        application_uri: format!("https://{}", provisioned.cname_record.domain_name),
+        git_repo_uri: provisioned.git_repository.uri,
+    })
+}
+
+#[delete("/applications/{name}")]
+pub async fn delete_application(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Owner)?;
+
+    // everything of the application goes before its row, a failed deletion must stay retryable by the owner.
+    remove_application_data(&context, application_id(&application), user.name.as_str())?;
+
+    let applications = context.applications();
+    let collaborators = context.collaborators();
+    let redirects = context.redirects();
+    let git_service = context.git_service();
+    let domain_name_service = context.domain_name_service();
+    let owner_collaborator = OwnerCollaboratorHook::new(collaborators.as_ref());
+    ApplicationProvisioner::new(applications.as_ref(), redirects.as_ref(), git_service.as_ref(), domain_name_service.as_ref())
+        .with_hook(&owner_collaborator)
+        .deprovision(&application)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Every step removes what is left, so it can run again after a failed deletion.
+fn remove_application_data(context: &ServerContext, application_id: i64, user_name: &str) -> Result<(), ApplicationError> {
+    // the stop events tell the runtime backend to stop the processes of the application.
+    let formations = context.formations();
+    let mut formation = formations.find(application_id)?;
+    let stopped = formation.stop_all();
+    formations.save(&formation, &stopped, user_name)?;
+    formations.remove_all(application_id)?;
+
+    context.config_vars().remove_all(application_id)?;
+    context.releases().remove_all(application_id)?;
+    context.builds().remove_all(application_id)?;
+    context.logs().remove_all(application_id);
+    context.health_checks().remove(application_id)?;
+    context.deploys().remove_all(application_id)?;
+    context.custom_domains().remove_all(application_id)?;
+    context.certificates().remove_all(application_id)?;
+
+    Ok(())
+}
+
+#[patch("/applications/{name}")]
+pub async fn rename_application(name: web::Path<String>, request: web::Json<ApplicationRenameRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<ApplicationRenameResponse, ApiError> {
+    let applications = context.applications();
+    let mut application = match applications.find_by_name(name.as_str())? {
+        Some(application) => application,
+        _ => return Err(ApiError::NotFound { message: format!("application {} not found", name) }),
+    };
+    let collaborators = context.collaborators();
+    let organizations = context.organizations();
+    ApplicationAccess::new(collaborators.as_ref(), organizations.as_ref()).require(&application, &user.name, Role::Admin)?;
+    let new_name = ApplicationName::new(request.name.as_str())?;
+
+    let redirects = context.redirects();
+    let git_service = context.git_service();
+    let domain_name_service = context.domain_name_service();
+    let grace_period = Duration::from_secs(context.settings().application.rename_grace_period_secs);
+    let renamed = ApplicationRenamer::new(applications.as_ref(), redirects.as_ref(), git_service.as_ref(), domain_name_service.as_ref(), grace_period)
+        .rename(&mut application, new_name)?;
+
+    Ok(ApplicationRenameResponse {
         name: application.accept(get_application_name),
-        application_uri: format!("https://{}", cname_record.domain_name),
-        git_repo_uri: git_repo.uri,
+        application_uri: format!("https://{}", renamed.cname_record.domain_name),
+        git_repo_uri: renamed.git_repository.uri,
     })
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct ApplicationTransferRequest {
+    owner: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ApplicationTransferResponse {
+    name: String,
+    owner: String,
+    git_repo_uri: String,
+}
+
+#[post("/applications/{name}/transfer")]
+pub async fn transfer_application(name: web::Path<String>, request: web::Json<ApplicationTransferRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let applications = context.applications();
+    let mut application = match applications.find_by_name(name.as_str())? {
+        Some(application) => application,
+        _ => return Err(ApiError::NotFound { message: format!("application {} not found", name) }),
+    };
+
+    let collaborators = context.collaborators();
+    let organizations = context.organizations();
+    ApplicationAccess::new(collaborators.as_ref(), organizations.as_ref()).require(&application, &user.name, Role::Owner)?;
+    if !can_own_applications(organizations.as_ref(), &user.name, &request.owner)? {
+        return Err(ApiError::Forbidden { message: format!("{} can not transfer applications to {}", user.name, request.owner) });
+    }
+
+    let git_service = context.git_service();
+    let transferred = OwnershipTransfer::new(git_service.as_ref(), collaborators.as_ref(), organizations.as_ref())
+        .transfer(&mut application, &request.owner)?;
+
+    let response = ApplicationTransferResponse {
+        name: application.accept(get_application_name),
+        owner: request.owner.clone(),
+        git_repo_uri: transferred.git_repository.uri,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&response).unwrap()))
+}
+
 fn get_application_name(_: i64, name: &str, _: &str, _: SystemTime) -> String {
     name.to_string()
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
 
     use actix_web::{App, http::{self}, test};
     use actix_web::dev::Service;
     use actix_web::middleware;
     use actix_web::web::Bytes;
 
-    use capsule_core::application::{ApplicationError, GitError, GitRepository, GitService};
-    use capsule_core::application::{CnameRecord, DomainNameService};
+    use capsule_core::application::{ApplicationError, Collaborator, Formation, GitError, GitRepository, GitService, ProcessScale, ProcessSize, Role, ScaledProcess};
+    use capsule_core::application::{CnameRecord, DnsRecord, DomainNameService, RecordType};
+    use capsule_core::application::{HealthCheckConfig, HealthChecks};
+    use capsule_core::CoreError;
+    use capsule_core::organization::{Member, Organization, OrganizationRole};
 
-    use crate::context::ServerContext;
-    use crate::Settings;
+    use crate::resources::test_support::{add_application, context, CreateRepoStub, GitServiceStub, InMemoryHealthChecks};
+    use crate::resources::USER_HEADER;
 
     use super::*;
 
     #[actix_web::test]
     async fn should_return_application_information_if_create_successfully() {
-        struct GitServiceStub;
-        impl GitService for GitServiceStub {
-            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
-                Ok(GitRepository { uri: "https://git.capsuleapp.cyou/capsule/first_capsule_application.git".to_string() })
-            }
-        }
-
         struct DomainNameServiceStub;
         impl DomainNameService for DomainNameServiceStub {
             fn add_cname_record(&self, cname: &str) -> Result<CnameRecord, ApplicationError> {
                 Ok(CnameRecord { domain_name: format!("{}.capsuleapp.cyou", cname) })
             }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
         }
 
+        let context = context(CreateRepoStub { fail: false }, DomainNameServiceStub);
+        let applications = context.applications();
+        let collaborators = context.collaborators();
         let app =
             test::init_service(App::new()
-                .app_data(web::Data::new(context(GitServiceStub, DomainNameServiceStub)))
+                .app_data(web::Data::new(context))
                 .wrap(middleware::Logger::default())
                 .service(create_application))
                 .await;
 
         let req = test::TestRequest::post()
             .uri("/applications")
-            .set_json(ApplicationCreateRequest { name: Some("first_capsule_application".to_string()) })
+            .set_json(ApplicationCreateRequest { owner: None, name: Some("first-capsule-application".to_string()) })
             .to_request();
 
         let resp = app.call(req).await.unwrap();
         assert_eq!(resp.status(), http::StatusCode::CREATED);
 
+        let application_id = applications.find_by_name("first-capsule-application").unwrap().unwrap().accept(|id, _, _, _| id);
+        let owner = collaborators.find(application_id, "capsule").unwrap().unwrap();
+        assert_eq!(owner.role, Role::Owner);
+
         let expect = ApplicationCreateResponse {
-            name: "first_capsule_application".to_string(),
-            application_uri: "https://first_capsule_application.capsuleapp.cyou".to_string(),
-            git_repo_uri: "https://git.capsuleapp.cyou/capsule/first_capsule_application.git".to_string(),
+            name: "first-capsule-application".to_string(),
+            application_uri: "https://first-capsule-application.capsuleapp.cyou".to_string(),
+            git_repo_uri: "https://git.capsuleapp.cyou/capsule/first-capsule-application.git".to_string(),
         };
         let expect_json = serde_json::to_string(&expect).unwrap();
 
@@ -149,30 +379,47 @@ mod tests {
 
     #[actix_web::test]
     async fn should_return_error_message_if_create_git_repository_failed() {
-        struct GitServiceStub;
-        impl GitService for GitServiceStub {
-            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
-                Err(GitError { })
-            }
-        }
-
         struct DomainNameServiceStub;
         impl DomainNameService for DomainNameServiceStub {
             fn add_cname_record(&self, cname: &str) -> Result<CnameRecord, ApplicationError> {
                 Ok(CnameRecord { domain_name: format!("{}.capsuleapp.cyou", cname) })
             }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
         }
 
         let app =
             test::init_service(App::new()
-                .app_data(web::Data::new(context(GitServiceStub, DomainNameServiceStub)))
+                .app_data(web::Data::new(context(CreateRepoStub { fail: true }, DomainNameServiceStub)))
                 .wrap(middleware::Logger::default())
                 .service(create_application))
                 .await;
 
         let req = test::TestRequest::post()
             .uri("/applications")
-            .set_json(ApplicationCreateRequest { name: Some("first_capsule_application".to_string()) })
+            .set_json(ApplicationCreateRequest { owner: None, name: Some("first-capsule-application".to_string()) })
             .to_request();
 
         let resp = app.call(req).await.unwrap();
@@ -186,30 +433,47 @@ mod tests {
 
     #[actix_web::test]
     async fn should_return_message_if_add_cname_record_failed() {
-        struct GitServiceStub;
-        impl GitService for GitServiceStub {
-            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
-                Ok(GitRepository { uri: "https://git.capsuleapp.cyou/capsule/first_capsule_application.git".to_string() })
-            }
-        }
-
         struct DomainNameServiceStub;
         impl DomainNameService for DomainNameServiceStub {
             fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
                 Err(ApplicationError::DomainNameError { message: "add application domain record failed.".to_string() })
             }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
         }
 
         let app =
             test::init_service(App::new()
-                .app_data(web::Data::new(context(GitServiceStub, DomainNameServiceStub)))
+                .app_data(web::Data::new(context(CreateRepoStub { fail: false }, DomainNameServiceStub)))
                 .wrap(middleware::Logger::default())
                 .service(create_application))
                 .await;
 
         let req = test::TestRequest::post()
             .uri("/applications")
-            .set_json(ApplicationCreateRequest { name: Some("first_capsule_application".to_string()) })
+            .set_json(ApplicationCreateRequest { owner: None, name: Some("first-capsule-application".to_string()) })
             .to_request();
 
         let resp = app.call(req).await.unwrap();
@@ -223,17 +487,88 @@ mod tests {
 
     #[actix_web::test]
     async fn should_500_if_internal_error_happened() {
-        struct GitServiceStub;
-        impl GitService for GitServiceStub {
-            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
-                Ok(GitRepository { uri: "https://git.capsuleapp.cyou/capsule/first_capsule_application.git".to_string() })
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                Err(ApplicationError::InternalError { message: "internal error.".to_string() })
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
             }
         }
 
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context(CreateRepoStub { fail: false }, DomainNameServiceStub)))
+                .wrap(middleware::Logger::default())
+                .service(create_application))
+                .await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications")
+            .set_json(ApplicationCreateRequest { owner: None, name: Some("first-capsule-application".to_string()) })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = test::read_body(resp).await;
+
+        let expect = Bytes::from(r#"{"message":"internal error."}"#);
+        assert_eq!(expect, body);
+    }
+
+    #[actix_web::test]
+    async fn should_return_field_error_if_application_name_invalid() {
         struct DomainNameServiceStub;
         impl DomainNameService for DomainNameServiceStub {
             fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
-                Err(ApplicationError::InternalError { message: "internal error.".to_string() })
+                panic!("should not add cname record for invalid name")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
             }
         }
 
@@ -246,26 +581,828 @@ mod tests {
 
         let req = test::TestRequest::post()
             .uri("/applications")
-            .set_json(ApplicationCreateRequest { name: Some("first_capsule_application".to_string()) })
+            .set_json(ApplicationCreateRequest { owner: None, name: Some("www".to_string()) })
             .to_request();
 
         let resp = app.call(req).await.unwrap();
-        assert_eq!(resp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
 
         let body = test::read_body(resp).await;
 
-        let expect = Bytes::from(r#"{"message":"internal error."}"#);
+        let expect = Bytes::from(r#"{"errors":{"name":"'www' is reserved"},"message":"validation failed"}"#);
         assert_eq!(expect, body);
     }
 
-    fn context(git_service: impl GitService + 'static, domain_service: impl DomainNameService + 'static) -> ServerContext {
-        std::env::set_var("CAPSULE_CONFIG_SERVER_DIR", "./_fixture");
-        std::env::set_var("CAPSULE_SERVER_CONFIG_FILE", "capsule-server.toml");
+    #[actix_web::test]
+    async fn should_not_keep_application_if_create_failed() {
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                Err(ApplicationError::DomainNameError { message: "add application domain record failed.".to_string() })
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let context = context(CreateRepoStub { fail: false }, DomainNameServiceStub);
+        let applications = context.applications();
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(create_application))
+                .await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications")
+            .set_json(ApplicationCreateRequest { owner: None, name: Some("first-capsule-application".to_string()) })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+        assert!(!applications.exists("first-capsule-application").unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_return_conflict_if_application_name_taken() {
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record for taken name")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                panic!("should not remove cname record of another application")
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
 
-        ServerContext {
-            settings: Arc::new(Settings::new()),
-            git_service: Arc::new(git_service),
-            domain_name_service: Arc::new(domain_service),
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
         }
+
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        let existing = Application::new(1, Some(ApplicationName::new("first-capsule-application").unwrap()), "other".to_string());
+        context.applications().add(&existing).unwrap();
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(create_application))
+                .await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications")
+            .set_json(ApplicationCreateRequest { owner: None, name: Some("first-capsule-application".to_string()) })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+        let body = test::read_body(resp).await;
+
+        let expect = Bytes::from(r#"{"message":"application name first-capsule-application is already taken"}"#);
+        assert_eq!(expect, body);
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn should_delete_application() {
+        struct GitServiceStub;
+        impl GitService for GitServiceStub {
+            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not create git repository when deleting application")
+            }
+
+            fn rename_repo(&self, _owner: &str, _app_name: &str, _new_app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not rename git repository")
+            }
+
+            fn transfer_repo(&self, _owner: &str, _app_name: &str, _new_owner: &str) -> Result<GitRepository, GitError> {
+                panic!("should not transfer git repository")
+            }
+
+            fn delete_repo(&self, owner: &str, app_name: &str) -> Result<(), GitError> {
+                assert_eq!((owner, app_name), ("capsule", "first-capsule-application"));
+                Ok(())
+            }
+        }
+
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record when deleting application")
+            }
+
+            fn remove_cname_record(&self, cname: &str) -> Result<(), ApplicationError> {
+                assert_eq!(cname, "first-capsule-application");
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        let applications = context.applications();
+        let formations = context.formations();
+        add_application(&context, 1, "first-capsule-application", "capsule");
+        let web_process = ScaledProcess { process_type: "web".to_string(), scale: ProcessScale { quantity: 2, size: ProcessSize::Small } };
+        formations.save(&Formation::new(1, BTreeMap::from([("web".to_string(), web_process.scale)])), &[web_process], "capsule").unwrap();
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(delete_application))
+                .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/applications/first-capsule-application")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+        assert!(!applications.exists("first-capsule-application").unwrap());
+        assert!(formations.find(1).unwrap().processes().is_empty());
+        let stopped = formations.events_after(1, 10).unwrap();
+        assert_eq!(vec![("web", 0)], stopped.iter().map(|e| (e.process_type.as_str(), e.scale.quantity)).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_deployer_to_delete_application() {
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record when deleting application")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                panic!("should not remove cname record without owner role")
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        let applications = context.applications();
+        add_application(&context, 1, "first-capsule-application", "capsule");
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "deploy_user".to_string(), role: Role::Deployer }).unwrap();
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(delete_application))
+                .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/applications/first-capsule-application")
+            .insert_header((USER_HEADER, "deploy_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+        assert!(applications.exists("first-capsule-application").unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_return_not_found_if_delete_not_exists_application() {
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record when deleting application")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                panic!("should not remove cname record of not exists application")
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context(GitServiceStub, DomainNameServiceStub)))
+                .wrap(middleware::Logger::default())
+                .service(delete_application))
+                .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/applications/first-capsule-application")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn should_be_able_to_retry_delete_if_git_server_failed() {
+        struct GitServiceStub {
+            failures: Mutex<u32>,
+        }
+        impl GitService for GitServiceStub {
+            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not create git repository when deleting application")
+            }
+
+            fn rename_repo(&self, _owner: &str, _app_name: &str, _new_app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not rename git repository")
+            }
+
+            fn transfer_repo(&self, _owner: &str, _app_name: &str, _new_owner: &str) -> Result<GitRepository, GitError> {
+                panic!("should not transfer git repository")
+            }
+
+            fn delete_repo(&self, _owner: &str, _app_name: &str) -> Result<(), GitError> {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return Err(GitError {});
+                }
+                Ok(())
+            }
+        }
+
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record when deleting application")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let context = context(GitServiceStub { failures: Mutex::new(1) }, DomainNameServiceStub);
+        let applications = context.applications();
+        add_application(&context, 1, "first-capsule-application", "capsule");
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(delete_application))
+                .await;
+
+        let req = test::TestRequest::delete().uri("/applications/first-capsule-application").to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(applications.exists("first-capsule-application").unwrap());
+
+        let req = test::TestRequest::delete().uri("/applications/first-capsule-application").to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+        assert!(!applications.exists("first-capsule-application").unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_be_able_to_retry_delete_if_removing_application_data_failed() {
+        struct GitServiceStub;
+        impl GitService for GitServiceStub {
+            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not create git repository when deleting application")
+            }
+
+            fn rename_repo(&self, _owner: &str, _app_name: &str, _new_app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not rename git repository")
+            }
+
+            fn transfer_repo(&self, _owner: &str, _app_name: &str, _new_owner: &str) -> Result<GitRepository, GitError> {
+                panic!("should not transfer git repository")
+            }
+
+            fn delete_repo(&self, _owner: &str, _app_name: &str) -> Result<(), GitError> {
+                Ok(())
+            }
+        }
+
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record when deleting application")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                Ok(())
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        struct HealthChecksStub {
+            failures: Mutex<u32>,
+            checks: InMemoryHealthChecks,
+        }
+        impl HealthChecks for HealthChecksStub {
+            fn find(&self, application_id: i64) -> Result<Option<HealthCheckConfig>, CoreError> {
+                self.checks.find(application_id)
+            }
+
+            fn save(&self, application_id: i64, config: &HealthCheckConfig) -> Result<(), CoreError> {
+                self.checks.save(application_id, config)
+            }
+
+            fn remove(&self, application_id: i64) -> Result<(), CoreError> {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return Err(diesel::result::Error::RollbackTransaction.into());
+                }
+                self.checks.remove(application_id)
+            }
+        }
+
+        let mut context = context(GitServiceStub, DomainNameServiceStub);
+        context.health_checks = Arc::new(HealthChecksStub { failures: Mutex::new(1), checks: InMemoryHealthChecks::new() });
+        let applications = context.applications();
+        let collaborators = context.collaborators();
+        let config_vars = context.config_vars();
+        let health_checks = context.health_checks();
+        add_application(&context, 1, "first-capsule-application", "capsule");
+        config_vars.apply(1, &BTreeMap::from([("DATABASE_URL".to_string(), Some("postgres://db".to_string()))]), "capsule").unwrap();
+        health_checks.save(1, &HealthCheckConfig::default()).unwrap();
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(delete_application))
+                .await;
+
+        let req = test::TestRequest::delete().uri("/applications/first-capsule-application").to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(applications.exists("first-capsule-application").unwrap());
+        assert!(health_checks.find(1).unwrap().is_some());
+
+        let req = test::TestRequest::delete().uri("/applications/first-capsule-application").to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+        assert!(!applications.exists("first-capsule-application").unwrap());
+        assert!(config_vars.find_all(1).unwrap().is_empty());
+        assert!(health_checks.find(1).unwrap().is_none());
+        assert!(collaborators.list(1).unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn should_rename_application_and_redirect_previous_name() {
+        struct GitServiceStub;
+        impl GitService for GitServiceStub {
+            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not create git repository when renaming application")
+            }
+
+            fn rename_repo(&self, owner: &str, app_name: &str, new_app_name: &str) -> Result<GitRepository, GitError> {
+                assert_eq!((owner, app_name), ("capsule", "first-capsule-application"));
+                Ok(GitRepository { uri: format!("https://git.capsuleapp.cyou/{}/{}.git", owner, new_app_name) })
+            }
+
+            fn transfer_repo(&self, _owner: &str, _app_name: &str, _new_owner: &str) -> Result<GitRepository, GitError> {
+                panic!("should not transfer git repository")
+            }
+
+            fn delete_repo(&self, _owner: &str, _app_name: &str) -> Result<(), GitError> {
+                panic!("should not delete git repository when renaming application")
+            }
+        }
+
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, cname: &str) -> Result<CnameRecord, ApplicationError> {
+                Ok(CnameRecord { domain_name: format!("{}.capsuleapp.cyou", cname) })
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                panic!("should keep cname record of previous name during grace period")
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        let applications = context.applications();
+        let redirects = context.redirects();
+        add_application(&context, 1, "first-capsule-application", "capsule");
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(rename_application))
+                .await;
+
+        let req = test::TestRequest::patch()
+            .uri("/applications/first-capsule-application")
+            .set_json(ApplicationRenameRequest { name: "renamed-application".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let expect = ApplicationRenameResponse {
+            name: "renamed-application".to_string(),
+            application_uri: "https://renamed-application.capsuleapp.cyou".to_string(),
+            git_repo_uri: "https://git.capsuleapp.cyou/capsule/renamed-application.git".to_string(),
+        };
+        let body = test::read_body(resp).await;
+        assert_eq!(Bytes::from(serde_json::to_string(&expect).unwrap()), body);
+
+        assert!(applications.exists("renamed-application").unwrap());
+        assert!(!applications.exists("first-capsule-application").unwrap());
+        let redirect = redirects.find_active("first-capsule-application", SystemTime::now()).unwrap().unwrap();
+        assert_eq!(redirect.application_id, 1);
+    }
+
+    #[actix_web::test]
+    async fn should_return_conflict_if_rename_to_taken_name() {
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record for taken name")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                panic!("should not remove cname record when renaming application")
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        let applications = context.applications();
+        add_application(&context, 1, "first-capsule-application", "capsule");
+        add_application(&context, 2, "renamed-application", "other");
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context))
+                .wrap(middleware::Logger::default())
+                .service(rename_application))
+                .await;
+
+        let req = test::TestRequest::patch()
+            .uri("/applications/first-capsule-application")
+            .set_json(ApplicationRenameRequest { name: "renamed-application".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+        assert!(applications.exists("first-capsule-application").unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_return_not_found_if_rename_not_exists_application() {
+        struct DomainNameServiceStub;
+        impl DomainNameService for DomainNameServiceStub {
+            fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+                panic!("should not add cname record of not exists application")
+            }
+
+            fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+                panic!("should not remove cname record of not exists application")
+            }
+
+            fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+
+            fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+                panic!("applications should not touch other dns records")
+            }
+        }
+
+        let app =
+            test::init_service(App::new()
+                .app_data(web::Data::new(context(GitServiceStub, DomainNameServiceStub)))
+                .wrap(middleware::Logger::default())
+                .service(rename_application))
+                .await;
+
+        let req = test::TestRequest::patch()
+            .uri("/applications/first-capsule-application")
+            .set_json(ApplicationRenameRequest { name: "renamed-application".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn should_transfer_application_to_organization() {
+        struct GitServiceStub;
+        impl GitService for GitServiceStub {
+            fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not create git repository when transferring application")
+            }
+
+            fn rename_repo(&self, _owner: &str, _app_name: &str, _new_app_name: &str) -> Result<GitRepository, GitError> {
+                panic!("should not rename git repository when transferring application")
+            }
+
+            fn transfer_repo(&self, owner: &str, app_name: &str, new_owner: &str) -> Result<GitRepository, GitError> {
+                assert_eq!((owner, app_name), ("first_capsule_user", "first-capsule-application"));
+                Ok(GitRepository { uri: format!("https://git.capsuleapp.cyou/{}/{}.git", new_owner, app_name) })
+            }
+
+            fn delete_repo(&self, _owner: &str, _app_name: &str) -> Result<(), GitError> {
+                panic!("should not delete git repository when transferring application")
+            }
+        }
+
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        add_application(&context, 1, "first-capsule-application", "first_capsule_user");
+        context.organizations().add(&Organization::new("capsule-team").unwrap(), "first_capsule_user").unwrap();
+        let applications = context.applications();
+        let collaborators = context.collaborators();
+
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(transfer_application)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications/first-capsule-application/transfer")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(ApplicationTransferRequest { owner: "capsule-team".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let expect = ApplicationTransferResponse {
+            name: "first-capsule-application".to_string(),
+            owner: "capsule-team".to_string(),
+            git_repo_uri: "https://git.capsuleapp.cyou/capsule-team/first-capsule-application.git".to_string(),
+        };
+        let body = test::read_body(resp).await;
+        assert_eq!(Bytes::from(serde_json::to_string(&expect).unwrap()), body);
+
+        let application = applications.find_by_name("first-capsule-application").unwrap().unwrap();
+        assert_eq!("capsule-team", application.accept(|_, _, owner, _| owner.to_string()));
+        assert_eq!(Role::Owner, collaborators.find(1, "capsule-team").unwrap().unwrap().role);
+        assert_eq!(Role::Admin, collaborators.find(1, "first_capsule_user").unwrap().unwrap().role);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_transfer_to_organization_not_administered() {
+        let context = context(NoGitService, DomainNameServiceStub);
+        add_application(&context, 1, "first-capsule-application", "first_capsule_user");
+        context.organizations().add(&Organization::new("capsule-team").unwrap(), "another_user").unwrap();
+
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(transfer_application)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications/first-capsule-application/transfer")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(ApplicationTransferRequest { owner: "capsule-team".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_creating_application_for_organization_as_member() {
+        let context = context(NoGitService, DomainNameServiceStub);
+        let organizations = context.organizations();
+        organizations.add(&Organization::new("capsule-team").unwrap(), "another_user").unwrap();
+        organizations.save_member(&Member { organization_name: "capsule-team".to_string(), user_name: "first_capsule_user".to_string(), role: OrganizationRole::Member }).unwrap();
+
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(create_application)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(ApplicationCreateRequest { name: None, owner: Some("capsule-team".to_string()) })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    struct NoGitService;
+    impl GitService for NoGitService {
+        fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
+            panic!("should not create git repository")
+        }
+
+        fn rename_repo(&self, _owner: &str, _app_name: &str, _new_app_name: &str) -> Result<GitRepository, GitError> {
+            panic!("should not rename git repository")
+        }
+
+        fn transfer_repo(&self, _owner: &str, _app_name: &str, _new_owner: &str) -> Result<GitRepository, GitError> {
+            panic!("should not transfer git repository")
+        }
+
+        fn delete_repo(&self, _owner: &str, _app_name: &str) -> Result<(), GitError> {
+            panic!("should not delete git repository")
+        }
+    }
+
+    struct DomainNameServiceStub;
+    impl DomainNameService for DomainNameServiceStub {
+        fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+            panic!("should not add cname record")
+        }
+
+        fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+            panic!("should not remove cname record")
+        }
+
+        fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+            panic!("applications should not touch other dns records")
+        }
+
+        fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+            panic!("applications should not touch other dns records")
+        }
+
+        fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+            panic!("applications should not touch other dns records")
+        }
+
+        fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+            panic!("applications should not touch other dns records")
+        }
+
+        fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+            panic!("applications should not touch other dns records")
+        }
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{get, HttpResponse, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{ApplicationError, Certificate, CertificateIssuer, CertificateManager, CertificateRenewal, CertificateTarget, ChallengeType, Page, Role};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+const TARGET_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct CertificateResponse {
+    hostname: String,
+    /// Milliseconds since the unix epoch.
+    not_after: u64,
+    issued_at: u64,
+}
+
+impl From<&Certificate> for CertificateResponse {
+    fn from(certificate: &Certificate) -> Self {
+        let millis = |time: SystemTime| time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default();
+
+        Self { hostname: certificate.hostname.clone(), not_after: millis(certificate.not_after), issued_at: millis(certificate.issued_at) }
+    }
+}
+
+/// Answers HTTP-01 challenges of the CA while a certificate is being obtained. The host router
+/// passes challenges on here whatever the host, so custom domains pointing at the server validate.
+#[get("/.well-known/acme-challenge/{token}")]
+pub async fn find_acme_challenge(token: web::Path<String>, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    match context.acme_challenges().key_authorization(token.as_str()) {
+        Some(key_authorization) => Ok(HttpResponse::Ok().content_type(ContentType::plaintext()).body(key_authorization)),
+        None => Err(ApiError::NotFound { message: format!("acme challenge {} not found", token) }),
+    }
+}
+
+/// Lists the certificates of the application, without their private keys.
+#[get("/applications/{name}/certificates")]
+pub async fn list_certificates(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+
+    let certificates = context.certificates().list(application_id(&application))?;
+    let response: Vec<CertificateResponse> = certificates.iter().map(CertificateResponse::from).collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&response).unwrap()))
+}
+
+/// Host names that should have a certificate: `{name}.{domain}` of every application, proven
+/// with the configured challenge, and every verified custom domain, proven with HTTP-01.
+pub fn certificate_targets(context: &ServerContext) -> Result<Vec<CertificateTarget>, ApplicationError> {
+    let settings = context.settings();
+    let challenge: ChallengeType = settings.acme.challenge.parse()?;
+    let domain = settings.platform_domain();
+
+    let applications = context.applications();
+    let mut targets = Vec::new();
+    for number in 1.. {
+        let page = applications.list(&Page::new(number, TARGET_PAGE_SIZE))?;
+        for (application_id, name) in page.iter().map(|a| a.accept(|id, name, _, _| (id, name.to_string()))) {
+            targets.push(CertificateTarget { application_id, hostname: format!("{}.{}", name, domain), challenge });
+        }
+        if (page.len() as i64) < TARGET_PAGE_SIZE {
+            break;
+        }
+    }
+
+    for custom_domain in context.custom_domains().verified()? {
+        targets.push(CertificateTarget { application_id: custom_domain.application_id, hostname: custom_domain.hostname, challenge: ChallengeType::Http01 });
+    }
+
+    Ok(targets)
+}
+
+/// Obtains the missing and expiring certificates, and drops the ones of host names no longer served.
+pub fn renew_certificates(context: &ServerContext, issuer: &dyn CertificateIssuer, now: SystemTime) -> Result<Vec<CertificateRenewal>, ApplicationError> {
+    let targets = certificate_targets(context)?;
+    let certificates = context.certificates();
+    let renew_before = Duration::from_secs(context.settings().acme.renew_before_days * 86400);
+    let manager = CertificateManager::new(certificates.as_ref(), issuer, renew_before);
+
+    let renewals = manager.renew_due(&targets, now)?;
+    manager.remove_untargeted(&targets)?;
+
+    Ok(renewals)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime};
+
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+
+    use capsule_core::application::{ApplicationError, ChallengeResponder, CustomDomain, IssuedCertificate};
+
+    use crate::resources::test_support::{add_application, context_with_application};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(86400);
+
+    /// Hands out certificates valid for 90 days, remembering what it was asked for.
+    struct IssuerStub {
+        issued: Mutex<Vec<(String, ChallengeType)>>,
+    }
+
+    impl CertificateIssuer for IssuerStub {
+        fn issue(&self, hostname: &str, challenge: ChallengeType) -> Result<IssuedCertificate, ApplicationError> {
+            self.issued.lock().unwrap().push((hostname.to_string(), challenge));
+            Ok(IssuedCertificate { certificate_chain: format!("chain of {}", hostname), private_key: "key".to_string(), not_after: SystemTime::now() + 90 * DAY })
+        }
+    }
+
+    fn certificate(application_id: i64, hostname: &str, not_after: SystemTime) -> Certificate {
+        Certificate { application_id, hostname: hostname.to_string(), certificate_chain: "chain".to_string(), private_key: "key".to_string(), not_after, issued_at: SystemTime::now() }
+    }
+
+    #[actix_web::test]
+    async fn should_serve_key_authorization_of_pending_challenge() {
+        let context = web::Data::new(context_with_application());
+        context.acme_challenges().present("first-capsule-application.capsuleapp.cyou", "token-1", "token-1.thumbprint").unwrap();
+        let app = test::init_service(App::new().app_data(context).service(find_acme_challenge)).await;
+
+        let resp = app.call(test::TestRequest::get().uri("/.well-known/acme-challenge/token-1").to_request()).await.unwrap();
+        assert_eq!(http::StatusCode::OK, resp.status());
+        assert_eq!("token-1.thumbprint", test::read_body(resp).await);
+
+        let resp = app.call(test::TestRequest::get().uri("/.well-known/acme-challenge/token-2").to_request()).await.unwrap();
+        assert_eq!(http::StatusCode::NOT_FOUND, resp.status());
+    }
+
+    #[actix_web::test]
+    async fn should_list_certificates_without_private_keys() {
+        let context = web::Data::new(context_with_application());
+        context.certificates().save(&certificate(1, "first-capsule-application.capsuleapp.cyou", SystemTime::now() + 90 * DAY)).unwrap();
+        context.certificates().save(&certificate(2, "other-application.capsuleapp.cyou", SystemTime::now() + 90 * DAY)).unwrap();
+        let app = test::init_service(App::new().app_data(context).service(list_certificates)).await;
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/certificates")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+
+        assert_eq!(http::StatusCode::OK, resp.status());
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        let certificates: Vec<CertificateResponse> = serde_json::from_str(&body).unwrap();
+        assert_eq!(vec!["first-capsule-application.capsuleapp.cyou"], certificates.iter().map(|c| c.hostname.as_str()).collect::<Vec<_>>());
+        assert!(!body.contains("key"));
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_non_collaborator_to_list_certificates() {
+        let app = test::init_service(App::new().app_data(web::Data::new(context_with_application())).service(list_certificates)).await;
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/certificates")
+            .insert_header((USER_HEADER, "other_user"))
+            .to_request();
+
+        assert_eq!(http::StatusCode::FORBIDDEN, app.call(req).await.unwrap().status());
+    }
+
+    #[actix_web::test]
+    async fn should_target_applications_and_verified_custom_domains() {
+        let context = context_with_application();
+        add_application(&context, 2, "second-capsule-application", "first_capsule_user");
+        let now = SystemTime::now();
+        for hostname in ["www.theirshop.com", "pending.theirshop.com"] {
+            context.custom_domains().add(&CustomDomain::new(1, hostname, "capsuleapp.cyou", now).unwrap()).unwrap();
+        }
+        context.custom_domains().mark_verified(1, "www.theirshop.com", now).unwrap();
+
+        let targets = certificate_targets(&context).unwrap();
+
+        assert_eq!(vec![
+            (1, "first-capsule-application.capsuleapp.cyou", ChallengeType::Http01),
+            (2, "second-capsule-application.capsuleapp.cyou", ChallengeType::Http01),
+            (1, "www.theirshop.com", ChallengeType::Http01),
+        ], targets.iter().map(|t| (t.application_id, t.hostname.as_str(), t.challenge)).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn should_renew_expiring_certificates_and_drop_untargeted_ones() {
+        let context = context_with_application();
+        add_application(&context, 2, "second-capsule-application", "first_capsule_user");
+        let now = SystemTime::now();
+        let certificates = context.certificates();
+        certificates.save(&certificate(1, "first-capsule-application.capsuleapp.cyou", now + 10 * DAY)).unwrap();
+        certificates.save(&certificate(2, "second-capsule-application.capsuleapp.cyou", now + 60 * DAY)).unwrap();
+        certificates.save(&certificate(1, "renamed-application.capsuleapp.cyou", now + 60 * DAY)).unwrap();
+        let issuer = IssuerStub { issued: Mutex::new(vec![]) };
+
+        let renewals = renew_certificates(&context, &issuer, now).unwrap();
+
+        assert_eq!(vec![("first-capsule-application.capsuleapp.cyou".to_string(), ChallengeType::Http01)], *issuer.issued.lock().unwrap());
+        assert!(renewals[0].result.is_ok());
+        let renewed = certificates.find("first-capsule-application.capsuleapp.cyou").unwrap().unwrap();
+        assert_eq!("chain of first-capsule-application.capsuleapp.cyou", renewed.certificate_chain);
+        assert!(certificates.find("renamed-application.capsuleapp.cyou").unwrap().is_none());
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+
+use actix_web::{delete, get, HttpResponse, put, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{Application, ApplicationAccess, Collaborator, Role};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application, find_application_by_name};
+
+#[derive(Deserialize, Serialize)]
+pub struct CollaboratorSaveRequest {
+    role: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct CollaboratorResponse {
+    user: String,
+    role: String,
+}
+
+impl From<&Collaborator> for CollaboratorResponse {
+    fn from(collaborator: &Collaborator) -> Self {
+        Self { user: collaborator.user_name.clone(), role: collaborator.role.to_string() }
+    }
+}
+
+#[get("/applications/{name}/collaborators")]
+pub async fn list_collaborators(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+    let collaborators = context.collaborators();
+
+    let listed: Vec<CollaboratorResponse> = collaborators.list(application_id(&application))?.iter().map(CollaboratorResponse::from).collect();
+
+    Ok(json_response(&listed))
+}
+
+/// Any collaborator may look up the others, and every user may look up their own role, which is
+/// how the git server authorizes pushes. The role includes what membership in the owning
+/// organization grants.
+#[get("/applications/{name}/collaborators/{user}")]
+pub async fn find_collaborator(path: web::Path<(String, String)>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let (name, collaborator_name) = path.into_inner();
+    let application = find_application_by_name(&context, name.as_str())?;
+    let collaborators = context.collaborators();
+    let organizations = context.organizations();
+    let access = ApplicationAccess::new(collaborators.as_ref(), organizations.as_ref());
+    if user.name != collaborator_name {
+        access.require(&application, &user.name, Role::Viewer)?;
+    }
+
+    match access.role_of(&application, collaborator_name.as_str())? {
+        Some(role) => Ok(json_response(&CollaboratorResponse { user: collaborator_name, role: role.to_string() })),
+        None => Err(ApiError::NotFound { message: format!("{} is not a collaborator of application {}", collaborator_name, name) }),
+    }
+}
+
+#[put("/applications/{name}/collaborators/{user}")]
+pub async fn save_collaborator(path: web::Path<(String, String)>, request: web::Json<CollaboratorSaveRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let (name, collaborator_name) = path.into_inner();
+    let application = find_application(&context, name.as_str(), &user, Role::Admin)?;
+    let collaborators = context.collaborators();
+
+    let role = Role::from_str(request.role.as_str())?;
+    if role == Role::Owner {
+        return Err(ApiError::FieldValidationFailed { field: "role".to_string(), message: "an application has exactly one owner".to_string() });
+    }
+    if is_owner(&application, collaborator_name.as_str()) {
+        return Err(ApiError::ValidationFailed { message: "the role of the owner can not be changed".to_string() });
+    }
+
+    let collaborator = Collaborator { application_id: application_id(&application), user_name: collaborator_name, role };
+    collaborators.save(&collaborator)?;
+
+    Ok(json_response(&CollaboratorResponse::from(&collaborator)))
+}
+
+/// Admins remove collaborators, and every collaborator but the owner may leave on their own.
+#[delete("/applications/{name}/collaborators/{user}")]
+pub async fn remove_collaborator(path: web::Path<(String, String)>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let (name, collaborator_name) = path.into_inner();
+    let application = find_application_by_name(&context, name.as_str())?;
+    let collaborators = context.collaborators();
+    let organizations = context.organizations();
+    if user.name != collaborator_name {
+        ApplicationAccess::new(collaborators.as_ref(), organizations.as_ref()).require(&application, &user.name, Role::Admin)?;
+    }
+
+    if is_owner(&application, collaborator_name.as_str()) {
+        return Err(ApiError::ValidationFailed { message: "the owner can not be removed".to_string() });
+    }
+
+    collaborators.remove(application_id(&application), collaborator_name.as_str())?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn is_owner(application: &Application, user_name: &str) -> bool {
+    application.accept(|_, _, owner, _| owner.to_string()) == user_name
+}
+
+fn json_response<T: Serialize>(body: &T) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(body).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+
+    use capsule_core::application::Role;
+
+    use crate::resources::test_support::{context_with_application};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn collaborator(user: &str, role: Role) -> Collaborator {
+        Collaborator { application_id: 1, user_name: user.to_string(), role }
+    }
+
+    #[actix_web::test]
+    async fn should_add_collaborator_if_admin() {
+        let context = context_with_application();
+        let collaborators = context.collaborators();
+        collaborators.save(&collaborator("admin_user", Role::Admin)).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(save_collaborator)).await;
+
+        let req = test::TestRequest::put()
+            .uri("/applications/first-capsule-application/collaborators/deploy_user")
+            .insert_header((USER_HEADER, "admin_user"))
+            .set_json(CollaboratorSaveRequest { role: "deployer".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(collaborators.find(1, "deploy_user").unwrap(), Some(collaborator("deploy_user", Role::Deployer)));
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_deployer_to_add_collaborator() {
+        let context = context_with_application();
+        let collaborators = context.collaborators();
+        collaborators.save(&collaborator("deploy_user", Role::Deployer)).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(save_collaborator)).await;
+
+        let req = test::TestRequest::put()
+            .uri("/applications/first-capsule-application/collaborators/other_user")
+            .insert_header((USER_HEADER, "deploy_user"))
+            .set_json(CollaboratorSaveRequest { role: "admin".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+        assert!(collaborators.find(1, "other_user").unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn should_reject_unknown_role_and_second_owner() {
+        let app = test::init_service(App::new().app_data(web::Data::new(context_with_application())).service(save_collaborator)).await;
+
+        for role in ["root", "owner"] {
+            let req = test::TestRequest::put()
+                .uri("/applications/first-capsule-application/collaborators/other_user")
+                .insert_header((USER_HEADER, "first_capsule_user"))
+                .set_json(CollaboratorSaveRequest { role: role.to_string() })
+                .to_request();
+
+            let resp = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    #[actix_web::test]
+    async fn should_list_collaborators_for_viewer() {
+        let context = context_with_application();
+        context.collaborators().save(&collaborator("view_user", Role::Viewer)).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(list_collaborators)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/collaborators")
+            .insert_header((USER_HEADER, "view_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let listed: Vec<CollaboratorResponse> = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(listed, vec![
+            CollaboratorResponse { user: "first_capsule_user".to_string(), role: "owner".to_string() },
+            CollaboratorResponse { user: "view_user".to_string(), role: "viewer".to_string() },
+        ]);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_stranger_to_list_collaborators() {
+        let app = test::init_service(App::new().app_data(web::Data::new(context_with_application())).service(list_collaborators)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/collaborators")
+            .insert_header((USER_HEADER, "stranger"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn should_let_user_look_up_own_role_only() {
+        let context = context_with_application();
+        context.collaborators().save(&collaborator("deploy_user", Role::Deployer)).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(find_collaborator)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/collaborators/deploy_user")
+            .insert_header((USER_HEADER, "deploy_user"))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/collaborators/stranger")
+            .insert_header((USER_HEADER, "stranger"))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/collaborators/deploy_user")
+            .insert_header((USER_HEADER, "stranger"))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn should_remove_collaborator_but_not_owner() {
+        let context = context_with_application();
+        let collaborators = context.collaborators();
+        collaborators.save(&collaborator("deploy_user", Role::Deployer)).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(remove_collaborator)).await;
+
+        let req = test::TestRequest::delete()
+            .uri("/applications/first-capsule-application/collaborators/deploy_user")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+        assert!(collaborators.find(1, "deploy_user").unwrap().is_none());
+
+        let req = test::TestRequest::delete()
+            .uri("/applications/first-capsule-application/collaborators/first_capsule_user")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(collaborators.find(1, "first_capsule_user").unwrap().is_some());
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use actix_web::{delete, get, HttpResponse, patch, put, web};
+use actix_web::http::header::ContentType;
+
+use capsule_core::application::{Application, ConfigVarChanges, ConfigVarMap, replacement_changes, Role, validate_key};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+/// Config vars hold secrets, so viewers can not read them; deployers manage them like the code they push.
+#[get("/applications/{name}/config")]
+pub async fn find_config_vars(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+
+    Ok(json_response(&context.config_vars().find_all(application_id(&application))?))
+}
+
+/// Replaces all config vars, keys missing in the request are unset.
+#[put("/applications/{name}/config")]
+pub async fn replace_config_vars(name: web::Path<String>, request: web::Json<ConfigVarMap>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+    let config_vars = context.config_vars();
+    let current = config_vars.find_all(application_id(&application))?;
+
+    apply(&context, &application, &user, &replacement_changes(&current, &request))
+}
+
+/// Sets the given config vars and unsets the ones set to `null`, leaving all others alone.
+#[patch("/applications/{name}/config")]
+pub async fn update_config_vars(name: web::Path<String>, request: web::Json<ConfigVarChanges>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+
+    apply(&context, &application, &user, &request)
+}
+
+#[delete("/applications/{name}/config")]
+pub async fn remove_config_vars(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+    let config_vars = context.config_vars();
+    let current = config_vars.find_all(application_id(&application))?;
+
+    config_vars.apply(application_id(&application), &replacement_changes(&current, &ConfigVarMap::new()), user.name.as_str())?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn apply(context: &ServerContext, application: &Application, user: &CurrentUser, changes: &ConfigVarChanges) -> Result<HttpResponse, ApiError> {
+    for key in changes.iter().filter(|(_, value)| value.is_some()).map(|(key, _)| key) {
+        validate_key(key)?;
+    }
+
+    let config_vars = context.config_vars();
+    config_vars.apply(application_id(application), changes, user.name.as_str())?;
+
+    Ok(json_response(&config_vars.find_all(application_id(application))?))
+}
+
+fn json_response(vars: &ConfigVarMap) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(vars).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+
+    use capsule_core::application::{Collaborator, Role};
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context_with_application};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn context_with_config_vars() -> ServerContext {
+        let context = context_with_application();
+        let changes = ConfigVarChanges::from([("PORT".to_string(), Some("8080".to_string())), ("SECRET".to_string(), Some("s3cr3t".to_string()))]);
+        context.config_vars().apply(1, &changes, "first_capsule_user").unwrap();
+        context
+    }
+
+    fn vars(entries: &[(&str, &str)]) -> ConfigVarMap {
+        entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[actix_web::test]
+    async fn should_return_config_vars_to_deployer() {
+        let context = context_with_config_vars();
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "deploy_user".to_string(), role: Role::Deployer }).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(find_config_vars)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/config")
+            .insert_header((USER_HEADER, "deploy_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body: ConfigVarMap = test::read_body_json(resp).await;
+        assert_eq!(vars(&[("PORT", "8080"), ("SECRET", "s3cr3t")]), body);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_viewer_to_read_config_vars() {
+        let context = context_with_config_vars();
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "view_user".to_string(), role: Role::Viewer }).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(find_config_vars)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/config")
+            .insert_header((USER_HEADER, "view_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn should_replace_config_vars() {
+        let context = context_with_config_vars();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(replace_config_vars)).await;
+
+        let req = test::TestRequest::put()
+            .uri("/applications/first-capsule-application/config")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(vars(&[("PORT", "9090")]))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body: ConfigVarMap = test::read_body_json(resp).await;
+        assert_eq!(vars(&[("PORT", "9090")]), body);
+    }
+
+    #[actix_web::test]
+    async fn should_update_config_vars_and_record_changed_keys() {
+        let context = context_with_config_vars();
+        let config_vars = context.config_vars();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(update_config_vars)).await;
+
+        let req = test::TestRequest::patch()
+            .uri("/applications/first-capsule-application/config")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(serde_json::json!({ "SECRET": null, "DEBUG": "true", "PORT": "8080" }))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body: ConfigVarMap = test::read_body_json(resp).await;
+        assert_eq!(vars(&[("DEBUG", "true"), ("PORT", "8080")]), body);
+
+        let last_change = config_vars.history(1).unwrap().pop().unwrap();
+        assert_eq!("first_capsule_user", last_change.user_name);
+        assert_eq!(vec!["DEBUG".to_string(), "SECRET".to_string()], last_change.keys);
+    }
+
+    #[actix_web::test]
+    async fn should_reject_invalid_config_var_key() {
+        let context = context_with_config_vars();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(update_config_vars)).await;
+
+        let req = test::TestRequest::patch()
+            .uri("/applications/first-capsule-application/config")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(serde_json::json!({ "NOT-VALID": "1" }))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn should_remove_all_config_vars() {
+        let context = context_with_config_vars();
+        let config_vars = context.config_vars();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(remove_config_vars)).await;
+
+        let req = test::TestRequest::delete()
+            .uri("/applications/first-capsule-application/config")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+        assert!(config_vars.find_all(1).unwrap().is_empty());
+    }
+}
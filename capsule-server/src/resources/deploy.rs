@@ -0,0 +1,268 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{get, HttpResponse, post, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{ApplicationError, Deploy, DeployCoordinator, DeployStatus, DeployStep, DeployTarget, HttpHealthProbe, Role};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+const LISTED_DEPLOYS: i64 = 20;
+
+#[derive(Deserialize, Serialize)]
+pub struct DeployRequest {
+    /// The latest release if absent.
+    pub release: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct DeployStepResponse {
+    step: String,
+    succeeded: bool,
+    message: String,
+    created_at: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct DeployResponse {
+    id: i32,
+    release: i32,
+    status: String,
+    created_by: String,
+    created_at: u64,
+    finished_at: Option<u64>,
+    steps: Vec<DeployStepResponse>,
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl From<&DeployStep> for DeployStepResponse {
+    fn from(step: &DeployStep) -> Self {
+        Self { step: step.kind.to_string(), succeeded: step.succeeded, message: step.message.clone(), created_at: seconds(step.create_at) }
+    }
+}
+
+impl From<&Deploy> for DeployResponse {
+    fn from(deploy: &Deploy) -> Self {
+        Self {
+            id: deploy.id,
+            release: deploy.release_version,
+            status: deploy.status.to_string(),
+            created_by: deploy.created_by.clone(),
+            created_at: seconds(deploy.create_at),
+            finished_at: deploy.finish_at.map(seconds),
+            steps: deploy.steps.iter().map(DeployStepResponse::from).collect(),
+        }
+    }
+}
+
+/// Queues a deploy of the release, the deploy runs in the background after the ones queued before.
+#[post("/applications/{name}/deploys")]
+pub async fn create_deploy(name: web::Path<String>, request: web::Json<DeployRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+    let application_id = application_id(&application);
+
+    let releases = context.releases();
+    let release = match request.release {
+        Some(version) => releases.find(application_id, version)?,
+        None => releases.latest(application_id)?,
+    };
+    let release = match release {
+        Some(release) => release,
+        None => return Err(ApiError::NotFound { message: format!("release to deploy of application {} not found", name) }),
+    };
+
+    let deploy = context.deploys().create(application_id, release.version, user.name.as_str())?;
+
+    Ok(HttpResponse::Accepted()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&DeployResponse::from(&deploy)).unwrap()))
+}
+
+#[get("/applications/{name}/deploys")]
+pub async fn list_deploys(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+
+    let listed: Vec<DeployResponse> = context.deploys().list(application_id(&application), LISTED_DEPLOYS)?.iter().map(DeployResponse::from).collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&listed).unwrap()))
+}
+
+/// Runs a queued deploy with the current formation and health check of its application. A deploy
+/// whose release is gone, or that can not be set up, fails right away.
+pub fn run_deploy(context: &ServerContext, deploy: &Deploy) -> Result<DeployStatus, ApplicationError> {
+    let deploys = context.deploys();
+    let target = match deploy_target(context, deploy) {
+        Ok(Some(target)) => target,
+        // left pending, the deploy would be picked up again every round.
+        not_deployable => {
+            deploys.update_status(deploy.id, DeployStatus::Failed)?;
+            return not_deployable.map(|_| DeployStatus::Failed);
+        }
+    };
+
+    let settings = context.settings();
+    let runtime = context.runtime();
+    let health_checker = context.health_checker();
+    let release_timeout = Duration::from_secs(settings.deploy.release_timeout_secs);
+    DeployCoordinator::new(runtime.as_ref(), &HttpHealthProbe, health_checker.as_ref(), deploys.as_ref(), release_timeout)
+        .deploy(deploy, &target)
+}
+
+fn deploy_target(context: &ServerContext, deploy: &Deploy) -> Result<Option<DeployTarget>, ApplicationError> {
+    let release = match context.releases().find(deploy.application_id, deploy.release_version)? {
+        Some(release) => release,
+        None => return Ok(None),
+    };
+
+    let settings = context.settings();
+    Ok(Some(DeployTarget {
+        release,
+        formation: context.formations().find(deploy.application_id)?,
+        working_dir: Path::new(&settings.deploy.release_dir).join(deploy.application_id.to_string()).join(deploy.release_version.to_string()),
+        health_check: context.health_checks().find(deploy.application_id)?.unwrap_or_default(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+    use serde_json::json;
+
+    use capsule_core::application::{Collaborator, DeployStepKind};
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context_with_application, release_spec};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn release(context: &ServerContext, commit_sha: &str) {
+        context.releases().create(1, &release_spec(commit_sha, &[("web", "./app")])).unwrap();
+    }
+
+    fn released_context() -> ServerContext {
+        let context = context_with_application();
+        release(&context, "aaa");
+        release(&context, "bbb");
+        context
+    }
+
+    async fn post_deploy(context: ServerContext, user: &str, body: serde_json::Value) -> (http::StatusCode, Option<DeployResponse>) {
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(create_deploy)).await;
+        let req = test::TestRequest::post()
+            .uri("/applications/first-capsule-application/deploys")
+            .insert_header((USER_HEADER, user))
+            .set_json(body)
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    #[actix_web::test]
+    async fn should_queue_deploy_of_latest_release() {
+        let context = released_context();
+        let deploys = context.deploys();
+
+        let (status, body) = post_deploy(context, "first_capsule_user", json!({})).await;
+
+        assert_eq!(http::StatusCode::ACCEPTED, status);
+        let body = body.unwrap();
+        assert_eq!((2, "pending", "first_capsule_user"), (body.release, body.status.as_str(), body.created_by.as_str()));
+        assert_eq!(vec![2], deploys.pending().unwrap().iter().map(|d| d.release_version).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn should_queue_deploy_of_given_release() {
+        let (status, body) = post_deploy(released_context(), "first_capsule_user", json!({"release": 1})).await;
+
+        assert_eq!(http::StatusCode::ACCEPTED, status);
+        assert_eq!(1, body.unwrap().release);
+    }
+
+    #[actix_web::test]
+    async fn should_not_queue_deploy_of_unknown_release() {
+        let (status, _) = post_deploy(released_context(), "first_capsule_user", json!({"release": 7})).await;
+
+        assert_eq!(http::StatusCode::NOT_FOUND, status);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_viewer_to_deploy() {
+        let context = released_context();
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "viewer".to_string(), role: Role::Viewer }).unwrap();
+        let deploys = context.deploys();
+
+        let (status, _) = post_deploy(context, "viewer", json!({})).await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+        assert!(deploys.pending().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn should_list_deploys_with_steps() {
+        let context = released_context();
+        let deploys = context.deploys();
+        let deploy = deploys.create(1, 2, "first_capsule_user").unwrap();
+        deploys.add_step(deploy.id, &DeployStep { kind: DeployStepKind::ReleasePhase, succeeded: false, message: "`exit 1` exited with code 1".to_string(), create_at: SystemTime::now() }).unwrap();
+        deploys.update_status(deploy.id, DeployStatus::Failed).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(list_deploys)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/deploys")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+
+        assert_eq!(http::StatusCode::OK, resp.status());
+        let body: Vec<DeployResponse> = test::read_body_json(resp).await;
+        assert_eq!(("failed", true), (body[0].status.as_str(), body[0].finished_at.is_some()));
+        assert_eq!(vec![("release_phase", false)], body[0].steps.iter().map(|s| (s.step.as_str(), s.succeeded)).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn should_run_queued_deploy() {
+        let context = released_context();
+        let deploy = context.deploys().create(1, 2, "first_capsule_user").unwrap();
+
+        assert_eq!(DeployStatus::Succeeded, run_deploy(&context, &deploy).unwrap());
+
+        let deployed = context.deploys().find(deploy.id).unwrap().unwrap();
+        assert_eq!(4, deployed.steps.len());
+        assert!(context.deploys().pending().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn should_fail_deploy_of_removed_release() {
+        let context = released_context();
+        let deploy = context.deploys().create(1, 2, "first_capsule_user").unwrap();
+        context.releases().remove_all(1).unwrap();
+
+        assert_eq!(DeployStatus::Failed, run_deploy(&context, &deploy).unwrap());
+        assert!(context.deploys().find(deploy.id).unwrap().unwrap().steps.is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::{delete, get, HttpResponse, post, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{CustomDomain, Role};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+#[derive(Deserialize, Serialize)]
+pub struct DomainCreateRequest {
+    /// Like `www.theirshop.com`.
+    pub hostname: String,
+}
+
+/// The TXT record the customer creates at their DNS provider to prove controlling the domain.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct VerificationRecord {
+    #[serde(rename = "type")]
+    record_type: String,
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct DomainResponse {
+    hostname: String,
+    /// `pending` until the verification record is found, `verified` once it is served.
+    status: String,
+    verification: VerificationRecord,
+    /// Milliseconds since the unix epoch.
+    verified_at: Option<u64>,
+    last_checked_at: Option<u64>,
+    last_error: Option<String>,
+}
+
+impl From<&CustomDomain> for DomainResponse {
+    fn from(domain: &CustomDomain) -> Self {
+        let millis = |time: Option<SystemTime>| time.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_millis() as u64);
+
+        Self {
+            hostname: domain.hostname.clone(),
+            status: if domain.is_verified() { "verified" } else { "pending" }.to_string(),
+            verification: VerificationRecord { record_type: "TXT".to_string(), name: domain.verification_name(), value: domain.verification_token.clone() },
+            verified_at: millis(domain.verified_at),
+            last_checked_at: millis(domain.last_checked_at),
+            last_error: domain.last_error.clone(),
+        }
+    }
+}
+
+/// Registers a custom domain, served by the application once the background verifier finds
+/// the verification record.
+#[post("/applications/{name}/domains")]
+pub async fn add_domain(name: web::Path<String>, request: web::Json<DomainCreateRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Admin)?;
+
+    let domain = CustomDomain::new(application_id(&application), request.hostname.as_str(), &context.settings().platform_domain(), SystemTime::now())?;
+    context.custom_domains().add(&domain)?;
+
+    Ok(HttpResponse::Created()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&DomainResponse::from(&domain)).unwrap()))
+}
+
+#[get("/applications/{name}/domains")]
+pub async fn list_domains(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+
+    let domains = context.custom_domains().list(application_id(&application))?;
+    let response: Vec<DomainResponse> = domains.iter().map(DomainResponse::from).collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&response).unwrap()))
+}
+
+#[delete("/applications/{name}/domains/{hostname}")]
+pub async fn remove_domain(path: web::Path<(String, String)>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let (name, hostname) = path.into_inner();
+    let application = find_application(&context, name.as_str(), &user, Role::Admin)?;
+
+    let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+    if !context.custom_domains().remove(application_id(&application), hostname.as_str())? {
+        return Err(ApiError::NotFound { message: format!("custom domain {} not found", hostname) });
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+    use serde_json::json;
+
+    use capsule_core::application::Collaborator;
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context_with_application};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    async fn post_domain(context: web::Data<ServerContext>, user: &str, hostname: &str) -> (http::StatusCode, String) {
+        let app = test::init_service(App::new().app_data(context).service(add_domain)).await;
+        let req = test::TestRequest::post()
+            .uri("/applications/first-capsule-application/domains")
+            .insert_header((USER_HEADER, user))
+            .set_json(json!({"hostname": hostname}))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        (status, String::from_utf8(test::read_body(resp).await.to_vec()).unwrap())
+    }
+
+    async fn get_domains(context: web::Data<ServerContext>, user: &str) -> (http::StatusCode, Option<Vec<DomainResponse>>) {
+        let app = test::init_service(App::new().app_data(context).service(list_domains)).await;
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/domains")
+            .insert_header((USER_HEADER, user))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    async fn delete_domain(context: web::Data<ServerContext>, user: &str, hostname: &str) -> http::StatusCode {
+        let app = test::init_service(App::new().app_data(context).service(remove_domain)).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/applications/first-capsule-application/domains/{}", hostname))
+            .insert_header((USER_HEADER, user))
+            .to_request();
+
+        app.call(req).await.unwrap().status()
+    }
+
+    #[actix_web::test]
+    async fn should_add_domain_pending_verification() {
+        let context = web::Data::new(context_with_application());
+
+        let (status, body) = post_domain(context.clone(), "first_capsule_user", "WWW.TheirShop.com").await;
+
+        assert_eq!(http::StatusCode::CREATED, status);
+        let body: DomainResponse = serde_json::from_str(&body).unwrap();
+        let saved = context.custom_domains().list(1).unwrap().remove(0);
+        assert_eq!(("www.theirshop.com", "pending"), (body.hostname.as_str(), body.status.as_str()));
+        assert_eq!(VerificationRecord { record_type: "TXT".to_string(), name: "_capsule-verification.www.theirshop.com".to_string(), value: saved.verification_token }, body.verification);
+        assert_eq!(1, saved.application_id);
+    }
+
+    #[actix_web::test]
+    async fn should_reject_invalid_and_platform_host_names() {
+        let context = web::Data::new(context_with_application());
+
+        let (status, body) = post_domain(context.clone(), "first_capsule_user", "not a host").await;
+        assert_eq!(http::StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert!(body.contains("hostname"));
+
+        let (status, _) = post_domain(context.clone(), "first_capsule_user", "other-application.capsuleapp.cyou").await;
+        assert_eq!(http::StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert!(context.custom_domains().list(1).unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn should_not_add_domain_taken_by_another_application() {
+        let context = web::Data::new(context_with_application());
+        context.custom_domains().add(&CustomDomain::new(2, "www.theirshop.com", "capsuleapp.cyou", SystemTime::now()).unwrap()).unwrap();
+        context.custom_domains().mark_verified(2, "www.theirshop.com", SystemTime::now()).unwrap();
+
+        let (status, _) = post_domain(context.clone(), "first_capsule_user", "www.theirshop.com").await;
+
+        assert_eq!(http::StatusCode::CONFLICT, status);
+        assert!(context.custom_domains().list(1).unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn should_add_domain_another_application_has_not_verified() {
+        let context = web::Data::new(context_with_application());
+        context.custom_domains().add(&CustomDomain::new(2, "www.theirshop.com", "capsuleapp.cyou", SystemTime::now()).unwrap()).unwrap();
+
+        let (status, _) = post_domain(context.clone(), "first_capsule_user", "www.theirshop.com").await;
+
+        assert_eq!(http::StatusCode::CREATED, status);
+        assert_eq!(1, context.custom_domains().list(1).unwrap().len());
+        assert_eq!(1, context.custom_domains().list(2).unwrap().len());
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_deployer_to_add_domain() {
+        let context = web::Data::new(context_with_application());
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "deployer".to_string(), role: Role::Deployer }).unwrap();
+
+        let (status, _) = post_domain(context.clone(), "deployer", "www.theirshop.com").await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+    }
+
+    #[actix_web::test]
+    async fn should_list_verified_and_pending_domains() {
+        let context = web::Data::new(context_with_application());
+        let now = SystemTime::now();
+        for hostname in ["shop.theirshop.com", "www.theirshop.com"] {
+            context.custom_domains().add(&CustomDomain::new(1, hostname, "capsuleapp.cyou", now).unwrap()).unwrap();
+        }
+        context.custom_domains().mark_verified(1, "www.theirshop.com", now).unwrap();
+        context.custom_domains().record_failed_check(1, "shop.theirshop.com", now, "no TXT record found at _capsule-verification.shop.theirshop.com").unwrap();
+
+        let (status, body) = get_domains(context.clone(), "first_capsule_user").await;
+
+        assert_eq!(http::StatusCode::OK, status);
+        let body = body.unwrap();
+        assert_eq!(vec![("shop.theirshop.com", "pending"), ("www.theirshop.com", "verified")],
+                   body.iter().map(|d| (d.hostname.as_str(), d.status.as_str())).collect::<Vec<_>>());
+        assert!(body[0].last_error.as_ref().unwrap().starts_with("no TXT record found"));
+        assert!(body[1].verified_at.is_some());
+    }
+
+    #[actix_web::test]
+    async fn should_remove_domain() {
+        let context = web::Data::new(context_with_application());
+        context.custom_domains().add(&CustomDomain::new(1, "www.theirshop.com", "capsuleapp.cyou", SystemTime::now()).unwrap()).unwrap();
+
+        assert_eq!(http::StatusCode::NO_CONTENT, delete_domain(context.clone(), "first_capsule_user", "www.theirshop.com").await);
+        assert_eq!(http::StatusCode::NOT_FOUND, delete_domain(context.clone(), "first_capsule_user", "www.theirshop.com").await);
+        assert!(context.custom_domains().list(1).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use actix_web::{get, HttpResponse, patch, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{Formation, FormationChange, OwnerLimits, ProcessScale, ProcessSize, Role};
+use capsule_core::buildpack::ProcessTypes;
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ProcessFormationResponse {
+    #[serde(rename = "type")]
+    process_type: String,
+    quantity: i32,
+    size: String,
+}
+
+/// Changes by process type, e.g. `{"web": {"quantity": 2, "size": "medium"}}`.
+pub type FormationUpdateRequest = BTreeMap<String, ProcessFormationUpdate>;
+
+#[derive(Deserialize, Serialize)]
+pub struct ProcessFormationUpdate {
+    pub quantity: Option<i32>,
+    pub size: Option<String>,
+}
+
+#[get("/applications/{name}/formation")]
+pub async fn find_formation(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+    let application_id = application_id(&application);
+
+    let formation = context.formations().find(application_id)?;
+
+    Ok(formation_response(&formation, &latest_process_types(&context, application_id)?))
+}
+
+#[patch("/applications/{name}/formation")]
+pub async fn update_formation(name: web::Path<String>, request: web::Json<FormationUpdateRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+    let (application_id, owner) = application.accept(|id, _, owner, _| (id, owner.to_string()));
+
+    let mut changes = BTreeMap::new();
+    for (process_type, update) in request.iter() {
+        let size = update.size.as_deref().map(ProcessSize::from_str).transpose()?;
+        changes.insert(process_type.clone(), FormationChange { quantity: update.quantity, size });
+    }
+
+    let process_types = latest_process_types(&context, application_id)?;
+    let limits = owner_limits(&context, owner.as_str())?;
+    let formation = context.formations().scale(application_id, owner.as_str(), &changes, &process_types, &limits, user.name.as_str())?;
+
+    Ok(formation_response(&formation, &process_types))
+}
+
+fn formation_response(formation: &Formation, process_types: &ProcessTypes) -> HttpResponse {
+    let processes: Vec<ProcessFormationResponse> = formation.of_process_types(process_types).into_iter()
+        .map(|(process_type, ProcessScale { quantity, size })| ProcessFormationResponse { process_type, quantity, size: size.to_string() })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&processes).unwrap())
+}
+
+fn latest_process_types(context: &ServerContext, application_id: i64) -> Result<ProcessTypes, ApiError> {
+    Ok(context.releases().latest(application_id)?.map(|r| r.spec.processes).unwrap_or_default())
+}
+
+fn owner_limits(context: &ServerContext, owner: &str) -> Result<OwnerLimits, ApiError> {
+    if let Some(limits) = context.owner_limits().find(owner)? {
+        return Ok(limits);
+    }
+
+    let settings = context.settings();
+    let max_size = ProcessSize::from_str(settings.formation.default_max_size.as_str())
+        .map_err(|e| ApiError::InternalError { message: e.to_string() })?;
+    Ok(OwnerLimits { max_instances: settings.formation.default_max_instances, max_size })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+    use serde_json::json;
+
+    use capsule_core::application::Collaborator;
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{add_application, context_with_application, release_spec};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn release(context: &ServerContext, application_id: i64) {
+        context.releases().create(application_id, &release_spec("aaa", &[("web", "./app"), ("worker", "./worker")])).unwrap();
+    }
+
+    fn released_context() -> ServerContext {
+        let context = context_with_application();
+        release(&context, 1);
+        context
+    }
+
+    async fn patch_formation(context: ServerContext, user: &str, body: serde_json::Value) -> (http::StatusCode, String) {
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(update_formation)).await;
+        let req = test::TestRequest::patch()
+            .uri("/applications/first-capsule-application/formation")
+            .insert_header((USER_HEADER, user))
+            .set_json(body)
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        (status, String::from_utf8(test::read_body(resp).await.to_vec()).unwrap())
+    }
+
+    fn process(process_type: &str, quantity: i32, size: &str) -> ProcessFormationResponse {
+        ProcessFormationResponse { process_type: process_type.to_string(), quantity, size: size.to_string() }
+    }
+
+    #[actix_web::test]
+    async fn should_list_process_types_of_latest_release() {
+        let context = released_context();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(find_formation)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/formation")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body: Vec<ProcessFormationResponse> = test::read_body_json(resp).await;
+        assert_eq!(vec![process("web", 0, "small"), process("worker", 0, "small")], body);
+    }
+
+    #[actix_web::test]
+    async fn should_scale_processes_and_record_events() {
+        let context = released_context();
+        let formations = context.formations();
+
+        let (status, body) = patch_formation(context, "first_capsule_user", json!({"web": {"quantity": 2, "size": "medium"}})).await;
+
+        assert_eq!(http::StatusCode::OK, status);
+        let body: Vec<ProcessFormationResponse> = serde_json::from_str(&body).unwrap();
+        assert_eq!(vec![process("web", 2, "medium"), process("worker", 0, "small")], body);
+        let events = formations.events_after(0, 10).unwrap();
+        assert_eq!(vec![("web", 2, "first_capsule_user")],
+                   events.iter().map(|e| (e.process_type.as_str(), e.scale.quantity, e.changed_by.as_str())).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn should_reject_unknown_process_type() {
+        let (status, body) = patch_formation(released_context(), "first_capsule_user", json!({"clock": {"quantity": 1}})).await;
+
+        assert_eq!(http::StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert!(body.contains("formation"));
+    }
+
+    #[actix_web::test]
+    async fn should_reject_unknown_size() {
+        let (status, _) = patch_formation(released_context(), "first_capsule_user", json!({"web": {"size": "huge"}})).await;
+
+        assert_eq!(http::StatusCode::UNPROCESSABLE_ENTITY, status);
+    }
+
+    #[actix_web::test]
+    async fn should_enforce_default_limits() {
+        let (status, _) = patch_formation(released_context(), "first_capsule_user", json!({"web": {"size": "large"}})).await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+    }
+
+    #[actix_web::test]
+    async fn should_count_instances_of_other_applications_of_owner() {
+        let context = released_context();
+        add_application(&context, 2, "second-capsule-application", "first_capsule_user");
+        release(&context, 2);
+        let formations = context.formations();
+        let mut other = formations.find(2).unwrap();
+        let scaled = other.scale(&BTreeMap::from([("web".to_string(), FormationChange { quantity: Some(8), size: None })]),
+                                 &ProcessTypes::from([("web".to_string(), "./app".to_string())]),
+                                 &OwnerLimits { max_instances: 10, max_size: ProcessSize::Small }, 0).unwrap();
+        formations.save(&other, &scaled, "first_capsule_user").unwrap();
+
+        let (status, _) = patch_formation(context, "first_capsule_user", json!({"web": {"quantity": 3}})).await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+    }
+
+    #[actix_web::test]
+    async fn should_use_limits_of_owner() {
+        let context = released_context();
+        context.owner_limits().save("first_capsule_user", &OwnerLimits { max_instances: 20, max_size: ProcessSize::Large }).unwrap();
+
+        let (status, _) = patch_formation(context, "first_capsule_user", json!({"web": {"quantity": 15, "size": "large"}})).await;
+
+        assert_eq!(http::StatusCode::OK, status);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_viewer_to_scale() {
+        let context = released_context();
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "view_user".to_string(), role: Role::Viewer }).unwrap();
+
+        let (status, _) = patch_formation(context, "view_user", json!({"web": {"quantity": 1}})).await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+    }
+}
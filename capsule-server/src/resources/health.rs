@@ -0,0 +1,267 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::UNIX_EPOCH;
+
+use actix_web::{get, HttpResponse, put, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{HealthCheckConfig, InstanceHealth, Role};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct HealthCheckBody {
+    pub path: String,
+    pub interval_secs: u32,
+    pub timeout_secs: u32,
+    pub healthy_threshold: u32,
+    pub unhealthy_threshold: u32,
+    pub deploy_timeout_secs: u32,
+}
+
+/// Fields left out get their default value.
+#[derive(Deserialize, Serialize)]
+pub struct HealthCheckRequest {
+    pub path: Option<String>,
+    pub interval_secs: Option<u32>,
+    pub timeout_secs: Option<u32>,
+    pub healthy_threshold: Option<u32>,
+    pub unhealthy_threshold: Option<u32>,
+    pub deploy_timeout_secs: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct InstanceHealthResponse {
+    process: String,
+    state: String,
+    consecutive_failures: u32,
+    restarts: u32,
+    last_error: Option<String>,
+    /// Milliseconds since the unix epoch.
+    last_checked: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct HealthResponse {
+    status: String,
+    check: HealthCheckBody,
+    instances: Vec<InstanceHealthResponse>,
+}
+
+impl From<&HealthCheckConfig> for HealthCheckBody {
+    fn from(config: &HealthCheckConfig) -> Self {
+        Self {
+            path: config.path.clone(),
+            interval_secs: config.interval_secs,
+            timeout_secs: config.timeout_secs,
+            healthy_threshold: config.healthy_threshold,
+            unhealthy_threshold: config.unhealthy_threshold,
+            deploy_timeout_secs: config.deploy_timeout_secs,
+        }
+    }
+}
+
+impl From<&InstanceHealth> for InstanceHealthResponse {
+    fn from(health: &InstanceHealth) -> Self {
+        Self {
+            process: health.instance.process_name(),
+            state: health.state.to_string(),
+            consecutive_failures: health.consecutive_failures,
+            restarts: health.restarts,
+            last_error: health.last_error.clone(),
+            last_checked: health.last_checked.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+#[get("/applications/{name}/health")]
+pub async fn find_health(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+    let application_id = application_id(&application);
+
+    let config = context.health_checks().find(application_id)?.unwrap_or_default();
+
+    Ok(health_response(&context, application_id, &config))
+}
+
+#[put("/applications/{name}/health")]
+pub async fn update_health_check(name: web::Path<String>, request: web::Json<HealthCheckRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+    let application_id = application_id(&application);
+
+    let request = request.into_inner();
+    let default = HealthCheckConfig::default();
+    let config = HealthCheckConfig {
+        path: request.path.unwrap_or(default.path),
+        interval_secs: request.interval_secs.unwrap_or(default.interval_secs),
+        timeout_secs: request.timeout_secs.unwrap_or(default.timeout_secs),
+        healthy_threshold: request.healthy_threshold.unwrap_or(default.healthy_threshold),
+        unhealthy_threshold: request.unhealthy_threshold.unwrap_or(default.unhealthy_threshold),
+        deploy_timeout_secs: request.deploy_timeout_secs.unwrap_or(default.deploy_timeout_secs),
+    };
+    config.validate()?;
+
+    context.health_checks().save(application_id, &config)?;
+    context.health_checker().configure(application_id, &config);
+
+    Ok(health_response(&context, application_id, &config))
+}
+
+fn health_response(context: &ServerContext, application_id: i64, config: &HealthCheckConfig) -> HttpResponse {
+    let health = context.health_checker().application_health(application_id);
+    let response = HealthResponse {
+        status: health.status.to_string(),
+        check: HealthCheckBody::from(config),
+        instances: health.instances.iter().map(InstanceHealthResponse::from).collect(),
+    };
+
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&response).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+    use serde_json::json;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{method, path};
+
+    use capsule_core::application::{Collaborator, ConfigVarMap, HealthChecker, HttpHealthProbe, ProcessInstance, ProcessSize, ProcessSpec, RestartPolicy};
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context_with_application, RunningRuntime};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn web_spec(index: u32) -> ProcessSpec {
+        ProcessSpec {
+            instance: ProcessInstance { application_id: 1, release_version: 1, process_type: "web".to_string(), index },
+            command: "./app".to_string(),
+            working_dir: PathBuf::from("/tmp"),
+            env: ConfigVarMap::new(),
+            size: ProcessSize::Small,
+        }
+    }
+
+    async fn get_health(context: ServerContext, user: &str) -> (http::StatusCode, Option<HealthResponse>) {
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(find_health)).await;
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/health")
+            .insert_header((USER_HEADER, user))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    async fn put_health_check(context: ServerContext, user: &str, body: serde_json::Value) -> (http::StatusCode, String) {
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(update_health_check)).await;
+        let req = test::TestRequest::put()
+            .uri("/applications/first-capsule-application/health")
+            .insert_header((USER_HEADER, user))
+            .set_json(body)
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        (status, String::from_utf8(test::read_body(resp).await.to_vec()).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn should_report_not_running_application_with_default_check() {
+        let (status, body) = get_health(context_with_application(), "first_capsule_user").await;
+
+        assert_eq!(http::StatusCode::OK, status);
+        let body = body.unwrap();
+        assert_eq!("not_running", body.status);
+        assert_eq!(HealthCheckBody::from(&HealthCheckConfig::default()), body.check);
+        assert!(body.instances.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn should_report_instances_probed_against_local_stub() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+        let mut context = context_with_application();
+        let checker = Arc::new(HealthChecker::new(Arc::new(RunningRuntime), Arc::new(HttpHealthProbe), RestartPolicy { max_restarts: 0 }));
+        let config = HealthCheckConfig { path: "/health".to_string(), interval_secs: 1, timeout_secs: 1, healthy_threshold: 1, unhealthy_threshold: 1, deploy_timeout_secs: 60 };
+        let now = Instant::now();
+        checker.watch(web_spec(0), mock_server.address().to_string(), config.clone(), now);
+        checker.watch(web_spec(1), "127.0.0.1:1".to_string(), config, now);
+        checker.check_due(now + Duration::from_secs(1));
+        context.health_checker = checker;
+
+        let body = get_health(context, "first_capsule_user").await.1.unwrap();
+
+        assert_eq!("degraded", body.status);
+        assert_eq!(vec![("web.0", "healthy"), ("web.1", "unhealthy")],
+                   body.instances.iter().map(|i| (i.process.as_str(), i.state.as_str())).collect::<Vec<_>>());
+        assert_eq!((1, 0), (body.instances[1].consecutive_failures, body.instances[1].restarts));
+        assert!(body.instances[0].last_checked.is_some());
+    }
+
+    #[actix_web::test]
+    async fn should_save_health_check_with_defaults_for_missing_fields() {
+        let context = context_with_application();
+        let health_checks = context.health_checks();
+
+        let (status, _) = put_health_check(context, "first_capsule_user", json!({"path": "/health", "interval_secs": 30})).await;
+
+        assert_eq!(http::StatusCode::OK, status);
+        let expected = HealthCheckConfig { path: "/health".to_string(), interval_secs: 30, ..HealthCheckConfig::default() };
+        assert_eq!(Some(expected), health_checks.find(1).unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_reject_invalid_health_check() {
+        let (status, body) = put_health_check(context_with_application(), "first_capsule_user", json!({"interval_secs": 5, "timeout_secs": 10})).await;
+
+        assert_eq!(http::StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert!(body.contains("health_check"));
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_viewer_to_change_health_check() {
+        let context = context_with_application();
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "viewer".to_string(), role: Role::Viewer }).unwrap();
+        let health_checks = context.health_checks();
+
+        let (status, _) = put_health_check(context, "viewer", json!({"path": "/health"})).await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+        assert_eq!(None, health_checks.find(1).unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_non_collaborator_to_see_health() {
+        let (status, _) = get_health(context_with_application(), "other_user").await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_web::{get, HttpRequest, HttpResponse, web};
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::rt::time::sleep;
+use actix_web::web::Bytes;
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{LogBuffer, LogFilter, LogLine, LogSource, Role};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+const DEFAULT_LINES: usize = 100;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Idle streams get a comment this often, so proxies do not close them.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+#[derive(Deserialize)]
+pub struct LogQuery {
+    /// Keeps the stream open for new lines.
+    pub tail: Option<bool>,
+    /// `build` or `app`.
+    pub source: Option<String>,
+    /// Process type, like `web`.
+    pub process: Option<String>,
+    pub lines: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct LogEventResponse {
+    /// Milliseconds since the unix epoch.
+    timestamp: u64,
+    source: String,
+    process: String,
+    message: String,
+}
+
+impl From<&LogLine> for LogEventResponse {
+    fn from(line: &LogLine) -> Self {
+        Self {
+            timestamp: line.timestamp.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+            source: line.source.to_string(),
+            process: line.process.clone(),
+            message: line.message.clone(),
+        }
+    }
+}
+
+/// Recent log lines as Server-Sent Events, with the line's seq as event id. A client reconnecting
+/// with `Last-Event-ID` gets the lines it missed instead of the recent ones.
+#[get("/applications/{name}/logs")]
+pub async fn stream_logs(name: web::Path<String>, query: web::Query<LogQuery>, req: HttpRequest, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+    let application_id = application_id(&application);
+    let filter = LogFilter {
+        source: query.source.as_deref().map(LogSource::from_str).transpose()?,
+        process_type: query.process.clone(),
+    };
+
+    let logs = context.logs();
+    let last_event_id = req.headers().get(LAST_EVENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let (initial, seen) = match last_event_id {
+        Some(seq) => (logs.since(application_id, &filter, seq), seq),
+        None => {
+            let seen = logs.last_seq();
+            (logs.recent(application_id, &filter, query.lines.unwrap_or(DEFAULT_LINES)), seen)
+        }
+    };
+    let cursor = initial.last().map_or(seen, |l| l.seq.max(seen));
+
+    let mut response = HttpResponse::Ok();
+    response.content_type("text/event-stream").insert_header((CACHE_CONTROL, "no-cache"));
+    if !query.tail.unwrap_or(false) {
+        return Ok(response.body(events(&initial)));
+    }
+
+    let tail = Tail { logs, application_id, filter, cursor, pending: Some(events(&initial)) };
+    Ok(response.streaming(stream::unfold(tail, |mut tail| async move {
+        let chunk = tail.next_chunk().await;
+        Some((Ok::<_, actix_web::Error>(chunk), tail))
+    })))
+}
+
+struct Tail {
+    logs: Arc<LogBuffer>,
+    application_id: i64,
+    filter: LogFilter,
+    cursor: u64,
+    pending: Option<Bytes>,
+}
+
+impl Tail {
+    /// Waits for lines after the cursor, or sends a keep-alive comment if none came for a while.
+    async fn next_chunk(&mut self) -> Bytes {
+        if let Some(pending) = self.pending.take().filter(|p| !p.is_empty()) {
+            return pending;
+        }
+
+        let mut idle = Duration::ZERO;
+        loop {
+            let lines = self.logs.since(self.application_id, &self.filter, self.cursor);
+            if let Some(last) = lines.last() {
+                self.cursor = last.seq;
+                return events(&lines);
+            }
+            if idle >= KEEP_ALIVE_INTERVAL {
+                return Bytes::from_static(b": keep-alive\n\n");
+            }
+
+            sleep(POLL_INTERVAL).await;
+            idle += POLL_INTERVAL;
+        }
+    }
+}
+
+fn events(lines: &[LogLine]) -> Bytes {
+    let mut events = String::new();
+    for line in lines {
+        events.push_str(&format!("id: {}\ndata: {}\n\n", line.seq, serde_json::to_string(&LogEventResponse::from(line)).unwrap()));
+    }
+
+    Bytes::from(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::time::Duration;
+
+    use actix_web::{App, http, test, web};
+    use actix_web::body::MessageBody;
+    use actix_web::dev::Service;
+
+    use capsule_core::application::LogSink;
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context_with_application};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn context_with_logs() -> ServerContext {
+        let context = context_with_application();
+        let logs = context.logs();
+        logs.append(1, LogSource::Build, "build.1", "compiling");
+        logs.append(1, LogSource::App, "web.0", "listening");
+        logs.append(1, LogSource::App, "worker.0", "working");
+        context
+    }
+
+    /// Event ids and log messages of an SSE body.
+    fn parse_events(body: &[u8]) -> Vec<(u64, String)> {
+        String::from_utf8(body.to_vec()).unwrap()
+            .split("\n\n")
+            .filter(|e| e.starts_with("id: "))
+            .map(|e| {
+                let (id, data) = e.split_once('\n').unwrap();
+                let event: LogEventResponse = serde_json::from_str(data.strip_prefix("data: ").unwrap()).unwrap();
+                (id.strip_prefix("id: ").unwrap().parse().unwrap(), event.message)
+            })
+            .collect()
+    }
+
+    async fn get_logs(context: ServerContext, uri: &str, last_event_id: Option<&str>) -> (http::StatusCode, Vec<(u64, String)>) {
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(stream_logs)).await;
+        let mut req = test::TestRequest::get().uri(uri).insert_header((USER_HEADER, "first_capsule_user"));
+        if let Some(id) = last_event_id {
+            req = req.insert_header((LAST_EVENT_ID, id));
+        }
+
+        let resp = app.call(req.to_request()).await.unwrap();
+        let status = resp.status();
+        (status, parse_events(&test::read_body(resp).await))
+    }
+
+    #[actix_web::test]
+    async fn should_send_recent_lines_as_events() {
+        let (status, events) = get_logs(context_with_logs(), "/applications/first-capsule-application/logs?lines=2", None).await;
+
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(vec![(2, "listening".to_string()), (3, "working".to_string())], events);
+    }
+
+    #[actix_web::test]
+    async fn should_filter_by_source_and_process_type() {
+        let context = context_with_logs();
+        context.logs().append(1, LogSource::App, "web.1", "listening too");
+
+        let (_, by_source) = get_logs(context_with_logs(), "/applications/first-capsule-application/logs?source=build", None).await;
+        let (_, by_process) = get_logs(context, "/applications/first-capsule-application/logs?source=app&process=web", None).await;
+
+        assert_eq!(vec!["compiling"], by_source.into_iter().map(|e| e.1).collect::<Vec<_>>());
+        assert_eq!(vec!["listening", "listening too"], by_process.into_iter().map(|e| e.1).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn should_reject_unknown_source() {
+        let (status, _) = get_logs(context_with_logs(), "/applications/first-capsule-application/logs?source=router", None).await;
+
+        assert_eq!(http::StatusCode::UNPROCESSABLE_ENTITY, status);
+    }
+
+    #[actix_web::test]
+    async fn should_resume_after_last_event_id() {
+        let (_, events) = get_logs(context_with_logs(), "/applications/first-capsule-application/logs", Some("2")).await;
+
+        assert_eq!(vec![(3, "working".to_string())], events);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_non_collaborator() {
+        let app = test::init_service(App::new().app_data(web::Data::new(context_with_logs())).service(stream_logs)).await;
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/logs")
+            .insert_header((USER_HEADER, "other_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+
+        assert_eq!(http::StatusCode::FORBIDDEN, resp.status());
+    }
+
+    #[actix_web::test]
+    async fn should_stream_new_lines_when_tailing() {
+        let context = context_with_logs();
+        let logs = context.logs();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(stream_logs)).await;
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/logs?tail=true&process=web")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!("text/event-stream", resp.headers().get(http::header::CONTENT_TYPE).unwrap());
+        let mut body = Box::pin(resp.into_body());
+
+        let recent = poll_fn(|cx| body.as_mut().poll_next(cx)).await.unwrap().unwrap();
+        logs.append(1, LogSource::App, "worker.0", "not for web");
+        logs.append(1, LogSource::App, "web.0", "GET /");
+        let tailed = actix_web::rt::time::timeout(Duration::from_secs(5), poll_fn(|cx| body.as_mut().poll_next(cx))).await
+            .expect("no line streamed").unwrap().unwrap();
+
+        assert_eq!(vec![(2, "listening".to_string())], parse_events(&recent));
+        assert_eq!(vec![(5, "GET /".to_string())], parse_events(&tailed));
+    }
+}
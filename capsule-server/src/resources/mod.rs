@@ -14,15 +14,90 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
 
-use actix_web::{error, HttpResponse};
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, error, FromRequest, HttpRequest, HttpResponse};
 use actix_web::http::StatusCode;
 use derive_more::Error;
 
+use capsule_core::application::{Application, ApplicationAccess, Role};
+use capsule_core::CoreError;
+
+use crate::context::ServerContext;
+
 pub mod application;
+pub mod build;
+pub mod certificate;
+pub mod collaborator;
+pub mod config_var;
+pub mod deploy;
+pub mod domain;
+pub mod formation;
+pub mod health;
+pub mod log;
+pub mod organization;
+pub mod process;
+pub mod release;
+#[cfg(test)]
+pub(crate) mod test_support;
+
+pub const USER_HEADER: &str = "X-Capsule-User";
+const DEFAULT_USER: &str = "capsule";
+
+/// The user a request acts for. The user is authenticated by the proxy in front of the server,
+/// which passes the name on in the `X-Capsule-User` header; without it the request acts for the
+/// single default user.
+pub struct CurrentUser {
+    pub name: String,
+}
+
+impl FromRequest for CurrentUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let name = match req.headers().get(USER_HEADER) {
+            Some(value) => match value.to_str() {
+                Ok(name) if !name.is_empty() => name.to_string(),
+                _ => return ready(Err(ApiError::ValidationFailed { message: format!("invalid {} header", USER_HEADER) })),
+            },
+            None => DEFAULT_USER.to_string(),
+        };
+
+        ready(Ok(CurrentUser { name }))
+    }
+}
+
+pub(crate) fn find_application_by_name(context: &ServerContext, name: &str) -> Result<Application, ApiError> {
+    match context.applications().find_by_name(name)? {
+        Some(application) => Ok(application),
+        None => Err(ApiError::NotFound { message: format!("application {} not found", name) }),
+    }
+}
+
+/// Finds the application `user` has at least the `required` role on.
+pub(crate) fn find_application(context: &ServerContext, name: &str, user: &CurrentUser, required: Role) -> Result<Application, ApiError> {
+    let application = find_application_by_name(context, name)?;
+
+    let collaborators = context.collaborators();
+    let organizations = context.organizations();
+    ApplicationAccess::new(collaborators.as_ref(), organizations.as_ref()).require(&application, &user.name, required)?;
+
+    Ok(application)
+}
+
+pub(crate) fn application_id(application: &Application) -> i64 {
+    application.accept(|id, _, _, _| id)
+}
 
 #[derive(Debug, Error)]
 pub enum ApiError {
     ValidationFailed { message: String },
+    FieldValidationFailed { field: String, message: String },
+    Conflict { message: String },
+    Unauthorized { message: String },
+    Forbidden { message: String },
+    NotFound { message: String },
     InternalError { message: String },
 }
 
@@ -35,6 +110,36 @@ impl Display for ApiError {
 
                 serde_json::to_string(&response).unwrap()
             }
+            ApiError::FieldValidationFailed { field, message } => {
+                let mut errors = HashMap::new();
+                errors.insert(field, message);
+
+                serde_json::json!({ "message": "validation failed", "errors": errors }).to_string()
+            }
+            ApiError::Conflict { message } => {
+                let mut response = HashMap::new();
+                response.insert("message", message);
+
+                serde_json::to_string(&response).unwrap()
+            }
+            ApiError::Unauthorized { message } => {
+                let mut response = HashMap::new();
+                response.insert("message", message);
+
+                serde_json::to_string(&response).unwrap()
+            }
+            ApiError::Forbidden { message } => {
+                let mut response = HashMap::new();
+                response.insert("message", message);
+
+                serde_json::to_string(&response).unwrap()
+            }
+            ApiError::NotFound { message } => {
+                let mut response = HashMap::new();
+                response.insert("message", message);
+
+                serde_json::to_string(&response).unwrap()
+            }
             ApiError::InternalError { message } => {
                 let mut response = HashMap::new();
                 response.insert("message", message);
@@ -47,10 +152,21 @@ impl Display for ApiError {
     }
 }
 
+impl From<CoreError> for ApiError {
+    fn from(e: CoreError) -> Self {
+        ApiError::InternalError { message: e.to_string() }
+    }
+}
+
 impl error::ResponseError for ApiError {
     fn status_code(&self) -> StatusCode {
         match self {
             ApiError::ValidationFailed { message: _ } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::FieldValidationFailed { field: _, message: _ } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Conflict { message: _ } => StatusCode::CONFLICT,
+            ApiError::Unauthorized { message: _ } => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden { message: _ } => StatusCode::FORBIDDEN,
+            ApiError::NotFound { message: _ } => StatusCode::NOT_FOUND,
             ApiError::InternalError { message: _ } => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -0,0 +1,244 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::str::FromStr;
+
+use actix_web::{delete, get, HttpResponse, post, put, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::organization::{change_membership, Member, Organization, OrganizationError, OrganizationRole, require_member_role};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, CurrentUser};
+
+#[derive(Deserialize, Serialize)]
+pub struct OrganizationCreateRequest {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct OrganizationResponse {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MemberSaveRequest {
+    role: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct MemberResponse {
+    user: String,
+    role: String,
+}
+
+impl From<&Member> for MemberResponse {
+    fn from(member: &Member) -> Self {
+        Self { user: member.user_name.clone(), role: member.role.to_string() }
+    }
+}
+
+impl From<OrganizationError> for ApiError {
+    fn from(e: OrganizationError) -> Self {
+        match e {
+            OrganizationError::InvalidOrganizationName { message } => {
+                ApiError::FieldValidationFailed { field: "name".to_string(), message }
+            }
+            e @ OrganizationError::OrganizationAlreadyExists { name: _ } => {
+                ApiError::Conflict { message: e.to_string() }
+            }
+            OrganizationError::InvalidRole { message } => {
+                ApiError::FieldValidationFailed { field: "role".to_string(), message }
+            }
+            OrganizationError::PermissionDenied { message } => {
+                ApiError::Forbidden { message }
+            }
+            OrganizationError::InvalidMembership { message } => {
+                ApiError::ValidationFailed { message }
+            }
+            OrganizationError::InternalError { message } => {
+                ApiError::InternalError { message }
+            }
+        }
+    }
+}
+
+/// Creates an organization with the requesting user as its first owner.
+#[post("/organizations")]
+pub async fn create_organization(request: web::Json<OrganizationCreateRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let organization = Organization::new(request.name.as_str())?;
+    context.organizations().add(&organization, user.name.as_str())?;
+
+    Ok(HttpResponse::Created()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&OrganizationResponse { name: organization.name }).unwrap()))
+}
+
+#[get("/organizations/{name}/members")]
+pub async fn list_members(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let organizations = context.organizations();
+    find_organization(&context, name.as_str())?;
+    require_member_role(organizations.as_ref(), name.as_str(), user.name.as_str(), OrganizationRole::Member)?;
+
+    let listed: Vec<MemberResponse> = organizations.list_members(name.as_str())?.iter().map(MemberResponse::from).collect();
+
+    Ok(json_response(&listed))
+}
+
+#[put("/organizations/{name}/members/{user}")]
+pub async fn save_member(path: web::Path<(String, String)>, request: web::Json<MemberSaveRequest>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let (name, member_name) = path.into_inner();
+    find_organization(&context, name.as_str())?;
+
+    let role = OrganizationRole::from_str(request.role.as_str())?;
+    change_membership(context.organizations().as_ref(), name.as_str(), user.name.as_str(), member_name.as_str(), Some(role))?;
+
+    Ok(json_response(&MemberResponse { user: member_name, role: role.to_string() }))
+}
+
+#[delete("/organizations/{name}/members/{user}")]
+pub async fn remove_member(path: web::Path<(String, String)>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let (name, member_name) = path.into_inner();
+    find_organization(&context, name.as_str())?;
+
+    change_membership(context.organizations().as_ref(), name.as_str(), user.name.as_str(), member_name.as_str(), None)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn find_organization(context: &ServerContext, name: &str) -> Result<Organization, ApiError> {
+    match context.organizations().find_by_name(name)? {
+        Some(organization) => Ok(organization),
+        None => Err(ApiError::NotFound { message: format!("organization {} not found", name) }),
+    }
+}
+
+fn json_response<T: Serialize>(body: &T) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(body).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context, DomainNameServiceStub, GitServiceStub};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn context_with_organization() -> ServerContext {
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        context.organizations().add(&Organization::new("capsule-team").unwrap(), "first_capsule_user").unwrap();
+        context
+    }
+
+    #[actix_web::test]
+    async fn should_create_organization_with_creator_as_owner() {
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        let organizations = context.organizations();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(create_organization)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/organizations")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(OrganizationCreateRequest { name: "capsule-team".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+        let owner = organizations.find_member("capsule-team", "first_capsule_user").unwrap().unwrap();
+        assert_eq!(owner.role, OrganizationRole::Owner);
+    }
+
+    #[actix_web::test]
+    async fn should_return_conflict_if_organization_name_taken() {
+        let context = context_with_organization();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(create_organization)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/organizations")
+            .set_json(OrganizationCreateRequest { name: "capsule-team".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn should_return_field_error_if_organization_name_invalid() {
+        let context = context(GitServiceStub, DomainNameServiceStub);
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(create_organization)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/organizations")
+            .set_json(OrganizationCreateRequest { name: "-capsule".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn should_add_member_if_owner() {
+        let context = context_with_organization();
+        let organizations = context.organizations();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(save_member)).await;
+
+        let req = test::TestRequest::put()
+            .uri("/organizations/capsule-team/members/another_user")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .set_json(MemberSaveRequest { role: "admin".to_string() })
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body: MemberResponse = test::read_body_json(resp).await;
+        assert_eq!(body, MemberResponse { user: "another_user".to_string(), role: "admin".to_string() });
+        assert_eq!(organizations.find_member("capsule-team", "another_user").unwrap().unwrap().role, OrganizationRole::Admin);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_stranger_to_list_members() {
+        let context = context_with_organization();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(list_members)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/organizations/capsule-team/members")
+            .insert_header((USER_HEADER, "stranger"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn should_not_remove_last_owner() {
+        let context = context_with_organization();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(remove_member)).await;
+
+        let req = test::TestRequest::delete()
+            .uri("/organizations/capsule-team/members/first_capsule_user")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
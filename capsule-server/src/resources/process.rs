@@ -0,0 +1,113 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use actix_web::{get, HttpResponse, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::Role;
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ProcessResponse {
+    #[serde(rename = "type")]
+    process_type: String,
+    command: String,
+}
+
+/// Process types of the latest release, `release` is absent until the application is released.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ProcessesResponse {
+    release: Option<i32>,
+    processes: Vec<ProcessResponse>,
+}
+
+#[get("/applications/{name}/processes")]
+pub async fn list_processes(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+
+    let latest = context.releases().latest(application_id(&application))?;
+    let response = ProcessesResponse {
+        release: latest.as_ref().map(|r| r.version),
+        processes: latest.map(|r| r.spec.processes.into_iter()
+            .map(|(process_type, command)| ProcessResponse { process_type, command })
+            .collect())
+            .unwrap_or_default(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&response).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context_with_application, release_spec};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    async fn list(context: ServerContext, user: &str) -> (http::StatusCode, Option<ProcessesResponse>) {
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(list_processes)).await;
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/processes")
+            .insert_header((USER_HEADER, user))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        if status != http::StatusCode::OK {
+            return (status, None);
+        }
+        (status, Some(test::read_body_json(resp).await))
+    }
+
+    #[actix_web::test]
+    async fn should_list_processes_of_latest_release() {
+        let context = context_with_application();
+        context.releases().create(1, &release_spec("aaa", &[("web", "./app")])).unwrap();
+        context.releases().create(1, &release_spec("aaa", &[("web", "./app --port $PORT"), ("worker", "./worker")])).unwrap();
+
+        let (status, body) = list(context, "first_capsule_user").await;
+
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(ProcessesResponse {
+            release: Some(2),
+            processes: vec![
+                ProcessResponse { process_type: "web".to_string(), command: "./app --port $PORT".to_string() },
+                ProcessResponse { process_type: "worker".to_string(), command: "./worker".to_string() },
+            ],
+        }, body.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_list_no_processes_before_first_release() {
+        let (status, body) = list(context_with_application(), "first_capsule_user").await;
+
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(ProcessesResponse { release: None, processes: vec![] }, body.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_non_collaborator_to_list_processes() {
+        let (status, _) = list(context_with_application(), "other_user").await;
+
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::UNIX_EPOCH;
+
+use actix_web::{get, HttpResponse, post, web};
+use actix_web::http::header::ContentType;
+use serde::{Deserialize, Serialize};
+
+use capsule_core::application::{Release, Role, rollback};
+
+use crate::context::ServerContext;
+use crate::resources::{ApiError, application_id, CurrentUser, find_application};
+
+/// A release as shown to every collaborator, the config vars are listed by key only.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ReleaseResponse {
+    version: i32,
+    commit_sha: String,
+    build_artifact: String,
+    config_vars: Vec<String>,
+    description: String,
+    created_by: String,
+    created_at: u64,
+}
+
+impl From<&Release> for ReleaseResponse {
+    fn from(release: &Release) -> Self {
+        Self {
+            version: release.version,
+            commit_sha: release.spec.commit_sha.clone(),
+            build_artifact: release.spec.build_artifact.clone(),
+            config_vars: release.spec.config_vars.keys().cloned().collect(),
+            description: release.spec.description.clone(),
+            created_by: release.spec.created_by.clone(),
+            created_at: release.create_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        }
+    }
+}
+
+#[get("/applications/{name}/releases")]
+pub async fn list_releases(name: web::Path<String>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let application = find_application(&context, name.as_str(), &user, Role::Viewer)?;
+
+    let listed: Vec<ReleaseResponse> = context.releases().list(application_id(&application))?.iter().map(ReleaseResponse::from).collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&listed).unwrap()))
+}
+
+#[post("/applications/{name}/releases/{version}/rollback")]
+pub async fn rollback_release(path: web::Path<(String, i32)>, user: CurrentUser, context: web::Data<ServerContext>) -> Result<HttpResponse, ApiError> {
+    let (name, version) = path.into_inner();
+    let application = find_application(&context, name.as_str(), &user, Role::Deployer)?;
+
+    match rollback(context.releases().as_ref(), application_id(&application), version, user.name.as_str())? {
+        Some(release) => Ok(HttpResponse::Created()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string(&ReleaseResponse::from(&release)).unwrap())),
+        None => Err(ApiError::NotFound { message: format!("release v{} of application {} not found", version, name) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+
+    use capsule_core::application::{Collaborator, ConfigVarMap, ReleaseSpec, Role};
+
+    use crate::context::ServerContext;
+    use crate::resources::test_support::{context_with_application, release_spec};
+    use crate::resources::USER_HEADER;
+
+    use super::*;
+
+    fn spec(commit_sha: &str) -> ReleaseSpec {
+        ReleaseSpec {
+            config_vars: ConfigVarMap::from([("SECRET".to_string(), "s3cr3t".to_string())]),
+            ..release_spec(commit_sha, &[("web", "./app")])
+        }
+    }
+
+    fn context_with_releases() -> ServerContext {
+        let context = context_with_application();
+        let releases = context.releases();
+        releases.create(1, &spec("aaa")).unwrap();
+        releases.create(1, &spec("bbb")).unwrap();
+        context
+    }
+
+    #[actix_web::test]
+    async fn should_list_releases_without_config_var_values() {
+        let context = context_with_releases();
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "view_user".to_string(), role: Role::Viewer }).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(list_releases)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/applications/first-capsule-application/releases")
+            .insert_header((USER_HEADER, "view_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        assert!(!String::from_utf8(body.to_vec()).unwrap().contains("s3cr3t"));
+        let listed: Vec<ReleaseResponse> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vec![(2, "bbb".to_string()), (1, "aaa".to_string())], listed.into_iter().map(|r| (r.version, r.commit_sha)).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn should_roll_back_to_older_release() {
+        let context = context_with_releases();
+        let releases = context.releases();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(rollback_release)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications/first-capsule-application/releases/1/rollback")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+        let body: ReleaseResponse = test::read_body_json(resp).await;
+        assert_eq!((3, "aaa", "Rollback to v1"), (body.version, body.commit_sha.as_str(), body.description.as_str()));
+        assert_eq!(spec("aaa").config_vars, releases.find(1, 3).unwrap().unwrap().spec.config_vars);
+    }
+
+    #[actix_web::test]
+    async fn should_return_not_found_if_rollback_release_not_exists() {
+        let context = context_with_releases();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(rollback_release)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications/first-capsule-application/releases/9/rollback")
+            .insert_header((USER_HEADER, "first_capsule_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn should_forbid_viewer_to_roll_back() {
+        let context = context_with_releases();
+        context.collaborators().save(&Collaborator { application_id: 1, user_name: "view_user".to_string(), role: Role::Viewer }).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).service(rollback_release)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/applications/first-capsule-application/releases/1/rollback")
+            .insert_header((USER_HEADER, "view_user"))
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+}
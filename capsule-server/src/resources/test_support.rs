@@ -0,0 +1,807 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use capsule_core::application::{AcmeAccount, Application, ApplicationError, ApplicationName, Applications, Build, Builds, BuildStatus, Certificate, Certificates, Collaborator, Collaborators, ConfigChange, Deploy, Deploys, DeployStatus, DeployStep, ConfigVarChanges, ConfigVarMap, ConfigVars, CnameRecord, CustomDomain, CustomDomains, DnsRecord, DomainNameService, Formation, FormationChange, FormationEvent, Formations, GitError, GitRepository, GitService, HealthCheckConfig, HealthChecker, HealthChecks, HttpChallenges, HttpHealthProbe, LogBuffer, OwnerLimits, OwnerLimitsRepository, Page, ProcessInstance, ProcessSpec, ProcessStatus, PushedRef, RecordType, Redirect, Redirects, Release, Releases, ReleaseSpec, RestartPolicy, Role, RuntimeBackend, RuntimeError, ScaledProcess, Updater};
+use capsule_core::buildpack::ProcessTypes;
+use capsule_core::CoreError;
+use capsule_core::id::SnowflakeIdGenerator;
+use capsule_core::organization::{Member, Organization, OrganizationError, OrganizationRole, Organizations};
+
+use crate::context::ServerContext;
+use crate::settings::Settings;
+
+type SavedApplications = Arc<Mutex<Vec<(i64, String, String)>>>;
+
+pub(crate) struct InMemoryApplications {
+    applications: SavedApplications,
+}
+
+struct InMemoryUpdater {
+    applications: SavedApplications,
+}
+
+impl Updater for InMemoryUpdater {
+    fn update(&self, application: &Application) -> Result<(), ApplicationError> {
+        let (id, name, owner) = application.accept(|id, name, owner, _| (id, name.to_string(), owner.to_string()));
+
+        for saved in self.applications.lock().unwrap().iter_mut().filter(|a| a.0 == id) {
+            saved.1 = name.clone();
+            saved.2 = owner.clone();
+        }
+
+        Ok(())
+    }
+}
+
+impl InMemoryApplications {
+    pub(crate) fn new() -> Self {
+        Self { applications: Arc::new(Mutex::new(vec![])) }
+    }
+
+    fn to_application(&self, saved: &(i64, String, String)) -> Application {
+        Application::new(saved.0, Some(ApplicationName::new(saved.1.as_str()).unwrap()), saved.2.clone())
+            .with_updater(Box::new(InMemoryUpdater { applications: self.applications.clone() }))
+    }
+}
+
+pub(crate) struct InMemoryRedirects {
+    redirects: Mutex<Vec<Redirect>>,
+}
+
+impl InMemoryRedirects {
+    pub(crate) fn new() -> Self {
+        Self { redirects: Mutex::new(vec![]) }
+    }
+}
+
+impl Redirects for InMemoryRedirects {
+    fn add(&self, redirect: &Redirect) -> Result<(), CoreError> {
+        let mut redirects = self.redirects.lock().unwrap();
+        redirects.retain(|r| r.from_name != redirect.from_name);
+        redirects.push(redirect.clone());
+        Ok(())
+    }
+
+    fn find_active(&self, from_name: &str, now: SystemTime) -> Result<Option<Redirect>, CoreError> {
+        Ok(self.redirects.lock().unwrap().iter().find(|r| r.from_name == from_name && r.expire_at > now).cloned())
+    }
+
+    fn find_expired(&self, now: SystemTime) -> Result<Vec<Redirect>, CoreError> {
+        Ok(self.redirects.lock().unwrap().iter().filter(|r| r.expire_at <= now).cloned().collect())
+    }
+
+    fn delete(&self, from_name: &str) -> Result<(), CoreError> {
+        self.redirects.lock().unwrap().retain(|r| r.from_name != from_name);
+        Ok(())
+    }
+}
+
+impl Applications for InMemoryApplications {
+    fn add(&self, application: &Application) -> Result<Application, CoreError> {
+        let saved = application.accept(|id, name, owner, _| (id, name.to_string(), owner.to_string()));
+        let added = self.to_application(&saved);
+
+        self.applications.lock().unwrap().push(saved);
+
+        Ok(added)
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Application>, CoreError> {
+        Ok(self.applications.lock().unwrap().iter().find(|a| a.1 == name).map(|a| self.to_application(a)))
+    }
+
+    fn find_by_id(&self, id: i64) -> Result<Option<Application>, CoreError> {
+        Ok(self.applications.lock().unwrap().iter().find(|a| a.0 == id).map(|a| self.to_application(a)))
+    }
+
+    fn list_by_owner(&self, owner: &str, page: &Page) -> Result<Vec<Application>, CoreError> {
+        Ok(self.applications.lock().unwrap().iter()
+            .filter(|a| a.2 == owner)
+            .skip(page.offset() as usize)
+            .take(page.limit() as usize)
+            .map(|a| self.to_application(a))
+            .collect())
+    }
+
+    fn list(&self, page: &Page) -> Result<Vec<Application>, CoreError> {
+        Ok(self.applications.lock().unwrap().iter()
+            .skip(page.offset() as usize)
+            .take(page.limit() as usize)
+            .map(|a| self.to_application(a))
+            .collect())
+    }
+
+    fn delete(&self, id: i64) -> Result<(), CoreError> {
+        self.applications.lock().unwrap().retain(|a| a.0 != id);
+        Ok(())
+    }
+
+    fn exists(&self, name: &str) -> Result<bool, CoreError> {
+        Ok(self.applications.lock().unwrap().iter().any(|a| a.1 == name))
+    }
+}
+
+/// For resources that never touch git repositories.
+pub(crate) struct GitServiceStub;
+
+impl GitService for GitServiceStub {
+    fn create_repo(&self, _owner: &str, _app_name: &str) -> Result<GitRepository, GitError> {
+        panic!("should not touch git repositories")
+    }
+
+    fn rename_repo(&self, _owner: &str, _app_name: &str, _new_app_name: &str) -> Result<GitRepository, GitError> {
+        panic!("should not touch git repositories")
+    }
+
+    fn transfer_repo(&self, _owner: &str, _app_name: &str, _new_owner: &str) -> Result<GitRepository, GitError> {
+        panic!("should not touch git repositories")
+    }
+
+    fn delete_repo(&self, _owner: &str, _app_name: &str) -> Result<(), GitError> {
+        panic!("should not touch git repositories")
+    }
+}
+
+/// For creating applications, the repository is created unless `fail` is set. Rolling a
+/// creation back deletes it again.
+pub(crate) struct CreateRepoStub {
+    pub(crate) fail: bool,
+}
+
+impl GitService for CreateRepoStub {
+    fn create_repo(&self, owner: &str, app_name: &str) -> Result<GitRepository, GitError> {
+        if self.fail {
+            return Err(GitError {});
+        }
+
+        Ok(GitRepository { uri: format!("https://git.capsuleapp.cyou/{}/{}.git", owner, app_name) })
+    }
+
+    fn rename_repo(&self, _owner: &str, _app_name: &str, _new_app_name: &str) -> Result<GitRepository, GitError> {
+        panic!("should not rename git repositories")
+    }
+
+    fn transfer_repo(&self, _owner: &str, _app_name: &str, _new_owner: &str) -> Result<GitRepository, GitError> {
+        panic!("should not transfer git repositories")
+    }
+
+    fn delete_repo(&self, _owner: &str, _app_name: &str) -> Result<(), GitError> {
+        Ok(())
+    }
+}
+
+/// For resources that never touch dns records.
+pub(crate) struct DomainNameServiceStub;
+
+impl DomainNameService for DomainNameServiceStub {
+    fn add_cname_record(&self, _cname: &str) -> Result<CnameRecord, ApplicationError> {
+        panic!("should not touch dns records")
+    }
+
+    fn remove_cname_record(&self, _cname: &str) -> Result<(), ApplicationError> {
+        panic!("should not touch dns records")
+    }
+
+    fn list_records(&self) -> Result<Vec<DnsRecord>, ApplicationError> {
+        panic!("should not touch dns records")
+    }
+
+    fn get_records(&self, _name: &str, _record_type: RecordType) -> Result<Vec<DnsRecord>, ApplicationError> {
+        panic!("should not touch dns records")
+    }
+
+    fn add_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+        panic!("should not touch dns records")
+    }
+
+    fn update_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+        panic!("should not touch dns records")
+    }
+
+    fn delete_record(&self, _record: &DnsRecord) -> Result<(), ApplicationError> {
+        panic!("should not touch dns records")
+    }
+}
+
+pub(crate) fn context(git_service: impl GitService + 'static, domain_service: impl DomainNameService + 'static) -> ServerContext {
+    std::env::set_var("CAPSULE_CONFIG_SERVER_DIR", "./_fixture");
+    std::env::set_var("CAPSULE_SERVER_CONFIG_FILE", "capsule-server.toml");
+    let applications = InMemoryApplications::new();
+    let formations = InMemoryFormations::new(applications.applications.clone());
+
+    ServerContext {
+        settings: Arc::new(Settings::new()),
+        git_service: Arc::new(git_service),
+        domain_name_service: Arc::new(domain_service),
+        id_generator: Arc::new(SnowflakeIdGenerator::new(1).unwrap()),
+        applications: Arc::new(applications),
+        redirects: Arc::new(InMemoryRedirects::new()),
+        collaborators: Arc::new(InMemoryCollaborators::new()),
+        organizations: Arc::new(InMemoryOrganizations::new()),
+        config_vars: Arc::new(InMemoryConfigVars::new()),
+        releases: Arc::new(InMemoryReleases::new()),
+        builds: Arc::new(InMemoryBuilds::new()),
+        formations: Arc::new(formations),
+        owner_limits: Arc::new(InMemoryOwnerLimits::new()),
+        logs: Arc::new(LogBuffer::new(100)),
+        health_checks: Arc::new(InMemoryHealthChecks::new()),
+        runtime: Arc::new(RunningRuntime),
+        health_checker: Arc::new(HealthChecker::new(Arc::new(RunningRuntime), Arc::new(HttpHealthProbe), RestartPolicy { max_restarts: 3 })),
+        deploys: Arc::new(InMemoryDeploys::new()),
+        custom_domains: Arc::new(InMemoryCustomDomains::new()),
+        certificates: Arc::new(InMemoryCertificates::new()),
+        acme_challenges: Arc::new(HttpChallenges::new()),
+    }
+}
+
+pub(crate) struct InMemoryCollaborators {
+    collaborators: Mutex<Vec<Collaborator>>,
+}
+
+impl InMemoryCollaborators {
+    pub(crate) fn new() -> Self {
+        Self { collaborators: Mutex::new(vec![]) }
+    }
+}
+
+impl Collaborators for InMemoryCollaborators {
+    fn save(&self, collaborator: &Collaborator) -> Result<(), CoreError> {
+        let mut collaborators = self.collaborators.lock().unwrap();
+        collaborators.retain(|c| !(c.application_id == collaborator.application_id && c.user_name == collaborator.user_name));
+        collaborators.push(collaborator.clone());
+        Ok(())
+    }
+
+    fn find(&self, application_id: i64, user_name: &str) -> Result<Option<Collaborator>, CoreError> {
+        Ok(self.collaborators.lock().unwrap().iter().find(|c| c.application_id == application_id && c.user_name == user_name).cloned())
+    }
+
+    fn list(&self, application_id: i64) -> Result<Vec<Collaborator>, CoreError> {
+        Ok(self.collaborators.lock().unwrap().iter().filter(|c| c.application_id == application_id).cloned().collect())
+    }
+
+    fn remove(&self, application_id: i64, user_name: &str) -> Result<(), CoreError> {
+        self.collaborators.lock().unwrap().retain(|c| !(c.application_id == application_id && c.user_name == user_name));
+        Ok(())
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.collaborators.lock().unwrap().retain(|c| c.application_id != application_id);
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryOrganizations {
+    organizations: Mutex<Vec<Organization>>,
+    members: Mutex<Vec<Member>>,
+}
+
+impl InMemoryOrganizations {
+    pub(crate) fn new() -> Self {
+        Self { organizations: Mutex::new(vec![]), members: Mutex::new(vec![]) }
+    }
+}
+
+impl Organizations for InMemoryOrganizations {
+    fn add(&self, organization: &Organization, owner: &str) -> Result<(), OrganizationError> {
+        let mut organizations = self.organizations.lock().unwrap();
+        if organizations.iter().any(|o| o.name == organization.name) {
+            return Err(OrganizationError::OrganizationAlreadyExists { name: organization.name.clone() });
+        }
+
+        organizations.push(organization.clone());
+        self.members.lock().unwrap().push(Member { organization_name: organization.name.clone(), user_name: owner.to_string(), role: OrganizationRole::Owner });
+        Ok(())
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Organization>, CoreError> {
+        Ok(self.organizations.lock().unwrap().iter().find(|o| o.name == name).cloned())
+    }
+
+    fn save_member(&self, member: &Member) -> Result<(), CoreError> {
+        let mut members = self.members.lock().unwrap();
+        members.retain(|m| !(m.organization_name == member.organization_name && m.user_name == member.user_name));
+        members.push(member.clone());
+        Ok(())
+    }
+
+    fn find_member(&self, organization_name: &str, user_name: &str) -> Result<Option<Member>, CoreError> {
+        Ok(self.members.lock().unwrap().iter().find(|m| m.organization_name == organization_name && m.user_name == user_name).cloned())
+    }
+
+    fn list_members(&self, organization_name: &str) -> Result<Vec<Member>, CoreError> {
+        Ok(self.members.lock().unwrap().iter().filter(|m| m.organization_name == organization_name).cloned().collect())
+    }
+
+    fn remove_member(&self, organization_name: &str, user_name: &str) -> Result<(), CoreError> {
+        self.members.lock().unwrap().retain(|m| !(m.organization_name == organization_name && m.user_name == user_name));
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryConfigVars {
+    vars: Mutex<Vec<(i64, String, String)>>,
+    changes: Mutex<Vec<ConfigChange>>,
+}
+
+impl InMemoryConfigVars {
+    pub(crate) fn new() -> Self {
+        Self { vars: Mutex::new(vec![]), changes: Mutex::new(vec![]) }
+    }
+}
+
+impl ConfigVars for InMemoryConfigVars {
+    fn find_all(&self, application_id: i64) -> Result<ConfigVarMap, ApplicationError> {
+        Ok(self.vars.lock().unwrap().iter().filter(|v| v.0 == application_id).map(|v| (v.1.clone(), v.2.clone())).collect())
+    }
+
+    fn apply(&self, application_id: i64, changes: &ConfigVarChanges, changed_by: &str) -> Result<Vec<String>, ApplicationError> {
+        let current = self.find_all(application_id)?;
+        let keys: Vec<String> = changes.iter().filter(|(k, v)| current.get(*k) != v.as_ref()).map(|(k, _)| k.clone()).collect();
+
+        let mut vars = self.vars.lock().unwrap();
+        for key in &keys {
+            vars.retain(|v| !(v.0 == application_id && &v.1 == key));
+            if let Some(Some(value)) = changes.get(key) {
+                vars.push((application_id, key.clone(), value.clone()));
+            }
+        }
+        if !keys.is_empty() {
+            self.changes.lock().unwrap().push(ConfigChange { application_id, user_name: changed_by.to_string(), keys: keys.clone(), change_at: SystemTime::now() });
+        }
+
+        Ok(keys)
+    }
+
+    fn history(&self, application_id: i64) -> Result<Vec<ConfigChange>, CoreError> {
+        Ok(self.changes.lock().unwrap().iter().filter(|c| c.application_id == application_id).cloned().collect())
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.vars.lock().unwrap().retain(|v| v.0 != application_id);
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryReleases {
+    releases: Mutex<Vec<Release>>,
+}
+
+impl InMemoryReleases {
+    pub(crate) fn new() -> Self {
+        Self { releases: Mutex::new(vec![]) }
+    }
+}
+
+impl Releases for InMemoryReleases {
+    fn create(&self, application_id: i64, spec: &ReleaseSpec) -> Result<Release, ApplicationError> {
+        let mut releases = self.releases.lock().unwrap();
+        let version = releases.iter().filter(|r| r.application_id == application_id).map(|r| r.version).max().unwrap_or(0) + 1;
+        let release = Release { application_id, version, spec: spec.clone(), create_at: SystemTime::now() };
+        releases.push(release.clone());
+        Ok(release)
+    }
+
+    fn find(&self, application_id: i64, version: i32) -> Result<Option<Release>, ApplicationError> {
+        Ok(self.releases.lock().unwrap().iter().find(|r| r.application_id == application_id && r.version == version).cloned())
+    }
+
+    fn latest(&self, application_id: i64) -> Result<Option<Release>, ApplicationError> {
+        Ok(self.releases.lock().unwrap().iter().filter(|r| r.application_id == application_id).max_by_key(|r| r.version).cloned())
+    }
+
+    fn list(&self, application_id: i64) -> Result<Vec<Release>, ApplicationError> {
+        let mut releases: Vec<Release> = self.releases.lock().unwrap().iter().filter(|r| r.application_id == application_id).cloned().collect();
+        releases.sort_by_key(|r| std::cmp::Reverse(r.version));
+        Ok(releases)
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.releases.lock().unwrap().retain(|r| r.application_id != application_id);
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryBuilds {
+    builds: Mutex<Vec<Build>>,
+}
+
+impl InMemoryBuilds {
+    pub(crate) fn new() -> Self {
+        Self { builds: Mutex::new(vec![]) }
+    }
+}
+
+impl Builds for InMemoryBuilds {
+    fn add(&self, application_id: i64, pushed_ref: &PushedRef, pushed_by: &str) -> Result<Build, CoreError> {
+        let mut builds = self.builds.lock().unwrap();
+        let build = Build {
+            id: builds.len() as i32 + 1,
+            application_id,
+            ref_name: pushed_ref.ref_name.clone(),
+            commit_sha: pushed_ref.new_sha.clone(),
+            pushed_by: pushed_by.to_string(),
+            status: BuildStatus::Pending,
+            create_at: SystemTime::now(),
+        };
+        builds.push(build.clone());
+        Ok(build)
+    }
+
+    fn list(&self, application_id: i64) -> Result<Vec<Build>, CoreError> {
+        Ok(self.builds.lock().unwrap().iter().rev().filter(|b| b.application_id == application_id).cloned().collect())
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.builds.lock().unwrap().retain(|b| b.application_id != application_id);
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryFormations {
+    applications: SavedApplications,
+    processes: Mutex<Vec<(i64, ScaledProcess)>>,
+    events: Mutex<Vec<FormationEvent>>,
+    scaling: Mutex<()>,
+}
+
+impl InMemoryFormations {
+    pub(crate) fn new(applications: SavedApplications) -> Self {
+        Self { applications, processes: Mutex::new(vec![]), events: Mutex::new(vec![]), scaling: Mutex::new(()) }
+    }
+}
+
+impl Formations for InMemoryFormations {
+    fn find(&self, application_id: i64) -> Result<Formation, CoreError> {
+        let processes = self.processes.lock().unwrap().iter()
+            .filter(|p| p.0 == application_id)
+            .map(|p| (p.1.process_type.clone(), p.1.scale))
+            .collect();
+        Ok(Formation::new(application_id, processes))
+    }
+
+    fn count_instances(&self, owner: &str, except_application_id: i64) -> Result<i32, CoreError> {
+        let owned: Vec<i64> = self.applications.lock().unwrap().iter().filter(|a| a.2 == owner && a.0 != except_application_id).map(|a| a.0).collect();
+        Ok(self.processes.lock().unwrap().iter().filter(|p| owned.contains(&p.0)).map(|p| p.1.scale.quantity).sum())
+    }
+
+    fn scale(&self, application_id: i64, owner: &str, changes: &BTreeMap<String, FormationChange>, process_types: &ProcessTypes,
+             limits: &OwnerLimits, changed_by: &str) -> Result<Formation, ApplicationError> {
+        let _scaling = self.scaling.lock().unwrap();
+        let other_instances = self.count_instances(owner, application_id)?;
+        let mut formation = self.find(application_id)?;
+        let scaled = formation.scale(changes, process_types, limits, other_instances)?;
+        self.save(&formation, &scaled, changed_by)?;
+        Ok(formation)
+    }
+
+    fn save(&self, formation: &Formation, scaled: &[ScaledProcess], changed_by: &str) -> Result<Vec<FormationEvent>, CoreError> {
+        let application_id = formation.application_id();
+        let mut processes = self.processes.lock().unwrap();
+        let mut events = self.events.lock().unwrap();
+        let mut saved_events = vec![];
+        for process in scaled {
+            processes.retain(|p| !(p.0 == application_id && p.1.process_type == process.process_type));
+            processes.push((application_id, process.clone()));
+
+            let event = FormationEvent {
+                id: events.len() as i32 + 1,
+                application_id,
+                process_type: process.process_type.clone(),
+                scale: process.scale,
+                changed_by: changed_by.to_string(),
+                create_at: SystemTime::now(),
+            };
+            events.push(event.clone());
+            saved_events.push(event);
+        }
+        Ok(saved_events)
+    }
+
+    fn events_after(&self, after_id: i32, limit: i64) -> Result<Vec<FormationEvent>, CoreError> {
+        Ok(self.events.lock().unwrap().iter().filter(|e| e.id > after_id).take(limit as usize).cloned().collect())
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.processes.lock().unwrap().retain(|p| p.0 != application_id);
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryOwnerLimits {
+    limits: Mutex<Vec<(String, OwnerLimits)>>,
+}
+
+impl InMemoryOwnerLimits {
+    pub(crate) fn new() -> Self {
+        Self { limits: Mutex::new(vec![]) }
+    }
+}
+
+impl OwnerLimitsRepository for InMemoryOwnerLimits {
+    fn find(&self, owner: &str) -> Result<Option<OwnerLimits>, CoreError> {
+        Ok(self.limits.lock().unwrap().iter().find(|l| l.0 == owner).map(|l| l.1))
+    }
+
+    fn save(&self, owner: &str, limits: &OwnerLimits) -> Result<(), CoreError> {
+        let mut saved = self.limits.lock().unwrap();
+        saved.retain(|l| l.0 != owner);
+        saved.push((owner.to_string(), *limits));
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryHealthChecks {
+    checks: Mutex<Vec<(i64, HealthCheckConfig)>>,
+}
+
+impl InMemoryHealthChecks {
+    pub(crate) fn new() -> Self {
+        Self { checks: Mutex::new(vec![]) }
+    }
+}
+
+impl HealthChecks for InMemoryHealthChecks {
+    fn find(&self, application_id: i64) -> Result<Option<HealthCheckConfig>, CoreError> {
+        Ok(self.checks.lock().unwrap().iter().find(|c| c.0 == application_id).map(|c| c.1.clone()))
+    }
+
+    fn save(&self, application_id: i64, config: &HealthCheckConfig) -> Result<(), CoreError> {
+        let mut checks = self.checks.lock().unwrap();
+        checks.retain(|c| c.0 != application_id);
+        checks.push((application_id, config.clone()));
+        Ok(())
+    }
+
+    fn remove(&self, application_id: i64) -> Result<(), CoreError> {
+        self.checks.lock().unwrap().retain(|c| c.0 != application_id);
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryDeploys {
+    deploys: Mutex<Vec<Deploy>>,
+}
+
+impl InMemoryDeploys {
+    pub(crate) fn new() -> Self {
+        Self { deploys: Mutex::new(vec![]) }
+    }
+}
+
+impl Deploys for InMemoryDeploys {
+    fn create(&self, application_id: i64, release_version: i32, created_by: &str) -> Result<Deploy, CoreError> {
+        let mut deploys = self.deploys.lock().unwrap();
+        let deploy = Deploy {
+            id: deploys.len() as i32 + 1,
+            application_id,
+            release_version,
+            status: DeployStatus::Pending,
+            steps: vec![],
+            created_by: created_by.to_string(),
+            create_at: SystemTime::now(),
+            finish_at: None,
+        };
+        deploys.push(deploy.clone());
+        Ok(deploy)
+    }
+
+    fn find(&self, deploy_id: i32) -> Result<Option<Deploy>, CoreError> {
+        Ok(self.deploys.lock().unwrap().iter().find(|d| d.id == deploy_id).cloned())
+    }
+
+    fn list(&self, application_id: i64, limit: i64) -> Result<Vec<Deploy>, CoreError> {
+        Ok(self.deploys.lock().unwrap().iter().rev().filter(|d| d.application_id == application_id).take(limit as usize).cloned().collect())
+    }
+
+    fn pending(&self) -> Result<Vec<Deploy>, CoreError> {
+        Ok(self.deploys.lock().unwrap().iter().filter(|d| d.status == DeployStatus::Pending).cloned().collect())
+    }
+
+    fn update_status(&self, deploy_id: i32, status: DeployStatus) -> Result<(), CoreError> {
+        for deploy in self.deploys.lock().unwrap().iter_mut().filter(|d| d.id == deploy_id) {
+            deploy.status = status;
+            if matches!(status, DeployStatus::Succeeded | DeployStatus::Failed) {
+                deploy.finish_at = Some(SystemTime::now());
+            }
+        }
+        Ok(())
+    }
+
+    fn add_step(&self, deploy_id: i32, step: &DeployStep) -> Result<(), CoreError> {
+        for deploy in self.deploys.lock().unwrap().iter_mut().filter(|d| d.id == deploy_id) {
+            deploy.steps.push(step.clone());
+        }
+        Ok(())
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.deploys.lock().unwrap().retain(|d| d.application_id != application_id);
+        Ok(())
+    }
+}
+
+/// A runtime whose instances are always running, restarts do nothing.
+pub(crate) struct RunningRuntime;
+
+impl RuntimeBackend for RunningRuntime {
+    fn start(&self, _spec: &ProcessSpec) -> Result<ProcessStatus, RuntimeError> {
+        Ok(ProcessStatus::Running { pid: 1 })
+    }
+
+    fn stop(&self, _instance: &ProcessInstance) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    fn status(&self, _instance: &ProcessInstance) -> Result<ProcessStatus, RuntimeError> {
+        Ok(ProcessStatus::Running { pid: 1 })
+    }
+
+    fn logs(&self, _instance: &ProcessInstance, _lines: usize) -> Result<Vec<String>, RuntimeError> {
+        Ok(vec![])
+    }
+
+    fn instances(&self, _application_id: i64) -> Result<Vec<ProcessInstance>, RuntimeError> {
+        Ok(vec![])
+    }
+}
+
+pub(crate) struct InMemoryCustomDomains {
+    domains: Mutex<Vec<CustomDomain>>,
+}
+
+impl InMemoryCustomDomains {
+    pub(crate) fn new() -> Self {
+        Self { domains: Mutex::new(vec![]) }
+    }
+}
+
+impl CustomDomains for InMemoryCustomDomains {
+    fn add(&self, domain: &CustomDomain) -> Result<(), ApplicationError> {
+        let mut domains = self.domains.lock().unwrap();
+        if domains.iter().any(|d| d.hostname == domain.hostname && (d.is_verified() || d.application_id == domain.application_id)) {
+            return Err(ApplicationError::CustomDomainAlreadyExists { hostname: domain.hostname.clone() });
+        }
+        domains.push(domain.clone());
+        Ok(())
+    }
+
+    fn find_verified(&self, hostname: &str) -> Result<Option<CustomDomain>, CoreError> {
+        Ok(self.domains.lock().unwrap().iter().find(|d| d.hostname == hostname && d.is_verified()).cloned())
+    }
+
+    fn list(&self, application_id: i64) -> Result<Vec<CustomDomain>, CoreError> {
+        Ok(self.domains.lock().unwrap().iter().filter(|d| d.application_id == application_id).cloned().collect())
+    }
+
+    fn unverified(&self) -> Result<Vec<CustomDomain>, CoreError> {
+        Ok(self.domains.lock().unwrap().iter().filter(|d| !d.is_verified()).cloned().collect())
+    }
+
+    fn verified(&self) -> Result<Vec<CustomDomain>, CoreError> {
+        Ok(self.domains.lock().unwrap().iter().filter(|d| d.is_verified()).cloned().collect())
+    }
+
+    fn mark_verified(&self, application_id: i64, hostname: &str, at: SystemTime) -> Result<bool, CoreError> {
+        let mut domains = self.domains.lock().unwrap();
+        let taken = domains.iter().any(|d| d.hostname == hostname && d.is_verified() && d.application_id != application_id);
+        domains.retain(|d| d.hostname != hostname || d.is_verified() || (d.application_id == application_id && !taken));
+        for domain in domains.iter_mut().filter(|d| d.application_id == application_id && d.hostname == hostname) {
+            domain.verified_at = Some(at);
+            domain.last_checked_at = Some(at);
+            domain.last_error = None;
+        }
+        Ok(!taken)
+    }
+
+    fn record_failed_check(&self, application_id: i64, hostname: &str, at: SystemTime, error: &str) -> Result<(), CoreError> {
+        for domain in self.domains.lock().unwrap().iter_mut().filter(|d| d.application_id == application_id && d.hostname == hostname) {
+            domain.last_checked_at = Some(at);
+            domain.last_error = Some(error.to_string());
+        }
+        Ok(())
+    }
+
+    fn remove(&self, application_id: i64, hostname: &str) -> Result<bool, CoreError> {
+        let mut domains = self.domains.lock().unwrap();
+        let before = domains.len();
+        domains.retain(|d| !(d.application_id == application_id && d.hostname == hostname));
+        Ok(domains.len() < before)
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.domains.lock().unwrap().retain(|d| d.application_id != application_id);
+        Ok(())
+    }
+}
+
+pub(crate) struct InMemoryCertificates {
+    certificates: Mutex<Vec<Certificate>>,
+    accounts: Mutex<Vec<AcmeAccount>>,
+}
+
+impl InMemoryCertificates {
+    pub(crate) fn new() -> Self {
+        Self { certificates: Mutex::new(vec![]), accounts: Mutex::new(vec![]) }
+    }
+}
+
+impl Certificates for InMemoryCertificates {
+    fn save(&self, certificate: &Certificate) -> Result<(), CoreError> {
+        let mut certificates = self.certificates.lock().unwrap();
+        certificates.retain(|c| c.hostname != certificate.hostname);
+        certificates.push(certificate.clone());
+        Ok(())
+    }
+
+    fn find(&self, hostname: &str) -> Result<Option<Certificate>, ApplicationError> {
+        Ok(self.certificates.lock().unwrap().iter().find(|c| c.hostname == hostname).cloned())
+    }
+
+    fn list(&self, application_id: i64) -> Result<Vec<Certificate>, ApplicationError> {
+        Ok(self.certificates.lock().unwrap().iter().filter(|c| c.application_id == application_id).cloned().collect())
+    }
+
+    fn hostnames(&self) -> Result<Vec<String>, CoreError> {
+        Ok(self.certificates.lock().unwrap().iter().map(|c| c.hostname.clone()).collect())
+    }
+
+    fn remove(&self, hostname: &str) -> Result<(), CoreError> {
+        self.certificates.lock().unwrap().retain(|c| c.hostname != hostname);
+        Ok(())
+    }
+
+    fn remove_all(&self, application_id: i64) -> Result<(), CoreError> {
+        self.certificates.lock().unwrap().retain(|c| c.application_id != application_id);
+        Ok(())
+    }
+
+    fn find_account(&self, directory_uri: &str) -> Result<Option<AcmeAccount>, ApplicationError> {
+        Ok(self.accounts.lock().unwrap().iter().find(|a| a.directory_uri == directory_uri).cloned())
+    }
+
+    fn save_account(&self, account: &AcmeAccount) -> Result<(), CoreError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts.retain(|a| a.directory_uri != account.directory_uri);
+        accounts.push(account.clone());
+        Ok(())
+    }
+}
+
+/// Adds an application owned by `owner`, the way provisioning would leave it.
+pub(crate) fn add_application(context: &ServerContext, id: i64, name: &str, owner: &str) {
+    context.applications().add(&Application::new(id, Some(ApplicationName::new(name).unwrap()), owner.to_string())).unwrap();
+    context.collaborators().save(&Collaborator { application_id: id, user_name: owner.to_string(), role: Role::Owner }).unwrap();
+}
+
+/// A context with application 1, `first-capsule-application` of `first_capsule_user`.
+pub(crate) fn context_with_application() -> ServerContext {
+    let context = context(GitServiceStub, DomainNameServiceStub);
+    add_application(&context, 1, "first-capsule-application", "first_capsule_user");
+    context
+}
+
+pub(crate) fn release_spec(commit_sha: &str, processes: &[(&str, &str)]) -> ReleaseSpec {
+    ReleaseSpec {
+        build_artifact: format!("registry.capsuleapp.cyou/first-capsule-application:{}", commit_sha),
+        commit_sha: commit_sha.to_string(),
+        config_vars: ConfigVarMap::new(),
+        processes: processes.iter().map(|(t, c)| (t.to_string(), c.to_string())).collect(),
+        description: format!("Deploy {}", commit_sha),
+        created_by: "first_capsule_user".to_string(),
+    }
+}
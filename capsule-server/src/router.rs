@@ -0,0 +1,253 @@
+// Copyright 2022 the original author or authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::{Error, FromRequest, HttpResponse, web};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::http::StatusCode;
+use futures_util::future::{LocalBoxFuture, ready};
+use isahc::{AsyncReadResponseExt, Request, RequestExt};
+
+use capsule_core::application::{HostRouter, Route};
+
+use crate::context::ServerContext;
+use crate::resources::ApiError;
+
+const ACME_CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+/// Headers of a single connection, these are not passed on between clients and instances.
+const HOP_BY_HOP_HEADERS: [&str; 9] = ["connection", "keep-alive", "proxy-authenticate", "proxy-authorization", "te", "trailer", "transfer-encoding", "upgrade", "content-length"];
+
+/// Spreads the requests over the healthy instances of an application.
+static NEXT_INSTANCE: AtomicUsize = AtomicUsize::new(0);
+
+/// Serves applications on `{name}.{platform domain}` and on their verified custom domains by
+/// passing requests on to a healthy web instance. Previous names of renamed applications are
+/// redirected permanently, keeping the path and query. Requests for any other host, and ACME
+/// HTTP-01 challenges on every host, go on to the API.
+pub fn route_host<S>(request: ServiceRequest, api: &S) -> LocalBoxFuture<'static, Result<ServiceResponse, Error>>
+    where S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>, S::Future: 'static {
+    if request.path().starts_with(ACME_CHALLENGE_PATH) {
+        return Box::pin(api.call(request));
+    }
+    let context = match request.app_data::<web::Data<ServerContext>>() {
+        Some(context) => context.clone(),
+        None => return Box::pin(api.call(request)),
+    };
+
+    let host = request.connection_info().host().to_string();
+    let path_and_query = request.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/").to_string();
+    let (applications, redirects, custom_domains) = (context.applications(), context.redirects(), context.custom_domains());
+    let route = HostRouter::new(applications.as_ref(), redirects.as_ref(), custom_domains.as_ref(), &context.settings().platform_domain())
+        .route(&host, &path_and_query);
+
+    match route {
+        Ok(Route::NotFound) => Box::pin(api.call(request)),
+        Ok(Route::Redirect { location }) => {
+            let response = HttpResponse::PermanentRedirect().insert_header((LOCATION, location)).finish();
+            Box::pin(ready(Ok(request.into_response(response))))
+        }
+        Ok(Route::Application { application_id, name }) => {
+            let addresses = context.health_checker().healthy_addresses(application_id);
+            if addresses.is_empty() {
+                let response = HttpResponse::ServiceUnavailable()
+                    .content_type(ContentType::plaintext())
+                    .body(format!("application {} has no healthy web instance", name));
+                return Box::pin(ready(Ok(request.into_response(response))));
+            }
+
+            let address = addresses[NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed) % addresses.len()].clone();
+            Box::pin(proxy(request, address, path_and_query))
+        }
+        Err(e) => Box::pin(ready(Ok(request.error_response(ApiError::from(e))))),
+    }
+}
+
+/// Passes the request on to the instance listening at `address`, the instance sees the original
+/// `Host` header. An instance not answering is a bad gateway.
+async fn proxy(request: ServiceRequest, address: String, path_and_query: String) -> Result<ServiceResponse, Error> {
+    let (request, mut payload) = request.into_parts();
+    let body = web::Bytes::from_request(&request, &mut payload).await?;
+
+    let mut upstream = Request::builder()
+        .method(request.method().as_str())
+        .uri(format!("http://{}{}", address, path_and_query));
+    for (name, value) in request.headers().iter().filter(|(name, _)| !HOP_BY_HOP_HEADERS.contains(&name.as_str())) {
+        upstream = upstream.header(name.as_str(), value.as_bytes());
+    }
+    if let Some(peer) = request.peer_addr() {
+        upstream = upstream.header("x-forwarded-for", peer.ip().to_string());
+    }
+    upstream = upstream.header("x-forwarded-proto", request.connection_info().scheme());
+
+    let sent = match upstream.body(body.to_vec()) {
+        Ok(upstream) => upstream.send_async().await,
+        Err(e) => return Ok(ServiceResponse::new(request, bad_gateway(&e.to_string()))),
+    };
+    let mut response = match sent {
+        Ok(response) => response,
+        Err(e) => return Ok(ServiceResponse::new(request, bad_gateway(&e.to_string()))),
+    };
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return Ok(ServiceResponse::new(request, bad_gateway(&e.to_string()))),
+    };
+
+    let mut proxied = HttpResponse::build(StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY));
+    for (name, value) in response.headers().iter().filter(|(name, _)| !HOP_BY_HOP_HEADERS.contains(&name.as_str())) {
+        proxied.append_header((name.as_str(), value.as_bytes()));
+    }
+
+    Ok(ServiceResponse::new(request, proxied.body(body)))
+}
+
+fn bad_gateway(message: &str) -> HttpResponse {
+    HttpResponse::BadGateway()
+        .content_type(ContentType::plaintext())
+        .body(format!("application instance error: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant, SystemTime};
+
+    use actix_web::{App, http, test, web};
+    use actix_web::dev::Service;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{header, method, path, query_param};
+
+    use capsule_core::application::{ChallengeResponder, ConfigVarMap, CustomDomain, HealthCheckConfig, ProcessInstance, ProcessSize, ProcessSpec, Redirect};
+
+    use crate::context::ServerContext;
+    use crate::resources::{certificate, process, USER_HEADER};
+    use crate::resources::test_support::{context_with_application};
+    use crate::router::route_host;
+
+    /// Watches a web instance at `address` and probes it once, which makes it healthy.
+    fn healthy_instance(context: &ServerContext, address: String) {
+        let spec = ProcessSpec {
+            instance: ProcessInstance { application_id: 1, release_version: 1, process_type: "web".to_string(), index: 0 },
+            command: "./app".to_string(),
+            working_dir: PathBuf::from("/tmp"),
+            env: ConfigVarMap::new(),
+            size: ProcessSize::Small,
+        };
+        let config = HealthCheckConfig { path: "/health".to_string(), healthy_threshold: 1, ..HealthCheckConfig::default() };
+        let now = Instant::now();
+        context.health_checker().watch(spec, address, config, now);
+        context.health_checker().check_due(now + Duration::from_secs(3600));
+    }
+
+    #[actix_web::test]
+    async fn should_pass_application_host_on_to_healthy_instance() {
+        let instance = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/health")).respond_with(ResponseTemplate::new(200)).mount(&instance).await;
+        Mock::given(method("GET")).and(path("/orders")).and(query_param("page", "2")).and(header("host", "first-capsule-application.capsuleapp.cyou"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("orders of page 2"))
+            .mount(&instance).await;
+        let context = context_with_application();
+        healthy_instance(&context, instance.address().to_string());
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).wrap_fn(route_host).service(process::list_processes)).await;
+
+        let req = test::TestRequest::get().uri("/orders?page=2").insert_header((http::header::HOST, "first-capsule-application.capsuleapp.cyou")).to_request();
+        let resp = app.call(req).await.unwrap();
+
+        assert_eq!(http::StatusCode::OK, resp.status());
+        assert_eq!("orders of page 2", test::read_body(resp).await);
+    }
+
+    #[actix_web::test]
+    async fn should_serve_only_verified_custom_domains() {
+        let instance = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/health")).respond_with(ResponseTemplate::new(200)).mount(&instance).await;
+        Mock::given(method("GET")).and(path("/")).and(header("host", "www.theirshop.com"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("their shop"))
+            .mount(&instance).await;
+        let context = context_with_application();
+        healthy_instance(&context, instance.address().to_string());
+        let mut verified = CustomDomain::new(1, "www.theirshop.com", "capsuleapp.cyou", SystemTime::now()).unwrap();
+        verified.verified_at = Some(SystemTime::now());
+        context.custom_domains().add(&verified).unwrap();
+        context.custom_domains().add(&CustomDomain::new(1, "shop.example.com", "capsuleapp.cyou", SystemTime::now()).unwrap()).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).wrap_fn(route_host).service(process::list_processes)).await;
+
+        let req = test::TestRequest::get().uri("/").insert_header((http::header::HOST, "www.theirshop.com")).to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(http::StatusCode::OK, resp.status());
+        assert_eq!("their shop", test::read_body(resp).await);
+
+        // unverified domains are not passed on to the application, the api has nothing at `/`.
+        let req = test::TestRequest::get().uri("/").insert_header((http::header::HOST, "shop.example.com")).to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(http::StatusCode::NOT_FOUND, resp.status());
+    }
+
+    #[actix_web::test]
+    async fn should_answer_acme_challenges_on_every_host() {
+        let context = context_with_application();
+        let mut verified = CustomDomain::new(1, "www.theirshop.com", "capsuleapp.cyou", SystemTime::now()).unwrap();
+        verified.verified_at = Some(SystemTime::now());
+        context.custom_domains().add(&verified).unwrap();
+        context.acme_challenges().present("www.theirshop.com", "token-1", "token-1.thumbprint").unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).wrap_fn(route_host).service(certificate::find_acme_challenge)).await;
+
+        for host in ["www.theirshop.com", "first-capsule-application.capsuleapp.cyou", "api.capsuleapp.cyou"] {
+            let req = test::TestRequest::get().uri("/.well-known/acme-challenge/token-1").insert_header((http::header::HOST, host)).to_request();
+            let resp = app.call(req).await.unwrap();
+
+            assert_eq!(http::StatusCode::OK, resp.status(), "{}", host);
+            assert_eq!("token-1.thumbprint", test::read_body(resp).await);
+        }
+    }
+
+    #[actix_web::test]
+    async fn should_answer_unavailable_without_healthy_instance() {
+        let app = test::init_service(App::new().app_data(web::Data::new(context_with_application())).wrap_fn(route_host).service(process::list_processes)).await;
+
+        let req = test::TestRequest::get().uri("/").insert_header((http::header::HOST, "first-capsule-application.capsuleapp.cyou")).to_request();
+        let resp = app.call(req).await.unwrap();
+
+        assert_eq!(http::StatusCode::SERVICE_UNAVAILABLE, resp.status());
+    }
+
+    #[actix_web::test]
+    async fn should_redirect_previous_name_keeping_path_and_query() {
+        let context = context_with_application();
+        context.redirects().add(&Redirect { from_name: "old-name".to_string(), application_id: 1, expire_at: SystemTime::now() + Duration::from_secs(3600) }).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(context)).wrap_fn(route_host).service(process::list_processes)).await;
+
+        let req = test::TestRequest::post().uri("/orders?page=2").insert_header((http::header::HOST, "old-name.capsuleapp.cyou")).to_request();
+        let resp = app.call(req).await.unwrap();
+
+        assert_eq!(http::StatusCode::PERMANENT_REDIRECT, resp.status());
+        assert_eq!("https://first-capsule-application.capsuleapp.cyou/orders?page=2", resp.headers().get(http::header::LOCATION).unwrap());
+    }
+
+    #[actix_web::test]
+    async fn should_pass_other_hosts_on_to_api() {
+        let app = test::init_service(App::new().app_data(web::Data::new(context_with_application())).wrap_fn(route_host).service(process::list_processes)).await;
+
+        for host in ["api.capsuleapp.cyou:8080", "capsuleapp.cyou", "unknown.capsuleapp.cyou", "[::1]:8080"] {
+            let req = test::TestRequest::get().uri("/applications/first-capsule-application/processes")
+                .insert_header((http::header::HOST, host))
+                .insert_header((USER_HEADER, "first_capsule_user"))
+                .to_request();
+            let resp = app.call(req).await.unwrap();
+
+            assert_eq!(http::StatusCode::OK, resp.status(), "{}", host);
+        }
+    }
+}